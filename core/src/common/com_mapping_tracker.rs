@@ -4,17 +4,23 @@
 //! between original COM objects and their proxy wrappers, enabling efficient
 //! lookup and lifecycle management in proxy scenarios.
 
+use super::CreationStack;
 use std::{
     any::type_name,
     collections::HashMap,
     ffi::c_void,
-    fmt::Debug,
+    fmt::{Debug, Write},
     marker::PhantomData,
-    mem::{forget, transmute_copy},
+    mem::forget,
+    path::PathBuf,
     ptr::null_mut,
+    sync::{Mutex, MutexGuard},
 };
 use windows::core::*;
 
+/// Maximum number of creation stacks listed in the growth report.
+const MAX_REPORTED_CREATION_STACKS: usize = 10;
+
 /// Increments the reference count of a COM interface object.
 ///
 /// # Safety
@@ -58,9 +64,18 @@ impl<T: Interface> NullableInterfaceOut<T> {
         Self(ptr, PhantomData)
     }
 
-    fn as_raw(&self) -> *mut c_void {
+    /// Returns the raw interface pointer, which is null for a null interface.
+    pub fn as_raw(&self) -> *mut c_void {
         self.0
     }
+
+    /// Returns a new reference to the interface, or `None` if it is null.
+    ///
+    /// The interface must still be alive, i.e. this must be called while the proxy it was
+    /// retrieved from is alive.
+    pub fn to_interface(&self) -> Option<T> {
+        unsafe { T::from_raw_borrowed(&self.0) }.cloned()
+    }
 }
 
 impl<T: Interface> Param<T> for NullableInterfaceOut<T> {
@@ -69,6 +84,46 @@ impl<T: Interface> Param<T> for NullableInterfaceOut<T> {
     }
 }
 
+/// Returns the identity of a COM object, which is the address of its `IUnknown` interface.
+///
+/// Pointers to different interfaces of the same object may differ, but COM requires querying any
+/// of them for `IUnknown` to return the same address. The address stays valid while the object is alive.
+pub fn com_identity<T: Interface>(obj: &T) -> *mut c_void {
+    obj.cast::<IUnknown>().map_or(obj.as_raw(), |unknown| unknown.as_raw())
+}
+
+/// Pointer to the interface a tracked object was registered with, the IID of that interface, and the identity of the object.
+///
+/// The identity is recorded at registration, as proxies cannot be queried anymore once they are being destroyed.
+#[derive(Debug, Clone, Copy)]
+struct TrackedInterface {
+    ptr: *mut c_void,
+    iid: GUID,
+    identity: *mut c_void,
+}
+
+impl TrackedInterface {
+    fn new<T: Interface>(obj: &T, identity: *mut c_void) -> Self {
+        Self {
+            ptr: obj.as_raw(),
+            iid: T::IID,
+            identity,
+        }
+    }
+
+    /// Returns a borrowed pointer to the `T` interface of the object, querying it if it was registered with another interface.
+    ///
+    /// The object must be alive. Returns `None` if the object does not implement `T`.
+    fn get<T: Interface>(&self) -> Option<*mut c_void> {
+        if self.iid == T::IID {
+            return Some(self.ptr);
+        }
+        // The queried reference is released right away, the pointer stays valid while the object is alive
+        let unknown = unsafe { IUnknown::from_raw_borrowed(&self.ptr) }?;
+        unknown.cast::<T>().ok().map(|interface| interface.as_raw())
+    }
+}
+
 /// Tracks bidirectional mappings between COM target objects and their proxy wrappers.
 ///
 /// This tracker maintains two hash maps to enable efficient lookups in both directions:
@@ -76,6 +131,18 @@ impl<T: Interface> Param<T> for NullableInterfaceOut<T> {
 /// - Proxy → Target: Find the original target object for a given proxy
 ///
 /// Used to ensure consistent proxy relationships and prevent duplicate proxy creation.
+/// The maps are protected by a lock of the tracker, so all methods take `&self`.
+///
+/// # Object Identity
+///
+/// Objects are keyed by their identity (see [`com_identity`]), not by the pointer of the interface
+/// they are passed as, so that an object registered as e.g. `IDirect3DTexture9` is also found when
+/// passed as `IDirect3DBaseTexture9`. Lookups with another interface than the one an object was
+/// registered with query the requested interface.
+///
+/// Objects are first looked up by the pointer they are passed as, which is the pointer they were
+/// registered with in most calls, and only queried for their identity if that misses. Querying a
+/// target calls into the driver, so it is never done while holding the lock.
 ///
 /// # Weak Reference Semantics
 ///
@@ -87,27 +154,57 @@ impl<T: Interface> Param<T> for NullableInterfaceOut<T> {
 /// This design requires careful coordination with proxy lifecycle management to ensure
 /// mappings are removed via [`on_proxy_destroy`] when proxies are dropped.
 ///
+/// # Growth Limit
+///
+/// A leak of proxies makes the tracker grow without bound. When a limit is set with
+/// [`with_max_tracked_objects`], exceeding it logs an error with the number of tracked
+/// proxies per interface type, and optionally dumps the same report to a file. Tracking
+/// continues normally afterwards, and the report is repeated each time the number of
+/// tracked proxies doubles.
+///
+/// With [`set_capture_creation_stacks`], the call stack is captured whenever a proxy is created,
+/// and the report additionally groups the tracked proxies by creation stack.
+///
 /// [`on_proxy_destroy`]: Self::on_proxy_destroy
-#[derive(Default)]
+/// [`with_max_tracked_objects`]: Self::with_max_tracked_objects
+/// [`set_capture_creation_stacks`]: Self::set_capture_creation_stacks
+#[derive(Debug, Default)]
 pub struct ComMappingTracker {
-    target_to_proxy: HashMap<*mut c_void, *mut c_void>,
-    proxy_to_target: HashMap<*mut c_void, *mut c_void>,
+    state: Mutex<TrackerState>,
+    /// File to also write growth reports to, outside of the lock.
+    report_file: Option<PathBuf>,
+}
+
+/// State of a [`ComMappingTracker`], protected by its lock.
+#[derive(Default)]
+struct TrackerState {
+    /// Proxies by the identity of their target.
+    target_to_proxy: HashMap<*mut c_void, TrackedInterface>,
+    /// Targets by the identity of their proxy.
+    proxy_to_target: HashMap<*mut c_void, TrackedInterface>,
+    /// Identities of the targets by the pointer they were registered with.
+    target_identities: HashMap<*mut c_void, *mut c_void>,
+    /// Identities of the proxies by the pointer they were registered with.
+    proxy_identities: HashMap<*mut c_void, *mut c_void>,
+    target_type_names: HashMap<*mut c_void, &'static str>,
+    max_tracked_objects: Option<usize>,
+    next_report_count: usize,
+    creation_stacks: Option<HashMap<*mut c_void, CreationStack>>,
 }
 
-unsafe impl Send for ComMappingTracker {}
-unsafe impl Sync for ComMappingTracker {}
+unsafe impl Send for TrackerState {}
 
-impl std::fmt::Debug for ComMappingTracker {
+impl std::fmt::Debug for TrackerState {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         const VERBOSE: bool = true;
 
         if VERBOSE {
-            f.debug_struct("ComMappingTracker")
+            f.debug_struct("TrackerState")
                 .field("target_to_proxy", &self.target_to_proxy)
                 .field("proxy_to_target", &self.proxy_to_target)
                 .finish()
         } else {
-            f.debug_struct("ComMappingTracker")
+            f.debug_struct("TrackerState")
                 .field("target_to_proxy_count", &self.target_to_proxy.len())
                 .field("proxy_to_target_count", &self.proxy_to_target.len())
                 .finish()
@@ -115,13 +212,182 @@ impl std::fmt::Debug for ComMappingTracker {
     }
 }
 
+impl TrackerState {
+    /// Returns the identity of the target tracked under `ptr`, which is the pointer it was registered with or its identity.
+    fn target_identity(&self, ptr: *mut c_void) -> Option<*mut c_void> {
+        self.target_identities.get(&ptr).copied().or_else(|| self.target_to_proxy.contains_key(&ptr).then_some(ptr))
+    }
+
+    /// Returns the identity of the proxy tracked under `ptr`, which is the pointer it was registered with or its identity.
+    fn proxy_identity(&self, ptr: *mut c_void) -> Option<*mut c_void> {
+        self.proxy_identities.get(&ptr).copied().or_else(|| self.proxy_to_target.contains_key(&ptr).then_some(ptr))
+    }
+
+    /// Returns a new reference to the proxy of the target with identity `target_ptr`, if it is tracked and implements `T`.
+    ///
+    /// Proxies are objects of this crate, so querying them does not call into the driver.
+    fn get_proxy<T: Interface>(&self, target_ptr: *mut c_void) -> Option<T> {
+        let proxy_ptr = self.target_to_proxy.get(&target_ptr)?.get::<T>()?;
+        Some(unsafe { add_ref(T::from_raw(proxy_ptr)) })
+    }
+
+    /// Returns the number of tracked proxies per interface type, sorted by descending count.
+    fn type_breakdown(&self) -> Vec<(&'static str, usize)> {
+        let mut counts = HashMap::<&'static str, usize>::new();
+        for type_name in self.target_type_names.values() {
+            *counts.entry(type_name).or_default() += 1;
+        }
+        let mut breakdown = counts.into_iter().collect::<Vec<_>>();
+        breakdown.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        breakdown
+    }
+
+    /// Formats the number of tracked proxies per interface type, one type per line.
+    fn format_type_breakdown(&self) -> String {
+        let mut report = String::new();
+        for (type_name, count) in self.type_breakdown() {
+            let _ = writeln!(report, "{count:>8} {type_name}");
+        }
+        report
+    }
+
+    /// Formats the tracked proxies grouped by creation stack, the most common stacks first.
+    ///
+    /// # Returns
+    /// * `Some(String)` - The report, listing up to `max_stacks` stacks
+    /// * `None` - If creation stacks are not captured
+    fn format_creation_stacks(&self, max_stacks: usize) -> Option<String> {
+        let creation_stacks = self.creation_stacks.as_ref()?;
+
+        let mut groups = HashMap::<&CreationStack, HashMap<&'static str, usize>>::new();
+        for (target_ptr, stack) in creation_stacks {
+            let type_name = self.target_type_names.get(target_ptr).copied().unwrap_or("?");
+            *groups.entry(stack).or_default().entry(type_name).or_default() += 1;
+        }
+        let mut groups = groups.into_iter().map(|(stack, types)| (types.values().sum::<usize>(), stack, types)).collect::<Vec<_>>();
+        groups.sort_by_key(|(count, _, _)| std::cmp::Reverse(*count));
+
+        let mut report = String::new();
+        for (count, stack, types) in groups.into_iter().take(max_stacks) {
+            let types = types.into_iter().map(|(type_name, count)| format!("{count} {type_name}")).collect::<Vec<_>>().join(", ");
+            let _ = write!(report, "{count} proxies ({types}) created at:\n{stack}");
+        }
+        Some(report)
+    }
+
+    /// Checks for runaway growth, i.e. whether the number of tracked proxies exceeded the limit, or doubled since the last report.
+    ///
+    /// # Returns
+    /// * `Some(String)` - The report to log, which is only formatted here so that the caller can log it after releasing the lock
+    /// * `None` - If there is nothing to report
+    fn check_growth(&mut self) -> Option<String> {
+        let max_tracked_objects = self.max_tracked_objects?;
+        let count = self.target_to_proxy.len();
+        if count < self.next_report_count {
+            return None;
+        }
+        self.next_report_count = count.saturating_mul(2);
+
+        let mut report = format!("{count} proxies tracked, exceeding the limit of {max_tracked_objects}:\n{}", self.format_type_breakdown());
+        if let Some(creation_stacks) = self.format_creation_stacks(MAX_REPORTED_CREATION_STACKS) {
+            report.push_str(&creation_stacks);
+        }
+        Some(report)
+    }
+}
+
 impl ComMappingTracker {
+    /// Creates a tracker that reports when more than `max_tracked_objects` proxies are tracked.
+    ///
+    /// # Arguments
+    /// * `max_tracked_objects` - The number of tracked proxies above which a report is logged
+    /// * `report_file` - A file to also write the report to, overwritten on each report
+    pub fn with_max_tracked_objects(max_tracked_objects: usize, report_file: Option<PathBuf>) -> Self {
+        Self {
+            state: Mutex::new(TrackerState {
+                max_tracked_objects: Some(max_tracked_objects),
+                next_report_count: max_tracked_objects.saturating_add(1),
+                ..Default::default()
+            }),
+            report_file,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, TrackerState> {
+        self.state.lock().unwrap()
+    }
+
+    /// Logs a report of [`TrackerState::check_growth`], and writes it to the report file if one is set.
+    ///
+    /// Must be called without holding the lock, as writing the file may block.
+    fn report_growth(&self, report: &str) {
+        #[cfg(feature = "tracing")]
+        tracing::error!("Possible proxy leak: {report}");
+
+        if let Some(report_file) = &self.report_file {
+            let _ = std::fs::write(report_file, report).inspect_err(|_err| {
+                #[cfg(feature = "tracing")]
+                tracing::error!("Failed to write tracker report to {}: {_err}", report_file.display());
+            });
+        }
+    }
+
+    /// Locks the tracker and looks up `obj` with `resolve`, first by the pointer it is passed as, then by its identity.
+    ///
+    /// `obj` is only queried for its identity if the first lookup misses, and without holding the lock.
+    ///
+    /// # Returns
+    /// The lock, and `Ok` with the identity of `obj` if it is tracked, or `Err` with its identity otherwise.
+    fn lock_resolved<T: Interface>(&self, obj: &T, resolve: fn(&TrackerState, *mut c_void) -> Option<*mut c_void>) -> (MutexGuard<'_, TrackerState>, std::result::Result<*mut c_void, *mut c_void>) {
+        let state = self.lock();
+        if let Some(identity) = resolve(&state, obj.as_raw()) {
+            return (state, Ok(identity));
+        }
+        drop(state);
+
+        let identity = com_identity(obj);
+        let state = self.lock();
+        let resolved = resolve(&state, identity).ok_or(identity);
+        (state, resolved)
+    }
+
+    /// Enables or disables capturing the call stack at the creation of each proxy.
+    ///
+    /// Capturing a stack on every proxy creation is costly, so this is disabled by default.
+    pub fn set_capture_creation_stacks(&self, enabled: bool) {
+        self.lock().creation_stacks = enabled.then(HashMap::new);
+    }
+
+    /// Returns the number of tracked proxies.
+    #[cfg(any(test, feature = "tracing"))]
+    pub fn len(&self) -> usize {
+        self.lock().target_to_proxy.len()
+    }
+
+    /// Formats the number of tracked proxies per interface type, one type per line.
+    #[cfg(feature = "tracing")]
+    pub fn format_type_breakdown(&self) -> String {
+        self.lock().format_type_breakdown()
+    }
+
+    /// Returns the stack captured at the creation of the proxy of the target with identity `target_ptr`, see [`com_identity`].
+    ///
+    /// # Returns
+    /// * `Some(CreationStack)` - The creation stack of the proxy
+    /// * `None` - If creation stacks are not captured, or `target_ptr` is not tracked
+    pub fn creation_stack(&self, target_ptr: *mut c_void) -> Option<CreationStack> {
+        self.lock().creation_stacks.as_ref()?.get(&target_ptr).cloned()
+    }
+
     /// Ensures a proxy exists for the given target COM object, creating one if necessary.
     ///
     /// This method first checks if a proxy already exists for the target object. If found,
     /// it returns the existing proxy (with proper reference counting). If not found, it
     /// creates a new proxy using the provided creation function and stores the mapping.
     ///
+    /// The creation function is called while holding the lock of the tracker, so that two threads
+    /// cannot create proxies for the same target. It must therefore not use the tracker.
+    ///
     /// # Type Parameters
     /// * `T` - The COM interface type that implements `Interface + Debug`
     ///
@@ -143,31 +409,46 @@ impl ComMappingTracker {
     ///     Ok(ProxyDevice::new(target))
     /// })?;
     /// ```
-    pub fn try_ensure_proxy<T: Interface + Debug>(&mut self, target: T, try_create_proxy_fn: impl FnOnce(T) -> Result<T>) -> Result<T> {
-        let target_ptr = target.as_raw();
-        if let Some(proxy_ptr) = self.target_to_proxy.get(&target_ptr) {
+    pub fn try_ensure_proxy<T: Interface + Debug>(&self, target: T, try_create_proxy_fn: impl FnOnce(T) -> Result<T>) -> Result<T> {
+        let (mut state, resolved) = self.lock_resolved(&target, TrackerState::target_identity);
+        let (Ok(target_ptr) | Err(target_ptr)) = resolved;
+        if let Some(proxy) = state.get_proxy::<T>(target_ptr) {
             // If we already have a proxy for this org surface, return it
             // - Decrease ref count of target via drop
             // - Increase ref count of proxy
             #[cfg(feature = "tracing")]
-            tracing::debug!("Found existing {} proxy: {proxy_ptr:?} (<=> {target_ptr:?})", type_name::<T>());
-            return Ok(unsafe { add_ref(T::from_raw(*proxy_ptr)) });
+            tracing::debug!("Found existing {} proxy: {:p} (<=> {target_ptr:p})", type_name::<T>(), proxy.as_raw());
+            return Ok(proxy);
         }
 
         // Create a new proxy if it doesn't exist
         // - Move the target reference to a proxy
         // - Keep ref count of proxy 1
+        let tracked_target = TrackedInterface::new(&target, target_ptr);
         let proxy = try_create_proxy_fn(target)?;
-        let proxy_ptr = proxy.as_raw();
+        let tracked_proxy = TrackedInterface::new(&proxy, com_identity(&proxy));
+        let proxy_ptr = tracked_proxy.identity;
 
         // Store the new proxy in the storage
-        self.target_to_proxy.insert(target_ptr, proxy_ptr);
-        self.proxy_to_target.insert(proxy_ptr, target_ptr);
+        state.target_to_proxy.insert(target_ptr, tracked_proxy);
+        state.proxy_to_target.insert(proxy_ptr, tracked_target);
+        state.target_identities.insert(tracked_target.ptr, target_ptr);
+        state.proxy_identities.insert(tracked_proxy.ptr, proxy_ptr);
+        state.target_type_names.insert(target_ptr, type_name::<T>());
+        if let Some(creation_stacks) = &mut state.creation_stacks {
+            creation_stacks.insert(target_ptr, CreationStack::capture());
+        }
+        let report = state.check_growth();
 
         #[cfg(feature = "tracing")]
         tracing::debug!("Created new {} proxy: {proxy_ptr:p} (<=> {target_ptr:p})", type_name::<T>());
         #[cfg(feature = "tracing")]
-        tracing::trace!("Current maps: {self:?}");
+        tracing::trace!("Current maps: {state:?}");
+
+        drop(state);
+        if let Some(report) = report {
+            self.report_growth(&report);
+        }
 
         // Return the pointer to the new proxy
         Ok(proxy)
@@ -191,7 +472,7 @@ impl ComMappingTracker {
     /// Same as [`try_ensure_proxy`]
     ///
     /// [`try_ensure_proxy`]: Self::try_ensure_proxy
-    pub fn ensure_proxy<T: Interface + Debug>(&mut self, target: T, create_proxy_fn: impl FnOnce(T) -> T) -> T {
+    pub fn ensure_proxy<T: Interface + Debug>(&self, target: T, create_proxy_fn: impl FnOnce(T) -> T) -> T {
         self.try_ensure_proxy(target, |target| Ok(create_proxy_fn(target))).unwrap()
     }
 
@@ -217,19 +498,30 @@ impl ComMappingTracker {
     ///
     /// [`try_ensure_proxy`]: Self::try_ensure_proxy
     /// [`ensure_proxy`]: Self::ensure_proxy
-    pub fn get_proxy<T: Interface + Debug>(&mut self, target: T) -> Option<T> {
+    pub fn get_proxy<T: Interface + Debug>(&self, target: T) -> Option<T> {
         // - Decrease ref count of target via drop
         // - Increase ref count of proxy
-        let target_ptr = target.as_raw();
-        let result = self.target_to_proxy.get(&target_ptr).map(|proxy_ptr| unsafe { add_ref(transmute_copy::<_, T>(proxy_ptr)) });
+        let (state, resolved) = self.lock_resolved(&target, TrackerState::target_identity);
+        let result = resolved.ok().and_then(|target_ptr| state.get_proxy::<T>(target_ptr));
+        drop(state);
         #[cfg(feature = "tracing")]
         match &result {
-            Some(proxy) => tracing::debug!("Retrieved {} proxy: {:p} (<=> {target_ptr:p})", type_name::<T>(), proxy.as_raw()),
-            None => tracing::warn!("No {} proxy found: NOTFOUND (<=> {target_ptr:p})", type_name::<T>()),
+            Some(proxy) => tracing::debug!("Retrieved {} proxy: {:p} (<=> {:p})", type_name::<T>(), proxy.as_raw(), target.as_raw()),
+            None => tracing::warn!("No {} proxy found: NOTFOUND (<=> {:p})", type_name::<T>(), target.as_raw()),
         };
         result
     }
 
+    /// Looks up the target of a proxy, querying its `T` interface without holding the lock.
+    ///
+    /// The target stays alive while the proxy is, which the caller holds.
+    fn find_target<T: Interface>(&self, proxy: &T) -> Option<NullableInterfaceOut<T>> {
+        let (state, resolved) = self.lock_resolved(proxy, TrackerState::proxy_identity);
+        let tracked_target = resolved.ok().and_then(|proxy_ptr| state.proxy_to_target.get(&proxy_ptr).copied());
+        drop(state);
+        tracked_target?.get::<T>().map(NullableInterfaceOut::new)
+    }
+
     /// Retrieves the original target COM object for a given proxy.
     ///
     /// This method performs the reverse lookup from proxy to target object.
@@ -254,21 +546,18 @@ impl ComMappingTracker {
     /// For cases where null proxies should map to null targets, use [`get_target_nullable`].
     ///
     /// [`get_target_nullable`]: Self::get_target_nullable
-    pub fn get_target<T: Interface + Debug, K: NullableInterfaceIn<T>>(&mut self, proxy: K) -> Option<NullableInterfaceOut<T>> {
+    pub fn get_target<T: Interface + Debug, K: NullableInterfaceIn<T>>(&self, proxy: K) -> Option<NullableInterfaceOut<T>> {
         // - No ref count changes here, both input and output are references
-        let proxy_ptr = match proxy.as_ref() {
-            Some(obj_ref) => obj_ref.as_raw(),
-            None => {
-                #[cfg(feature = "tracing")]
-                tracing::warn!("Attempted to get target for a null proxy reference of type {}, treating as not found", type_name::<T>());
-                return None;
-            }
+        let Some(proxy) = proxy.as_ref() else {
+            #[cfg(feature = "tracing")]
+            tracing::warn!("Attempted to get target for a null proxy reference of type {}, treating as not found", type_name::<T>());
+            return None;
         };
-        let result = self.proxy_to_target.get(&proxy_ptr).map(|target_ptr| NullableInterfaceOut::new(*target_ptr));
+        let result = self.find_target(proxy);
         #[cfg(feature = "tracing")]
         match &result {
-            Some(target) => tracing::debug!("Retrieved {} target of proxy: {proxy_ptr:p} (<=> {:p})", type_name::<T>(), target.as_raw()),
-            None => tracing::warn!("No target found for {} proxy: {proxy_ptr:p} (<=> NOTFOUND)", type_name::<T>()),
+            Some(target) => tracing::debug!("Retrieved {} target of proxy: {:p} (<=> {:p})", type_name::<T>(), proxy.as_raw(), target.as_raw()),
+            None => tracing::warn!("No target found for {} proxy: {:p} (<=> NOTFOUND)", type_name::<T>(), proxy.as_raw()),
         };
         result
     }
@@ -300,21 +589,18 @@ impl ComMappingTracker {
     ///
     /// [`get_target`]: Self::get_target
     /// [`get_target_nullable`]: Self::get_target_nullable
-    pub fn get_target_nullable<T: Interface + Debug, K: NullableInterfaceIn<T>>(&mut self, proxy: K) -> Option<NullableInterfaceOut<T>> {
+    pub fn get_target_nullable<T: Interface + Debug, K: NullableInterfaceIn<T>>(&self, proxy: K) -> Option<NullableInterfaceOut<T>> {
         // - No ref count changes here, both input and output are references
-        let proxy_ptr = match proxy.as_ref() {
-            Some(obj_ref) => obj_ref.as_raw(),
-            None => {
-                #[cfg(feature = "tracing")]
-                tracing::debug!("Returning nullptr for null proxy reference of type {}", type_name::<T>());
-                return Some(NullableInterfaceOut::new(null_mut()));
-            }
+        let Some(proxy) = proxy.as_ref() else {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("Returning nullptr for null proxy reference of type {}", type_name::<T>());
+            return Some(NullableInterfaceOut::new(null_mut()));
         };
-        let result = self.proxy_to_target.get(&proxy_ptr).map(|target_ptr| NullableInterfaceOut::new(*target_ptr));
+        let result = self.find_target(proxy);
         #[cfg(feature = "tracing")]
         match &result {
-            Some(target) => tracing::debug!("Retrieved {} target of proxy: {proxy_ptr:p} (<=> {:p})", type_name::<T>(), target.as_raw()),
-            None => tracing::warn!("No target found for {} proxy pointer: {proxy_ptr:p} (<=> NOTFOUND)", type_name::<T>()),
+            Some(target) => tracing::debug!("Retrieved {} target of proxy: {:p} (<=> {:p})", type_name::<T>(), proxy.as_raw(), target.as_raw()),
+            None => tracing::warn!("No target found for {} proxy pointer: {:p} (<=> NOTFOUND)", type_name::<T>(), proxy.as_raw()),
         };
         result
     }
@@ -347,15 +633,148 @@ impl ComMappingTracker {
     ///     }
     /// }
     /// ```
-    pub fn on_proxy_destroy<T: Interface + Debug>(&mut self, target: &T) {
-        let target_ptr = target.as_raw();
-        if let Some(proxy_ptr) = self.target_to_proxy.remove(&target_ptr) {
-            self.proxy_to_target.remove(&proxy_ptr);
+    pub fn on_proxy_destroy<T: Interface + Debug>(&self, target: &T) {
+        let (mut state, resolved) = self.lock_resolved(target, TrackerState::target_identity);
+        let Ok(target_ptr) = resolved else {
             #[cfg(feature = "tracing")]
-            tracing::debug!("{} proxy destroyed: {proxy_ptr:p} (<=> {target_ptr:p})", type_name::<T>());
-        } else {
+            tracing::warn!(
+                "{} proxy destroyed, but no entry found in storage for target pointer: NOTFOUND (<=> {:p})",
+                type_name::<T>(),
+                target.as_raw()
+            );
+            return;
+        };
+
+        if let Some(proxy) = state.target_to_proxy.remove(&target_ptr) {
+            let proxy_ptr = proxy.identity;
+            if let Some(tracked_target) = state.proxy_to_target.remove(&proxy_ptr) {
+                state.target_identities.remove(&tracked_target.ptr);
+            }
+            state.proxy_identities.remove(&proxy.ptr);
+            state.target_type_names.remove(&target_ptr);
+            if let Some(creation_stacks) = &mut state.creation_stacks {
+                creation_stacks.remove(&target_ptr);
+            }
             #[cfg(feature = "tracing")]
-            tracing::warn!("{} proxy destroyed, but no entry found in storage for target pointer: NOTFOUND (<=> {target_ptr:p})", type_name::<T>());
+            tracing::debug!("{} proxy destroyed: {proxy_ptr:p} (<=> {target_ptr:p})", type_name::<T>());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use windows::Win32::System::Com::{IClassFactory, IClassFactory_Impl, IPersist, IPersist_Impl};
+
+    /// Object with two unrelated interfaces, whose pointers differ from each other and from its identity.
+    #[implement(IPersist, IClassFactory)]
+    struct Dummy;
+
+    impl IPersist_Impl for Dummy_Impl {
+        fn GetClassID(&self) -> Result<GUID> {
+            Err(Error::empty())
+        }
+    }
+
+    impl IClassFactory_Impl for Dummy_Impl {
+        fn CreateInstance(&self, _punkouter: Ref<IUnknown>, _riid: *const GUID, _ppvobject: *mut *mut c_void) -> Result<()> {
+            Err(Error::empty())
         }
+
+        fn LockServer(&self, _flock: BOOL) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Registers a dummy target with a dummy proxy as `IPersist`, returning both.
+    fn register(tracker: &ComMappingTracker) -> (IPersist, IPersist) {
+        let target: IPersist = Dummy.into();
+        let proxy = tracker.ensure_proxy(target.clone(), |_| Dummy.into());
+        (target, proxy)
+    }
+
+    #[test]
+    fn dummy_interfaces_have_distinct_pointers() {
+        let persist: IPersist = Dummy.into();
+        let factory: IClassFactory = persist.cast().unwrap();
+        let unknown: IUnknown = persist.cast().unwrap();
+        assert_ne!(persist.as_raw(), factory.as_raw());
+        assert_ne!(persist.as_raw(), unknown.as_raw());
+        assert_ne!(factory.as_raw(), unknown.as_raw());
+    }
+
+    #[test]
+    fn objects_are_found_through_any_interface() {
+        let tracker = ComMappingTracker::default();
+        let (target, proxy) = register(&tracker);
+        let target_factory: IClassFactory = target.cast().unwrap();
+        let proxy_factory: IClassFactory = proxy.cast().unwrap();
+
+        assert_eq!(tracker.get_proxy(target.clone()), Some(proxy.clone()));
+        assert_eq!(tracker.get_proxy(target_factory.clone()), Some(proxy_factory.clone()));
+        assert_eq!(com_identity(&tracker.get_proxy(target.cast::<IUnknown>().unwrap()).unwrap()), com_identity(&proxy));
+
+        assert_eq!(tracker.get_target(Some(&proxy)).unwrap().as_raw(), target.as_raw());
+        assert_eq!(tracker.get_target(Some(&proxy_factory)).unwrap().as_raw(), target_factory.as_raw());
+        assert_eq!(tracker.get_target_nullable(Some(&proxy.cast::<IUnknown>().unwrap())).unwrap().as_raw(), com_identity(&target));
+
+        // Targets are not proxies and vice versa
+        assert!(tracker.get_target(Some(&target_factory)).is_none());
+        assert!(tracker.get_proxy(proxy_factory.clone()).is_none());
+        tracker.on_proxy_destroy(&target);
+    }
+
+    #[test]
+    fn ensure_proxy_reuses_proxy_of_other_interface() {
+        let tracker = ComMappingTracker::default();
+        let (target, proxy) = register(&tracker);
+
+        let proxy_factory = tracker.ensure_proxy(target.cast::<IClassFactory>().unwrap(), |_| panic!("created a second proxy"));
+        assert_eq!(proxy_factory, proxy.cast().unwrap());
+        assert_eq!(tracker.len(), 1);
+        tracker.on_proxy_destroy(&target);
+    }
+
+    #[test]
+    fn destroy_forgets_all_interfaces() {
+        let tracker = ComMappingTracker::default();
+        let (target, proxy) = register(&tracker);
+        let (other_target, other_proxy) = register(&tracker);
+        assert_eq!(tracker.len(), 2);
+
+        tracker.on_proxy_destroy(&target.cast::<IClassFactory>().unwrap());
+        assert_eq!(tracker.len(), 1);
+        assert!(tracker.get_proxy(target.clone()).is_none());
+        assert!(tracker.get_target(Some(&proxy)).is_none());
+        assert!(tracker.get_target(Some(&proxy.cast::<IClassFactory>().unwrap())).is_none());
+        assert_eq!(tracker.get_proxy(other_target.clone()), Some(other_proxy));
+
+        tracker.on_proxy_destroy(&other_target);
+        assert_eq!(tracker.len(), 0);
+    }
+
+    #[test]
+    fn growth_report_is_written_to_file() {
+        let report_file = std::env::temp_dir().join(format!("dxproxy-tracker-report-{}.txt", std::process::id()));
+        let _ = std::fs::remove_file(&report_file);
+        let tracker = ComMappingTracker::with_max_tracked_objects(1, Some(report_file.clone()));
+
+        let (target, _proxy) = register(&tracker);
+        assert!(!report_file.exists());
+        let (other_target, _other_proxy) = register(&tracker);
+        let report = std::fs::read_to_string(&report_file).unwrap();
+        assert!(report.starts_with("2 proxies tracked, exceeding the limit of 1:"), "{report}");
+        assert!(report.contains(type_name::<IPersist>()), "{report}");
+
+        tracker.on_proxy_destroy(&target);
+        tracker.on_proxy_destroy(&other_target);
+        std::fs::remove_file(&report_file).unwrap();
+    }
+
+    #[test]
+    fn null_proxies_are_not_found_or_mapped_to_null() {
+        let tracker = ComMappingTracker::default();
+        assert!(tracker.get_target::<IPersist, _>(None).is_none());
+        assert!(tracker.get_target_nullable::<IPersist, _>(None).unwrap().as_raw().is_null());
     }
 }
@@ -0,0 +1,78 @@
+//! Call stacks captured at the creation of proxies, for attributing leaks to their creation sites.
+//!
+//! Symbols are not resolved, as that requires debug information that is usually not available
+//! for the application. Instead, each frame is printed as a raw address together with the module
+//! it belongs to and the offset into it, which can be resolved later with a debugger or symbol tools.
+
+use std::{
+    ffi::c_void,
+    fmt::{self, Display},
+};
+use windows::{
+    Win32::{
+        Foundation::*,
+        System::{Diagnostics::Debug::*, LibraryLoader::*},
+    },
+    core::*,
+};
+
+/// Maximum number of frames captured per stack.
+const MAX_CREATION_STACK_FRAMES: usize = 24;
+
+/// Call stack captured at the creation of a proxy.
+///
+/// Stacks are compared by their frame addresses, so they can be used to group proxies by creation site.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CreationStack {
+    frames: Vec<usize>,
+}
+
+impl CreationStack {
+    /// Captures the call stack of the caller.
+    ///
+    /// This walks the stack on every call, so only use it when creation stacks were requested.
+    pub fn capture() -> Self {
+        let mut frames = [null_frame(); MAX_CREATION_STACK_FRAMES];
+        // Skip this function itself
+        let count = unsafe { RtlCaptureStackBackTrace(1, &mut frames, None) } as usize;
+        Self {
+            frames: frames[..count].iter().map(|&frame| frame as usize).collect(),
+        }
+    }
+}
+
+/// Formats one frame per line as `0x<address> <module>+0x<offset>`.
+impl Display for CreationStack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for &frame in &self.frames {
+            match module_of(frame) {
+                Some((module_name, base)) => writeln!(f, "    {frame:#x} {module_name}+{:#x}", frame - base)?,
+                None => writeln!(f, "    {frame:#x}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+const fn null_frame() -> *mut c_void {
+    std::ptr::null_mut()
+}
+
+/// Returns the file name and base address of the module containing `address`.
+fn module_of(address: usize) -> Option<(String, usize)> {
+    let mut module = HMODULE::default();
+    unsafe {
+        GetModuleHandleExW(
+            GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS | GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+            PCWSTR(address as *const u16),
+            &mut module,
+        )
+    }
+    .ok()?;
+
+    let mut buffer = [0u16; 260];
+    let len = unsafe { GetModuleFileNameW(Some(module), &mut buffer) } as usize;
+    let path = String::from_utf16_lossy(&buffer[..len]);
+    let module_name = path.rsplit(['\\', '/']).next().unwrap_or(&path).to_string();
+    Some((module_name, module.0 as usize))
+}
@@ -0,0 +1,118 @@
+//! Test-only capture of the events logged with `tracing`, to assert on logging behavior.
+//!
+//! [`with_captured_logs`] installs a [`LogCaptureLayer`] as the default subscriber of the current
+//! thread while running a closure, so that tests running in parallel do not see each other's events.
+
+use std::{
+    fmt::{self, Write},
+    sync::{Arc, Mutex},
+};
+use tracing::{
+    Event, Level, Subscriber,
+    field::{Field, Visit},
+};
+use tracing_subscriber::{Layer, layer::Context, prelude::*};
+
+/// Event captured by [`with_captured_logs`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedEvent {
+    /// Level of the event.
+    pub level: Level,
+    /// Target of the event, i.e. the module path by default.
+    pub target: String,
+    /// Message of the event, followed by its other fields as ` name=value`.
+    pub message: String,
+}
+
+/// Layer that appends each event it sees to a shared list.
+#[derive(Debug, Default, Clone)]
+pub struct LogCaptureLayer {
+    events: Arc<Mutex<Vec<CapturedEvent>>>,
+}
+
+impl LogCaptureLayer {
+    /// Returns the events captured so far.
+    pub fn events(&self) -> Vec<CapturedEvent> {
+        self.events.lock().unwrap().clone()
+    }
+}
+
+impl<S: Subscriber> Layer<S> for LogCaptureLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        visitor.message.push_str(&visitor.fields);
+
+        let metadata = event.metadata();
+        self.events.lock().unwrap().push(CapturedEvent {
+            level: *metadata.level(),
+            target: metadata.target().to_string(),
+            message: visitor.message,
+        });
+    }
+}
+
+/// Formats the `message` field and the other fields of an event separately.
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{value:?}");
+        } else {
+            let _ = write!(self.fields, " {}={value:?}", field.name());
+        }
+    }
+}
+
+/// Runs `f` and returns the events it logged on the current thread, at all levels.
+pub fn with_captured_logs(f: impl FnOnce()) -> Vec<CapturedEvent> {
+    let layer = LogCaptureLayer::default();
+    tracing::subscriber::with_default(tracing_subscriber::registry().with(layer.clone()), f);
+    layer.events()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn captures_events_of_the_closure_only() {
+        tracing::warn!("Before");
+        let events = with_captured_logs(|| {
+            tracing::warn!(count = 2, "Converted {} surfaces", 3);
+            tracing::trace!(target: "dxproxy::test", "Traced");
+        });
+        tracing::warn!("After");
+
+        assert_eq!(
+            events,
+            [
+                CapturedEvent {
+                    level: Level::WARN,
+                    target: module_path!().to_string(),
+                    message: "Converted 3 surfaces count=2".to_string(),
+                },
+                CapturedEvent {
+                    level: Level::TRACE,
+                    target: "dxproxy::test".to_string(),
+                    message: "Traced".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn captures_events_of_other_threads_separately() {
+        let events = with_captured_logs(|| {
+            std::thread::spawn(|| tracing::warn!("Other thread")).join().unwrap();
+            tracing::info!("This thread");
+        });
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].message, "This thread");
+    }
+}
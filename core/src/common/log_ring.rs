@@ -0,0 +1,71 @@
+//! Bounded in-memory ring of the most recent log lines.
+//!
+//! Log files can be truncated when the process crashes, especially with asynchronous logging.
+//! Keeping the last lines in memory guarantees that the context right before a fault can still
+//! be recovered, by dumping the ring to a file from a hotkey or a panic hook.
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+/// Ring buffer of the most recent formatted log lines, shared among clones.
+///
+/// Use it as the writer of a `tracing_subscriber::fmt` layer, which writes each event with a single
+/// `write` call, so that every write becomes one entry of the ring. When the ring is full, the oldest
+/// entry is dropped.
+#[derive(Debug, Clone)]
+pub struct LogRing {
+    lines: Arc<Mutex<VecDeque<String>>>,
+    capacity: usize,
+}
+
+impl LogRing {
+    /// Creates an empty ring that keeps up to `capacity` lines.
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            lines: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    /// Appends a line, dropping the oldest one if the ring is full.
+    pub fn push(&self, line: String) {
+        // Keep logging after a panic while the lock was held, as dumping then matters most
+        let mut lines = self.lines.lock().unwrap_or_else(|err| err.into_inner());
+        if lines.len() == self.capacity {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    /// Writes the lines currently in the ring to the file at `path`, oldest first, replacing its contents.
+    ///
+    /// # Returns
+    /// The number of lines written.
+    pub fn dump(&self, path: &Path) -> io::Result<usize> {
+        let lines = self.lines.lock().unwrap_or_else(|err| err.into_inner()).clone();
+        let mut file = io::BufWriter::new(File::create(path)?);
+        for line in &lines {
+            writeln!(file, "{line}")?;
+        }
+        file.flush()?;
+        Ok(lines.len())
+    }
+}
+
+/// Appends each write as a line, without its trailing line break.
+impl Write for LogRing {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.push(String::from_utf8_lossy(buf).trim_end_matches(['\r', '\n']).to_string());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
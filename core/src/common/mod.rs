@@ -4,7 +4,29 @@
 //! parameter handling, and mapping between proxy and target objects.
 
 mod com_mapping_tracker;
+mod creation_stack;
+#[cfg(all(test, feature = "tracing"))]
+mod log_capture;
+#[cfg(any(feature = "tracing", feature = "tracing-instrument"))]
+mod log_ring;
+#[cfg(any(feature = "tracing", feature = "tracing-instrument"))]
+mod non_blocking_writer;
+#[cfg(feature = "experimental-serialize-device-calls")]
+mod serial_executor;
+#[cfg(any(feature = "tracing", feature = "tracing-instrument"))]
+mod session_event_format;
 mod try_out_param;
 
 pub use com_mapping_tracker::*;
+pub use creation_stack::*;
+#[cfg(all(test, feature = "tracing"))]
+pub use log_capture::*;
+#[cfg(any(feature = "tracing", feature = "tracing-instrument"))]
+pub use log_ring::*;
+#[cfg(any(feature = "tracing", feature = "tracing-instrument"))]
+pub use non_blocking_writer::*;
+#[cfg(feature = "experimental-serialize-device-calls")]
+pub use serial_executor::*;
+#[cfg(any(feature = "tracing", feature = "tracing-instrument"))]
+pub use session_event_format::*;
 pub use try_out_param::*;
@@ -0,0 +1,94 @@
+//! Writer that performs writes on a background thread, to keep slow disks off the calling threads.
+
+use std::{
+    io::{self, Write},
+    sync::mpsc::{Receiver, Sender, channel},
+    thread,
+    time::Duration,
+};
+
+/// A request sent to the writer thread.
+enum Message {
+    /// Bytes to write.
+    Write(Vec<u8>),
+    /// Request to flush everything written so far, acknowledged through the sender.
+    Flush(Sender<()>),
+}
+
+/// Writer that enqueues writes and performs them on a dedicated background thread.
+///
+/// Writes never block on I/O, at the cost of losing the enqueued data if the process is terminated
+/// before the background thread has written it. Call [`flush`](Self::flush) to wait for the writes
+/// enqueued so far, e.g. before exiting. The queue is unbounded, so a writer that cannot keep up
+/// grows it rather than stalling the callers.
+///
+/// Clones share the same background thread, which exits once all clones are dropped.
+#[derive(Debug, Clone)]
+pub struct NonBlockingWriter {
+    sender: Sender<Message>,
+}
+
+impl NonBlockingWriter {
+    /// Spawns the background thread with the specified name, which writes to `writer`.
+    pub fn new<W: Write + Send + 'static>(name: &str, writer: W) -> io::Result<Self> {
+        let (sender, receiver) = channel();
+        thread::Builder::new().name(name.to_string()).spawn(move || run_writer(receiver, writer))?;
+        Ok(Self { sender })
+    }
+
+    /// Blocks until all writes enqueued so far have been written and flushed.
+    ///
+    /// Returns immediately if the background thread has exited.
+    pub fn flush(&self) {
+        let (done_sender, done_receiver) = channel();
+        if self.sender.send(Message::Flush(done_sender)).is_ok() {
+            let _ = done_receiver.recv();
+        }
+    }
+
+    /// Like [`flush`](Self::flush), but gives up after `timeout`.
+    ///
+    /// Useful where the background thread may no longer run, e.g. during process termination,
+    /// where other threads are terminated without notice. Returns `true` if the writes were flushed.
+    pub fn flush_timeout(&self, timeout: Duration) -> bool {
+        let (done_sender, done_receiver) = channel();
+        self.sender.send(Message::Flush(done_sender)).is_ok() && done_receiver.recv_timeout(timeout).is_ok()
+    }
+}
+
+/// Enqueues the data without blocking. Flushing is a no-op, see [`NonBlockingWriter::flush`] for waiting on the writes.
+impl Write for NonBlockingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.sender.send(Message::Write(buf.to_vec())).map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Performs the enqueued requests on `writer`, flushing it whenever the queue runs empty.
+fn run_writer(receiver: Receiver<Message>, writer: impl Write) {
+    let mut writer = io::BufWriter::new(writer);
+    while let Ok(message) = receiver.recv() {
+        handle_message(message, &mut writer);
+        while let Ok(message) = receiver.try_recv() {
+            handle_message(message, &mut writer);
+        }
+        let _ = writer.flush();
+    }
+}
+
+/// Performs a single request on `writer`. Write errors are ignored, as there is nowhere to report them.
+fn handle_message(message: Message, writer: &mut impl Write) {
+    match message {
+        Message::Write(data) => {
+            let _ = writer.write_all(&data);
+        }
+        Message::Flush(done) => {
+            let _ = writer.flush();
+            let _ = done.send(());
+        }
+    }
+}
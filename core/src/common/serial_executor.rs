@@ -0,0 +1,144 @@
+//! Executor that runs closures one at a time on a dedicated worker thread.
+
+use std::{
+    fmt::Debug,
+    mem::transmute,
+    panic::{AssertUnwindSafe, catch_unwind, resume_unwind},
+    sync::{
+        Mutex,
+        mpsc::{Sender, channel},
+    },
+    thread::{self, ThreadId},
+};
+
+/// A job sent to the worker thread. Returns `false` if the closure panicked.
+type Job = Box<dyn FnOnce() -> bool + Send + 'static>;
+
+/// Runs closures on a dedicated worker thread, serializing them regardless of the calling thread.
+///
+/// Calls block until the closure has finished on the worker thread, so closures may borrow
+/// from the caller's stack. Closures invoked from the worker thread itself run inline,
+/// which keeps nested calls from deadlocking.
+///
+/// The worker thread exits when the executor is dropped.
+pub struct SerialExecutor {
+    sender: Mutex<Sender<(Job, Sender<bool>)>>,
+    worker_thread_id: ThreadId,
+}
+
+impl SerialExecutor {
+    /// Spawns the worker thread with the specified name.
+    ///
+    /// # Panics
+    /// Panics if the worker thread cannot be spawned.
+    pub fn new(name: &str) -> Self {
+        let (sender, receiver) = channel::<(Job, Sender<bool>)>();
+        let worker = thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || {
+                for (job, done) in receiver {
+                    let _ = done.send(job());
+                }
+            })
+            .expect("failed to spawn serial executor thread");
+
+        Self {
+            sender: Mutex::new(sender),
+            worker_thread_id: worker.thread().id(),
+        }
+    }
+
+    /// Runs `f` on the worker thread and returns its result.
+    ///
+    /// If `f` panics, the panic is propagated to the caller.
+    ///
+    /// # Safety
+    /// `f` and its result are moved between threads although they are not required to be [`Send`],
+    /// as Direct3D 9 interfaces are not marked as such. The caller must ensure that everything `f`
+    /// captures, and the value it returns, may be used from the worker thread while the calling thread
+    /// is blocked, e.g. interfaces of devices created with `D3DCREATE_MULTITHREADED`, and no
+    /// thread-local state of the calling thread.
+    pub unsafe fn run<R, F: FnOnce() -> R>(&self, f: F) -> R {
+        if thread::current().id() == self.worker_thread_id {
+            return f();
+        }
+
+        let mut result = None;
+        let mut panic_payload = None;
+        let job: Box<dyn FnOnce() -> bool + '_> = Box::new(|| match catch_unwind(AssertUnwindSafe(f)) {
+            Ok(value) => {
+                result = Some(value);
+                true
+            }
+            Err(payload) => {
+                panic_payload = Some(payload);
+                false
+            }
+        });
+
+        // SAFETY: The job only borrows from this stack frame, and we block below until the worker
+        // thread has finished running it, so the borrows never outlive this call.
+        // The caller guarantees that the captured data is usable from the worker thread.
+        let job: Job = unsafe { transmute::<Box<dyn FnOnce() -> bool + '_>, Job>(job) };
+
+        let (done_sender, done_receiver) = channel();
+        self.sender.lock().unwrap().send((job, done_sender)).expect("serial executor thread has exited");
+        let succeeded = done_receiver.recv().expect("serial executor thread has exited");
+
+        if !succeeded && let Some(payload) = panic_payload {
+            resume_unwind(payload);
+        }
+        result.expect("serial executor job did not produce a result")
+    }
+}
+
+impl Debug for SerialExecutor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SerialExecutor").field("worker_thread_id", &self.worker_thread_id).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn run_returns_result_computed_on_worker_thread() {
+        let executor = SerialExecutor::new("test-worker");
+        let values = [1, 2, 3];
+
+        let (sum, thread_id) = unsafe { executor.run(|| (values.iter().sum::<i32>(), thread::current().id())) };
+        assert_eq!(sum, 6);
+        assert_eq!(thread_id, executor.worker_thread_id);
+        assert_ne!(thread_id, thread::current().id());
+    }
+
+    #[test]
+    fn run_writes_through_borrows_of_caller() {
+        let executor = SerialExecutor::new("test-worker");
+        let mut output = String::new();
+
+        unsafe { executor.run(|| output.push_str("written on worker")) };
+        assert_eq!(output, "written on worker");
+    }
+
+    #[test]
+    fn nested_run_executes_inline() {
+        let executor = Arc::new(SerialExecutor::new("test-worker"));
+
+        let inner_thread_id = unsafe { executor.run(|| executor.run(|| thread::current().id())) };
+        assert_eq!(inner_thread_id, executor.worker_thread_id);
+    }
+
+    #[test]
+    fn run_propagates_panics_and_keeps_working() {
+        let executor = SerialExecutor::new("test-worker");
+
+        let result = catch_unwind(AssertUnwindSafe(|| unsafe { executor.run(|| panic!("job failed")) }));
+        let payload = result.expect_err("panic was not propagated");
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"job failed"));
+
+        assert_eq!(unsafe { executor.run(|| 42) }, 42);
+    }
+}
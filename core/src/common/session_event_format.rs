@@ -0,0 +1,45 @@
+//! Log event formatter that labels every event with a session name.
+//!
+//! This module provides a wrapper around a `tracing-subscriber` event formatter,
+//! which makes it easy to tell apart logs captured across several runs.
+
+use std::fmt;
+use tracing::{Event, Subscriber};
+use tracing_subscriber::{
+    fmt::{FmtContext, FormatEvent, FormatFields, format::Writer},
+    registry::LookupSpan,
+};
+
+/// Event formatter that prefixes every event with `session=<name>`.
+///
+/// Events are formatted by the inner formatter unchanged when no session name is set.
+#[derive(Debug)]
+pub struct SessionEventFormat<E> {
+    session: Option<String>,
+    inner: E,
+}
+
+impl<E> SessionEventFormat<E> {
+    /// Wraps an event formatter with the specified session name.
+    ///
+    /// # Arguments
+    /// * `session` - The session name, or `None` to leave events unlabeled
+    /// * `inner` - The event formatter to wrap
+    pub fn new(session: Option<String>, inner: E) -> Self {
+        Self { session, inner }
+    }
+}
+
+impl<S, N, E> FormatEvent<S, N> for SessionEventFormat<E>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+    E: FormatEvent<S, N>,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        if let Some(session) = &self.session {
+            write!(writer, "session={session} ")?;
+        }
+        self.inner.format_event(ctx, writer, event)
+    }
+}
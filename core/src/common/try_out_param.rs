@@ -1,8 +1,9 @@
 //! Utility for handling COM-style output parameters with proper error handling.
 //!
-//! This module provides a helper function for working with output parameters
+//! This module provides helper functions for working with output parameters
 //! in COM-style APIs, ensuring proper error handling when parameters are not set.
 
+use std::{ffi::c_void, mem::transmute_copy, ptr::null_mut};
 use windows::{Win32::Foundation::*, core::*};
 
 /// Executes a function that takes an output parameter and returns the result or an error.
@@ -29,3 +30,34 @@ where
         None => Err(E_POINTER.into()), // Should never happen if the function is implemented correctly
     }
 }
+
+/// Runs the body of a method with a required COM output parameter, checking in debug builds
+/// that the parameter is written whenever the method succeeds.
+///
+/// In debug builds, the output parameter is cleared before calling `func`, and a panic naming
+/// `method` is raised if `func` returns `Ok` without writing a non-null interface to it. This
+/// catches early returns that would hand an uninitialized pointer to the application as if it
+/// were valid. In release builds, `func` is simply called.
+///
+/// # Arguments
+/// * `method` - The name of the method, for the panic message
+/// * `out` - The output parameter, which is passed on to `func`
+/// * `func` - The method body, which must write `out` on success
+pub fn with_required_out<'a, T, F>(method: &str, out: OutRef<'a, T>, func: F) -> Result<()>
+where
+    T: Interface,
+    F: FnOnce(OutRef<'a, T>) -> Result<()>,
+{
+    if !cfg!(debug_assertions) || out.is_null() {
+        return func(out);
+    }
+
+    // `OutRef` of an interface is a transparent wrapper around a pointer to the interface pointer
+    let slot: *mut *mut c_void = unsafe { transmute_copy(&out) };
+    unsafe { *slot = null_mut() };
+    let result = func(out);
+    if result.is_ok() && unsafe { *slot }.is_null() {
+        panic!("{method} succeeded without writing its output parameter");
+    }
+    result
+}
@@ -0,0 +1,373 @@
+//! Back buffer readback for features that need the rendered pixels.
+//!
+//! Screenshots, frame hashing, and color filters all need to copy the back buffer into
+//! a lockable system memory surface and read its pixels. This module provides that as
+//! [`read_back_buffer`], which works on target devices.
+//!
+//! Images are saved as PNG with the minimal [`encode_png`] encoder rather than a full image crate,
+//! which keeps the DLL small.
+
+use crate::try_out_param;
+use std::{
+    ptr::{null, null_mut},
+    slice::from_raw_parts,
+};
+use windows::{Win32::Graphics::Direct3D9::*, core::*};
+
+/// Pixels read back from a surface.
+///
+/// Rows are stored with the pitch of the locked surface, which may exceed `width * bytes_per_pixel`.
+/// Use [`row`](Self::row) to access the pixels of a row without the padding.
+#[derive(Debug, Clone)]
+pub struct ReadbackImage {
+    /// Width in pixels.
+    pub width: u32,
+    /// Height in pixels.
+    pub height: u32,
+    /// Pixel format of the data.
+    pub format: D3DFORMAT,
+    /// Number of bytes between the starts of two consecutive rows in `data`.
+    pub pitch: u32,
+    /// Pixel data, `pitch * height` bytes.
+    pub data: Vec<u8>,
+}
+
+impl ReadbackImage {
+    /// Returns the pixels of row `y`, excluding the padding at the end of the row.
+    ///
+    /// # Returns
+    /// * `Some(&[u8])` - The `width * bytes_per_pixel` bytes of the row
+    /// * `None` - If `y` is out of range or the format is not supported by [`bytes_per_pixel`]
+    pub fn row(&self, y: u32) -> Option<&[u8]> {
+        if y >= self.height {
+            return None;
+        }
+        let start = (y * self.pitch) as usize;
+        let len = (self.width * bytes_per_pixel(self.format)?) as usize;
+        self.data.get(start..start + len)
+    }
+}
+
+/// Returns the number of bytes per pixel of uncompressed render target formats.
+///
+/// # Returns
+/// * `Some(u32)` - The size of a pixel in bytes
+/// * `None` - If the format is not a supported uncompressed format
+pub fn bytes_per_pixel(format: D3DFORMAT) -> Option<u32> {
+    match format {
+        D3DFMT_A8R8G8B8 | D3DFMT_X8R8G8B8 | D3DFMT_A8B8G8R8 | D3DFMT_X8B8G8R8 | D3DFMT_A2R10G10B10 | D3DFMT_A2B10G10R10 | D3DFMT_R32F | D3DFMT_G16R16F => Some(4),
+        D3DFMT_R5G6B5 | D3DFMT_X1R5G5B5 | D3DFMT_A1R5G5B5 | D3DFMT_A4R4G4B4 | D3DFMT_X4R4G4B4 | D3DFMT_R16F => Some(2),
+        D3DFMT_R8G8B8 => Some(3),
+        D3DFMT_A16B16G16R16 | D3DFMT_A16B16G16R16F | D3DFMT_G32R32F => Some(8),
+        D3DFMT_A32B32G32R32F => Some(16),
+        _ => None,
+    }
+}
+
+/// Reads back the pixels of back buffer 0 of swap chain 0.
+///
+/// Copies the back buffer into a system memory surface with `GetRenderTargetData` and reads it.
+/// Multisampled back buffers are resolved into a temporary render target first.
+///
+/// # Arguments
+/// * `device` - The target device to read the back buffer from
+pub fn read_back_buffer(device: &IDirect3DDevice9) -> Result<ReadbackImage> {
+    let back_buffer = unsafe { device.GetBackBuffer(0, 0, D3DBACKBUFFER_TYPE_MONO) }?;
+    read_render_target(device, &back_buffer)
+}
+
+/// Reads back the pixels of a render target surface.
+///
+/// # Arguments
+/// * `device` - The target device that owns `render_target`
+/// * `render_target` - The render target surface to read
+pub fn read_render_target(device: &IDirect3DDevice9, render_target: &IDirect3DSurface9) -> Result<ReadbackImage> {
+    let mut desc = D3DSURFACE_DESC::default();
+    unsafe { render_target.GetDesc(&mut desc) }?;
+
+    // GetRenderTargetData does not support multisampled surfaces, so resolve them first
+    let resolved;
+    let source = if desc.MultiSampleType != D3DMULTISAMPLE_NONE {
+        resolved = try_out_param(|out| unsafe { device.CreateRenderTarget(desc.Width, desc.Height, desc.Format, D3DMULTISAMPLE_NONE, 0, false, out, null_mut()) })?;
+        unsafe { device.StretchRect(render_target, null(), &resolved, null(), D3DTEXF_NONE) }?;
+        &resolved
+    } else {
+        render_target
+    };
+
+    let surface = try_out_param(|out| unsafe { device.CreateOffscreenPlainSurface(desc.Width, desc.Height, desc.Format, D3DPOOL_SYSTEMMEM, out, null_mut()) })?;
+    unsafe { device.GetRenderTargetData(source, &surface) }?;
+
+    let mut locked = D3DLOCKED_RECT::default();
+    unsafe { surface.LockRect(&mut locked, null(), D3DLOCK_READONLY as u32) }?;
+    // Nothing between LockRect and UnlockRect can fail, so the surface is always unlocked
+    let pitch = locked.Pitch as u32;
+    let data = unsafe { from_raw_parts(locked.pBits as *const u8, (pitch * desc.Height) as usize) }.to_vec();
+    unsafe { surface.UnlockRect() }?;
+
+    Ok(ReadbackImage {
+        width: desc.Width,
+        height: desc.Height,
+        format: desc.Format,
+        pitch,
+        data,
+    })
+}
+
+/// PNG file signature.
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// Maximum length of a stored (uncompressed) deflate block.
+const MAX_STORED_BLOCK_LEN: usize = 0xffff;
+
+/// Encodes RGBA8 pixels as a PNG image.
+///
+/// The image data is written with store-mode deflate and no row filtering, so encoding is a fast
+/// copy and needs no compression library, but files are slightly larger than the raw pixels.
+/// This favors speed and a small DLL over file size; recompress the files offline if needed.
+///
+/// # Arguments
+/// * `width` - Width of the image in pixels
+/// * `height` - Height of the image in pixels
+/// * `rgba` - Pixels in row-major order, 4 bytes per pixel in R, G, B, A order, without row padding
+///
+/// # Returns
+/// * `Some(Vec<u8>)` - The contents of the PNG file
+/// * `None` - If the length of `rgba` does not match the dimensions, or the image is empty
+pub fn encode_png(width: u32, height: u32, rgba: &[u8]) -> Option<Vec<u8>> {
+    let row_len = (width as usize).checked_mul(4)?;
+    if width == 0 || height == 0 || row_len.checked_mul(height as usize)? != rgba.len() {
+        return None;
+    }
+
+    // Each row is prefixed with its filter type, which is always 0 (none)
+    let mut raw = Vec::with_capacity((row_len + 1) * height as usize);
+    for row in rgba.chunks_exact(row_len) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&width.to_be_bytes());
+    ihdr.extend_from_slice(&height.to_be_bytes());
+    // Bit depth 8, color type 6 (RGBA), deflate compression, adaptive filtering, no interlacing
+    ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    let idat = zlib_store(&raw);
+
+    let mut png = Vec::with_capacity(PNG_SIGNATURE.len() + 3 * 12 + ihdr.len() + idat.len());
+    png.extend_from_slice(&PNG_SIGNATURE);
+    write_png_chunk(&mut png, b"IHDR", &ihdr);
+    write_png_chunk(&mut png, b"IDAT", &idat);
+    write_png_chunk(&mut png, b"IEND", &[]);
+    Some(png)
+}
+
+/// Appends a PNG chunk with its length and CRC.
+fn write_png_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(chunk_type);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Wraps data in a zlib stream of stored (uncompressed) deflate blocks.
+fn zlib_store(data: &[u8]) -> Vec<u8> {
+    let block_count = data.len().div_ceil(MAX_STORED_BLOCK_LEN).max(1);
+    let mut zlib = Vec::with_capacity(2 + data.len() + block_count * 5 + 4);
+    // Deflate with a 32 KiB window, no preset dictionary, fastest compression level
+    zlib.extend_from_slice(&[0x78, 0x01]);
+
+    let mut blocks = data.chunks(MAX_STORED_BLOCK_LEN).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend_from_slice(&[1, 0x00, 0x00, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let is_final = blocks.peek().is_none();
+        let len = block.len() as u16;
+        zlib.push(is_final as u8);
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+
+    zlib.extend_from_slice(&adler32(data).to_be_bytes());
+    zlib
+}
+
+/// Lookup table for [`crc32`], for the polynomial used by PNG.
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+};
+
+/// Computes the CRC-32 of data, as used by PNG chunks.
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+/// Computes the Adler-32 checksum of data, as used by zlib streams.
+fn adler32(data: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+    // 5552 is the largest number of bytes that can be summed without overflowing before the modulo
+    let (a, b) = data.chunks(5552).fold((1u32, 0u32), |(mut a, mut b), chunk| {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        (a % MOD_ADLER, b % MOD_ADLER)
+    });
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dx9::com::mock::*;
+
+    fn padded_image() -> ReadbackImage {
+        // 2x2 D3DFMT_R5G6B5 image with 3 bytes of padding per row
+        ReadbackImage {
+            width: 2,
+            height: 2,
+            format: D3DFMT_R5G6B5,
+            pitch: 7,
+            data: vec![1, 2, 3, 4, 0xee, 0xee, 0xee, 5, 6, 7, 8, 0xee, 0xee, 0xee],
+        }
+    }
+
+    #[test]
+    fn row_excludes_padding() {
+        let image = padded_image();
+        assert_eq!(image.row(0), Some(&[1, 2, 3, 4][..]));
+        assert_eq!(image.row(1), Some(&[5, 6, 7, 8][..]));
+        assert_eq!(image.row(2), None);
+    }
+
+    #[test]
+    fn row_rejects_unsupported_formats_and_short_data() {
+        let image = ReadbackImage {
+            format: D3DFMT_DXT1,
+            ..padded_image()
+        };
+        assert_eq!(image.row(0), None);
+
+        let mut image = padded_image();
+        image.data.truncate(10);
+        assert_eq!(image.row(1), None);
+    }
+
+    #[test]
+    fn read_render_target_keeps_pitch_of_locked_surface() {
+        let device = ComObject::new(MockDevice::default());
+        device.state.lock().unwrap().offscreen_pitch_padding = 12;
+        let render_target = ComObject::new(MockSurface::new(device.log.clone(), 3, 2, D3DFMT_A8R8G8B8, 12));
+        *render_target.data.lock().unwrap() = (0..24).collect();
+
+        let image = read_render_target(&device.to_interface::<IDirect3DDevice9Ex>().into(), &render_target.to_interface()).unwrap();
+
+        assert_eq!((image.width, image.height, image.format), (3, 2, D3DFMT_A8R8G8B8));
+        assert_eq!(image.pitch, 24);
+        assert_eq!(image.data.len(), 48);
+        assert_eq!(image.row(0), Some(&(0..12).collect::<Vec<u8>>()[..]));
+        assert_eq!(image.row(1), Some(&(12..24).collect::<Vec<u8>>()[..]));
+        assert_eq!(device.log.calls(), ["GetDesc", "CreateOffscreenPlainSurface", "GetRenderTargetData", "LockRect", "UnlockRect"]);
+    }
+
+    /// Decodes a PNG written by [`encode_png`], checking its structure and checksums.
+    ///
+    /// Only stored deflate blocks and unfiltered rows are supported, which is what [`encode_png`] writes.
+    fn decode_png(png: &[u8]) -> (u32, u32, Vec<u8>) {
+        assert_eq!(png[..8], PNG_SIGNATURE);
+        let mut chunks = Vec::new();
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let (chunk_type, data) = (&rest[4..8], &rest[8..8 + len]);
+            let crc = u32::from_be_bytes(rest[8 + len..12 + len].try_into().unwrap());
+            assert_eq!(crc, crc32(&rest[4..8 + len]), "CRC of {}", String::from_utf8_lossy(chunk_type));
+            chunks.push((chunk_type, data));
+            rest = &rest[12 + len..];
+        }
+        let chunk_types = chunks.iter().map(|(chunk_type, _)| *chunk_type).collect::<Vec<_>>();
+        assert_eq!(chunk_types, [b"IHDR", b"IDAT", b"IEND"]);
+
+        let ihdr = chunks[0].1;
+        let width = u32::from_be_bytes(ihdr[..4].try_into().unwrap());
+        let height = u32::from_be_bytes(ihdr[4..8].try_into().unwrap());
+        assert_eq!(ihdr[8..], [8, 6, 0, 0, 0]);
+
+        let zlib = chunks[1].1;
+        assert_eq!((zlib[0] as u16 * 256 + zlib[1] as u16) % 31, 0, "zlib header check bits");
+        let mut raw = Vec::new();
+        let mut pos = 2;
+        loop {
+            let header = zlib[pos];
+            assert_eq!(header & 0b110, 0, "block type must be stored");
+            let len = u16::from_le_bytes([zlib[pos + 1], zlib[pos + 2]]);
+            let nlen = u16::from_le_bytes([zlib[pos + 3], zlib[pos + 4]]);
+            assert_eq!(nlen, !len);
+            raw.extend_from_slice(&zlib[pos + 5..pos + 5 + len as usize]);
+            pos += 5 + len as usize;
+            if header & 1 != 0 {
+                break;
+            }
+        }
+        assert_eq!(zlib[pos..], adler32(&raw).to_be_bytes());
+
+        let rgba = raw
+            .chunks_exact(width as usize * 4 + 1)
+            .flat_map(|row| {
+                assert_eq!(row[0], 0, "filter type");
+                row[1..].to_vec()
+            })
+            .collect::<Vec<_>>();
+        (width, height, rgba)
+    }
+
+    #[test]
+    fn encode_png_round_trips() {
+        let rgba = (0..3 * 2 * 4).map(|i| i as u8).collect::<Vec<_>>();
+        let png = encode_png(3, 2, &rgba).unwrap();
+        assert_eq!(decode_png(&png), (3, 2, rgba));
+    }
+
+    #[test]
+    fn encode_png_splits_large_images_into_blocks() {
+        // 129 rows of 513 bytes do not fit in a single stored block
+        let rgba = (0..128 * 129 * 4).map(|i| (i * 7) as u8).collect::<Vec<_>>();
+        let png = encode_png(128, 129, &rgba).unwrap();
+        assert_eq!(decode_png(&png), (128, 129, rgba));
+    }
+
+    #[test]
+    fn encode_png_rejects_mismatched_sizes() {
+        assert_eq!(encode_png(0, 1, &[]), None);
+        assert_eq!(encode_png(2, 2, &[0; 15]), None);
+        assert_eq!(encode_png(u32::MAX, u32::MAX, &[0; 4]), None);
+    }
+
+    #[test]
+    fn checksums_match_known_vectors() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(b"IEND"), 0xae426082);
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+        // Exceeds the number of bytes summed between two modulos
+        assert_eq!(adler32(&[0xff; 6000]), 0xa49759ea);
+    }
+}
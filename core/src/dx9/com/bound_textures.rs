@@ -0,0 +1,64 @@
+//! Tracking of the textures bound to each sampler stage.
+//!
+//! Analysis features need to know which textures are bound at draw time. Querying the
+//! device with `GetTexture` for each stage is costly and returns target objects, so the
+//! bindings made through the proxy `SetTexture` are recorded here instead.
+
+use windows::Win32::Graphics::Direct3D9::*;
+
+/// Number of pixel shader sampler stages.
+const PIXEL_SAMPLER_COUNT: usize = 16;
+
+/// Number of sampler stages tracked, including the displacement map and vertex texture samplers.
+const TRACKED_SAMPLER_COUNT: usize = PIXEL_SAMPLER_COUNT + 1 + 4;
+
+/// Textures bound to each sampler stage, identified by the address of their proxy.
+///
+/// Only the addresses are recorded, without holding references, so the bindings do not keep
+/// textures alive. An address is only meaningful while the texture is still bound.
+///
+/// Bindings changed by applying state blocks are not tracked.
+#[derive(Debug, Clone, Default)]
+pub struct DX9BoundTextures {
+    textures: [usize; TRACKED_SAMPLER_COUNT],
+}
+
+impl DX9BoundTextures {
+    /// Records the texture bound to `stage`, returning the previously bound texture.
+    ///
+    /// # Arguments
+    /// * `stage` - The stage passed to `SetTexture`, including `D3DDMAPSAMPLER` and `D3DVERTEXTEXTURESAMPLER0..3`
+    /// * `texture` - The address of the proxy texture, or `0` to unbind
+    ///
+    /// # Returns
+    /// * `Some(usize)` - The address of the previously bound texture, or `0` if none
+    /// * `None` - If `stage` is not a valid sampler stage
+    pub fn set(&mut self, stage: u32, texture: usize) -> Option<usize> {
+        let index = sampler_index(stage)?;
+        Some(std::mem::replace(&mut self.textures[index], texture))
+    }
+
+    /// Returns the address of the proxy texture bound to `stage`.
+    ///
+    /// # Returns
+    /// * `Some(usize)` - The address of the bound texture, or `0` if none
+    /// * `None` - If `stage` is not a valid sampler stage
+    pub fn get(&self, stage: u32) -> Option<usize> {
+        sampler_index(stage).map(|index| self.textures[index])
+    }
+
+    /// Unbinds all textures, as done by resetting the device.
+    pub fn clear(&mut self) {
+        self.textures = [0; TRACKED_SAMPLER_COUNT];
+    }
+}
+
+/// Maps a `SetTexture` stage to an index into the tracked stages.
+fn sampler_index(stage: u32) -> Option<usize> {
+    match stage {
+        0..16 => Some(stage as usize),
+        D3DDMAPSAMPLER => Some(PIXEL_SAMPLER_COUNT),
+        D3DVERTEXTEXTURESAMPLER0..=D3DVERTEXTEXTURESAMPLER3 => Some(PIXEL_SAMPLER_COUNT + 1 + (stage - D3DVERTEXTEXTURESAMPLER0) as usize),
+        _ => None,
+    }
+}
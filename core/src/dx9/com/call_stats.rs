@@ -0,0 +1,104 @@
+//! Per-device call statistics.
+//!
+//! When [`collect_call_stats`] is enabled, each device counts its frames, draw calls and
+//! primitives in a [`DX9CallStats`]. The counters can be reset at any time, e.g. with the
+//! [`reset_stats_key`] hotkey, to measure a specific gameplay segment rather than everything
+//! since launch.
+//!
+//! With [`measure_driver_time`] also enabled, the time spent in the target driver is measured
+//! for selected methods, to tell stalls in the driver apart from overhead of the proxy.
+//!
+//! [`collect_call_stats`]: crate::dx9::DX9ProxyConfig::collect_call_stats
+//! [`reset_stats_key`]: crate::dx9::DX9ProxyConfig::reset_stats_key
+//! [`measure_driver_time`]: crate::dx9::DX9ProxyConfig::measure_driver_time
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+/// Time spent in the target driver by calls of a method.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DX9DriverTime {
+    /// Number of timed calls.
+    pub calls: u64,
+    /// Total time spent in the driver.
+    pub total: Duration,
+}
+
+impl DX9DriverTime {
+    /// Returns the average time spent in the driver per call.
+    pub fn average(&self) -> Duration {
+        self.total.div_f64(self.calls.max(1) as f64)
+    }
+}
+
+/// Counters of the calls made to a device since the last reset.
+#[derive(Debug, Clone)]
+pub struct DX9CallStats {
+    /// Time of the last reset, or of the device creation.
+    pub since: Instant,
+    /// Number of presented frames.
+    pub frames: u64,
+    /// Number of draw calls, including user-pointer draws.
+    pub draw_calls: u64,
+    /// Number of primitives drawn.
+    pub primitives: u64,
+    /// Time spent in the driver per method name, if driver time is measured.
+    pub driver_times: BTreeMap<&'static str, DX9DriverTime>,
+}
+
+impl Default for DX9CallStats {
+    fn default() -> Self {
+        Self {
+            since: Instant::now(),
+            frames: 0,
+            draw_calls: 0,
+            primitives: 0,
+            driver_times: BTreeMap::new(),
+        }
+    }
+}
+
+impl DX9CallStats {
+    /// Records a draw call of `primitives` primitives.
+    pub fn record_draw_call(&mut self, primitives: u32) {
+        self.draw_calls += 1;
+        self.primitives += primitives as u64;
+    }
+
+    /// Records a presented frame.
+    pub fn record_frame(&mut self) {
+        self.frames += 1;
+    }
+
+    /// Records a call of `method` that spent `elapsed` in the driver.
+    pub fn record_driver_time(&mut self, method: &'static str, elapsed: Duration) {
+        let time = self.driver_times.entry(method).or_default();
+        time.calls += 1;
+        time.total += elapsed;
+    }
+}
+
+impl Display for DX9CallStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let elapsed = self.since.elapsed().as_secs_f64();
+        let frames = self.frames.max(1) as f64;
+        write!(
+            f,
+            "{} frames in {elapsed:.1} s ({:.1} fps), {} draw calls ({:.1} per frame), {} primitives ({:.1} per frame)",
+            self.frames,
+            self.frames as f64 / elapsed.max(f64::EPSILON),
+            self.draw_calls,
+            self.draw_calls as f64 / frames,
+            self.primitives,
+            self.primitives as f64 / frames,
+        )?;
+        for (index, (method, time)) in self.driver_times.iter().enumerate() {
+            let separator = if index == 0 { "; driver time per call: " } else { ", " };
+            write!(f, "{separator}{method} {:.3} ms ({} calls)", time.average().as_secs_f64() * 1000.0, time.calls)?;
+        }
+        Ok(())
+    }
+}
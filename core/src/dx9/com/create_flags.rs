@@ -0,0 +1,84 @@
+//! Direct3D 9 device and resource creation flags and their symbolic names.
+
+use windows::Win32::Graphics::Direct3D9::*;
+
+/// `D3DCREATE_*` flags and their names without the prefix, in bit order.
+const CREATE_FLAG_NAMES: &[(i32, &str)] = &[
+    (D3DCREATE_FPU_PRESERVE, "FPU_PRESERVE"),
+    (D3DCREATE_MULTITHREADED, "MULTITHREADED"),
+    (D3DCREATE_PUREDEVICE, "PUREDEVICE"),
+    (D3DCREATE_SOFTWARE_VERTEXPROCESSING, "SOFTWARE_VERTEXPROCESSING"),
+    (D3DCREATE_HARDWARE_VERTEXPROCESSING, "HARDWARE_VERTEXPROCESSING"),
+    (D3DCREATE_MIXED_VERTEXPROCESSING, "MIXED_VERTEXPROCESSING"),
+    (D3DCREATE_DISABLE_DRIVER_MANAGEMENT, "DISABLE_DRIVER_MANAGEMENT"),
+    (D3DCREATE_ADAPTERGROUP_DEVICE, "ADAPTERGROUP_DEVICE"),
+    (D3DCREATE_DISABLE_DRIVER_MANAGEMENT_EX, "DISABLE_DRIVER_MANAGEMENT_EX"),
+    (D3DCREATE_NOWINDOWCHANGES, "NOWINDOWCHANGES"),
+    (D3DCREATE_DISABLE_PSGP_THREADING, "DISABLE_PSGP_THREADING"),
+    (D3DCREATE_ENABLE_PRESENTSTATS, "ENABLE_PRESENTSTATS"),
+    (D3DCREATE_DISABLE_PRINTSCREEN, "DISABLE_PRINTSCREEN"),
+    (D3DCREATE_SCREENSAVER, "SCREENSAVER"),
+];
+
+/// `D3DUSAGE_*` flags valid at resource creation and their names without the prefix, in bit order.
+const USAGE_FLAG_NAMES: &[(i32, &str)] = &[
+    (D3DUSAGE_RENDERTARGET, "RENDERTARGET"),
+    (D3DUSAGE_DEPTHSTENCIL, "DEPTHSTENCIL"),
+    (D3DUSAGE_WRITEONLY, "WRITEONLY"),
+    (D3DUSAGE_SOFTWAREPROCESSING, "SOFTWAREPROCESSING"),
+    (D3DUSAGE_DONOTCLIP, "DONOTCLIP"),
+    (D3DUSAGE_POINTS, "POINTS"),
+    (D3DUSAGE_RTPATCHES, "RTPATCHES"),
+    (D3DUSAGE_NPATCHES, "NPATCHES"),
+    (D3DUSAGE_DYNAMIC, "DYNAMIC"),
+    (D3DUSAGE_AUTOGENMIPMAP, "AUTOGENMIPMAP"),
+    (D3DUSAGE_RESTRICTED_CONTENT, "RESTRICTED_CONTENT"),
+    (D3DUSAGE_RESTRICT_SHARED_RESOURCE_DRIVER, "RESTRICT_SHARED_RESOURCE_DRIVER"),
+    (D3DUSAGE_RESTRICT_SHARED_RESOURCE, "RESTRICT_SHARED_RESOURCE"),
+    (D3DUSAGE_DMAP, "DMAP"),
+    (D3DUSAGE_NONSECURE, "NONSECURE"),
+    (D3DUSAGE_TEXTAPI, "TEXTAPI"),
+];
+
+/// Returns a readable description of `D3DCREATE_*` behavior flags, such as `HARDWARE_VERTEXPROCESSING | MULTITHREADED`.
+///
+/// This is meant for logging. Unknown bits are appended in hexadecimal, and no flags are described as `0`.
+pub fn describe_create_flags(flags: u32) -> String {
+    describe_flags(flags, CREATE_FLAG_NAMES)
+}
+
+/// Returns a readable description of `D3DUSAGE_*` resource usage flags, such as `WRITEONLY | DYNAMIC`.
+///
+/// This is meant for logging. Unknown bits are appended in hexadecimal, and no flags are described as `0`.
+pub fn describe_usage(usage: u32) -> String {
+    describe_flags(usage, USAGE_FLAG_NAMES)
+}
+
+/// Returns the name of a memory pool without the `D3DPOOL_` prefix, such as `DEFAULT`.
+///
+/// Unknown pools are described by their numeric value.
+pub fn describe_pool(pool: D3DPOOL) -> String {
+    match pool {
+        D3DPOOL_DEFAULT => "DEFAULT".to_string(),
+        D3DPOOL_MANAGED => "MANAGED".to_string(),
+        D3DPOOL_SYSTEMMEM => "SYSTEMMEM".to_string(),
+        D3DPOOL_SCRATCH => "SCRATCH".to_string(),
+        D3DPOOL(value) => value.to_string(),
+    }
+}
+
+/// Joins the names of the flags set in `flags`, appending unknown bits in hexadecimal.
+fn describe_flags(flags: u32, flag_names: &[(i32, &str)]) -> String {
+    let mut names = Vec::new();
+    let mut remaining = flags;
+    for &(flag, name) in flag_names {
+        if flags & flag as u32 != 0 {
+            names.push(name.to_string());
+            remaining &= !(flag as u32);
+        }
+    }
+    if remaining != 0 || names.is_empty() {
+        names.push(format!("{remaining:#x}"));
+    }
+    names.join(" | ")
+}
@@ -0,0 +1,238 @@
+//! Upscaling of the hardware cursor set with `SetCursorProperties`.
+//!
+//! Games size their cursor bitmap for the resolution they expect, so the cursor looks tiny when the
+//! resolution is forced higher. The bitmap is scaled on the CPU with nearest-neighbor sampling, which
+//! keeps its edges crisp. `StretchRect` cannot be used, as cursor bitmaps usually live in the system
+//! memory or scratch pools, which it cannot read from.
+//!
+//! Many games set the same cursor again on every frame, so the last scaled cursor is cached by
+//! [`DX9ScaledCursorCache`] and only scaled again when the bitmap or the hotspot changes.
+//!
+//! See [`DX9ProxyConfig::cursor_scale`].
+
+use super::*;
+use std::ptr::{null, null_mut};
+use windows::{Win32::Graphics::Direct3D9::*, core::*};
+
+/// Bytes per pixel of `D3DFMT_A8R8G8B8`, the only format `SetCursorProperties` accepts.
+const BYTES_PER_PIXEL: usize = 4;
+
+/// Largest width or height the cursor bitmap is scaled to, well above any cursor size in use.
+const MAX_SCALED_SIZE: u32 = 256;
+
+/// Cursor bitmap scaled last, with the source it was scaled from.
+#[derive(Debug)]
+struct ScaledCursor {
+    /// Address of the source bitmap, as a cheap check before comparing pixels.
+    source: usize,
+    width: u32,
+    height: u32,
+    /// Pixels of the source bitmap, without row padding.
+    pixels: Vec<u8>,
+    hotspot: (u32, u32),
+    scaled: IDirect3DSurface9,
+    scaled_hotspot: (u32, u32),
+}
+
+/// Cache of the cursor bitmap scaled last by [`get_or_scale`](Self::get_or_scale).
+///
+/// The pixels of the source bitmap are still read on each call, as games may animate the cursor by
+/// drawing into the same surface, but the scaled copy is only created again when they change.
+#[derive(Debug, Default)]
+pub struct DX9ScaledCursorCache {
+    last: Option<ScaledCursor>,
+}
+
+impl DX9ScaledCursorCache {
+    /// Returns a copy of the cursor bitmap `surface` scaled by `scale`, and the hotspot scaled accordingly.
+    ///
+    /// The copy is created in the scratch pool of `device`, which must be the target device, unless the last
+    /// copy was made from the same bitmap with the same contents and hotspot. The bitmap must be lockable and
+    /// in `D3DFMT_A8R8G8B8`, as `SetCursorProperties` requires.
+    ///
+    /// # Arguments
+    /// * `device` - The target device, on which the scaled copy is created.
+    /// * `surface` - The target cursor bitmap.
+    /// * `hotspot` - The hotspot of the cursor, in pixels of `surface`.
+    /// * `scale` - The scale factor, which must be the same on each call. It is lowered so that the copy is at most
+    ///   [`MAX_SCALED_SIZE`] pixels wide and high, unless the bitmap already is larger.
+    pub fn get_or_scale(&mut self, device: &IDirect3DDevice9, surface: &IDirect3DSurface9, hotspot: (u32, u32), scale: f32) -> Result<(IDirect3DSurface9, (u32, u32))> {
+        let mut desc = D3DSURFACE_DESC::default();
+        unsafe { surface.GetDesc(&mut desc) }?;
+        if desc.Format != D3DFMT_A8R8G8B8 || desc.Width == 0 || desc.Height == 0 {
+            return Err(D3DERR_INVALIDCALL.into());
+        }
+
+        let source = surface.as_raw() as usize;
+        let pixels = read_pixels(surface, desc.Width, desc.Height)?;
+        if let Some(last) = &self.last
+            && last.source == source
+            && (last.width, last.height) == (desc.Width, desc.Height)
+            && last.hotspot == hotspot
+            && last.pixels == pixels
+        {
+            #[cfg(feature = "tracing")]
+            tracing::trace!("Reusing the scaled cursor bitmap of {surface:?}");
+            return Ok((last.scaled.clone(), last.scaled_hotspot));
+        }
+
+        // The configured scale has no upper bound, and a huge one would exhaust the memory
+        let max_scale = (MAX_SCALED_SIZE as f32 / desc.Width.max(desc.Height) as f32).max(1.0);
+        let scale = if scale > max_scale {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(
+                "Cursor scale {scale} exceeds the limit of {MAX_SCALED_SIZE} pixels for a {}x{} bitmap, using {max_scale}",
+                desc.Width,
+                desc.Height
+            );
+            max_scale
+        } else {
+            scale
+        };
+        let scaled_size = |size: u32| ((size as f32 * scale).round() as u32).max(1);
+        let (width, height) = (scaled_size(desc.Width), scaled_size(desc.Height));
+        let scaled = try_out_param(|out| unsafe { device.CreateOffscreenPlainSurface(width, height, D3DFMT_A8R8G8B8, D3DPOOL_SCRATCH, out, null_mut()) })?;
+        write_scaled_pixels(&scaled, &pixels, desc.Width, desc.Height, width, height)?;
+
+        // In u64, as the product overflows u32 for large bitmaps and scales
+        let scaled_hotspot = |hotspot: u32, size: u32, scaled_size: u32| (u64::from(hotspot) * u64::from(scaled_size) / u64::from(size)).min(u64::from(scaled_size) - 1) as u32;
+        let scaled_hotspot = (scaled_hotspot(hotspot.0, desc.Width, width), scaled_hotspot(hotspot.1, desc.Height, height));
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            "Scaled cursor bitmap from {}x{} to {width}x{height}, hotspot {hotspot:?} to {scaled_hotspot:?}",
+            desc.Width,
+            desc.Height
+        );
+
+        self.last = Some(ScaledCursor {
+            source,
+            width: desc.Width,
+            height: desc.Height,
+            pixels,
+            hotspot,
+            scaled: scaled.clone(),
+            scaled_hotspot,
+        });
+        Ok((scaled, scaled_hotspot))
+    }
+}
+
+/// Reads the pixels of a `D3DFMT_A8R8G8B8` surface of `width`x`height` pixels, without row padding.
+fn read_pixels(surface: &IDirect3DSurface9, width: u32, height: u32) -> Result<Vec<u8>> {
+    let row_len = width as usize * BYTES_PER_PIXEL;
+    let mut locked = D3DLOCKED_RECT::default();
+    unsafe { surface.LockRect(&mut locked, null(), D3DLOCK_READONLY as u32) }?;
+    let mut pixels = Vec::with_capacity(row_len * height as usize);
+    for y in 0..height as usize {
+        pixels.extend_from_slice(unsafe { std::slice::from_raw_parts((locked.pBits as *const u8).add(y * locked.Pitch as usize), row_len) });
+    }
+    unsafe { surface.UnlockRect() }?;
+    Ok(pixels)
+}
+
+/// Writes `pixels` of `width`x`height` pixels into `surface` of `scaled_width`x`scaled_height` pixels, with nearest-neighbor sampling.
+fn write_scaled_pixels(surface: &IDirect3DSurface9, pixels: &[u8], width: u32, height: u32, scaled_width: u32, scaled_height: u32) -> Result<()> {
+    let mut locked = D3DLOCKED_RECT::default();
+    unsafe { surface.LockRect(&mut locked, null(), 0) }?;
+    for y in 0..scaled_height as usize {
+        let source_y = y * height as usize / scaled_height as usize;
+        for x in 0..scaled_width as usize {
+            let source_x = x * width as usize / scaled_width as usize;
+            let offset = (source_y * width as usize + source_x) * BYTES_PER_PIXEL;
+            let pixel = &pixels[offset..offset + BYTES_PER_PIXEL];
+            unsafe {
+                let dest = (locked.pBits as *mut u8).add(y * locked.Pitch as usize + x * BYTES_PER_PIXEL);
+                std::ptr::copy_nonoverlapping(pixel.as_ptr(), dest, BYTES_PER_PIXEL);
+            }
+        }
+    }
+    unsafe { surface.UnlockRect() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dx9::com::mock::*;
+
+    /// Creates a 2x2 cursor bitmap with distinct pixels and padded rows.
+    fn cursor_bitmap(log: &CallLog) -> ComObject<MockSurface> {
+        let bitmap = ComObject::new(MockSurface::new(log.clone(), 2, 2, D3DFMT_A8R8G8B8, 12));
+        let mut data = bitmap.data.lock().unwrap();
+        data[0..8].copy_from_slice(&[1, 1, 1, 1, 2, 2, 2, 2]);
+        data[12..20].copy_from_slice(&[3, 3, 3, 3, 4, 4, 4, 4]);
+        drop(data);
+        bitmap
+    }
+
+    #[test]
+    fn scales_pixels_and_hotspot() {
+        let mock = ComObject::new(MockDevice::default());
+        let device: IDirect3DDevice9Ex = mock.to_interface();
+        let bitmap = cursor_bitmap(&mock.log);
+
+        let (scaled, hotspot) = DX9ScaledCursorCache::default().get_or_scale(&device.into(), &bitmap.to_interface(), (1, 1), 2.0).unwrap();
+        assert_eq!(hotspot, (2, 2));
+        let scaled: &MockSurface = unsafe { scaled.as_impl() };
+        assert_eq!((scaled.desc.Width, scaled.desc.Height), (4, 4));
+        assert_eq!(scaled.row(0), [1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2]);
+        assert_eq!(scaled.row(3), [3, 3, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4]);
+    }
+
+    #[test]
+    fn scales_hotspot_of_large_bitmaps_without_overflow() {
+        let mock = ComObject::new(MockDevice::default());
+        let device: IDirect3DDevice9Ex = mock.to_interface();
+        let bitmap: IDirect3DSurface9 = MockSurface::new(mock.log.clone(), 70000, 1, D3DFMT_A8R8G8B8, 70000 * 4).into();
+
+        let (_, hotspot) = DX9ScaledCursorCache::default().get_or_scale(&device.into(), &bitmap, (69999, 0), 1.0).unwrap();
+        assert_eq!(hotspot, (69999, 0));
+    }
+
+    #[test]
+    fn reuses_scaled_bitmap_until_it_changes() {
+        let mock = ComObject::new(MockDevice::default());
+        let device: IDirect3DDevice9 = mock.to_interface::<IDirect3DDevice9Ex>().into();
+        let bitmap = cursor_bitmap(&mock.log);
+        let surface: IDirect3DSurface9 = bitmap.to_interface();
+        let mut cache = DX9ScaledCursorCache::default();
+
+        let (first, _) = cache.get_or_scale(&device, &surface, (0, 0), 2.0).unwrap();
+        let (second, _) = cache.get_or_scale(&device, &surface, (0, 0), 2.0).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(mock.log.count("CreateOffscreenPlainSurface"), 1);
+
+        // A new hotspot, new contents or another bitmap are scaled again
+        let (_, hotspot) = cache.get_or_scale(&device, &surface, (1, 0), 2.0).unwrap();
+        assert_eq!(hotspot, (2, 0));
+        bitmap.data.lock().unwrap()[0] = 5;
+        let (changed, _) = cache.get_or_scale(&device, &surface, (1, 0), 2.0).unwrap();
+        let changed: &MockSurface = unsafe { changed.as_impl() };
+        assert_eq!(changed.row(0)[0], 5);
+        cache.get_or_scale(&device, &cursor_bitmap(&mock.log).to_interface(), (1, 0), 2.0).unwrap();
+        assert_eq!(mock.log.count("CreateOffscreenPlainSurface"), 4);
+    }
+
+    #[test]
+    fn limits_scaled_size() {
+        let mock = ComObject::new(MockDevice::default());
+        let device: IDirect3DDevice9Ex = mock.to_interface();
+        let bitmap = cursor_bitmap(&mock.log);
+
+        let (scaled, hotspot) = DX9ScaledCursorCache::default().get_or_scale(&device.into(), &bitmap.to_interface(), (1, 1), 1000.0).unwrap();
+        assert_eq!(hotspot, (128, 128));
+        let scaled: &MockSurface = unsafe { scaled.as_impl() };
+        assert_eq!((scaled.desc.Width, scaled.desc.Height), (MAX_SCALED_SIZE, MAX_SCALED_SIZE));
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        let mock = ComObject::new(MockDevice::default());
+        let device: IDirect3DDevice9Ex = mock.to_interface();
+        let bitmap: IDirect3DSurface9 = MockSurface::new(mock.log.clone(), 2, 2, D3DFMT_X8R8G8B8, 8).into();
+
+        let err = DX9ScaledCursorCache::default().get_or_scale(&device.into(), &bitmap, (0, 0), 2.0).unwrap_err();
+        assert_eq!(err.code(), D3DERR_INVALIDCALL);
+        assert_eq!(mock.log.count("CreateOffscreenPlainSurface"), 0);
+    }
+}
@@ -0,0 +1,39 @@
+//! Tracking of live `D3DPOOL_DEFAULT` resources, for diagnosing failed resets.
+//!
+//! `Reset` fails as long as the application holds any resource in the default pool, and the
+//! runtime does not say which one. When [`track_default_pool`] is enabled, each device registers
+//! its default pool resources in a [`DX9DefaultPoolTracker`] at creation and deregisters them when
+//! their proxies are destroyed, so the resources still alive can be logged when a reset fails.
+//!
+//! [`track_default_pool`]: crate::dx9::DX9ProxyConfig::track_default_pool
+
+use std::{collections::HashMap, ffi::c_void};
+
+/// Live default pool resources of a device, keyed by the identity of their target object (see [`com_identity`](crate::com_identity)).
+#[derive(Debug, Default)]
+pub struct DX9DefaultPoolTracker {
+    resources: HashMap<usize, String>,
+}
+
+impl DX9DefaultPoolTracker {
+    /// Registers a created default pool resource with a description of it.
+    pub fn on_created(&mut self, target_ptr: *mut c_void, description: String) {
+        self.resources.insert(target_ptr as usize, description);
+    }
+
+    /// Deregisters a resource whose proxy is being destroyed. Resources that are not registered are ignored.
+    pub fn on_destroyed(&mut self, target_ptr: *mut c_void) {
+        self.resources.remove(&(target_ptr as usize));
+    }
+
+    /// Returns the live default pool resources as target addresses and descriptions, sorted by address.
+    pub fn live_resources(&self) -> Vec<(*mut c_void, String)> {
+        let mut resources = self
+            .resources
+            .iter()
+            .map(|(&target_ptr, description)| (target_ptr as *mut c_void, description.clone()))
+            .collect::<Vec<_>>();
+        resources.sort_by_key(|(target_ptr, _)| *target_ptr as usize);
+        resources
+    }
+}
@@ -5,12 +5,23 @@
 //! It handles configuration, COM object mapping, and thread-safe access to shared state.
 
 use super::*;
-use crate::{ComMappingTracker, NullableInterfaceIn, NullableInterfaceOut};
+use crate::{ComMappingTracker, NullableInterfaceIn, NullableInterfaceOut, com_identity};
 use std::{
     fmt::Debug,
-    sync::{Arc, Mutex},
+    sync::{
+        Arc, Mutex, MutexGuard, Weak,
+        atomic::{AtomicBool, AtomicI32, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use windows::{
+    Win32::{
+        Foundation::RECT,
+        Graphics::Direct3D9::*,
+        UI::{Input::KeyboardAndMouse::GetAsyncKeyState, WindowsAndMessaging::ClipCursor},
+    },
+    core::*,
 };
-use windows::core::*;
 
 /// Internal implementation of the DirectX 9 proxy device context.
 ///
@@ -19,12 +30,64 @@ use windows::core::*;
 #[derive(Debug)]
 pub struct DX9ProxyDeviceContextImpl {
     config: DX9ProxyConfig,
-    tracker: Mutex<ComMappingTracker>,
+    forced_render_states: Option<Mutex<DX9ForcedRenderStates>>,
+    forced_sampler_states: Vec<(D3DSAMPLERSTATETYPE, u32)>,
+    tracker: ComMappingTracker,
+    scratch_buffers: Mutex<DX9ScratchBuffers>,
+    shader_constant_shadow: Option<Mutex<Box<DX9ShaderConstantShadow>>>,
+    render_target_size: Mutex<Option<(u32, u32)>>,
+    viewport_clamped: AtomicBool,
+    present_params: Mutex<Option<D3DPRESENT_PARAMETERS>>,
+    surface_cache: Mutex<DX9SurfaceCache>,
+    bound_textures: Mutex<DX9BoundTextures>,
+    bound_index_buffer: Option<Mutex<BoundIndexBuffer>>,
+    lock_tracker: Option<Mutex<DX9LockTracker>>,
+    default_pool_tracker: Option<Mutex<DX9DefaultPoolTracker>>,
+    call_stats: Option<Mutex<DX9CallStats>>,
+    reset_stats_key_down: AtomicBool,
+    clears_disabled: AtomicBool,
+    disable_clears_key_down: AtomicBool,
+    force_z_enable_key_down: AtomicBool,
+    force_z_write_enable_key_down: AtomicBool,
+    force_color_write_enable_key_down: AtomicBool,
+    log_ring_dump_key_down: AtomicBool,
+    cursor_clipped: AtomicBool,
+    cooperative_level: AtomicI32,
+    watermark: Option<Mutex<DX9Watermark>>,
+    stretch_scratch: Option<Mutex<DX9StretchScratch>>,
+    frame_pacer: Option<Mutex<DX9FramePacer>>,
+    cursor_cache: Option<Mutex<DX9ScaledCursorCache>>,
+    #[cfg(feature = "experimental-serialize-device-calls")]
+    executor: Option<crate::SerialExecutor>,
 }
 
 unsafe impl Send for DX9ProxyDeviceContextImpl {}
 unsafe impl Sync for DX9ProxyDeviceContextImpl {}
 
+/// Weak reference to the proxy of the index buffer bound through the proxy device.
+///
+/// See [`DX9ProxyDeviceContext::set_bound_index_buffer`].
+struct BoundIndexBuffer(Option<windows::core::Weak<IDirect3DIndexBuffer9>>);
+
+impl Debug for BoundIndexBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("BoundIndexBuffer").field(&self.0.as_ref().map(|_| "Weak")).finish()
+    }
+}
+
+/// Contexts of all live devices, for features that act on every device (e.g. global hotkeys).
+///
+/// Contexts register themselves on creation and deregister when dropped, i.e. when the device
+/// proxy and all resource proxies of the device have been released.
+static CONTEXT_REGISTRY: Mutex<Vec<Weak<DX9ProxyDeviceContextImpl>>> = Mutex::new(Vec::new());
+
+impl Drop for DX9ProxyDeviceContextImpl {
+    fn drop(&mut self) {
+        let this = self as *const Self;
+        CONTEXT_REGISTRY.lock().unwrap().retain(|context| context.as_ptr() != this);
+    }
+}
+
 /// Thread-safe DirectX 9 proxy device context.
 ///
 /// This context is shared among DirectX 9 proxy objects and provides:
@@ -40,10 +103,94 @@ pub struct DX9ProxyDeviceContext(Arc<DX9ProxyDeviceContextImpl>);
 impl DX9ProxyDeviceContext {
     /// Creates a new DirectX 9 proxy device context with the specified configuration.
     pub fn new(config: DX9ProxyConfig) -> Self {
-        Self(Arc::new(DX9ProxyDeviceContextImpl {
+        let forced_render_states = config.forced_render_states();
+        let forced_sampler_states = config.forced_sampler_states();
+        #[cfg(feature = "tracing")]
+        if !forced_render_states.is_empty() || !forced_sampler_states.is_empty() {
+            tracing::info!("Forcing render states {forced_render_states:?} and sampler states {forced_sampler_states:?}");
+        }
+
+        let tracker = match config.max_tracked_objects {
+            0 => ComMappingTracker::default(),
+            max_tracked_objects => ComMappingTracker::with_max_tracked_objects(max_tracked_objects as usize, config.tracker_report_file.clone()),
+        };
+        tracker.set_capture_creation_stacks(config.capture_creation_stacks);
+
+        if let Some(path) = &config.effective_config_file {
+            let _ = std::fs::write(path, config.format_env_file()).inspect_err(|_err| {
+                #[cfg(feature = "tracing")]
+                tracing::error!("Failed to write effective configuration to {}: {_err}", path.display());
+            });
+        }
+
+        let has_render_state_keys = config.force_z_enable_key.is_some() || config.force_z_write_enable_key.is_some() || config.force_color_write_enable_key.is_some();
+        let forced_render_states = (!forced_render_states.is_empty() || has_render_state_keys).then(|| Mutex::new(DX9ForcedRenderStates::new(forced_render_states)));
+
+        let context = Arc::new(DX9ProxyDeviceContextImpl {
+            #[cfg(feature = "experimental-serialize-device-calls")]
+            executor: config.serialize_device_calls.then(|| crate::SerialExecutor::new("dxproxy-device-worker")),
+            tracker,
+            scratch_buffers: Mutex::new(DX9ScratchBuffers::default()),
+            shader_constant_shadow: config.capture_shader_constants.then(|| Mutex::new(Box::default())),
+            render_target_size: Mutex::new(None),
+            viewport_clamped: AtomicBool::new(false),
+            present_params: Mutex::new(None),
+            surface_cache: Mutex::new(DX9SurfaceCache::default()),
+            bound_textures: Mutex::new(DX9BoundTextures::default()),
+            bound_index_buffer: config.capture_index_buffers.then(|| Mutex::new(BoundIndexBuffer(None))),
+            lock_tracker: config.check_lock_leaks.then(|| Mutex::new(DX9LockTracker::default())),
+            default_pool_tracker: config.track_default_pool.then(|| Mutex::new(DX9DefaultPoolTracker::default())),
+            call_stats: config.collect_call_stats.then(|| Mutex::new(DX9CallStats::default())),
+            reset_stats_key_down: AtomicBool::new(false),
+            clears_disabled: AtomicBool::new(config.disable_clears),
+            disable_clears_key_down: AtomicBool::new(false),
+            force_z_enable_key_down: AtomicBool::new(false),
+            force_z_write_enable_key_down: AtomicBool::new(false),
+            force_color_write_enable_key_down: AtomicBool::new(false),
+            log_ring_dump_key_down: AtomicBool::new(false),
+            cursor_clipped: AtomicBool::new(false),
+            cooperative_level: AtomicI32::new(D3D_OK.0),
+            watermark: config.watermark.clone().map(|text| Mutex::new(DX9Watermark::new(text))),
+            stretch_scratch: config.allow_self_stretchrect.then(|| Mutex::new(DX9StretchScratch::default())),
+            frame_pacer: config
+                .frame_pacing
+                .then(|| Mutex::new(DX9FramePacer::new(config.frame_pacing_window as usize, Duration::from_millis(config.frame_pacing_max_sleep_ms.into())))),
+            cursor_cache: config.cursor_scale.is_some().then(|| Mutex::new(DX9ScaledCursorCache::default())),
+            forced_render_states,
+            forced_sampler_states,
             config,
-            tracker: Mutex::new(ComMappingTracker::default()),
-        }))
+        });
+        CONTEXT_REGISTRY.lock().unwrap().push(Arc::downgrade(&context));
+        Self(context)
+    }
+
+    /// Returns the contexts of all live devices.
+    ///
+    /// Useful for actions that apply to every device, such as global hotkeys in applications
+    /// that create several devices (e.g. for separate windows).
+    pub fn live_contexts() -> Vec<Self> {
+        CONTEXT_REGISTRY.lock().unwrap().iter().filter_map(Weak::upgrade).map(Self).collect()
+    }
+
+    /// Forgets all contexts registered so far, so that [`live_contexts`](Self::live_contexts) only returns contexts created afterwards.
+    ///
+    /// Contexts that are still alive stay usable. See [`shutdown`](crate::dx9::shutdown).
+    pub fn clear_registry() {
+        CONTEXT_REGISTRY.lock().unwrap().clear();
+    }
+
+    /// Logs the proxies and locked resources that are still alive, which indicate leaks at shutdown.
+    pub fn report_leaks(&self) {
+        #[cfg(feature = "tracing")]
+        {
+            let tracker = &self.0.tracker;
+            if tracker.len() > 0 {
+                tracing::warn!("{} proxies still alive:\n{}", tracker.len(), tracker.format_type_breakdown());
+            }
+            if let Some(locked_count) = self.get_locked_resource_count().filter(|&count| count > 0) {
+                tracing::warn!("{locked_count} resources still locked");
+            }
+        }
     }
 
     /// Returns a reference to the underlying configuration.
@@ -51,39 +198,596 @@ impl DX9ProxyDeviceContext {
         &self.0.config
     }
 
+    /// Returns the value to set when the application sets `state` to `value`, which differs if the state is forced.
+    ///
+    /// See [`DX9ProxyConfig::forced_render_states`].
+    pub fn override_render_state(&self, state: D3DRENDERSTATETYPE, value: u32) -> u32 {
+        match &self.0.forced_render_states {
+            Some(forced_render_states) => forced_render_states.lock().unwrap().on_set(state, value),
+            None => value,
+        }
+    }
+
+    /// Returns the render states to set to re-apply the forced render states, including the values
+    /// to restore for states whose forcing has been lifted since the last call.
+    ///
+    /// See [`DX9ProxyConfig::forced_render_states`].
+    pub fn take_forced_render_states(&self) -> Vec<(D3DRENDERSTATETYPE, u32)> {
+        match &self.0.forced_render_states {
+            Some(forced_render_states) => forced_render_states.lock().unwrap().take_states_to_apply(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns `true` if any render or sampler state may be forced, i.e. if forced states need to be re-applied.
+    pub fn has_forced_states(&self) -> bool {
+        self.0.forced_render_states.is_some() || !self.0.forced_sampler_states.is_empty()
+    }
+
+    /// Returns the sampler states forced by the configuration, see [`DX9ProxyConfig::forced_sampler_states`].
+    pub fn get_forced_sampler_states(&self) -> &[(D3DSAMPLERSTATETYPE, u32)] {
+        &self.0.forced_sampler_states
+    }
+
+    /// Locks and returns the scratch buffers used for rewriting user-pointer draws.
+    ///
+    /// See [`DX9ProxyConfig::optimize_up_draws`].
+    pub fn lock_scratch_buffers(&self) -> MutexGuard<'_, DX9ScratchBuffers> {
+        self.0.scratch_buffers.lock().unwrap()
+    }
+
+    /// Locks and returns the shadow copy of the shader float constants.
+    ///
+    /// # Returns
+    /// * `Some(MutexGuard)` - If [`DX9ProxyConfig::capture_shader_constants`] is enabled
+    /// * `None` - Otherwise
+    pub fn lock_shader_constant_shadow(&self) -> Option<MutexGuard<'_, Box<DX9ShaderConstantShadow>>> {
+        self.0.shader_constant_shadow.as_ref().map(|shadow| shadow.lock().unwrap())
+    }
+
+    /// Locks and returns the watermark drawn at each `Present`.
+    ///
+    /// # Returns
+    /// * `Some(MutexGuard)` - If [`DX9ProxyConfig::watermark`] is set
+    /// * `None` - Otherwise
+    pub fn lock_watermark(&self) -> Option<MutexGuard<'_, DX9Watermark>> {
+        self.0.watermark.as_ref().map(|watermark| watermark.lock().unwrap())
+    }
+
+    /// Locks and returns the scratch surface for `StretchRect` calls from a surface onto itself.
+    ///
+    /// # Returns
+    /// * `Some(MutexGuard)` - If [`DX9ProxyConfig::allow_self_stretchrect`] is enabled
+    /// * `None` - Otherwise
+    pub fn lock_stretch_scratch(&self) -> Option<MutexGuard<'_, DX9StretchScratch>> {
+        self.0.stretch_scratch.as_ref().map(|scratch| scratch.lock().unwrap())
+    }
+
+    /// Locks and returns the frame pacer, which delays `Present` to smooth out frame intervals.
+    ///
+    /// # Returns
+    /// * `Some(MutexGuard)` - If [`DX9ProxyConfig::frame_pacing`] is enabled
+    /// * `None` - Otherwise
+    pub fn lock_frame_pacer(&self) -> Option<MutexGuard<'_, DX9FramePacer>> {
+        self.0.frame_pacer.as_ref().map(|pacer| pacer.lock().unwrap())
+    }
+
+    /// Locks and returns the cache of the cursor bitmap scaled last by `SetCursorProperties`.
+    ///
+    /// # Returns
+    /// * `Some(MutexGuard)` - If [`DX9ProxyConfig::cursor_scale`] is set
+    /// * `None` - Otherwise
+    pub fn lock_cursor_cache(&self) -> Option<MutexGuard<'_, DX9ScaledCursorCache>> {
+        self.0.cursor_cache.as_ref().map(|cache| cache.lock().unwrap())
+    }
+
+    /// Locks and returns the textures bound to each sampler stage through the proxy device.
+    pub fn lock_bound_textures(&self) -> MutexGuard<'_, DX9BoundTextures> {
+        self.0.bound_textures.lock().unwrap()
+    }
+
+    /// Returns the proxy of the index buffer bound through the proxy device, if any and still alive.
+    ///
+    /// Always returns `None` unless [`DX9ProxyConfig::capture_index_buffers`] is enabled.
+    /// Bindings changed by applying state blocks are not tracked.
+    pub fn get_bound_index_buffer(&self) -> Option<IDirect3DIndexBuffer9> {
+        self.0.bound_index_buffer.as_ref()?.lock().unwrap().0.as_ref()?.upgrade()
+    }
+
+    /// Records the proxy of the index buffer bound through the proxy device.
+    ///
+    /// Does nothing unless [`DX9ProxyConfig::capture_index_buffers`] is enabled. The proxy is recorded
+    /// as a weak reference, since it holds the proxy device and this context, which would form a
+    /// reference cycle keeping the device alive.
+    pub fn set_bound_index_buffer(&self, index_buffer: Option<&IDirect3DIndexBuffer9>) {
+        if let Some(bound_index_buffer) = &self.0.bound_index_buffer {
+            bound_index_buffer.lock().unwrap().0 = index_buffer.and_then(|index_buffer| index_buffer.downgrade().ok());
+        }
+    }
+
+    /// Records a successful lock of a resource.
+    ///
+    /// Does nothing unless [`DX9ProxyConfig::check_lock_leaks`] is enabled.
+    ///
+    /// # Arguments
+    /// * `count` - The lock count of the resource proxy
+    /// * `resource` - The resource proxy, for reporting
+    pub fn on_resource_lock(&self, count: &DX9LockCount, resource: &impl Debug) {
+        if let Some(tracker) = &self.0.lock_tracker
+            && count.lock()
+        {
+            tracker.lock().unwrap().on_locked(count, || format!("{resource:?}"));
+        }
+    }
+
+    /// Records a successful unlock of a resource.
+    ///
+    /// Does nothing unless [`DX9ProxyConfig::check_lock_leaks`] is enabled.
+    pub fn on_resource_unlock(&self, count: &DX9LockCount) {
+        if let Some(tracker) = &self.0.lock_tracker
+            && count.unlock()
+        {
+            tracker.lock().unwrap().on_unlocked(count);
+        }
+    }
+
+    /// Forgets a resource that is being destroyed, whether or not it is still locked.
+    pub fn on_resource_destroy(&self, count: &DX9LockCount) {
+        if let Some(tracker) = &self.0.lock_tracker {
+            tracker.lock().unwrap().on_unlocked(count);
+        }
+    }
+
+    /// Returns the number of resources of the device that are currently locked.
+    ///
+    /// # Returns
+    /// * `Some(usize)` - If [`DX9ProxyConfig::check_lock_leaks`] is enabled
+    /// * `None` - Otherwise
+    pub fn get_locked_resource_count(&self) -> Option<usize> {
+        self.0.lock_tracker.as_ref().map(|tracker| tracker.lock().unwrap().locked_count())
+    }
+
+    /// Logs the pool and usage of a created resource, and registers it if it is in the default pool.
+    ///
+    /// Does nothing unless [`DX9ProxyConfig::track_default_pool`] is enabled. Call this before the
+    /// proxy of `target` is created, so that the resource is deregistered when the proxy is destroyed.
+    ///
+    /// # Arguments
+    /// * `target` - The created target resource
+    /// * `pool` - The pool the resource was created in
+    /// * `usage` - The usage the resource was created with, after any overrides
+    /// * `description` - Describes the resource, e.g. its size, format and type
+    pub fn on_resource_create<T: Interface>(&self, target: &T, pool: D3DPOOL, usage: u32, description: impl FnOnce() -> String) {
+        if let Some(tracker) = &self.0.default_pool_tracker {
+            let description = format!("{} in pool {} with usage {}", description(), describe_pool(pool), describe_usage(usage));
+            #[cfg(feature = "tracing")]
+            tracing::info!("Created {description} ({:p})", target.as_raw());
+            if pool == D3DPOOL_DEFAULT {
+                let target_ptr = com_identity(target);
+                tracker.lock().unwrap().on_created(target_ptr, description);
+            }
+        }
+    }
+
+    /// Forgets the presentation parameters, and logs the default pool resources of the device that are still alive, after `Reset` or `ResetEx` failed.
+    ///
+    /// Each resource is logged by the address of its target object, along with its creation stack if
+    /// [`DX9ProxyConfig::capture_creation_stacks`] is enabled. Resources are only logged if
+    /// [`DX9ProxyConfig::track_default_pool`] is enabled.
+    pub fn on_reset_failed(&self) {
+        self.set_present_params(None);
+
+        let Some(tracker) = &self.0.default_pool_tracker else {
+            return;
+        };
+
+        let resources = tracker.lock().unwrap().live_resources();
+        let mut _report = String::new();
+        for (target_ptr, description) in &resources {
+            _report.push_str(&format!("\n{target_ptr:p}: {description}"));
+            if let Some(stack) = self.0.tracker.creation_stack(*target_ptr) {
+                _report.push_str(&format!(", created at:\n{}", stack.to_string().trim_end()));
+            }
+        }
+
+        #[cfg(feature = "tracing")]
+        if resources.is_empty() {
+            tracing::warn!("Reset failed, but no default pool resources are alive");
+        } else {
+            tracing::error!("Reset failed with {} default pool resources alive:{_report}", resources.len());
+        }
+    }
+
+    /// Records a draw call of `primitives` primitives in the call statistics.
+    ///
+    /// Does nothing unless [`DX9ProxyConfig::collect_call_stats`] is enabled.
+    pub fn record_draw_call(&self, primitives: u32) {
+        if let Some(stats) = &self.0.call_stats {
+            stats.lock().unwrap().record_draw_call(primitives);
+        }
+    }
+
+    /// Logs a private data call on `resource`, with the GUID, the data size and the result.
+    ///
+    /// Does nothing unless [`DX9ProxyConfig::log_private_data`] is enabled.
+    ///
+    /// # Arguments
+    /// * `resource` - The resource proxy the call was made on.
+    /// * `method` - The name of the method, e.g. `SetPrivateData`.
+    /// * `refguid` - The GUID passed by the application, or `None` if null.
+    /// * `size` - The data size passed or returned, if any.
+    /// * `result` - The result returned by the target.
+    pub fn log_private_data(&self, resource: &dyn Debug, method: &str, refguid: Option<&GUID>, size: Option<u32>, result: &Result<()>) {
+        if !self.0.config.log_private_data {
+            return;
+        }
+
+        #[cfg(feature = "tracing")]
+        {
+            let guid = match refguid {
+                Some(guid) => format!("{{{guid:?}}}"),
+                None => "null".to_string(),
+            };
+            let size = size.map_or(String::new(), |size| format!(", {size} bytes"));
+            let result = hresult_name(result.as_ref().map_or_else(Error::code, |()| D3D_OK));
+            tracing::info!("{method}({guid}{size}) on {resource:?} returned {result}");
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = (resource, method, refguid, size, result);
+    }
+
+    /// Calls `f`, which forwards `method` to the target driver, and records the time spent in it.
+    ///
+    /// Does nothing but call `f` unless [`DX9ProxyConfig::collect_call_stats`] and
+    /// [`DX9ProxyConfig::measure_driver_time`] are enabled.
+    pub fn time_driver_call<R>(&self, method: &'static str, f: impl FnOnce() -> R) -> R {
+        match &self.0.call_stats {
+            Some(stats) if self.0.config.measure_driver_time => {
+                let started = Instant::now();
+                let result = f();
+                let elapsed = started.elapsed();
+                stats.lock().unwrap().record_driver_time(method, elapsed);
+                result
+            }
+            _ => f(),
+        }
+    }
+
+    /// Returns a snapshot of the call statistics.
+    ///
+    /// # Returns
+    /// * `Some(DX9CallStats)` - If [`DX9ProxyConfig::collect_call_stats`] is enabled
+    /// * `None` - Otherwise
+    pub fn get_call_stats(&self) -> Option<DX9CallStats> {
+        self.0.call_stats.as_ref().map(|stats| stats.lock().unwrap().clone())
+    }
+
+    /// Logs and resets the call statistics, to start measuring a new interval.
+    ///
+    /// All counters are cleared at once, so no call is counted in both or neither interval.
+    ///
+    /// # Returns
+    /// * `Some(DX9CallStats)` - The statistics up to the reset, if [`DX9ProxyConfig::collect_call_stats`] is enabled
+    /// * `None` - Otherwise
+    pub fn reset_stats(&self) -> Option<DX9CallStats> {
+        let stats = self.0.call_stats.as_ref().map(|stats| std::mem::take(&mut *stats.lock().unwrap()))?;
+        #[cfg(feature = "tracing")]
+        tracing::info!("Call statistics before reset: {stats}");
+        Some(stats)
+    }
+
+    /// Performs the per-frame bookkeeping of the context, to be called after each successful `Present`.
+    ///
+    /// Counts the frame, handles the [`DX9ProxyConfig::reset_stats_key`] and [`DX9ProxyConfig::disable_clears_key`]
+    /// hotkeys, and checks for lock leaks.
+    pub fn on_present(&self) {
+        if let Some(stats) = &self.0.call_stats {
+            stats.lock().unwrap().record_frame();
+
+            if let Some(key) = self.0.config.reset_stats_key
+                && poll_hotkey(key, &self.0.reset_stats_key_down)
+            {
+                self.reset_stats();
+            }
+        }
+
+        if let Some(key) = self.0.config.disable_clears_key
+            && poll_hotkey(key, &self.0.disable_clears_key_down)
+        {
+            let _disabled = !self.0.clears_disabled.fetch_xor(true, Ordering::Relaxed);
+            #[cfg(feature = "tracing")]
+            tracing::info!("Clears are now {}", if _disabled { "suppressed" } else { "enabled" });
+        }
+
+        if let Some(key) = self.0.config.log_ring_dump_key
+            && poll_hotkey(key, &self.0.log_ring_dump_key_down)
+        {
+            #[cfg(any(feature = "tracing", feature = "tracing-instrument"))]
+            crate::dx9::dump_log_ring();
+        }
+
+        let config = &self.0.config;
+        let render_state_keys = [
+            (
+                config.force_z_enable_key,
+                &self.0.force_z_enable_key_down,
+                D3DRS_ZENABLE,
+                config.force_z_enable.map_or(D3DZB_FALSE.0 as u32, u32::from),
+            ),
+            (
+                config.force_z_write_enable_key,
+                &self.0.force_z_write_enable_key_down,
+                D3DRS_ZWRITEENABLE,
+                config.force_z_write_enable.map_or(0, u32::from),
+            ),
+            (
+                config.force_color_write_enable_key,
+                &self.0.force_color_write_enable_key_down,
+                D3DRS_COLORWRITEENABLE,
+                config.force_color_write_enable.unwrap_or(D3DCOLORWRITEENABLE_RED),
+            ),
+        ];
+        for (key, down, state, value) in render_state_keys {
+            if let Some(key) = key
+                && poll_hotkey(key, down)
+            {
+                self.toggle_forced_render_state(state, value);
+            }
+        }
+
+        self.check_lock_leaks();
+    }
+
+    /// Forces `state` to `value`, or lifts its forcing if it is already forced, and logs the active overrides.
+    ///
+    /// The change takes effect at the next `BeginScene`. See [`DX9ProxyConfig::force_z_enable_key`].
+    fn toggle_forced_render_state(&self, state: D3DRENDERSTATETYPE, value: u32) {
+        let Some(forced_render_states) = &self.0.forced_render_states else {
+            return;
+        };
+        let mut forced_render_states = forced_render_states.lock().unwrap();
+        let value = if forced_render_states.get(state).is_some() { None } else { Some(value) };
+        forced_render_states.set(state, value);
+
+        #[cfg(feature = "tracing")]
+        {
+            match value {
+                Some(value) => tracing::info!("Forcing render state {state:?} to {value}"),
+                None => tracing::info!("No longer forcing render state {state:?}"),
+            }
+            tracing::info!("Forced render states are now {:?}", forced_render_states.forced());
+        }
+    }
+
+    /// Returns the result forced for `method` by [`DX9ProxyConfig::hresult_overrides`], logging it.
+    ///
+    /// Methods that can be overridden check this first, and return the forced result without calling the driver.
+    ///
+    /// # Returns
+    /// * `Some(Result<()>)` - The forced result, if `method` is overridden
+    /// * `None` - Otherwise
+    pub fn get_forced_result(&self, method: DX9OverridableMethod) -> Option<Result<()>> {
+        let result = self.0.config.hresult_overrides.iter().find(|entry| entry.method == method)?.result;
+        #[cfg(feature = "tracing")]
+        tracing::debug!("Forcing {method:?} to return {}", hresult_name(result));
+        Some(result.ok())
+    }
+
+    /// Returns `true` if `Clear` calls should currently be suppressed.
+    ///
+    /// See [`DX9ProxyConfig::disable_clears`].
+    pub fn are_clears_disabled(&self) -> bool {
+        self.0.clears_disabled.load(Ordering::Relaxed)
+    }
+
+    /// Warns about resources that have been locked for more than one frame.
+    ///
+    /// Does nothing unless [`DX9ProxyConfig::check_lock_leaks`] is enabled.
+    fn check_lock_leaks(&self) {
+        if let Some(tracker) = &self.0.lock_tracker {
+            let _leaks = tracker.lock().unwrap().end_frame();
+            #[cfg(feature = "tracing")]
+            for (resource, frames) in _leaks {
+                tracing::warn!("{resource} has been locked for {frames} frames without being unlocked");
+            }
+        }
+    }
+
+    /// Clips the cursor to `rect` in screen coordinates, or releases the clip set by this context if `None`.
+    ///
+    /// See [`DX9ProxyConfig::clip_cursor_in_window`].
+    pub fn set_cursor_clip(&self, rect: Option<RECT>) {
+        match rect {
+            Some(rect) => {
+                if unsafe { ClipCursor(Some(&rect)) }.is_ok() && !self.0.cursor_clipped.swap(true, Ordering::Relaxed) {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!("Clipping cursor to {rect:?}");
+                }
+            }
+            None => {
+                if self.0.cursor_clipped.swap(false, Ordering::Relaxed) {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!("Releasing cursor clip");
+                    let _ = unsafe { ClipCursor(None) };
+                }
+            }
+        }
+    }
+
+    /// Records the result of `TestCooperativeLevel`, returning the previous result if it changed.
+    ///
+    /// Lost devices report the same error every frame, so callers log only the transitions.
+    pub fn update_cooperative_level(&self, result: HRESULT) -> Option<HRESULT> {
+        let previous = HRESULT(self.0.cooperative_level.swap(result.0, Ordering::Relaxed));
+        (previous != result).then_some(previous)
+    }
+
+    /// Returns the cached size of render target 0, querying it with `query_fn` if not cached.
+    ///
+    /// See [`DX9ProxyConfig::clamp_viewport`].
+    pub fn get_render_target_size(&self, query_fn: impl FnOnce() -> Result<(u32, u32)>) -> Result<(u32, u32)> {
+        let mut size = self.0.render_target_size.lock().unwrap();
+        if let Some(size) = *size {
+            return Ok(size);
+        }
+        let queried = query_fn()?;
+        *size = Some(queried);
+        Ok(queried)
+    }
+
+    /// Records that a viewport was clamped, returning whether it is the first time on this device.
+    ///
+    /// Games may set the same oversized viewport every frame, so callers warn only about the first one.
+    pub fn on_viewport_clamped(&self) -> bool {
+        !self.0.viewport_clamped.swap(true, Ordering::Relaxed)
+    }
+
+    /// Discards the cached size of render target 0, which must be done whenever render target 0 may change.
+    pub fn invalidate_render_target_size(&self) {
+        *self.0.render_target_size.lock().unwrap() = None;
+    }
+
+    /// Returns the effective presentation parameters of the implicit swap chain, as of the last device creation or reset.
+    ///
+    /// Lets features that depend on the presentation parameters (e.g. whether the device is windowed)
+    /// avoid querying them from the driver. Returns `None` if they are unknown, e.g. after a failed reset.
+    pub fn get_present_params(&self) -> Option<D3DPRESENT_PARAMETERS> {
+        *self.0.present_params.lock().unwrap()
+    }
+
+    /// Records the effective presentation parameters of the implicit swap chain, to be called after successful creation and reset.
+    pub fn set_present_params(&self, params: Option<D3DPRESENT_PARAMETERS>) {
+        *self.0.present_params.lock().unwrap() = params;
+    }
+
+    /// Returns the proxy of the surface bound to `slot`, from the cache if possible.
+    ///
+    /// Otherwise, the surface is queried with `query_fn`, its proxy is looked up or created with
+    /// `create_proxy_fn` as with [`ensure_proxy`](Self::ensure_proxy), and the proxy is cached.
+    /// The returned proxy is therefore always the one the tracker maps the surface to.
+    pub fn get_surface_proxy(
+        &self,
+        slot: DX9SurfaceSlot,
+        query_fn: impl FnOnce() -> Result<IDirect3DSurface9>,
+        create_proxy_fn: impl FnOnce(IDirect3DSurface9) -> IDirect3DSurface9,
+    ) -> Result<IDirect3DSurface9> {
+        let mut cache = self.0.surface_cache.lock().unwrap();
+        if let Some(proxy) = cache.get(slot) {
+            return Ok(proxy);
+        }
+        let target = query_fn()?;
+        let target_ptr = target.as_raw();
+        let proxy = self.ensure_proxy(target, create_proxy_fn);
+        cache.insert(slot, target_ptr, &proxy);
+        Ok(proxy)
+    }
+
+    /// Discards the cached proxy of the surface bound to `slot`, which must be done whenever it may change.
+    pub fn invalidate_surface_proxy(&self, slot: DX9SurfaceSlot) {
+        self.0.surface_cache.lock().unwrap().invalidate(slot);
+    }
+
+    /// Discards all cached surface proxies, which must be done when resetting the device.
+    pub fn clear_surface_proxies(&self) {
+        self.0.surface_cache.lock().unwrap().clear();
+    }
+
+    /// Runs a device call, serializing it through the device worker thread if enabled.
+    ///
+    /// See [`DX9ProxyConfig::serialize_device_calls`]. Without the `experimental-serialize-device-calls`
+    /// feature, or when the option is disabled, `f` is called directly on the current thread.
+    ///
+    /// Only use this for calls that do not create or return interfaces, as those would be
+    /// created on the worker thread and may confuse drivers that track the creating thread.
+    ///
+    /// # Safety
+    /// `f` may run on another thread, see `SerialExecutor::run`. It must only capture data that can be
+    /// used from the worker thread, such as the target device and pointers passed by the application,
+    /// which stay valid as the calling thread blocks meanwhile.
+    pub unsafe fn serialize<R>(&self, f: impl FnOnce() -> R) -> R {
+        #[cfg(feature = "experimental-serialize-device-calls")]
+        if let Some(executor) = &self.0.executor {
+            return unsafe { executor.run(f) };
+        }
+
+        f()
+    }
+
     /// See [`ComMappingTracker::ensure_proxy`].
     pub fn ensure_proxy<T: Interface + Debug>(&self, target: T, create_proxy_fn: impl FnOnce(T) -> T) -> T {
-        let mut storage = self.0.tracker.lock().unwrap();
-        storage.ensure_proxy(target, create_proxy_fn)
+        self.0.tracker.ensure_proxy(target, create_proxy_fn)
     }
 
     /// See [`ComMappingTracker::try_ensure_proxy`].
     pub fn try_ensure_proxy<T: Interface + Debug>(&self, target: T, try_create_proxy_fn: impl FnOnce(T) -> Result<T>) -> Result<T> {
-        let mut storage = self.0.tracker.lock().unwrap();
-        storage.try_ensure_proxy(target, try_create_proxy_fn)
+        self.0.tracker.try_ensure_proxy(target, try_create_proxy_fn)
     }
 
     /// See [`ComMappingTracker::get_proxy`].
     pub fn get_proxy<T: Interface + Debug>(&self, target: T) -> Option<T> {
-        let mut storage = self.0.tracker.lock().unwrap();
-        storage.get_proxy(target)
+        self.0.tracker.get_proxy(target)
     }
 
     /// See [`ComMappingTracker::get_target`].
     pub fn get_target<T: Interface + Debug, K: NullableInterfaceIn<T>>(&self, proxy: K) -> Option<NullableInterfaceOut<T>> {
-        let mut storage = self.0.tracker.lock().unwrap();
-        storage.get_target(proxy)
+        self.0.tracker.get_target(proxy)
     }
 
     /// See [`ComMappingTracker::get_target_nullable`].
     pub fn get_target_nullable<T: Interface + Debug, K: NullableInterfaceIn<T>>(&self, proxy: K) -> Option<NullableInterfaceOut<T>> {
-        let mut storage = self.0.tracker.lock().unwrap();
-        storage.get_target_nullable(proxy)
+        self.0.tracker.get_target_nullable(proxy)
     }
 
-    /// See [`ComMappingTracker::on_proxy_destroy`].
+    /// See [`ComMappingTracker::on_proxy_destroy`]. Also discards the proxy from the surface cache,
+    /// and deregisters the resource from the default pool tracker, if any.
+    ///
+    /// Called from the `Drop` of proxies, which hold a clone of this context, so the context and its
+    /// tracker always outlive them regardless of the drop order of device fields. As the tracker lock
+    /// is not reentrant, proxies must never be dropped while it is held, e.g. inside the creation
+    /// closures of [`ensure_proxy`](Self::ensure_proxy) and [`try_ensure_proxy`](Self::try_ensure_proxy).
     pub fn on_proxy_destroy<T: Interface + Debug>(&self, target: &T) {
-        let mut storage = self.0.tracker.lock().unwrap();
-        storage.on_proxy_destroy(target);
+        self.0.tracker.on_proxy_destroy(target);
+        self.0.surface_cache.lock().unwrap().on_proxy_destroy(target.as_raw());
+
+        if let Some(tracker) = &self.0.default_pool_tracker {
+            let target_ptr = com_identity(target);
+            tracker.lock().unwrap().on_destroyed(target_ptr);
+        }
+    }
+}
+
+/// Polls a hotkey, returning `true` once each time it is pressed.
+///
+/// `down` holds whether the key was down at the previous poll.
+fn poll_hotkey(key: u32, down: &AtomicBool) -> bool {
+    let is_down = unsafe { GetAsyncKeyState(key as i32) } as u16 & 0x8000 != 0;
+    let was_down = down.swap(is_down, Ordering::Relaxed);
+    is_down && !was_down
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns whether `context` is among the live contexts, as other tests may create contexts concurrently.
+    fn is_live(context: &DX9ProxyDeviceContext) -> bool {
+        DX9ProxyDeviceContext::live_contexts().iter().any(|live| Arc::ptr_eq(&live.0, &context.0))
+    }
+
+    #[test]
+    fn contexts_register_and_deregister_on_drop() {
+        let first = DX9ProxyDeviceContext::new(DX9ProxyConfig::default());
+        let second = DX9ProxyDeviceContext::new(DX9ProxyConfig::default());
+        assert!(is_live(&first));
+        assert!(is_live(&second));
+
+        let weak_first = Arc::downgrade(&first.0);
+        let first_clone = first.clone();
+        drop(first);
+        assert!(is_live(&first_clone));
+        drop(first_clone);
+
+        assert!(is_live(&second));
+        assert!(!CONTEXT_REGISTRY.lock().unwrap().iter().any(|context| context.ptr_eq(&weak_first)));
     }
 }
@@ -0,0 +1,116 @@
+//! Overrides of the display modes enumerated by [`IDirect3D9`] and [`IDirect3D9Ex`].
+//!
+//! Some games filter the enumerated display modes with hardcoded rules, hiding resolutions such
+//! as 21:9 modes that the monitor supports. [`DX9DisplayModeOverride`] splices synthetic modes
+//! into the real enumeration and hides unwanted ones, as configured by
+//! [`DX9ProxyConfig::add_display_modes`] and [`DX9ProxyConfig::hide_display_modes`].
+
+use super::*;
+use std::{collections::HashMap, sync::Mutex};
+use windows::Win32::Graphics::Direct3D9::*;
+
+/// Effective display mode lists for the enumeration parameters `(adapter, format, scanline ordering)`.
+type DX9DisplayModeCache = HashMap<(u32, u32, Option<i32>), Vec<D3DDISPLAYMODEEX>>;
+
+/// Display modes to add to and hide from the enumerated display modes.
+///
+/// The effective mode list is computed once per adapter, format and scanline ordering, and
+/// cached, so the mode count and the modes returned by index always agree.
+#[derive(Debug)]
+pub struct DX9DisplayModeOverride {
+    added: Vec<DX9DisplayModeSpec>,
+    hidden: Vec<DX9DisplayModeSpec>,
+    modes: Mutex<DX9DisplayModeCache>,
+}
+
+impl DX9DisplayModeOverride {
+    /// Creates the display mode override for a configuration.
+    ///
+    /// # Returns
+    /// * `Some(Self)` - If the configuration adds or hides any display modes
+    /// * `None` - Otherwise, in which case the enumeration should be passed through
+    pub fn from_config(config: &DX9ProxyConfig) -> Option<Self> {
+        if config.add_display_modes.is_empty() && config.hide_display_modes.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            added: config.add_display_modes.clone(),
+            hidden: config.hide_display_modes.clone(),
+            modes: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Returns the effective display mode list, computing it from the real one if not cached.
+    ///
+    /// # Arguments
+    /// * `adapter` - The adapter being enumerated
+    /// * `format` - The format of the modes being enumerated
+    /// * `scanlineordering` - The scanline ordering of the modes being enumerated with `EnumAdapterModesEx`, or `None` for `EnumAdapterModes`
+    /// * `enumerate_fn` - A function that enumerates the real display modes
+    pub fn get_modes(&self, adapter: u32, format: D3DFORMAT, scanlineordering: Option<D3DSCANLINEORDERING>, enumerate_fn: impl FnOnce() -> Vec<D3DDISPLAYMODEEX>) -> Vec<D3DDISPLAYMODEEX> {
+        let mut cache = self.modes.lock().unwrap();
+        cache
+            .entry((adapter, format.0, scanlineordering.map(|scanlineordering| scanlineordering.0)))
+            .or_insert_with(|| {
+                let mut modes = enumerate_fn();
+                modes.retain(|mode| !self.hidden.iter().any(|hidden| hidden.matches(mode)));
+
+                // Added modes are progressive, so they are only listed if progressive modes are enumerated
+                if matches!(scanlineordering, None | Some(D3DSCANLINEORDERING_UNKNOWN | D3DSCANLINEORDERING_PROGRESSIVE)) {
+                    for added in &self.added {
+                        let mode = added.to_display_mode(format);
+                        if !modes.iter().any(|existing| added.matches(existing)) {
+                            modes.push(mode);
+                        }
+                    }
+                }
+
+                // Keep the ordering of the runtime, which lists modes by size and then refresh rate
+                modes.sort_by_key(|mode| (mode.Width, mode.Height, mode.RefreshRate));
+
+                #[cfg(feature = "tracing")]
+                tracing::info!(
+                    "Effective display modes of adapter {adapter} for {format:?}: {:?}",
+                    modes.iter().map(|mode| format!("{}x{}@{}", mode.Width, mode.Height, mode.RefreshRate)).collect::<Vec<_>>()
+                );
+
+                modes
+            })
+            .clone()
+    }
+}
+
+/// Enumerates the real display modes of an adapter with [`IDirect3D9::EnumAdapterModes`].
+pub(super) fn enumerate_adapter_modes(target: &IDirect3D9, adapter: u32, format: D3DFORMAT) -> Vec<D3DDISPLAYMODEEX> {
+    let count = unsafe { target.GetAdapterModeCount(adapter, format) };
+    (0..count)
+        .filter_map(|index| {
+            let mut mode = D3DDISPLAYMODE::default();
+            unsafe { target.EnumAdapterModes(adapter, format, index, &mut mode) }.ok()?;
+            Some(D3DDISPLAYMODEEX {
+                Size: size_of::<D3DDISPLAYMODEEX>() as u32,
+                Width: mode.Width,
+                Height: mode.Height,
+                RefreshRate: mode.RefreshRate,
+                Format: mode.Format,
+                ScanLineOrdering: D3DSCANLINEORDERING_UNKNOWN,
+            })
+        })
+        .collect()
+}
+
+/// Enumerates the real display modes of an adapter with [`IDirect3D9Ex::EnumAdapterModesEx`].
+pub(super) fn enumerate_adapter_modes_ex(target: &IDirect3D9Ex, adapter: u32, filter: &D3DDISPLAYMODEFILTER) -> Vec<D3DDISPLAYMODEEX> {
+    let count = unsafe { target.GetAdapterModeCountEx(adapter, filter) };
+    (0..count)
+        .filter_map(|index| {
+            let mut mode = D3DDISPLAYMODEEX {
+                Size: size_of::<D3DDISPLAYMODEEX>() as u32,
+                ..Default::default()
+            };
+            unsafe { target.EnumAdapterModesEx(adapter, filter, index, &mut mode) }.ok()?;
+            Some(mode)
+        })
+        .collect()
+}
@@ -0,0 +1,223 @@
+//! Emulation of query types that are not supported by the driver.
+//!
+//! Some drivers fail `CreateQuery` for query types that games expect to exist, which can break
+//! the game. When [`DX9ProxyConfig::emulate_queries`] is enabled, such queries are replaced with
+//! [`EmulatedDirect3DQuery9`], which returns plausible values. The emulation is approximate:
+//!
+//! - `D3DQUERYTYPE_EVENT`: Completes immediately, as no GPU fence is available.
+//! - `D3DQUERYTYPE_OCCLUSION`: Completes when the GPU has processed the query, and always reports
+//!   [`EMULATED_OCCLUSION_PIXEL_COUNT`] visible pixels, i.e. everything is treated as visible.
+//! - `D3DQUERYTYPE_TIMESTAMP`: Completes when the GPU has processed the query, and reports the CPU
+//!   time at which the completion was first observed, in nanoseconds. Timings measured this way
+//!   include the polling delay and are only a rough estimate of GPU timings.
+//! - `D3DQUERYTYPE_TIMESTAMPDISJOINT`: Completes like a timestamp query, and never reports a disjoint.
+//! - `D3DQUERYTYPE_TIMESTAMPFREQ`: Completes like a timestamp query, and reports a frequency of 1 GHz,
+//!   matching the nanosecond timestamps.
+//!
+//! Completion is tracked with an event query created on the target device as a stand-in, if available.
+//! Other query types, whose data cannot be reasonably made up, are not emulated.
+
+use super::*;
+use std::{
+    any::type_name,
+    ffi::c_void,
+    sync::{Mutex, OnceLock},
+    time::Instant,
+};
+use windows::{Win32::Graphics::Direct3D9::*, core::*};
+
+/// Number of visible pixels reported by emulated occlusion queries.
+pub const EMULATED_OCCLUSION_PIXEL_COUNT: u32 = 1 << 24;
+
+/// Frequency of the timestamps reported by emulated timestamp queries, in ticks per second.
+const EMULATED_TIMESTAMP_FREQUENCY: u64 = 1_000_000_000;
+
+/// Returns `true` if queries of type `r#type` can be emulated by [`EmulatedDirect3DQuery9`].
+pub fn is_emulated_query_type(r#type: D3DQUERYTYPE) -> bool {
+    matches!(
+        r#type,
+        D3DQUERYTYPE_EVENT | D3DQUERYTYPE_OCCLUSION | D3DQUERYTYPE_TIMESTAMP | D3DQUERYTYPE_TIMESTAMPDISJOINT | D3DQUERYTYPE_TIMESTAMPFREQ
+    )
+}
+
+/// Returns the current time in nanoseconds since the first call, as the value of emulated timestamps.
+fn emulated_timestamp() -> u64 {
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_nanos() as u64
+}
+
+/// Query that emulates a query type not supported by the driver.
+///
+/// Unlike other proxies, this has no target object, so it is not registered in the mapping tracker.
+/// See the [module documentation](self) for the values reported per query type.
+#[implement(IDirect3DQuery9)]
+pub struct EmulatedDirect3DQuery9 {
+    r#type: D3DQUERYTYPE,
+    event: Option<IDirect3DQuery9>,
+    timestamp: Mutex<Option<u64>>,
+    proxy_device: IDirect3DDevice9,
+}
+
+impl EmulatedDirect3DQuery9 {
+    /// Creates an emulated query.
+    ///
+    /// # Arguments
+    /// * `r#type` - The query type to emulate, for which [`is_emulated_query_type`] must return `true`
+    /// * `event` - An event query on the target device to track completion with, if available
+    /// * `proxy_device` - The proxy device returned by `GetDevice`
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(level = "debug"))]
+    pub fn new(r#type: D3DQUERYTYPE, event: Option<IDirect3DQuery9>, proxy_device: IDirect3DDevice9) -> Self {
+        Self {
+            r#type,
+            event,
+            timestamp: Mutex::new(None),
+            proxy_device,
+        }
+    }
+}
+
+impl std::fmt::Debug for EmulatedDirect3DQuery9_Impl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {:p} (emulated {:?})", type_name::<Self>(), self.as_interface::<IUnknown>().as_raw(), self.r#type)
+    }
+}
+
+#[allow(non_snake_case, clippy::not_unsafe_ptr_arg_deref)]
+impl IDirect3DQuery9_Impl for EmulatedDirect3DQuery9_Impl {
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
+    fn GetDevice(&self) -> Result<IDirect3DDevice9> {
+        Ok(self.proxy_device.clone())
+    }
+
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(ret, level = "trace"))]
+    fn GetType(&self) -> D3DQUERYTYPE {
+        self.r#type
+    }
+
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(ret, level = "trace"))]
+    fn GetDataSize(&self) -> u32 {
+        match self.r#type {
+            D3DQUERYTYPE_TIMESTAMP | D3DQUERYTYPE_TIMESTAMPFREQ => size_of::<u64>() as u32,
+            _ => size_of::<u32>() as u32,
+        }
+    }
+
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
+    fn Issue(&self, dwissueflags: u32) -> Result<()> {
+        // Completion is only meaningful at the end of the issued range
+        if dwissueflags & D3DISSUE_END == 0 {
+            return Ok(());
+        }
+
+        *self.timestamp.lock().unwrap() = None;
+        if let Some(event) = &self.event {
+            unsafe { event.Issue(D3DISSUE_END) }?;
+        }
+        Ok(())
+    }
+
+    // S_FALSE is returned as an error while polling, so do not log errors at the error level
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err(level = "trace"), ret, level = "trace"))]
+    fn GetData(&self, pdata: *mut c_void, dwsize: u32, dwgetdataflags: u32) -> Result<()> {
+        if let Some(event) = &self.event
+            && self.r#type != D3DQUERYTYPE_EVENT
+        {
+            unsafe { get_query_data(event, std::ptr::null_mut(), 0, dwgetdataflags) }?;
+        }
+
+        if pdata.is_null() {
+            return Ok(());
+        }
+        if dwsize < self.GetDataSize() {
+            return Err(D3DERR_INVALIDCALL.into());
+        }
+
+        unsafe {
+            match self.r#type {
+                D3DQUERYTYPE_EVENT => *(pdata as *mut BOOL) = BOOL::from(true),
+                D3DQUERYTYPE_OCCLUSION => *(pdata as *mut u32) = EMULATED_OCCLUSION_PIXEL_COUNT,
+                D3DQUERYTYPE_TIMESTAMP => *(pdata as *mut u64) = *self.timestamp.lock().unwrap().get_or_insert_with(emulated_timestamp),
+                D3DQUERYTYPE_TIMESTAMPDISJOINT => *(pdata as *mut BOOL) = BOOL::from(false),
+                D3DQUERYTYPE_TIMESTAMPFREQ => *(pdata as *mut u64) = EMULATED_TIMESTAMP_FREQUENCY,
+                _ => return Err(D3DERR_INVALIDCALL.into()),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dx9::com::mock::*;
+    use windows::Win32::Foundation::{FALSE, S_FALSE, TRUE};
+
+    fn emulated_query(r#type: D3DQUERYTYPE, event: Option<&ComObject<MockQuery>>) -> IDirect3DQuery9 {
+        let device: IDirect3DDevice9Ex = MockDevice::default().into();
+        EmulatedDirect3DQuery9::new(r#type, event.map(|event| event.to_interface()), device.into()).into()
+    }
+
+    fn get_data<T: Default>(query: &IDirect3DQuery9) -> Result<T> {
+        let mut data = T::default();
+        unsafe { get_query_data(query, &mut data as *mut T as *mut c_void, size_of::<T>() as u32, D3DGETDATA_FLUSH) }.map(|_| data)
+    }
+
+    #[test]
+    fn event_completes_immediately_without_stand_in() {
+        let query = emulated_query(D3DQUERYTYPE_EVENT, None);
+        unsafe { query.Issue(D3DISSUE_END) }.unwrap();
+        assert_eq!(unsafe { query.GetDataSize() }, size_of::<BOOL>() as u32);
+        assert_eq!(get_data::<BOOL>(&query).unwrap(), TRUE);
+    }
+
+    #[test]
+    fn occlusion_waits_for_stand_in_event() {
+        let event = ComObject::new(MockQuery::new(CallLog::default(), [S_FALSE, S_FALSE]));
+        let query = emulated_query(D3DQUERYTYPE_OCCLUSION, Some(&event));
+
+        unsafe { query.Issue(D3DISSUE_BEGIN) }.unwrap();
+        assert_eq!(event.log.count("Issue"), 0);
+        unsafe { query.Issue(D3DISSUE_END) }.unwrap();
+        assert_eq!(event.log.count("Issue"), 1);
+
+        assert_eq!(get_data::<u32>(&query).unwrap_err().code(), S_FALSE);
+        assert_eq!(get_data::<u32>(&query).unwrap_err().code(), S_FALSE);
+        assert_eq!(get_data::<u32>(&query).unwrap(), EMULATED_OCCLUSION_PIXEL_COUNT);
+        assert_eq!(event.log.count("GetData"), 3);
+    }
+
+    #[test]
+    fn stand_in_errors_are_returned() {
+        let event = ComObject::new(MockQuery::new(CallLog::default(), [D3DERR_DEVICELOST]));
+        let query = emulated_query(D3DQUERYTYPE_OCCLUSION, Some(&event));
+        unsafe { query.Issue(D3DISSUE_END) }.unwrap();
+        assert_eq!(get_data::<u32>(&query).unwrap_err().code(), D3DERR_DEVICELOST);
+    }
+
+    #[test]
+    fn timestamp_is_fixed_until_reissued() {
+        let query = emulated_query(D3DQUERYTYPE_TIMESTAMP, None);
+        unsafe { query.Issue(D3DISSUE_END) }.unwrap();
+        let timestamp = get_data::<u64>(&query).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        assert_eq!(get_data::<u64>(&query).unwrap(), timestamp);
+
+        unsafe { query.Issue(D3DISSUE_END) }.unwrap();
+        assert!(get_data::<u64>(&query).unwrap() > timestamp);
+    }
+
+    #[test]
+    fn timestamp_frequency_and_disjoint() {
+        let frequency = emulated_query(D3DQUERYTYPE_TIMESTAMPFREQ, None);
+        assert_eq!(get_data::<u64>(&frequency).unwrap(), EMULATED_TIMESTAMP_FREQUENCY);
+
+        let disjoint = emulated_query(D3DQUERYTYPE_TIMESTAMPDISJOINT, None);
+        assert_eq!(get_data::<BOOL>(&disjoint).unwrap(), FALSE);
+    }
+
+    #[test]
+    fn get_data_rejects_small_buffers() {
+        let query = emulated_query(D3DQUERYTYPE_TIMESTAMP, None);
+        assert_eq!(get_data::<u32>(&query).unwrap_err().code(), D3DERR_INVALIDCALL);
+    }
+}
@@ -0,0 +1,134 @@
+//! Table of render states forced to fixed values, which hotkeys can change at runtime.
+//!
+//! `SetRenderState` calls for forced states are overridden with the forced values, and the forced
+//! values are re-applied at each `BeginScene` and after state blocks are applied. The values the
+//! application requested are remembered, so that they can be restored when a forcing is lifted.
+//!
+//! See [`DX9ProxyConfig::forced_render_states`](crate::dx9::DX9ProxyConfig::forced_render_states).
+
+use windows::Win32::Graphics::Direct3D9::*;
+
+/// `D3DCOLORWRITEENABLE_RED` flag of `D3DRS_COLORWRITEENABLE`, which is missing from the `windows` crate.
+pub const D3DCOLORWRITEENABLE_RED: u32 = 1;
+
+/// Render states currently forced on a device.
+#[derive(Debug, Default)]
+pub struct DX9ForcedRenderStates {
+    /// Forced states and the values they are forced to.
+    forced: Vec<(D3DRENDERSTATETYPE, u32)>,
+    /// Last values requested by the application for forced states.
+    requested: Vec<(D3DRENDERSTATETYPE, u32)>,
+    /// Requested values of states whose forcing was lifted, to be set at the next re-application.
+    pending_restores: Vec<(D3DRENDERSTATETYPE, u32)>,
+}
+
+impl DX9ForcedRenderStates {
+    /// Creates a table with the specified states forced.
+    pub fn new(forced: Vec<(D3DRENDERSTATETYPE, u32)>) -> Self {
+        Self { forced, ..Default::default() }
+    }
+
+    /// Returns the forced states and the values they are forced to.
+    pub fn forced(&self) -> &[(D3DRENDERSTATETYPE, u32)] {
+        &self.forced
+    }
+
+    /// Returns the value forced for `state`, if any.
+    pub fn get(&self, state: D3DRENDERSTATETYPE) -> Option<u32> {
+        self.forced.iter().find(|(forced_state, _)| *forced_state == state).map(|&(_, value)| value)
+    }
+
+    /// Returns the value to set when the application sets `state` to `value`.
+    ///
+    /// This is the forced value if `state` is forced, in which case `value` is remembered.
+    pub fn on_set(&mut self, state: D3DRENDERSTATETYPE, value: u32) -> u32 {
+        let Some(forced_value) = self.get(state) else {
+            return value;
+        };
+        self.requested.retain(|(requested_state, _)| *requested_state != state);
+        self.requested.push((state, value));
+        forced_value
+    }
+
+    /// Forces `state` to `value`, or lifts its forcing if `None`.
+    ///
+    /// When a forcing is lifted, the value last requested by the application is restored at the next
+    /// re-application. If the application has not set the state since it was forced, the forced value stays
+    /// in effect until the application sets it.
+    pub fn set(&mut self, state: D3DRENDERSTATETYPE, value: Option<u32>) {
+        self.forced.retain(|(forced_state, _)| *forced_state != state);
+        self.pending_restores.retain(|(restore_state, _)| *restore_state != state);
+        match value {
+            Some(value) => self.forced.push((state, value)),
+            None => {
+                if let Some(index) = self.requested.iter().position(|(requested_state, _)| *requested_state == state) {
+                    self.pending_restores.push(self.requested.swap_remove(index));
+                }
+            }
+        }
+    }
+
+    /// Returns the states to set on the device to re-apply the table: pending restores followed by the forced states.
+    pub fn take_states_to_apply(&mut self) -> Vec<(D3DRENDERSTATETYPE, u32)> {
+        let mut states = std::mem::take(&mut self.pending_restores);
+        states.extend_from_slice(&self.forced);
+        states
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forced_states_override_requested_values() {
+        let mut states = DX9ForcedRenderStates::new(vec![(D3DRS_ZENABLE, 0)]);
+        assert_eq!(states.on_set(D3DRS_ZENABLE, 1), 0);
+        assert_eq!(states.on_set(D3DRS_ZWRITEENABLE, 1), 1);
+        assert_eq!(states.take_states_to_apply(), [(D3DRS_ZENABLE, 0)]);
+
+        states.set(D3DRS_ZWRITEENABLE, Some(0));
+        assert_eq!(states.get(D3DRS_ZWRITEENABLE), Some(0));
+        assert_eq!(states.on_set(D3DRS_ZWRITEENABLE, 1), 0);
+        assert_eq!(states.take_states_to_apply(), [(D3DRS_ZENABLE, 0), (D3DRS_ZWRITEENABLE, 0)]);
+    }
+
+    #[test]
+    fn lifting_restores_last_requested_value_once() {
+        let mut states = DX9ForcedRenderStates::new(vec![(D3DRS_COLORWRITEENABLE, D3DCOLORWRITEENABLE_RED)]);
+        states.on_set(D3DRS_COLORWRITEENABLE, 0x3);
+        states.on_set(D3DRS_COLORWRITEENABLE, 0xF);
+
+        states.set(D3DRS_COLORWRITEENABLE, None);
+        assert_eq!(states.get(D3DRS_COLORWRITEENABLE), None);
+        assert_eq!(states.on_set(D3DRS_ZENABLE, 1), 1);
+        assert_eq!(states.take_states_to_apply(), [(D3DRS_COLORWRITEENABLE, 0xF)]);
+        assert_eq!(states.take_states_to_apply(), []);
+
+        // The application's values pass through after the lift
+        assert_eq!(states.on_set(D3DRS_COLORWRITEENABLE, 0x1), 0x1);
+        assert_eq!(states.take_states_to_apply(), []);
+    }
+
+    #[test]
+    fn lifting_without_requested_value_restores_nothing() {
+        let mut states = DX9ForcedRenderStates::new(vec![(D3DRS_ZWRITEENABLE, 1)]);
+        states.set(D3DRS_ZWRITEENABLE, None);
+        assert!(states.forced().is_empty());
+        assert_eq!(states.take_states_to_apply(), []);
+    }
+
+    #[test]
+    fn reforcing_cancels_pending_restore() {
+        let mut states = DX9ForcedRenderStates::new(vec![(D3DRS_ZENABLE, 0)]);
+        states.on_set(D3DRS_ZENABLE, 1);
+        states.set(D3DRS_ZENABLE, None);
+        states.set(D3DRS_ZENABLE, Some(0));
+        assert_eq!(states.take_states_to_apply(), [(D3DRS_ZENABLE, 0)]);
+
+        // The requested value is remembered anew while forced
+        states.on_set(D3DRS_ZENABLE, 2);
+        states.set(D3DRS_ZENABLE, None);
+        assert_eq!(states.take_states_to_apply(), [(D3DRS_ZENABLE, 2)]);
+    }
+}
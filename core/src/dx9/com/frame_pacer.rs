@@ -0,0 +1,143 @@
+//! Frame pacing that smooths out the variance of frame intervals.
+//!
+//! Some games stutter even though their average frame rate is fine, because their frame times
+//! jitter. [`DX9FramePacer`] keeps the natural intervals of recent frames in a ring buffer and
+//! delays frames that finished faster than the median interval, so that frames are presented at
+//! a more consistent rate. Frames that are slower than the median cannot be sped up.
+//!
+//! This is a heuristic: it can lower the average frame rate slightly, and delaying `Present` can add up
+//! to a frame of latency. See [`DX9ProxyConfig::frame_pacing`](crate::dx9::DX9ProxyConfig::frame_pacing).
+
+use std::{
+    collections::VecDeque,
+    thread::{sleep, yield_now},
+    time::{Duration, Instant},
+};
+
+/// Remaining time below which the pacer spins rather than sleeps, as sleeps are not precise enough.
+const SPIN_THRESHOLD: Duration = Duration::from_millis(2);
+
+/// Delays `Present` calls towards the median of the recent frame intervals.
+#[derive(Debug)]
+pub struct DX9FramePacer {
+    /// Natural intervals of recent frames, measured from the previous paced `Present` to the next one, oldest first.
+    intervals: VecDeque<Duration>,
+    window: usize,
+    max_sleep: Duration,
+    last_present: Option<Instant>,
+}
+
+impl DX9FramePacer {
+    /// Creates a pacer that targets the median of the last `window` intervals, and never delays a frame by more than `max_sleep`.
+    pub fn new(window: usize, max_sleep: Duration) -> Self {
+        let window = window.max(1);
+        Self {
+            intervals: VecDeque::with_capacity(window),
+            window,
+            max_sleep,
+            last_present: None,
+        }
+    }
+
+    /// Records the interval of the current frame and waits until the target interval has elapsed, to be called right before `Present`.
+    pub fn pace(&mut self) {
+        let now = Instant::now();
+        if let Some(last_present) = self.last_present {
+            let delay = self.record_interval(now - last_present);
+            if !delay.is_zero() {
+                wait_until(now + delay);
+            }
+        }
+        self.last_present = Some(Instant::now());
+    }
+
+    /// Records the natural interval of a frame, and returns how long to delay it towards the median interval.
+    ///
+    /// # Returns
+    /// The delay, which is at most `max_sleep`, and zero if the frame was not faster than the median.
+    fn record_interval(&mut self, interval: Duration) -> Duration {
+        if self.intervals.len() == self.window {
+            self.intervals.pop_front();
+        }
+        self.intervals.push_back(interval);
+
+        self.median_interval().saturating_sub(interval).min(self.max_sleep)
+    }
+
+    /// Forgets the recorded intervals, e.g. after a device reset, where the frame rate may change.
+    pub fn clear(&mut self) {
+        self.intervals.clear();
+        self.last_present = None;
+    }
+
+    /// Returns the median of the recorded intervals.
+    fn median_interval(&self) -> Duration {
+        let mut intervals = self.intervals.iter().copied().collect::<Vec<_>>();
+        intervals.sort_unstable();
+        intervals.get(intervals.len() / 2).copied().unwrap_or_default()
+    }
+}
+
+/// Waits until `deadline`, sleeping for most of the time and spinning for the remainder.
+fn wait_until(deadline: Instant) {
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return;
+        }
+        if remaining > SPIN_THRESHOLD {
+            sleep(remaining - SPIN_THRESHOLD);
+        } else {
+            yield_now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_SLEEP: Duration = Duration::from_millis(100);
+
+    fn record(pacer: &mut DX9FramePacer, intervals_ms: &[u64]) -> Vec<Duration> {
+        intervals_ms.iter().map(|&ms| pacer.record_interval(Duration::from_millis(ms))).collect()
+    }
+
+    #[test]
+    fn delays_faster_frames_to_median() {
+        let mut pacer = DX9FramePacer::new(5, MAX_SLEEP);
+        record(&mut pacer, &[10, 10, 10]);
+        assert_eq!(record(&mut pacer, &[4]), [Duration::from_millis(6)]);
+    }
+
+    #[test]
+    fn does_not_delay_slower_frames() {
+        let mut pacer = DX9FramePacer::new(5, MAX_SLEEP);
+        assert_eq!(record(&mut pacer, &[10, 10, 30]), [Duration::ZERO; 3]);
+    }
+
+    #[test]
+    fn caps_delay_at_max_sleep() {
+        let mut pacer = DX9FramePacer::new(5, Duration::from_millis(8));
+        record(&mut pacer, &[20, 20]);
+        assert_eq!(record(&mut pacer, &[1]), [Duration::from_millis(8)]);
+    }
+
+    #[test]
+    fn evicts_oldest_intervals_beyond_window() {
+        let mut pacer = DX9FramePacer::new(3, MAX_SLEEP);
+        record(&mut pacer, &[10, 10, 10, 20, 20]);
+        // The median of [20, 20, 5]; it would be 10 if the first intervals were kept
+        assert_eq!(record(&mut pacer, &[5]), [Duration::from_millis(15)]);
+        assert_eq!(pacer.intervals.len(), 3);
+    }
+
+    #[test]
+    fn window_of_zero_keeps_one_interval() {
+        let mut pacer = DX9FramePacer::new(0, MAX_SLEEP);
+        assert_eq!(pacer.window, 1);
+        // Only the frame itself is recorded, so it is never delayed
+        assert_eq!(record(&mut pacer, &[10, 5]), [Duration::ZERO; 2]);
+        assert_eq!(pacer.intervals, [Duration::from_millis(5)]);
+    }
+}
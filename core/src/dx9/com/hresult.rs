@@ -0,0 +1,203 @@
+//! Direct3D 9 HRESULT codes and their symbolic names.
+
+use windows::Win32::Foundation::*;
+use windows_core::HRESULT;
+
+/// Creates a Direct3D-specific HRESULT from a given error code.
+#[allow(non_snake_case)]
+const fn MAKE_D3DHRESULT(code: u32) -> HRESULT {
+    // MAKE_HRESULT(1, _FACD3D, code) where _FACD3D is 0x876
+    // -> (1 << 31) | (0x876 << 16) | code
+    HRESULT((0x88760000 | code) as i32)
+}
+
+/// Creates a Direct3D-specific success HRESULT from a given status code.
+#[allow(non_snake_case)]
+const fn MAKE_D3DSTATUS(code: u32) -> HRESULT {
+    // MAKE_HRESULT(0, _FACD3D, code) where _FACD3D is 0x876
+    // -> (0x876 << 16) | code
+    HRESULT((0x08760000 | code) as i32)
+}
+
+/// Standard success result for Direct3D operations.
+pub const D3D_OK: HRESULT = S_OK;
+
+/// Device lost error - occurs when the Direct3D device becomes unavailable.
+pub const D3DERR_DEVICELOST: HRESULT = MAKE_D3DHRESULT(2152);
+
+/// Invalid call error - indicates improper API usage or invalid parameters.
+pub const D3DERR_INVALIDCALL: HRESULT = MAKE_D3DHRESULT(2156);
+
+/// Device not reset error - the device is lost but can be reset.
+pub const D3DERR_DEVICENOTRESET: HRESULT = MAKE_D3DHRESULT(2153);
+
+/// Not available error - the queried technique or format is not supported.
+pub const D3DERR_NOTAVAILABLE: HRESULT = MAKE_D3DHRESULT(2154);
+
+/// Out of video memory error - not enough display memory to perform the operation.
+pub const D3DERR_OUTOFVIDEOMEMORY: HRESULT = MAKE_D3DHRESULT(380);
+
+/// Still drawing error - the previous operation using the resource has not completed.
+pub const D3DERR_WASSTILLDRAWING: HRESULT = MAKE_D3DHRESULT(540);
+
+/// Device removed error - the hardware adapter has been removed (`IDirect3DDevice9Ex` only).
+pub const D3DERR_DEVICEREMOVED: HRESULT = MAKE_D3DHRESULT(2160);
+
+/// Device hung error - the device stopped responding (`IDirect3DDevice9Ex` only).
+pub const D3DERR_DEVICEHUNG: HRESULT = MAKE_D3DHRESULT(2164);
+
+/// Autogeneration of mipmaps is not supported for the format, but the format is otherwise valid.
+pub const D3DOK_NOAUTOGEN: HRESULT = MAKE_D3DSTATUS(2159);
+
+/// The presentation area is occluded (`IDirect3DDevice9Ex` only).
+pub const S_PRESENT_OCCLUDED: HRESULT = MAKE_D3DSTATUS(2168);
+
+/// Returns the symbolic name of a Direct3D 9 or common COM HRESULT.
+///
+/// This is meant for logging, so that failures read like `D3DERR_INVALIDCALL`
+/// instead of a raw `0x8876086C`.
+///
+/// # Arguments
+/// * `hr` - The HRESULT to look up
+///
+/// # Returns
+/// The name of the HRESULT constant, or `"UNKNOWN"` if the code is not recognized.
+pub fn hresult_name(hr: HRESULT) -> &'static str {
+    match hr {
+        S_OK => "S_OK",
+        S_FALSE => "S_FALSE",
+        D3DOK_NOAUTOGEN => "D3DOK_NOAUTOGEN",
+        S_PRESENT_OCCLUDED => "S_PRESENT_OCCLUDED",
+        _ if hr == MAKE_D3DSTATUS(2165) => "S_NOT_RESIDENT",
+        _ if hr == MAKE_D3DSTATUS(2166) => "S_RESIDENT_IN_SHARED_MEMORY",
+        _ if hr == MAKE_D3DSTATUS(2167) => "S_PRESENT_MODE_CHANGED",
+
+        D3DERR_DEVICELOST => "D3DERR_DEVICELOST",
+        D3DERR_DEVICENOTRESET => "D3DERR_DEVICENOTRESET",
+        D3DERR_NOTAVAILABLE => "D3DERR_NOTAVAILABLE",
+        D3DERR_INVALIDCALL => "D3DERR_INVALIDCALL",
+        D3DERR_OUTOFVIDEOMEMORY => "D3DERR_OUTOFVIDEOMEMORY",
+        D3DERR_WASSTILLDRAWING => "D3DERR_WASSTILLDRAWING",
+        D3DERR_DEVICEREMOVED => "D3DERR_DEVICEREMOVED",
+        D3DERR_DEVICEHUNG => "D3DERR_DEVICEHUNG",
+        _ if hr == MAKE_D3DHRESULT(2072) => "D3DERR_WRONGTEXTUREFORMAT",
+        _ if hr == MAKE_D3DHRESULT(2073) => "D3DERR_UNSUPPORTEDCOLOROPERATION",
+        _ if hr == MAKE_D3DHRESULT(2074) => "D3DERR_UNSUPPORTEDCOLORARG",
+        _ if hr == MAKE_D3DHRESULT(2075) => "D3DERR_UNSUPPORTEDALPHAOPERATION",
+        _ if hr == MAKE_D3DHRESULT(2076) => "D3DERR_UNSUPPORTEDALPHAARG",
+        _ if hr == MAKE_D3DHRESULT(2077) => "D3DERR_TOOMANYOPERATIONS",
+        _ if hr == MAKE_D3DHRESULT(2078) => "D3DERR_CONFLICTINGTEXTUREFILTER",
+        _ if hr == MAKE_D3DHRESULT(2079) => "D3DERR_UNSUPPORTEDFACTORVALUE",
+        _ if hr == MAKE_D3DHRESULT(2081) => "D3DERR_CONFLICTINGRENDERSTATE",
+        _ if hr == MAKE_D3DHRESULT(2082) => "D3DERR_UNSUPPORTEDTEXTUREFILTER",
+        _ if hr == MAKE_D3DHRESULT(2086) => "D3DERR_CONFLICTINGTEXTUREPALETTE",
+        _ if hr == MAKE_D3DHRESULT(2087) => "D3DERR_DRIVERINTERNALERROR",
+        _ if hr == MAKE_D3DHRESULT(2150) => "D3DERR_NOTFOUND",
+        _ if hr == MAKE_D3DHRESULT(2151) => "D3DERR_MOREDATA",
+        _ if hr == MAKE_D3DHRESULT(2155) => "D3DERR_INVALIDDEVICE",
+        _ if hr == MAKE_D3DHRESULT(2157) => "D3DERR_DRIVERINVALIDCALL",
+        _ if hr == MAKE_D3DHRESULT(2171) => "D3DERR_UNSUPPORTEDOVERLAY",
+        _ if hr == MAKE_D3DHRESULT(2172) => "D3DERR_UNSUPPORTEDOVERLAYFORMAT",
+        _ if hr == MAKE_D3DHRESULT(2173) => "D3DERR_CANNOTPROTECTCONTENT",
+        _ if hr == MAKE_D3DHRESULT(2174) => "D3DERR_UNSUPPORTEDCRYPTO",
+        _ if hr == MAKE_D3DHRESULT(2180) => "D3DERR_PRESENT_STATISTICS_DISJOINT",
+
+        E_FAIL => "E_FAIL",
+        E_INVALIDARG => "E_INVALIDARG",
+        E_NOINTERFACE => "E_NOINTERFACE",
+        E_NOTIMPL => "E_NOTIMPL",
+        E_OUTOFMEMORY => "E_OUTOFMEMORY",
+        E_POINTER => "E_POINTER",
+        E_UNEXPECTED => "E_UNEXPECTED",
+        E_ACCESSDENIED => "E_ACCESSDENIED",
+        E_ABORT => "E_ABORT",
+        E_HANDLE => "E_HANDLE",
+        _ => "UNKNOWN",
+    }
+}
+
+/// Parses an HRESULT given as a symbolic name (e.g. `D3DERR_INVALIDCALL`), in `0x`-prefixed hexadecimal or in decimal.
+///
+/// Symbolic names are those of the constants in this module, `D3D_OK`, and the common COM codes known by [`hresult_name`].
+pub fn parse_hresult(text: &str) -> Option<HRESULT> {
+    const NAMED: &[HRESULT] = &[
+        S_OK,
+        S_FALSE,
+        D3DOK_NOAUTOGEN,
+        S_PRESENT_OCCLUDED,
+        D3DERR_DEVICELOST,
+        D3DERR_DEVICENOTRESET,
+        D3DERR_NOTAVAILABLE,
+        D3DERR_INVALIDCALL,
+        D3DERR_OUTOFVIDEOMEMORY,
+        D3DERR_WASSTILLDRAWING,
+        D3DERR_DEVICEREMOVED,
+        D3DERR_DEVICEHUNG,
+        E_FAIL,
+        E_INVALIDARG,
+        E_NOINTERFACE,
+        E_NOTIMPL,
+        E_OUTOFMEMORY,
+        E_POINTER,
+        E_UNEXPECTED,
+        E_ACCESSDENIED,
+        E_ABORT,
+        E_HANDLE,
+    ];
+
+    let text = text.trim();
+    if text == "D3D_OK" {
+        return Some(D3D_OK);
+    }
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        return u32::from_str_radix(hex, 16).ok().map(|code| HRESULT(code as i32));
+    }
+    if let Ok(code) = text.parse() {
+        return Some(HRESULT(code));
+    }
+    NAMED.iter().copied().find(|&hr| hresult_name(hr) == text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hresult_name_knows_d3d_and_com_codes() {
+        assert_eq!(hresult_name(D3D_OK), "S_OK");
+        assert_eq!(hresult_name(D3DERR_INVALIDCALL), "D3DERR_INVALIDCALL");
+        assert_eq!(hresult_name(HRESULT(0x8876086Cu32 as i32)), "D3DERR_INVALIDCALL");
+        assert_eq!(hresult_name(D3DERR_DEVICELOST), "D3DERR_DEVICELOST");
+        assert_eq!(hresult_name(HRESULT(0x88760818u32 as i32)), "D3DERR_WRONGTEXTUREFORMAT");
+        assert_eq!(hresult_name(S_PRESENT_OCCLUDED), "S_PRESENT_OCCLUDED");
+        assert_eq!(hresult_name(E_OUTOFMEMORY), "E_OUTOFMEMORY");
+        assert_eq!(hresult_name(HRESULT(0x12345678)), "UNKNOWN");
+    }
+
+    #[test]
+    fn parse_hresult_accepts_names_and_numbers() {
+        assert_eq!(parse_hresult("D3D_OK"), Some(D3D_OK));
+        assert_eq!(parse_hresult(" D3DERR_DEVICELOST "), Some(D3DERR_DEVICELOST));
+        assert_eq!(parse_hresult("E_NOTIMPL"), Some(E_NOTIMPL));
+        assert_eq!(parse_hresult("0x8876086C"), Some(D3DERR_INVALIDCALL));
+        assert_eq!(parse_hresult("0X8876086c"), Some(D3DERR_INVALIDCALL));
+        assert_eq!(parse_hresult("-2005530516"), Some(D3DERR_INVALIDCALL));
+        assert_eq!(parse_hresult("1"), Some(S_FALSE));
+    }
+
+    #[test]
+    fn parse_hresult_rejects_unknown_text() {
+        assert_eq!(parse_hresult(""), None);
+        assert_eq!(parse_hresult("UNKNOWN"), None);
+        assert_eq!(parse_hresult("D3DERR_NOSUCHERROR"), None);
+        assert_eq!(parse_hresult("0x1_0000_0000"), None);
+        assert_eq!(parse_hresult("0x100000000"), None);
+    }
+
+    #[test]
+    fn parse_hresult_round_trips_hresult_name() {
+        for hr in [S_FALSE, D3DOK_NOAUTOGEN, D3DERR_NOTAVAILABLE, D3DERR_WASSTILLDRAWING, D3DERR_DEVICEHUNG, E_POINTER, E_HANDLE] {
+            assert_eq!(parse_hresult(hresult_name(hr)), Some(hr));
+        }
+    }
+}
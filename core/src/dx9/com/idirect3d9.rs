@@ -23,12 +23,19 @@ use windows::{
 #[derive(Debug)]
 pub struct ProxyDirect3D9 {
     target: IDirect3D9,
+    display_mode_override: Option<DX9DisplayModeOverride>,
+    multisample_overrides: Vec<DX9MultiSampleOverride>,
 }
 
 impl ProxyDirect3D9 {
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(ret))]
     pub fn new(target: IDirect3D9) -> Self {
-        Self { target }
+        let config = DX9ProxyConfig::from_env();
+        Self {
+            target,
+            display_mode_override: DX9DisplayModeOverride::from_config(&config),
+            multisample_overrides: config.multisample_overrides,
+        }
     }
 
     /// Creates a new proxy container or upgrades to an Ex version if available.
@@ -60,6 +67,13 @@ impl ProxyDirect3D9 {
     }
 }
 
+impl ProxyDirect3D9 {
+    /// Returns the display mode override, if any display modes are added or hidden by the configuration.
+    pub(super) fn get_display_mode_override(&self) -> Option<&DX9DisplayModeOverride> {
+        self.display_mode_override.as_ref()
+    }
+}
+
 impl Drop for ProxyDirect3D9 {
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(ret))]
     fn drop(&mut self) {}
@@ -86,17 +100,37 @@ impl ProxyDirect3D9_Impl {
         ppresentationparameters: *mut D3DPRESENT_PARAMETERS,
         ppreturneddeviceinterface: OutRef<IDirect3DDevice9>,
     ) -> Result<()> {
-        check_nullptr!(ppreturneddeviceinterface);
+        with_required_out("CreateDevice", ppreturneddeviceinterface, |ppreturneddeviceinterface| {
+            check_nullptr!(ppreturneddeviceinterface);
+
+            let config = DX9ProxyConfig::from_env();
+            let adapter = apply_fullscreen_monitor_override(&config, &self.target, adapter);
+            let hfocuswindow = apply_focus_window_override(&config, hfocuswindow);
+            let behaviorflags = apply_behavior_flag_overrides(&config, behaviorflags);
+            let count = present::params_count(behaviorflags, |caps| unsafe { self.target.GetDeviceCaps(adapter, devicetype, caps) });
+            let mut params = unsafe {
+                present::with_overridden_params(ppresentationparameters, count, |params| {
+                    apply_device_window_override(&config, params);
+                    apply_back_buffer_format_override(&config, &self.target, adapter, devicetype, params);
+                })
+            };
 
-        let device = try_out_param(|out| unsafe { self.target.CreateDevice(adapter, devicetype, hfocuswindow, behaviorflags, ppresentationparameters, out) })?;
+            let device = try_out_param(|out| unsafe {
+                self.target
+                    .CreateDevice(adapter, devicetype, hfocuswindow, behaviorflags, present::params_ptr(&mut params, ppresentationparameters), out)
+            })?;
+            if let Some(params) = &params {
+                unsafe { present::write_back_params(ppresentationparameters, params) };
+            }
 
-        let config = DX9ProxyConfig;
+            apply_max_frame_latency(&device, &config);
 
-        #[cfg(feature = "tracing")]
-        tracing::debug!("Creating ProxyDirect3DDevice9 for {device:?} with config: {config:?}");
+            #[cfg(feature = "tracing")]
+            tracing::debug!("Creating ProxyDirect3DDevice9 for {device:?} with config: {config:?}");
 
-        let proxy = ProxyDirect3DDevice9::new_or_upgrade(device, config, get_self_interface());
-        ppreturneddeviceinterface.write(Some(proxy))
+            let proxy = ProxyDirect3DDevice9::new_or_upgrade(device, config, get_self_interface(), params.map(|params| params[0]));
+            ppreturneddeviceinterface.write(Some(proxy))
+        })
     }
 }
 
@@ -124,11 +158,30 @@ impl IDirect3D9_Impl for ProxyDirect3D9_Impl {
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(ret, level = "debug"))]
     fn GetAdapterModeCount(&self, adapter: u32, format: D3DFORMAT) -> u32 {
+        if let Some(display_mode_override) = &self.display_mode_override {
+            return display_mode_override.get_modes(adapter, format, None, || enumerate_adapter_modes(&self.target, adapter, format)).len() as u32;
+        }
+
         unsafe { self.target.GetAdapterModeCount(adapter, format) }
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "debug"))]
     fn EnumAdapterModes(&self, adapter: u32, format: D3DFORMAT, mode: u32, pmode: *mut D3DDISPLAYMODE) -> Result<()> {
+        if let Some(display_mode_override) = &self.display_mode_override {
+            check_nullptr!(pmode);
+            let modes = display_mode_override.get_modes(adapter, format, None, || enumerate_adapter_modes(&self.target, adapter, format));
+            let found = modes.get(mode as usize).ok_or(D3DERR_INVALIDCALL)?;
+            unsafe {
+                *pmode = D3DDISPLAYMODE {
+                    Width: found.Width,
+                    Height: found.Height,
+                    RefreshRate: found.RefreshRate,
+                    Format: found.Format,
+                }
+            };
+            return Ok(());
+        }
+
         unsafe { self.target.EnumAdapterModes(adapter, format, mode, pmode) }
     }
 
@@ -149,6 +202,17 @@ impl IDirect3D9_Impl for ProxyDirect3D9_Impl {
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "debug"))]
     fn CheckDeviceMultiSampleType(&self, adapter: u32, devicetype: D3DDEVTYPE, surfaceformat: D3DFORMAT, windowed: BOOL, multisampletype: D3DMULTISAMPLE_TYPE, pqualitylevels: *mut u32) -> Result<()> {
+        if let Some(multisample_override) = self.multisample_overrides.iter().find(|entry| entry.matches(surfaceformat, multisampletype)) {
+            #[cfg(feature = "tracing")]
+            tracing::info!("Overriding CheckDeviceMultiSampleType for format {surfaceformat:?} and type {multisampletype:?}: {multisample_override:?}");
+
+            let quality_levels = multisample_override.quality_levels.ok_or(D3DERR_NOTAVAILABLE)?;
+            if !pqualitylevels.is_null() {
+                unsafe { *pqualitylevels = quality_levels };
+            }
+            return Ok(());
+        }
+
         unsafe {
             self.target
                 .CheckDeviceMultiSampleType(adapter, devicetype, surfaceformat, windowed.into(), multisampletype, pqualitylevels)
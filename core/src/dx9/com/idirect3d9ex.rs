@@ -21,6 +21,11 @@ use windows::{
 /// and display mode operations, forwarding them to the underlying target interface.
 ///
 /// Methods of [`IDirect3D9`] are delegated to the inner [`IDirect3D9`] proxy, which is implemented by [`ProxyDirect3D9`].
+///
+/// Querying this object for [`IDirect3D9`] returns this object itself, never the inner proxy, since
+/// [`IDirect3D9Ex`] inherits from it. Applications that call `Direct3DCreate9Ex` but only use the
+/// base interface therefore keep a consistent identity, and devices they create through it report
+/// this object as their container.
 #[implement(IDirect3D9Ex)]
 #[derive(Debug)]
 pub struct ProxyDirect3D9Ex {
@@ -58,24 +63,58 @@ impl IDirect3D9Ex_Impl for ProxyDirect3D9Ex_Impl {
         pfullscreendisplaymode: *mut D3DDISPLAYMODEEX,
         ppreturneddeviceinterface: OutRef<IDirect3DDevice9Ex>,
     ) -> Result<()> {
-        check_nullptr!(ppreturneddeviceinterface);
+        with_required_out("CreateDeviceEx", ppreturneddeviceinterface, |ppreturneddeviceinterface| {
+            check_nullptr!(ppreturneddeviceinterface);
+
+            let config = DX9ProxyConfig::from_env();
+            let adapter = apply_fullscreen_monitor_override(&config, &self.target, adapter);
+            let hfocuswindow = apply_focus_window_override(&config, hfocuswindow);
+            let behaviorflags = apply_behavior_flag_overrides(&config, behaviorflags);
+            let count = present::params_count(behaviorflags, |caps| unsafe { self.target.GetDeviceCaps(adapter, devicetype, caps) });
+            let mut params = unsafe {
+                present::with_overridden_params(ppresentationparameters, count, |params| {
+                    apply_device_window_override(&config, params);
+                    apply_back_buffer_format_override(&config, &self.target, adapter, devicetype, params);
+                })
+            };
 
-        let device = try_out_param(|out| unsafe {
-            self.target
-                .CreateDeviceEx(adapter, devicetype, hfocuswindow, behaviorflags, ppresentationparameters, pfullscreendisplaymode, out)
-        })?;
+            let device = try_out_param(|out| unsafe {
+                self.target.CreateDeviceEx(
+                    adapter,
+                    devicetype,
+                    hfocuswindow,
+                    behaviorflags,
+                    present::params_ptr(&mut params, ppresentationparameters),
+                    pfullscreendisplaymode,
+                    out,
+                )
+            })?;
+            if let Some(params) = &params {
+                unsafe { present::write_back_params(ppresentationparameters, params) };
+            }
 
-        let config = DX9ProxyConfig;
+            apply_max_frame_latency(&device.clone().into(), &config);
 
-        #[cfg(feature = "tracing")]
-        tracing::debug!("Creating ProxyDirect3DDevice9Ex for {device:?} with config: {config:?}");
+            #[cfg(feature = "tracing")]
+            tracing::debug!("Creating ProxyDirect3DDevice9Ex for {device:?} with config: {config:?}");
 
-        let proxy = ProxyDirect3DDevice9Ex::new(device, config, self.to_interface());
-        ppreturneddeviceinterface.write(Some(proxy.into()))
+            let proxy: IDirect3DDevice9Ex = ProxyDirect3DDevice9Ex::new(device, config, self.to_interface(), params.map(|params| params[0])).into();
+            ppreturneddeviceinterface.write(Some(proxy))
+        })
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "debug"))]
     fn EnumAdapterModesEx(&self, adapter: u32, pfilter: *const D3DDISPLAYMODEFILTER, mode: u32, pmode: *mut D3DDISPLAYMODEEX) -> Result<()> {
+        if let Some(display_mode_override) = self.proxy.get_display_mode_override()
+            && let Some(filter) = unsafe { pfilter.as_ref() }
+        {
+            check_nullptr!(pmode);
+            let modes = display_mode_override.get_modes(adapter, filter.Format, Some(filter.ScanLineOrdering), || enumerate_adapter_modes_ex(&self.target, adapter, filter));
+            let found = modes.get(mode as usize).ok_or(D3DERR_INVALIDCALL)?;
+            unsafe { *pmode = *found };
+            return Ok(());
+        }
+
         unsafe { self.target.EnumAdapterModesEx(adapter, pfilter, mode, pmode) }
     }
 
@@ -91,6 +130,14 @@ impl IDirect3D9Ex_Impl for ProxyDirect3D9Ex_Impl {
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(ret, level = "debug"))]
     fn GetAdapterModeCountEx(&self, adapter: u32, pfilter: *const D3DDISPLAYMODEFILTER) -> u32 {
+        if let Some(display_mode_override) = self.proxy.get_display_mode_override()
+            && let Some(filter) = unsafe { pfilter.as_ref() }
+        {
+            return display_mode_override
+                .get_modes(adapter, filter.Format, Some(filter.ScanLineOrdering), || enumerate_adapter_modes_ex(&self.target, adapter, filter))
+                .len() as u32;
+        }
+
         unsafe { self.target.GetAdapterModeCountEx(adapter, pfilter) }
     }
 }
@@ -192,3 +239,32 @@ impl IDirect3D9_Impl for ProxyDirect3D9Ex_Impl {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dx9::com::mock::*;
+
+    #[test]
+    fn base_interface_creates_proxied_devices() {
+        let target = ComObject::new(MockDirect3D9::default());
+        let factory: IDirect3D9Ex = ProxyDirect3D9Ex::new(target.to_interface()).into();
+        let base: IDirect3D9 = factory.cast().unwrap();
+        assert_eq!(base.cast::<IUnknown>().unwrap(), factory.cast::<IUnknown>().unwrap());
+
+        let mut params = D3DPRESENT_PARAMETERS {
+            BackBufferWidth: 640,
+            BackBufferHeight: 480,
+            SwapEffect: D3DSWAPEFFECT_DISCARD,
+            Windowed: true.into(),
+            ..Default::default()
+        };
+        let device = try_out_param(|out| unsafe { base.CreateDevice(D3DADAPTER_DEFAULT, D3DDEVTYPE_HAL, HWND::default(), D3DCREATE_HARDWARE_VERTEXPROCESSING as u32, &mut params, out) }).unwrap();
+
+        assert_eq!(target.log.calls(), ["CreateDevice"]);
+        // The target device does not implement GetDirect3D, so the container must come from a proxy
+        let container = unsafe { device.GetDirect3D() }.unwrap();
+        assert_eq!(container.cast::<IUnknown>().unwrap(), factory.cast::<IUnknown>().unwrap());
+        assert!(device.cast::<IDirect3DDevice9Ex>().is_ok());
+    }
+}
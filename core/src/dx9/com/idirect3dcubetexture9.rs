@@ -10,18 +10,25 @@ pub struct ProxyDirect3DCubeTexture9 {
     target: IDirect3DCubeTexture9,
     context: DX9ProxyDeviceContext,
     proxy_device: IDirect3DDevice9,
+    lock_count: DX9LockCount,
 }
 
 impl ProxyDirect3DCubeTexture9 {
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(ret, level = "debug"))]
     pub fn new(target: IDirect3DCubeTexture9, context: DX9ProxyDeviceContext, proxy_device: IDirect3DDevice9) -> Self {
-        Self { target, context, proxy_device }
+        Self {
+            target,
+            context,
+            proxy_device,
+            lock_count: DX9LockCount::default(),
+        }
     }
 }
 
 impl Drop for ProxyDirect3DCubeTexture9 {
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(ret, level = "debug"))]
     fn drop(&mut self) {
+        self.context.on_resource_destroy(&self.lock_count);
         self.context.on_proxy_destroy(&self.target);
     }
 }
@@ -45,12 +52,17 @@ impl IDirect3DCubeTexture9_Impl for ProxyDirect3DCubeTexture9_Impl {
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn LockRect(&self, facetype: D3DCUBEMAP_FACES, level: u32, plockedrect: *mut D3DLOCKED_RECT, prect: *const RECT, flags: u32) -> Result<()> {
-        unsafe { self.target.LockRect(facetype, level, plockedrect, prect, flags) }
+        self.context
+            .time_driver_call("LockRect", || unsafe { self.target.LockRect(facetype, level, plockedrect, prect, flags) })?;
+        self.context.on_resource_lock(&self.lock_count, self);
+        Ok(())
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn UnlockRect(&self, facetype: D3DCUBEMAP_FACES, level: u32) -> Result<()> {
-        unsafe { self.target.UnlockRect(facetype, level) }
+        unsafe { self.target.UnlockRect(facetype, level) }?;
+        self.context.on_resource_unlock(&self.lock_count);
+        Ok(())
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
@@ -99,20 +111,7 @@ impl IDirect3DResource9_Impl for ProxyDirect3DCubeTexture9_Impl {
         Ok(self.proxy_device.clone())
     }
 
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
-    fn SetPrivateData(&self, refguid: *const GUID, pdata: *const c_void, sizeofdata: u32, flags: u32) -> Result<()> {
-        unsafe { self.target.SetPrivateData(refguid, pdata, sizeofdata, flags) }
-    }
-
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
-    fn GetPrivateData(&self, refguid: *const GUID, pdata: *mut c_void, psizeofdata: *mut u32) -> Result<()> {
-        unsafe { self.target.GetPrivateData(refguid, pdata, psizeofdata) }
-    }
-
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
-    fn FreePrivateData(&self, refguid: *const GUID) -> Result<()> {
-        unsafe { self.target.FreePrivateData(refguid) }
-    }
+    impl_private_data!();
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(ret, level = "trace"))]
     fn SetPriority(&self, prioritynew: u32) -> u32 {
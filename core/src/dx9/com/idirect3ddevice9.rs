@@ -10,6 +10,7 @@ use windows::{
     Win32::{
         Foundation::*,
         Graphics::{Direct3D9::*, Gdi::*},
+        UI::WindowsAndMessaging::{GA_ROOT, GetAncestor, GetClientRect, GetForegroundWindow, IsWindow},
     },
     core::*,
 };
@@ -30,12 +31,10 @@ pub struct ProxyDirect3DDevice9 {
 
 impl ProxyDirect3DDevice9 {
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(ret))]
-    pub fn new(target: IDirect3DDevice9, config: DX9ProxyConfig, container: IDirect3D9) -> Self {
-        Self {
-            target,
-            context: DX9ProxyDeviceContext::new(config),
-            container,
-        }
+    pub fn new(target: IDirect3DDevice9, config: DX9ProxyConfig, container: IDirect3D9, present_params: Option<D3DPRESENT_PARAMETERS>) -> Self {
+        let context = DX9ProxyDeviceContext::new(config);
+        context.set_present_params(present_params);
+        Self { target, context, container }
     }
 
     /// Creates a new proxy device or upgrades to an Ex version if available.
@@ -52,6 +51,7 @@ impl ProxyDirect3DDevice9 {
     /// * `target` - The target device to wrap.
     /// * `context` - The device context for the proxy.
     /// * `container` - The Direct3D container associated with the device.
+    /// * `present_params` - The effective presentation parameters the device was created with, if known.
     ///
     /// # Returns
     /// An [`IDirect3DDevice9`] instance, which may be a proxy for either
@@ -59,27 +59,96 @@ impl ProxyDirect3DDevice9 {
     ///
     /// [`new`]: Self::new
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(ret))]
-    pub fn new_or_upgrade(target: IDirect3DDevice9, config: DX9ProxyConfig, container: IDirect3D9) -> IDirect3DDevice9 {
-        if let Ok(ex_target) = target.cast::<IDirect3DDevice9Ex>() {
-            if let Ok(ex_container) = container.cast::<IDirect3D9Ex>() {
-                let ex_interface: IDirect3DDevice9Ex = ProxyDirect3DDevice9Ex::new(ex_target, config, ex_container).into();
+    pub fn new_or_upgrade(target: IDirect3DDevice9, config: DX9ProxyConfig, container: IDirect3D9, present_params: Option<D3DPRESENT_PARAMETERS>) -> IDirect3DDevice9 {
+        match (target.cast::<IDirect3DDevice9Ex>(), container.cast::<IDirect3D9Ex>()) {
+            (Ok(ex_target), Ok(ex_container)) => {
+                let ex_interface: IDirect3DDevice9Ex = ProxyDirect3DDevice9Ex::new(ex_target, config, ex_container, present_params).into();
                 return ex_interface.into();
             }
+            (Ok(_), Err(_)) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!("Target {target:?} is IDirect3DDevice9Ex but its container {container:?} is not IDirect3D9Ex, downgrading to ProxyDirect3DDevice9");
+            }
+            (Err(_), Ok(_)) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!("Container {container:?} is IDirect3D9Ex but its device {target:?} is not IDirect3DDevice9Ex, downgrading to ProxyDirect3DDevice9");
+            }
+            (Err(_), Err(_)) => {}
         }
 
         // If the target and/or container are not an Ex version, we downgrade to the regular device.
-        Self::new(target, config, container).into()
+        Self::new(target, config, container, present_params).into()
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(ret, level = "trace"))]
     pub(super) fn get_context(&self) -> &DX9ProxyDeviceContext {
         &self.context
     }
+
+    /// Logs the shader float constants changed since the previous draw call.
+    ///
+    /// Does nothing unless [`DX9ProxyConfig::capture_shader_constants`] is enabled.
+    fn capture_shader_constants(&self, _draw_call: &str) {
+        if let Some(mut shadow) = self.context.lock_shader_constant_shadow() {
+            let _changes = shadow.take_changes();
+            #[cfg(feature = "tracing")]
+            if !_changes.is_empty() {
+                tracing::debug!("{_draw_call}: {_changes}");
+            }
+        }
+    }
+
+    /// Logs the format and size of the index buffer used by an indexed draw call.
+    ///
+    /// Does nothing unless [`DX9ProxyConfig::capture_index_buffers`] is enabled.
+    fn capture_index_buffer(&self, _draw_call: &str) {
+        if !self.context.get_config().capture_index_buffers {
+            return;
+        }
+
+        // Query the target, so that the description query is not recorded as a call of the application
+        let Some((_index_buffer, target)) = self
+            .context
+            .get_bound_index_buffer()
+            .and_then(|index_buffer| Some((index_buffer.clone(), self.context.get_target(Some(&index_buffer))?.to_interface()?)))
+        else {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("{_draw_call}: No index buffer bound");
+            return;
+        };
+
+        let mut _desc = D3DINDEXBUFFER_DESC::default();
+        match unsafe { target.GetDesc(&mut _desc) } {
+            Ok(()) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("{_draw_call}: Index buffer {_index_buffer:?} format {:?} size {}", _desc.Format, _desc.Size);
+            }
+            Err(_err) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!("{_draw_call}: Failed to get description of index buffer {_index_buffer:?}: {_err}");
+            }
+        }
+    }
 }
 
 impl Drop for ProxyDirect3DDevice9 {
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(ret))]
-    fn drop(&mut self) {}
+    fn drop(&mut self) {
+        self.context.set_cursor_clip(None);
+
+        if self.context.get_config().flush_on_drop {
+            // Flushing a lost device would fail anyway, and its pending work is discarded
+            if unsafe { self.target.TestCooperativeLevel() }.is_err() {
+                #[cfg(feature = "tracing")]
+                tracing::info!("Skipping flush before destroying lost device {:?}", self.target);
+                return;
+            }
+
+            #[cfg(feature = "tracing")]
+            tracing::info!("Flushing device {:?} before destroying it", self.target);
+            flush_device(&self.target);
+        }
+    }
 }
 
 impl_debug!(ProxyDirect3DDevice9_Impl);
@@ -99,13 +168,19 @@ impl ProxyDirect3DDevice9_Impl {
         ppresentationparameters: *mut D3DPRESENT_PARAMETERS,
         pswapchain: OutRef<IDirect3DSwapChain9>,
     ) -> Result<()> {
-        check_nullptr!(pswapchain);
+        with_required_out("CreateAdditionalSwapChain", pswapchain, |pswapchain| {
+            check_nullptr!(pswapchain);
 
-        let target = try_out_param(|out| unsafe { self.target.CreateAdditionalSwapChain(ppresentationparameters, out) })?;
-        let proxy = self
-            .context
-            .ensure_proxy(target, |target| ProxyDirect3DSwapChain9::new_or_upgrade(target, self.context.clone(), get_self_interface()));
-        pswapchain.write(Some(proxy))
+            let target = self.context.time_driver_call("CreateAdditionalSwapChain", || {
+                try_out_param(|out| unsafe { self.target.CreateAdditionalSwapChain(ppresentationparameters, out) })
+            })?;
+            // The runtime fills in zero back buffer sizes and counts, so the parameters are read after the call
+            let present_params = unsafe { ppresentationparameters.as_ref() }.copied();
+            let proxy = self.context.ensure_proxy(target, |target| {
+                ProxyDirect3DSwapChain9::new_or_upgrade(target, self.context.clone(), get_self_interface(), present_params)
+            });
+            pswapchain.write(Some(proxy))
+        })
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(get_self_interface)))]
@@ -113,7 +188,7 @@ impl ProxyDirect3DDevice9_Impl {
         let target = unsafe { self.target.GetSwapChain(iswapchain) }?;
         let proxy = self
             .context
-            .ensure_proxy(target, |target| ProxyDirect3DSwapChain9::new_or_upgrade(target, self.context.clone(), get_self_interface()));
+            .ensure_proxy(target, |target| ProxyDirect3DSwapChain9::new_or_upgrade(target, self.context.clone(), get_self_interface(), None));
         Ok(proxy)
     }
 
@@ -135,13 +210,45 @@ impl ProxyDirect3DDevice9_Impl {
         pptexture: OutRef<IDirect3DTexture9>,
         psharedhandle: *mut HANDLE,
     ) -> Result<()> {
-        check_nullptr!(pptexture);
-
-        let target = try_out_param(|out| unsafe { self.target.CreateTexture(width, height, levels, usage, format, pool, out, psharedhandle) })?;
-        let proxy = self
-            .context
-            .ensure_proxy(target, |target| ProxyDirect3DTexture9::new(target, self.context.clone(), get_self_interface()).into());
-        pptexture.write(Some(proxy))
+        with_required_out("CreateTexture", pptexture, |pptexture| {
+            check_nullptr!(pptexture);
+
+            // Only textures without mipmaps are changed, as the application fills the levels of other textures itself
+            let autogen_mipmaps = self.context.get_config().force_autogen_mipmaps
+                && levels == 1
+                && usage & D3DUSAGE_AUTOGENMIPMAP as u32 == 0
+                && (pool == D3DPOOL_DEFAULT || pool == D3DPOOL_MANAGED)
+                && match supports_autogen_mipmaps(&self.target, usage | D3DUSAGE_AUTOGENMIPMAP as u32, format) {
+                    Ok(supported) => supported,
+                    Err(_err) => {
+                        #[cfg(feature = "tracing")]
+                        tracing::warn!("Failed to check automatic mipmap generation support of {format:?}: {_err}");
+                        false
+                    }
+                };
+            let (levels, usage) = if autogen_mipmaps {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("Creating {width}x{height} {format:?} texture with automatically generated mipmaps");
+                (0, usage | D3DUSAGE_AUTOGENMIPMAP as u32)
+            } else {
+                (levels, usage)
+            };
+
+            let target = self.context.time_driver_call("CreateTexture", || {
+                try_out_param(|out| unsafe { self.target.CreateTexture(width, height, levels, usage, format, pool, out, psharedhandle) })
+            })?;
+            self.context.on_resource_create(&target, pool, usage, || format!("{width}x{height} {format:?} texture"));
+            if autogen_mipmaps {
+                let _ = unsafe { target.SetAutoGenFilterType(D3DTEXF_LINEAR) }.inspect_err(|_err| {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!("Failed to set mipmap generation filter of {target:?}: {_err}");
+                });
+            }
+            let proxy = self
+                .context
+                .ensure_proxy(target, |target| ProxyDirect3DTexture9::new(target, self.context.clone(), get_self_interface()).into());
+            pptexture.write(Some(proxy))
+        })
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(get_self_interface, ppvolumetexture)))]
@@ -158,13 +265,18 @@ impl ProxyDirect3DDevice9_Impl {
         ppvolumetexture: OutRef<IDirect3DVolumeTexture9>,
         psharedhandle: *mut HANDLE,
     ) -> Result<()> {
-        check_nullptr!(ppvolumetexture);
+        with_required_out("CreateVolumeTexture", ppvolumetexture, |ppvolumetexture| {
+            check_nullptr!(ppvolumetexture);
 
-        let target = try_out_param(|out| unsafe { self.target.CreateVolumeTexture(width, height, depth, levels, usage, format, pool, out, psharedhandle) })?;
-        let proxy = self
-            .context
-            .ensure_proxy(target, |target| ProxyDirect3DVolumeTexture9::new(target, self.context.clone(), get_self_interface()).into());
-        ppvolumetexture.write(Some(proxy))
+            let target = self.context.time_driver_call("CreateVolumeTexture", || {
+                try_out_param(|out| unsafe { self.target.CreateVolumeTexture(width, height, depth, levels, usage, format, pool, out, psharedhandle) })
+            })?;
+            self.context.on_resource_create(&target, pool, usage, || format!("{width}x{height}x{depth} {format:?} volume texture"));
+            let proxy = self
+                .context
+                .ensure_proxy(target, |target| ProxyDirect3DVolumeTexture9::new(target, self.context.clone(), get_self_interface()).into());
+            ppvolumetexture.write(Some(proxy))
+        })
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(get_self_interface, ppcubetexture)))]
@@ -179,13 +291,18 @@ impl ProxyDirect3DDevice9_Impl {
         ppcubetexture: OutRef<IDirect3DCubeTexture9>,
         psharedhandle: *mut HANDLE,
     ) -> Result<()> {
-        check_nullptr!(ppcubetexture);
+        with_required_out("CreateCubeTexture", ppcubetexture, |ppcubetexture| {
+            check_nullptr!(ppcubetexture);
 
-        let target = try_out_param(|out| unsafe { self.target.CreateCubeTexture(edgelength, levels, usage, format, pool, out, psharedhandle) })?;
-        let proxy = self
-            .context
-            .ensure_proxy(target, |target| ProxyDirect3DCubeTexture9::new(target, self.context.clone(), get_self_interface()).into());
-        ppcubetexture.write(Some(proxy))
+            let target = self.context.time_driver_call("CreateCubeTexture", || {
+                try_out_param(|out| unsafe { self.target.CreateCubeTexture(edgelength, levels, usage, format, pool, out, psharedhandle) })
+            })?;
+            self.context.on_resource_create(&target, pool, usage, || format!("{edgelength}x{edgelength} {format:?} cube texture"));
+            let proxy = self
+                .context
+                .ensure_proxy(target, |target| ProxyDirect3DCubeTexture9::new(target, self.context.clone(), get_self_interface()).into());
+            ppcubetexture.write(Some(proxy))
+        })
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(get_self_interface, ppvertexbuffer)))]
@@ -199,13 +316,18 @@ impl ProxyDirect3DDevice9_Impl {
         ppvertexbuffer: OutRef<IDirect3DVertexBuffer9>,
         psharedhandle: *mut HANDLE,
     ) -> Result<()> {
-        check_nullptr!(ppvertexbuffer);
+        with_required_out("CreateVertexBuffer", ppvertexbuffer, |ppvertexbuffer| {
+            check_nullptr!(ppvertexbuffer);
 
-        let target = try_out_param(|out| unsafe { self.target.CreateVertexBuffer(length, usage, fvf, pool, out, psharedhandle) })?;
-        let proxy = self
-            .context
-            .ensure_proxy(target, |target| ProxyDirect3DVertexBuffer9::new(target, self.context.clone(), get_self_interface()).into());
-        ppvertexbuffer.write(Some(proxy))
+            let target = self.context.time_driver_call("CreateVertexBuffer", || {
+                try_out_param(|out| unsafe { self.target.CreateVertexBuffer(length, usage, fvf, pool, out, psharedhandle) })
+            })?;
+            self.context.on_resource_create(&target, pool, usage, || format!("{length} byte vertex buffer"));
+            let proxy = self
+                .context
+                .ensure_proxy(target, |target| ProxyDirect3DVertexBuffer9::new(target, self.context.clone(), get_self_interface()).into());
+            ppvertexbuffer.write(Some(proxy))
+        })
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(get_self_interface, ppindexbuffer)))]
@@ -219,13 +341,18 @@ impl ProxyDirect3DDevice9_Impl {
         ppindexbuffer: OutRef<IDirect3DIndexBuffer9>,
         psharedhandle: *mut HANDLE,
     ) -> Result<()> {
-        check_nullptr!(ppindexbuffer);
+        with_required_out("CreateIndexBuffer", ppindexbuffer, |ppindexbuffer| {
+            check_nullptr!(ppindexbuffer);
 
-        let target = try_out_param(|out| unsafe { self.target.CreateIndexBuffer(length, usage, format, pool, out, psharedhandle) })?;
-        let proxy = self
-            .context
-            .ensure_proxy(target, |target| ProxyDirect3DIndexBuffer9::new(target, self.context.clone(), get_self_interface()).into());
-        ppindexbuffer.write(Some(proxy))
+            let target = self.context.time_driver_call("CreateIndexBuffer", || {
+                try_out_param(|out| unsafe { self.target.CreateIndexBuffer(length, usage, format, pool, out, psharedhandle) })
+            })?;
+            self.context.on_resource_create(&target, pool, usage, || format!("{length} byte {format:?} index buffer"));
+            let proxy = self
+                .context
+                .ensure_proxy(target, |target| ProxyDirect3DIndexBuffer9::new(target, self.context.clone(), get_self_interface()).into());
+            ppindexbuffer.write(Some(proxy))
+        })
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(get_self_interface, ppsurface)))]
@@ -241,16 +368,22 @@ impl ProxyDirect3DDevice9_Impl {
         ppsurface: OutRef<IDirect3DSurface9>,
         psharedhandle: *mut HANDLE,
     ) -> Result<()> {
-        check_nullptr!(ppsurface);
-
-        let target = try_out_param(|out| unsafe {
-            self.target
-                .CreateDepthStencilSurface(width, height, format, multisample, multisamplequality, discard.into(), out, psharedhandle)
-        })?;
-        let proxy = self.context.ensure_proxy(target, |target| {
-            ProxyDirect3DSurface9::new(target, self.context.clone(), get_self_interface(), DX9SurfaceContainer::Standalone).into()
-        });
-        ppsurface.write(Some(proxy))
+        with_required_out("CreateDepthStencilSurface", ppsurface, |ppsurface| {
+            check_nullptr!(ppsurface);
+
+            let target = self.context.time_driver_call("CreateDepthStencilSurface", || {
+                try_out_param(|out| unsafe {
+                    self.target
+                        .CreateDepthStencilSurface(width, height, format, multisample, multisamplequality, discard.into(), out, psharedhandle)
+                })
+            })?;
+            self.context
+                .on_resource_create(&target, D3DPOOL_DEFAULT, D3DUSAGE_DEPTHSTENCIL as u32, || format!("{width}x{height} {format:?} depth stencil surface"));
+            let proxy = self.context.ensure_proxy(target, |target| {
+                ProxyDirect3DSurface9::new(target, self.context.clone(), get_self_interface(), DX9SurfaceContainer::Standalone).into()
+            });
+            ppsurface.write(Some(proxy))
+        })
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(get_self_interface, ppsurface)))]
@@ -264,13 +397,18 @@ impl ProxyDirect3DDevice9_Impl {
         ppsurface: OutRef<IDirect3DSurface9>,
         psharedhandle: *mut HANDLE,
     ) -> Result<()> {
-        check_nullptr!(ppsurface);
+        with_required_out("CreateOffscreenPlainSurface", ppsurface, |ppsurface| {
+            check_nullptr!(ppsurface);
 
-        let target = try_out_param(|out| unsafe { self.target.CreateOffscreenPlainSurface(width, height, format, pool, out, psharedhandle) })?;
-        let proxy = self.context.ensure_proxy(target, |target| {
-            ProxyDirect3DSurface9::new(target, self.context.clone(), get_self_interface(), DX9SurfaceContainer::Standalone).into()
-        });
-        ppsurface.write(Some(proxy))
+            let target = self.context.time_driver_call("CreateOffscreenPlainSurface", || {
+                try_out_param(|out| unsafe { self.target.CreateOffscreenPlainSurface(width, height, format, pool, out, psharedhandle) })
+            })?;
+            self.context.on_resource_create(&target, pool, 0, || format!("{width}x{height} {format:?} offscreen plain surface"));
+            let proxy = self.context.ensure_proxy(target, |target| {
+                ProxyDirect3DSurface9::new(target, self.context.clone(), get_self_interface(), DX9SurfaceContainer::Standalone).into()
+            });
+            ppsurface.write(Some(proxy))
+        })
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(get_self_interface, ppsurface)))]
@@ -286,34 +424,40 @@ impl ProxyDirect3DDevice9_Impl {
         ppsurface: OutRef<IDirect3DSurface9>,
         psharedhandle: *mut HANDLE,
     ) -> Result<()> {
-        check_nullptr!(ppsurface);
-
-        let target = try_out_param(|out| unsafe {
-            self.target
-                .CreateRenderTarget(width, height, format, multisample, multisamplequality, lockable.into(), out, psharedhandle)
-        })?;
-        let proxy = self.context.ensure_proxy(target, |target| {
-            ProxyDirect3DSurface9::new(target, self.context.clone(), get_self_interface(), DX9SurfaceContainer::Standalone).into()
-        });
-        ppsurface.write(Some(proxy))
+        with_required_out("CreateRenderTarget", ppsurface, |ppsurface| {
+            check_nullptr!(ppsurface);
+
+            let target = self.context.time_driver_call("CreateRenderTarget", || {
+                try_out_param(|out| unsafe {
+                    self.target
+                        .CreateRenderTarget(width, height, format, multisample, multisamplequality, lockable.into(), out, psharedhandle)
+                })
+            })?;
+            self.context
+                .on_resource_create(&target, D3DPOOL_DEFAULT, D3DUSAGE_RENDERTARGET as u32, || format!("{width}x{height} {format:?} render target"));
+            let proxy = self.context.ensure_proxy(target, |target| {
+                ProxyDirect3DSurface9::new(target, self.context.clone(), get_self_interface(), DX9SurfaceContainer::Standalone).into()
+            });
+            ppsurface.write(Some(proxy))
+        })
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(get_self_interface)))]
     pub(super) unsafe fn GetRenderTarget_Impl<F: FnOnce() -> IDirect3DDevice9>(&self, get_self_interface: F, rendertargetindex: u32) -> Result<IDirect3DSurface9> {
-        let target = unsafe { self.target.GetRenderTarget(rendertargetindex) }?;
-        let proxy = self.context.ensure_proxy(target, |target| {
-            ProxyDirect3DSurface9::new(target, self.context.clone(), get_self_interface(), DX9SurfaceContainer::Standalone).into()
-        });
-        Ok(proxy)
+        self.context.get_surface_proxy(
+            DX9SurfaceSlot::RenderTarget(rendertargetindex),
+            || unsafe { self.target.GetRenderTarget(rendertargetindex) },
+            |target| ProxyDirect3DSurface9::new(target, self.context.clone(), get_self_interface(), DX9SurfaceContainer::Standalone).into(),
+        )
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(get_self_interface)))]
     pub(super) unsafe fn GetDepthStencilSurface_Impl<F: FnOnce() -> IDirect3DDevice9>(&self, get_self_interface: F) -> Result<IDirect3DSurface9> {
-        let target = unsafe { self.target.GetDepthStencilSurface() }?;
-        let proxy = self.context.ensure_proxy(target, |target| {
-            ProxyDirect3DSurface9::new(target, self.context.clone(), get_self_interface(), DX9SurfaceContainer::Standalone).into()
-        });
-        Ok(proxy)
+        self.context.get_surface_proxy(
+            DX9SurfaceSlot::DepthStencil,
+            || unsafe { self.target.GetDepthStencilSurface() },
+            |target| ProxyDirect3DSurface9::new(target, self.context.clone(), get_self_interface(), DX9SurfaceContainer::Standalone).into(),
+        )
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(get_self_interface)))]
@@ -334,6 +478,20 @@ impl ProxyDirect3DDevice9_Impl {
         Ok(proxy)
     }
 
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(get_self_interface)))]
+    pub(super) unsafe fn BeginScene_Impl<F: FnOnce() -> IDirect3DDevice9>(&self, get_self_interface: F) -> Result<()> {
+        unsafe { self.target.BeginScene() }?;
+        apply_forced_states(&self.target, &self.context);
+        call_scene_hooks(&self.target, get_self_interface, |hook, device| hook.on_begin_scene(device));
+        Ok(())
+    }
+
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(get_self_interface)))]
+    pub(super) unsafe fn EndScene_Impl<F: FnOnce() -> IDirect3DDevice9>(&self, get_self_interface: F) -> Result<()> {
+        call_scene_hooks(&self.target, get_self_interface, |hook, device| hook.on_end_scene(device));
+        unsafe { self.target.EndScene() }
+    }
+
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(get_self_interface)))]
     pub(super) unsafe fn CreateVertexDeclaration_Impl<F: FnOnce() -> IDirect3DDevice9>(&self, get_self_interface: F, pvertexelements: *const D3DVERTEXELEMENT9) -> Result<IDirect3DVertexDeclaration9> {
         let target = unsafe { self.target.CreateVertexDeclaration(pvertexelements) }?;
@@ -417,7 +575,22 @@ impl ProxyDirect3DDevice9_Impl {
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(get_self_interface)))]
     pub(super) unsafe fn CreateQuery_Impl<F: FnOnce() -> IDirect3DDevice9>(&self, get_self_interface: F, r#type: D3DQUERYTYPE) -> Result<IDirect3DQuery9> {
-        let target = unsafe { self.target.CreateQuery(r#type) }?;
+        let target = match unsafe { self.target.CreateQuery(r#type) } {
+            Ok(target) => target,
+            Err(_err) if self.context.get_config().emulate_queries && is_emulated_query_type(r#type) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!("CreateQuery failed for {type:?}: {_err}, emulating the query", type = r#type);
+
+                // Used as a stand-in to track completion, which fails if the emulated type is EVENT itself
+                let event = unsafe { self.target.CreateQuery(D3DQUERYTYPE_EVENT) }.ok();
+                return Ok(EmulatedDirect3DQuery9::new(r#type, event, get_self_interface()).into());
+            }
+            Err(err) => return Err(err),
+        };
+        if !self.context.get_config().proxy_queries {
+            return Ok(target);
+        }
+
         let proxy = self
             .context
             .ensure_proxy(target, |target| ProxyDirect3DQuery9::new(target, self.context.clone(), get_self_interface()).into());
@@ -432,9 +605,25 @@ impl ProxyDirect3DDevice9_Impl {
 /// when dealing with interface inheritance (e.g., [`IDirect3DDevice9Ex`] extending [`IDirect3DDevice9`]).
 #[allow(non_snake_case, clippy::not_unsafe_ptr_arg_deref)]
 impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9_Impl {
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
+    // Lost devices fail this every frame, so errors are not logged at the error level, and only state transitions are logged
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err(level = "trace"), ret, level = "trace"))]
     fn TestCooperativeLevel(&self) -> Result<()> {
-        unsafe { self.target.TestCooperativeLevel() }
+        if let Some(result) = self.context.get_forced_result(DX9OverridableMethod::TestCooperativeLevel) {
+            return result;
+        }
+
+        let result = unsafe { self.target.TestCooperativeLevel() };
+
+        let _previous = self.context.update_cooperative_level(result.as_ref().map_or_else(|err| err.code(), |()| D3D_OK));
+        #[cfg(feature = "tracing")]
+        if let Some(previous) = _previous {
+            match &result {
+                Ok(()) => tracing::info!("Device recovered from {}", hresult_name(previous)),
+                Err(err) => tracing::info!("Device state changed from {} to {}", hresult_name(previous), hresult_name(err.code())),
+            }
+        }
+
+        result
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(ret, level = "trace"))]
@@ -444,6 +633,10 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9_Impl {
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn EvictManagedResources(&self) -> Result<()> {
+        if let Some(result) = self.context.get_forced_result(DX9OverridableMethod::EvictManagedResources) {
+            return result;
+        }
+
         unsafe { self.target.EvictManagedResources() }
     }
 
@@ -467,9 +660,32 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9_Impl {
         unsafe { self.target.GetCreationParameters(pparameters) }
     }
 
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(pcursorbitmap)))]
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(pcursorbitmap), fields(pcursorbitmap = ?TracedObject::new(pcursorbitmap.as_ref()))))]
     fn SetCursorProperties(&self, xhotspot: u32, yhotspot: u32, pcursorbitmap: Ref<IDirect3DSurface9>) -> Result<()> {
+        if let Some(result) = self.context.get_forced_result(DX9OverridableMethod::SetCursorProperties) {
+            return result;
+        }
+
         let target = self.context.get_target_nullable(pcursorbitmap).ok_or(D3DERR_INVALIDCALL)?;
+        if let Some(scale) = self.context.get_config().cursor_scale
+            && let Some(mut cache) = self.context.lock_cursor_cache()
+            && let Some(bitmap) = target.to_interface()
+        {
+            match cache.get_or_scale(&self.target, &bitmap, (xhotspot, yhotspot), scale) {
+                Ok((scaled, (xhotspot, yhotspot))) => match unsafe { self.target.SetCursorProperties(xhotspot, yhotspot, &scaled) } {
+                    Ok(()) => return Ok(()),
+                    Err(_err) => {
+                        #[cfg(feature = "tracing")]
+                        tracing::warn!("Failed to set the scaled cursor, using the original one: {_err}");
+                    }
+                },
+                Err(_err) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!("Failed to scale the cursor bitmap {bitmap:?}, using the original one: {_err}");
+                }
+            }
+        }
+
         unsafe { self.target.SetCursorProperties(xhotspot, yhotspot, target) }
     }
 
@@ -500,12 +716,63 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9_Impl {
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn Reset(&self, ppresentationparameters: *mut D3DPRESENT_PARAMETERS) -> Result<()> {
-        unsafe { self.target.Reset(ppresentationparameters) }
+        // Default pool resources must be released before resetting the device
+        self.context.lock_scratch_buffers().release();
+        if let Some(mut watermark) = self.context.lock_watermark() {
+            watermark.release();
+        }
+        if let Some(mut scratch) = self.context.lock_stretch_scratch() {
+            scratch.release();
+        }
+        if let Some(mut pacer) = self.context.lock_frame_pacer() {
+            pacer.clear();
+        }
+        self.context.invalidate_render_target_size();
+        self.context.clear_surface_proxies();
+        self.context.lock_bound_textures().clear();
+        self.context.set_bound_index_buffer(None);
+        let mut params = unsafe {
+            present::with_overridden_params(ppresentationparameters, reset_params_count(&self.target), |params| {
+                apply_reset_overrides(&self.context, &self.target, params)
+            })
+        };
+
+        unsafe { self.target.Reset(present::params_ptr(&mut params, ppresentationparameters)) }.inspect_err(|_err| self.context.on_reset_failed())?;
+        if let Some(params) = &params {
+            unsafe { present::write_back_params(ppresentationparameters, params) };
+        }
+        self.context.set_present_params(params.map(|params| params[0]));
+        Ok(())
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn Present(&self, psourcerect: *const RECT, pdestrect: *const RECT, hdestwindowoverride: HWND, pdirtyregion: *const RGNDATA) -> Result<()> {
-        unsafe { self.target.Present(psourcerect, pdestrect, hdestwindowoverride, pdirtyregion) }
+        let present_guard = present::enter_present("Present");
+        if !present_guard.is_outermost() {
+            return unsafe { self.target.Present(psourcerect, pdestrect, hdestwindowoverride, pdirtyregion) };
+        }
+
+        if let Some(mut pacer) = self.context.lock_frame_pacer() {
+            pacer.pace();
+        }
+
+        let call = || {
+            if let Some(mut watermark) = self.context.lock_watermark() {
+                watermark.draw(&self.target);
+            }
+            self.context
+                .time_driver_call("Present", || unsafe { self.target.Present(psourcerect, pdestrect, hdestwindowoverride, pdirtyregion) })
+        };
+        // SAFETY: The call only uses the context, the target device and the arguments of the application
+        unsafe { self.context.serialize(call) }?;
+        self.context.on_present();
+        update_cursor_clip(&self.target, &self.context);
+
+        if self.context.get_config().flush_after_present {
+            flush_device(&self.target);
+        }
+
+        Ok(())
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
@@ -520,6 +787,10 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9_Impl {
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn SetDialogBoxMode(&self, benabledialogs: BOOL) -> Result<()> {
+        if let Some(result) = self.context.get_forced_result(DX9OverridableMethod::SetDialogBoxMode) {
+            return result;
+        }
+
         unsafe { self.target.SetDialogBoxMode(benabledialogs.into()) }
     }
 
@@ -604,49 +875,78 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9_Impl {
         unsafe { self.CreateRenderTarget_Impl(|| self.to_interface(), width, height, format, multisample, multisamplequality, lockable, ppsurface, psharedhandle) }
     }
 
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(psourcesurface, pdestinationsurface)))]
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(psourcesurface, pdestinationsurface), fields(psourcesurface = ?TracedObject::new(psourcesurface.as_ref()), pdestinationsurface = ?TracedObject::new(pdestinationsurface.as_ref()))))]
     fn UpdateSurface(&self, psourcesurface: Ref<IDirect3DSurface9>, psourcerect: *const RECT, pdestinationsurface: Ref<IDirect3DSurface9>, pdestpoint: *const POINT) -> Result<()> {
         let target_source = self.context.get_target_nullable(psourcesurface).ok_or(D3DERR_INVALIDCALL)?;
         let target_dest = self.context.get_target_nullable(pdestinationsurface).ok_or(D3DERR_INVALIDCALL)?;
         unsafe { self.target.UpdateSurface(target_source, psourcerect, target_dest, pdestpoint) }
     }
 
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(psourcetexture, pdestinationtexture)))]
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(psourcetexture, pdestinationtexture), fields(psourcetexture = ?TracedObject::new(psourcetexture.as_ref()), pdestinationtexture = ?TracedObject::new(pdestinationtexture.as_ref()))))]
     fn UpdateTexture(&self, psourcetexture: Ref<IDirect3DBaseTexture9>, pdestinationtexture: Ref<IDirect3DBaseTexture9>) -> Result<()> {
+        // Texture proxies are registered as IDirect3DTexture9 / IDirect3DCubeTexture9 / IDirect3DVolumeTexture9, but the
+        // tracker keys on object identity, so they are also found when passed as IDirect3DBaseTexture9. Texture proxies
+        // themselves take no sibling resources (AddDirtyRect only takes a RECT), so this and SetTexture are the only places
+        // where textures need translation.
         let target_source = self.context.get_target_nullable(psourcetexture).ok_or(D3DERR_INVALIDCALL)?;
         let target_dest = self.context.get_target_nullable(pdestinationtexture).ok_or(D3DERR_INVALIDCALL)?;
         unsafe { self.target.UpdateTexture(target_source, target_dest) }
     }
 
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(prendertarget, pdestsurface)))]
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(prendertarget, pdestsurface), fields(prendertarget = ?TracedObject::new(prendertarget.as_ref()), pdestsurface = ?TracedObject::new(pdestsurface.as_ref()))))]
     fn GetRenderTargetData(&self, prendertarget: Ref<IDirect3DSurface9>, pdestsurface: Ref<IDirect3DSurface9>) -> Result<()> {
         let target_render_target = self.context.get_target_nullable(prendertarget).ok_or(D3DERR_INVALIDCALL)?;
         let target_dest = self.context.get_target_nullable(pdestsurface).ok_or(D3DERR_INVALIDCALL)?;
         unsafe { self.target.GetRenderTargetData(target_render_target, target_dest) }
     }
 
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(pdestsurface)))]
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(pdestsurface), fields(pdestsurface = ?TracedObject::new(pdestsurface.as_ref()))))]
     fn GetFrontBufferData(&self, iswapchain: u32, pdestsurface: Ref<IDirect3DSurface9>) -> Result<()> {
-        let target = self.context.get_target_nullable(pdestsurface).ok_or(D3DERR_INVALIDCALL)?;
-        unsafe { self.target.GetFrontBufferData(iswapchain, target) }
+        let target = self.context.get_target_nullable(pdestsurface.as_ref()).ok_or(D3DERR_INVALIDCALL)?;
+        unsafe { self.target.GetFrontBufferData(iswapchain, target) }?;
+
+        if self.context.get_config().crop_front_buffer_to_window
+            && let Some(surface) = pdestsurface.as_ref()
+        {
+            let _ = unsafe { self.target.GetSwapChain(iswapchain) }
+                .and_then(|swap_chain| crop_front_buffer_to_window(&swap_chain, surface))
+                .inspect_err(|_err| {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!("Failed to crop front buffer data to the device window, passing through: {_err}");
+                });
+        }
+
+        Ok(())
     }
 
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(psourcesurface, pdestsurface)))]
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(psourcesurface, pdestsurface), fields(psourcesurface = ?TracedObject::new(psourcesurface.as_ref()), pdestsurface = ?TracedObject::new(pdestsurface.as_ref()))))]
     fn StretchRect(&self, psourcesurface: Ref<IDirect3DSurface9>, psourcerect: *const RECT, pdestsurface: Ref<IDirect3DSurface9>, pdestrect: *const RECT, filter: D3DTEXTUREFILTERTYPE) -> Result<()> {
         let target_source = self.context.get_target_nullable(psourcesurface).ok_or(D3DERR_INVALIDCALL)?;
         let target_dest = self.context.get_target_nullable(pdestsurface).ok_or(D3DERR_INVALIDCALL)?;
+        if target_source.as_raw() == target_dest.as_raw()
+            && let Some(surface) = target_source.to_interface()
+            && let Some(mut scratch) = self.context.lock_stretch_scratch()
+        {
+            #[cfg(feature = "tracing")]
+            tracing::debug!("Performing StretchRect of {surface:?} onto itself through a scratch surface");
+            return unsafe { scratch.stretch_rect_to_self(&self.target, &surface, psourcerect, pdestrect, filter) };
+        }
         unsafe { self.target.StretchRect(target_source, psourcerect, target_dest, pdestrect, filter) }
     }
 
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(psurface)))]
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(psurface), fields(psurface = ?TracedObject::new(psurface.as_ref()))))]
     fn ColorFill(&self, psurface: Ref<IDirect3DSurface9>, prect: *const RECT, color: u32) -> Result<()> {
         let target = self.context.get_target_nullable(psurface).ok_or(D3DERR_INVALIDCALL)?;
         unsafe { self.target.ColorFill(target, prect, color) }
     }
 
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(prendertarget)))]
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(prendertarget), fields(prendertarget = ?TracedObject::new(prendertarget.as_ref()))))]
     fn SetRenderTarget(&self, rendertargetindex: u32, prendertarget: Ref<IDirect3DSurface9>) -> Result<()> {
         let target = self.context.get_target_nullable(prendertarget).ok_or(D3DERR_INVALIDCALL)?;
+        self.context.invalidate_surface_proxy(DX9SurfaceSlot::RenderTarget(rendertargetindex));
+        if rendertargetindex == 0 {
+            self.context.invalidate_render_target_size();
+        }
         unsafe { self.target.SetRenderTarget(rendertargetindex, target) }
     }
 
@@ -655,9 +955,10 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9_Impl {
         unsafe { self.GetRenderTarget_Impl(|| self.to_interface(), rendertargetindex) }
     }
 
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(pnewzstencil)))]
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(pnewzstencil), fields(pnewzstencil = ?TracedObject::new(pnewzstencil.as_ref()))))]
     fn SetDepthStencilSurface(&self, pnewzstencil: Ref<IDirect3DSurface9>) -> Result<()> {
         let target = self.context.get_target_nullable(pnewzstencil).ok_or(D3DERR_INVALIDCALL)?;
+        self.context.invalidate_surface_proxy(DX9SurfaceSlot::DepthStencil);
         unsafe { self.target.SetDepthStencilSurface(target) }
     }
 
@@ -668,16 +969,28 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9_Impl {
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn BeginScene(&self) -> Result<()> {
-        unsafe { self.target.BeginScene() }
+        unsafe { self.BeginScene_Impl(|| self.to_interface()) }
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn EndScene(&self) -> Result<()> {
-        unsafe { self.target.EndScene() }
+        unsafe { self.EndScene_Impl(|| self.to_interface()) }
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn Clear(&self, count: u32, prects: *const D3DRECT, flags: u32, color: u32, z: f32, stencil: u32) -> Result<()> {
+        if self.context.are_clears_disabled()
+            && self
+                .context
+                .get_config()
+                .disable_clears_render_target
+                .is_none_or(|index| unsafe { self.target.GetRenderTarget(index) }.is_ok())
+        {
+            #[cfg(feature = "tracing")]
+            tracing::trace!("Suppressing Clear with flags {flags:#x}");
+            return Ok(());
+        }
+
         unsafe { self.target.Clear(count, prects, flags, color, z, stencil) }
     }
 
@@ -698,6 +1011,35 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9_Impl {
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn SetViewport(&self, pviewport: *const D3DVIEWPORT9) -> Result<()> {
+        if self.context.get_config().clamp_viewport
+            && let Some(viewport) = unsafe { pviewport.as_ref() }
+        {
+            let size = self.context.get_render_target_size(|| {
+                let mut desc = D3DSURFACE_DESC::default();
+                unsafe { self.target.GetRenderTarget(0)?.GetDesc(&mut desc) }?;
+                Ok((desc.Width, desc.Height))
+            });
+            match size {
+                Ok((width, height)) => {
+                    let clamped = clamp_viewport(viewport, width, height);
+                    if clamped != *viewport {
+                        let _first = self.context.on_viewport_clamped();
+                        #[cfg(feature = "tracing")]
+                        if _first {
+                            tracing::warn!("Clamped viewport {viewport:?} to render target size {width}x{height}, further clamps are logged at debug level");
+                        } else {
+                            tracing::debug!("Clamped viewport {viewport:?} to render target size {width}x{height}");
+                        }
+                        return unsafe { self.target.SetViewport(&clamped) };
+                    }
+                }
+                Err(_err) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!("Failed to get render target size for clamping viewport: {_err}");
+                }
+            }
+        }
+
         unsafe { self.target.SetViewport(pviewport) }
     }
 
@@ -733,6 +1075,7 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9_Impl {
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn GetLightEnable(&self, index: u32, penable: *mut BOOL) -> Result<()> {
+        // The runtime may report enabled lights with nonzero values other than TRUE, which are passed through as is
         unsafe { self.target.GetLightEnable(index, penable) }
     }
 
@@ -748,7 +1091,12 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9_Impl {
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn SetRenderState(&self, state: D3DRENDERSTATETYPE, value: u32) -> Result<()> {
-        unsafe { self.target.SetRenderState(state, value) }
+        let forced_value = self.context.override_render_state(state, value);
+        #[cfg(feature = "tracing")]
+        if forced_value != value {
+            tracing::trace!("Forcing render state {state:?} to {forced_value} instead of {value}");
+        }
+        unsafe { self.target.SetRenderState(state, forced_value) }
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
@@ -781,10 +1129,21 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9_Impl {
         unsafe { self.target.GetClipStatus(pclipstatus) }
     }
 
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(ptexture)))]
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(ptexture), fields(ptexture = ?TracedObject::new(ptexture.as_ref()))))]
     fn SetTexture(&self, stage: u32, ptexture: Ref<IDirect3DBaseTexture9>) -> Result<()> {
-        let target = self.context.get_target_nullable(ptexture).ok_or(D3DERR_INVALIDCALL)?;
-        unsafe { self.target.SetTexture(stage, target) }
+        let target = self.context.get_target_nullable(ptexture.as_ref()).ok_or(D3DERR_INVALIDCALL)?;
+        unsafe { self.target.SetTexture(stage, target) }?;
+
+        // Record the proxy rather than the target, as that is what the application sees
+        let texture = ptexture.as_ref().map_or(0, |texture| texture.as_raw() as usize);
+        let _previous = self.context.lock_bound_textures().set(stage, texture);
+        #[cfg(feature = "tracing")]
+        if let Some(previous) = _previous
+            && previous != texture
+        {
+            tracing::trace!("Texture binding of stage {stage} changed: {previous:#x} -> {texture:#x}");
+        }
+        Ok(())
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
@@ -792,7 +1151,7 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9_Impl {
         let target = unsafe { self.target.GetTexture(stage) }?;
         let proxy = self.context.get_proxy(target).ok_or(D3DERR_INVALIDCALL).inspect_err(|_err| {
             #[cfg(feature = "tracing")]
-            tracing::error!("Failed to get texture proxy: {_err}");
+            tracing::error!("Failed to get texture proxy: {}", hresult_name(*_err));
         })?;
         Ok(proxy)
     }
@@ -814,12 +1173,54 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9_Impl {
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn SetSamplerState(&self, sampler: u32, r#type: D3DSAMPLERSTATETYPE, value: u32) -> Result<()> {
+        let value = match self.context.get_forced_sampler_states().iter().find(|(forced_type, _)| *forced_type == r#type) {
+            Some(&(_, forced_value)) => {
+                #[cfg(feature = "tracing")]
+                if forced_value != value {
+                    tracing::trace!("Forcing sampler state {type:?} of sampler {sampler} to {forced_value} instead of {value}", type = r#type);
+                }
+                forced_value
+            }
+            None => value,
+        };
         unsafe { self.target.SetSamplerState(sampler, r#type, value) }
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn ValidateDevice(&self, pnumpasses: *mut u32) -> Result<()> {
-        unsafe { self.target.ValidateDevice(pnumpasses) }
+        let log_validate_device = self.context.get_config().log_validate_device;
+
+        let Some(result) = self.context.get_forced_result(DX9OverridableMethod::ValidateDevice) else {
+            let result = unsafe { self.target.ValidateDevice(pnumpasses) };
+            #[cfg(feature = "tracing")]
+            if log_validate_device {
+                let numpasses = unsafe { pnumpasses.as_ref() }.filter(|_| result.is_ok());
+                tracing::info!(
+                    "ValidateDevice returned {} with {numpasses:?} passes",
+                    hresult_name(result.as_ref().map_or_else(Error::code, |()| D3D_OK))
+                );
+            }
+            return result;
+        };
+
+        // Call the driver anyway to compare its result with the forced one
+        if log_validate_device {
+            let mut real_numpasses = 0;
+            let _real_result = unsafe { self.target.ValidateDevice(&mut real_numpasses) };
+            #[cfg(feature = "tracing")]
+            tracing::info!(
+                "ValidateDevice returned {} with {real_numpasses} passes, forced to {}",
+                hresult_name(_real_result.as_ref().map_or_else(Error::code, |()| D3D_OK)),
+                hresult_name(result.as_ref().map_or_else(Error::code, |()| D3D_OK))
+            );
+        }
+
+        if result.is_ok()
+            && let Some(numpasses) = unsafe { pnumpasses.as_mut() }
+        {
+            *numpasses = 1;
+        }
+        result
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
@@ -864,6 +1265,10 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9_Impl {
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn SetNPatchMode(&self, nsegments: f32) -> Result<()> {
+        #[cfg(feature = "tracing")]
+        tracing::debug!("SetNPatchMode requested with {nsegments} segments");
+
+        let nsegments = if self.context.get_config().disable_npatch { 0.0 } else { nsegments };
         unsafe { self.target.SetNPatchMode(nsegments) }
     }
 
@@ -874,17 +1279,53 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9_Impl {
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn DrawPrimitive(&self, primitivetype: D3DPRIMITIVETYPE, startvertex: u32, primitivecount: u32) -> Result<()> {
-        unsafe { self.target.DrawPrimitive(primitivetype, startvertex, primitivecount) }
+        self.capture_shader_constants("DrawPrimitive");
+        self.context.record_draw_call(primitivecount);
+        let call = || {
+            self.context
+                .time_driver_call("DrawPrimitive", || unsafe { self.target.DrawPrimitive(primitivetype, startvertex, primitivecount) })
+        };
+        // SAFETY: The call only uses the context, the target device and the arguments of the application
+        unsafe { self.context.serialize(call) }
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn DrawIndexedPrimitive(&self, param0: D3DPRIMITIVETYPE, basevertexindex: i32, minvertexindex: u32, numvertices: u32, startindex: u32, primcount: u32) -> Result<()> {
-        unsafe { self.target.DrawIndexedPrimitive(param0, basevertexindex, minvertexindex, numvertices, startindex, primcount) }
+        self.capture_shader_constants("DrawIndexedPrimitive");
+        self.context.record_draw_call(primcount);
+        self.capture_index_buffer("DrawIndexedPrimitive");
+        let call = || {
+            self.context.time_driver_call("DrawIndexedPrimitive", || unsafe {
+                self.target.DrawIndexedPrimitive(param0, basevertexindex, minvertexindex, numvertices, startindex, primcount)
+            })
+        };
+        // SAFETY: The call only uses the context, the target device and the arguments of the application
+        unsafe { self.context.serialize(call) }
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn DrawPrimitiveUP(&self, primitivetype: D3DPRIMITIVETYPE, primitivecount: u32, pvertexstreamzerodata: *const c_void, vertexstreamzerostride: u32) -> Result<()> {
-        unsafe { self.target.DrawPrimitiveUP(primitivetype, primitivecount, pvertexstreamzerodata, vertexstreamzerostride) }
+        self.capture_shader_constants("DrawPrimitiveUP");
+        self.context.record_draw_call(primitivecount);
+        let call = || {
+            self.context.time_driver_call("DrawPrimitiveUP", || {
+                if self.context.get_config().optimize_up_draws {
+                    let result = unsafe {
+                        self.context
+                            .lock_scratch_buffers()
+                            .draw_primitive_up(&self.target, primitivetype, primitivecount, pvertexstreamzerodata, vertexstreamzerostride)
+                    };
+                    match result {
+                        Ok(()) => return Ok(()),
+                        Err(err) => self.context.lock_scratch_buffers().log_fallback("DrawPrimitiveUP", &err),
+                    }
+                }
+
+                unsafe { self.target.DrawPrimitiveUP(primitivetype, primitivecount, pvertexstreamzerodata, vertexstreamzerostride) }
+            })
+        };
+        // SAFETY: The call only uses the context, the target device and the arguments of the application
+        unsafe { self.context.serialize(call) }
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
@@ -899,21 +1340,53 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9_Impl {
         pvertexstreamzerodata: *const c_void,
         vertexstreamzerostride: u32,
     ) -> Result<()> {
-        unsafe {
-            self.target.DrawIndexedPrimitiveUP(
-                primitivetype,
-                minvertexindex,
-                numvertices,
-                primitivecount,
-                pindexdata,
-                indexdataformat,
-                pvertexstreamzerodata,
-                vertexstreamzerostride,
-            )
+        self.capture_shader_constants("DrawIndexedPrimitiveUP");
+        self.context.record_draw_call(primitivecount);
+        #[cfg(feature = "tracing")]
+        if self.context.get_config().capture_index_buffers {
+            tracing::debug!("DrawIndexedPrimitiveUP: User pointer indices format {indexdataformat:?}");
         }
-    }
-
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(pdestbuffer, pvertexdecl)))]
+        let call = || {
+            self.context.time_driver_call("DrawIndexedPrimitiveUP", || {
+                if self.context.get_config().optimize_up_draws {
+                    let result = unsafe {
+                        self.context.lock_scratch_buffers().draw_indexed_primitive_up(
+                            &self.target,
+                            primitivetype,
+                            minvertexindex,
+                            numvertices,
+                            primitivecount,
+                            pindexdata,
+                            indexdataformat,
+                            pvertexstreamzerodata,
+                            vertexstreamzerostride,
+                        )
+                    };
+                    match result {
+                        Ok(()) => return Ok(()),
+                        Err(err) => self.context.lock_scratch_buffers().log_fallback("DrawIndexedPrimitiveUP", &err),
+                    }
+                }
+
+                unsafe {
+                    self.target.DrawIndexedPrimitiveUP(
+                        primitivetype,
+                        minvertexindex,
+                        numvertices,
+                        primitivecount,
+                        pindexdata,
+                        indexdataformat,
+                        pvertexstreamzerodata,
+                        vertexstreamzerostride,
+                    )
+                }
+            })
+        };
+        // SAFETY: The call only uses the context, the target device and the arguments of the application
+        unsafe { self.context.serialize(call) }
+    }
+
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(pdestbuffer, pvertexdecl), fields(pdestbuffer = ?TracedObject::new(pdestbuffer.as_ref()), pvertexdecl = ?TracedObject::new(pvertexdecl.as_ref()))))]
     fn ProcessVertices(&self, srcstartindex: u32, destindex: u32, vertexcount: u32, pdestbuffer: Ref<IDirect3DVertexBuffer9>, pvertexdecl: Ref<IDirect3DVertexDeclaration9>, flags: u32) -> Result<()> {
         let target_dest = self.context.get_target_nullable(pdestbuffer).ok_or(D3DERR_INVALIDCALL)?;
         let target_decl = self.context.get_target_nullable(pvertexdecl).ok_or(D3DERR_INVALIDCALL)?;
@@ -925,7 +1398,7 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9_Impl {
         unsafe { self.CreateVertexDeclaration_Impl(|| self.to_interface(), pvertexelements) }
     }
 
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(pdecl)))]
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(pdecl), fields(pdecl = ?TracedObject::new(pdecl.as_ref()))))]
     fn SetVertexDeclaration(&self, pdecl: Ref<IDirect3DVertexDeclaration9>) -> Result<()> {
         let target = self.context.get_target_nullable(pdecl).ok_or(D3DERR_INVALIDCALL)?;
         unsafe { self.target.SetVertexDeclaration(target) }
@@ -951,7 +1424,7 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9_Impl {
         unsafe { self.CreateVertexShader_Impl(|| self.to_interface(), pfunction) }
     }
 
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(pshader)))]
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(pshader), fields(pshader = ?TracedObject::new(pshader.as_ref()))))]
     fn SetVertexShader(&self, pshader: Ref<IDirect3DVertexShader9>) -> Result<()> {
         let target = self.context.get_target_nullable(pshader).ok_or(D3DERR_INVALIDCALL)?;
         unsafe { self.target.SetVertexShader(target) }
@@ -964,7 +1437,11 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9_Impl {
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn SetVertexShaderConstantF(&self, startregister: u32, pconstantdata: *const f32, vector4fcount: u32) -> Result<()> {
-        unsafe { self.target.SetVertexShaderConstantF(startregister, pconstantdata, vector4fcount) }
+        unsafe { self.target.SetVertexShaderConstantF(startregister, pconstantdata, vector4fcount) }?;
+        if let Some(mut shadow) = self.context.lock_shader_constant_shadow() {
+            unsafe { shadow.set_vertex_constants(startregister, pconstantdata, vector4fcount) };
+        }
+        Ok(())
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
@@ -992,7 +1469,7 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9_Impl {
         unsafe { self.target.GetVertexShaderConstantB(startregister, pconstantdata, boolcount) }
     }
 
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(pstreamdata)))]
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(pstreamdata), fields(pstreamdata = ?TracedObject::new(pstreamdata.as_ref()))))]
     fn SetStreamSource(&self, streamnumber: u32, pstreamdata: Ref<IDirect3DVertexBuffer9>, offsetinbytes: u32, stride: u32) -> Result<()> {
         let target = self.context.get_target_nullable(pstreamdata).ok_or(D3DERR_INVALIDCALL)?;
         unsafe { self.target.SetStreamSource(streamnumber, target, offsetinbytes, stride) }
@@ -1005,7 +1482,19 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9_Impl {
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn SetStreamSourceFreq(&self, streamnumber: u32, setting: u32) -> Result<()> {
-        unsafe { self.target.SetStreamSourceFreq(streamnumber, setting) }
+        let is_instanced = setting & (D3DSTREAMSOURCE_INDEXEDDATA | D3DSTREAMSOURCE_INSTANCEDATA) != 0;
+        let effective_setting = if is_instanced && self.context.get_config().disable_instancing { 1 } else { setting };
+
+        #[cfg(feature = "tracing")]
+        if is_instanced {
+            tracing::trace!(
+                "Stream {streamnumber} frequency: {} -> {}",
+                describe_stream_source_freq(setting),
+                describe_stream_source_freq(effective_setting)
+            );
+        }
+
+        unsafe { self.target.SetStreamSourceFreq(streamnumber, effective_setting) }
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
@@ -1013,10 +1502,13 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9_Impl {
         unsafe { self.target.GetStreamSourceFreq(streamnumber, psetting) }
     }
 
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(pindexdata)))]
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(pindexdata), fields(pindexdata = ?TracedObject::new(pindexdata.as_ref()))))]
     fn SetIndices(&self, pindexdata: Ref<IDirect3DIndexBuffer9>) -> Result<()> {
-        let target = self.context.get_target_nullable(pindexdata).ok_or(D3DERR_INVALIDCALL)?;
-        unsafe { self.target.SetIndices(target) }
+        let target = self.context.get_target_nullable(pindexdata.as_ref()).ok_or(D3DERR_INVALIDCALL)?;
+        unsafe { self.target.SetIndices(target) }?;
+
+        self.context.set_bound_index_buffer(pindexdata.as_ref());
+        Ok(())
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
@@ -1029,7 +1521,7 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9_Impl {
         unsafe { self.CreatePixelShader_Impl(|| self.to_interface(), pfunction) }
     }
 
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(pshader)))]
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(pshader), fields(pshader = ?TracedObject::new(pshader.as_ref()))))]
     fn SetPixelShader(&self, pshader: Ref<IDirect3DPixelShader9>) -> Result<()> {
         let target = self.context.get_target_nullable(pshader).ok_or(D3DERR_INVALIDCALL)?;
         unsafe { self.target.SetPixelShader(target) }
@@ -1042,7 +1534,11 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9_Impl {
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn SetPixelShaderConstantF(&self, startregister: u32, pconstantdata: *const f32, vector4fcount: u32) -> Result<()> {
-        unsafe { self.target.SetPixelShaderConstantF(startregister, pconstantdata, vector4fcount) }
+        unsafe { self.target.SetPixelShaderConstantF(startregister, pconstantdata, vector4fcount) }?;
+        if let Some(mut shadow) = self.context.lock_shader_constant_shadow() {
+            unsafe { shadow.set_pixel_constants(startregister, pconstantdata, vector4fcount) };
+        }
+        Ok(())
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
@@ -1090,3 +1586,675 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9_Impl {
         unsafe { self.CreateQuery_Impl(|| self.to_interface(), r#type) }
     }
 }
+
+/// Applies [`DX9ProxyConfig::max_frame_latency`] to a newly created target device.
+///
+/// # Arguments
+/// * `target` - The target device, which must be an [`IDirect3DDevice9Ex`] for the setting to take effect.
+/// * `config` - The configuration of the device.
+pub(super) fn apply_max_frame_latency(target: &IDirect3DDevice9, config: &DX9ProxyConfig) {
+    let Some(max_frame_latency) = config.max_frame_latency else {
+        return;
+    };
+
+    match target.cast::<IDirect3DDevice9Ex>() {
+        Ok(target_ex) => {
+            let _ = unsafe { target_ex.SetMaximumFrameLatency(max_frame_latency) }.inspect_err(|_err| {
+                #[cfg(feature = "tracing")]
+                tracing::warn!("Failed to set maximum frame latency to {max_frame_latency}: {_err}");
+            });
+        }
+        Err(_) => {
+            #[cfg(feature = "tracing")]
+            tracing::warn!("Maximum frame latency requires IDirect3DDevice9Ex, ignoring for {target:?}; consider DXPROXY_FLUSH_AFTER_PRESENT instead");
+        }
+    }
+}
+
+/// Checks whether textures of `format` can be created with `usage` including `D3DUSAGE_AUTOGENMIPMAP` on `target`.
+///
+/// See [`DX9ProxyConfig::force_autogen_mipmaps`].
+fn supports_autogen_mipmaps(target: &IDirect3DDevice9, usage: u32, format: D3DFORMAT) -> Result<bool> {
+    let mut parameters = D3DDEVICE_CREATION_PARAMETERS::default();
+    unsafe { target.GetCreationParameters(&mut parameters) }?;
+    let mut display_mode = D3DDISPLAYMODE::default();
+    unsafe { target.GetDisplayMode(0, &mut display_mode) }?;
+    let direct3d = unsafe { target.GetDirect3D() }?;
+
+    // `CheckDeviceFormat` reports missing support for automatic mipmap generation with the success code
+    // D3DOK_NOAUTOGEN, which `IDirect3D9::CheckDeviceFormat` maps to `Ok`, so call through the vtable
+    let result = unsafe {
+        (Interface::vtable(&direct3d).CheckDeviceFormat)(
+            Interface::as_raw(&direct3d),
+            parameters.AdapterOrdinal,
+            parameters.DeviceType,
+            display_mode.Format,
+            usage,
+            D3DRTYPE_TEXTURE,
+            format,
+        )
+    };
+    if result == D3DOK_NOAUTOGEN || result == D3DERR_NOTAVAILABLE {
+        // Warn only once per format, as games create many textures of the same format
+        #[cfg(feature = "tracing")]
+        {
+            static WARNED_FORMATS: std::sync::Mutex<Vec<D3DFORMAT>> = std::sync::Mutex::new(Vec::new());
+            let mut warned_formats = WARNED_FORMATS.lock().unwrap();
+            if !warned_formats.contains(&format) {
+                warned_formats.push(format);
+                tracing::warn!("Automatic mipmap generation is not supported for {format:?}, keeping textures without mipmaps");
+            }
+        }
+        return Ok(false);
+    }
+    result.ok()?;
+    Ok(true)
+}
+
+/// Returns the window configured by a window override, if it is a valid window.
+fn valid_window_override(_name: &str, window: Option<isize>) -> Option<HWND> {
+    let window = HWND(window? as *mut c_void);
+    if !unsafe { IsWindow(Some(window)) }.as_bool() {
+        #[cfg(feature = "tracing")]
+        tracing::warn!("Ignoring {_name} override {window:?}, which is not a valid window");
+        return None;
+    }
+    Some(window)
+}
+
+/// Applies [`DX9ProxyConfig::focus_window_override`] at device creation.
+///
+/// # Arguments
+/// * `config` - The configuration of the device.
+/// * `hfocuswindow` - The focus window passed by the application.
+///
+/// # Returns
+/// The focus window to create the device with.
+pub(super) fn apply_focus_window_override(config: &DX9ProxyConfig, hfocuswindow: HWND) -> HWND {
+    match valid_window_override("focus window", config.focus_window_override) {
+        Some(window) => {
+            #[cfg(feature = "tracing")]
+            tracing::info!("Replacing focus window {hfocuswindow:?} with {window:?}");
+            window
+        }
+        None => hfocuswindow,
+    }
+}
+
+/// Applies [`DX9ProxyConfig::disable_driver_management`] and [`DX9ProxyConfig::disable_driver_management_ex`]
+/// to the behavior flags passed to device creation.
+///
+/// # Returns
+/// The behavior flags to create the device with.
+pub(super) fn apply_behavior_flag_overrides(config: &DX9ProxyConfig, behaviorflags: u32) -> u32 {
+    let mut flags = behaviorflags;
+    for (value, flag) in [
+        (config.disable_driver_management, D3DCREATE_DISABLE_DRIVER_MANAGEMENT as u32),
+        (config.disable_driver_management_ex, D3DCREATE_DISABLE_DRIVER_MANAGEMENT_EX as u32),
+    ] {
+        match value {
+            Some(true) => flags |= flag,
+            Some(false) => flags &= !flag,
+            None => {}
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    if flags != behaviorflags {
+        tracing::info!("Replacing behavior flags {} with {}", describe_create_flags(behaviorflags), describe_create_flags(flags));
+    }
+    flags
+}
+
+/// Applies [`DX9ProxyConfig::fullscreen_monitor`] to the adapter passed to device creation.
+///
+/// Adapters out of range are ignored with a warning.
+///
+/// # Returns
+/// The adapter to create the device on.
+pub(super) fn apply_fullscreen_monitor_override(config: &DX9ProxyConfig, direct3d: &IDirect3D9, adapter: u32) -> u32 {
+    let Some(monitor_adapter) = config.fullscreen_monitor else {
+        return adapter;
+    };
+
+    let adapter_count = unsafe { direct3d.GetAdapterCount() };
+    if monitor_adapter >= adapter_count {
+        #[cfg(feature = "tracing")]
+        tracing::warn!("Ignoring fullscreen monitor {monitor_adapter}, as there are only {adapter_count} adapters");
+        return adapter;
+    }
+
+    #[cfg(feature = "tracing")]
+    {
+        let monitor = unsafe { direct3d.GetAdapterMonitor(monitor_adapter) };
+        let mut monitor_info = MONITORINFOEXW::default();
+        monitor_info.monitorInfo.cbSize = size_of::<MONITORINFOEXW>() as u32;
+        if unsafe { GetMonitorInfoW(monitor, &mut monitor_info.monitorInfo) }.as_bool() {
+            let rect = monitor_info.monitorInfo.rcMonitor;
+            let name_len = monitor_info.szDevice.iter().position(|&c| c == 0).unwrap_or(monitor_info.szDevice.len());
+            tracing::info!(
+                "Replacing adapter {adapter} with {monitor_adapter} on monitor {} ({}, {})-({}, {})",
+                String::from_utf16_lossy(&monitor_info.szDevice[..name_len]),
+                rect.left,
+                rect.top,
+                rect.right,
+                rect.bottom
+            );
+        } else {
+            tracing::info!("Replacing adapter {adapter} with {monitor_adapter} on monitor {monitor:?}");
+        }
+    }
+    monitor_adapter
+}
+
+/// Applies [`DX9ProxyConfig::device_window_override`] to presentation parameters passed to device creation or reset.
+pub(super) fn apply_device_window_override(config: &DX9ProxyConfig, params: &mut D3DPRESENT_PARAMETERS) {
+    if let Some(window) = valid_window_override("device window", config.device_window_override) {
+        #[cfg(feature = "tracing")]
+        tracing::info!("Replacing device window {:?} with {window:?}", params.hDeviceWindow);
+        params.hDeviceWindow = window;
+    }
+}
+
+/// Applies [`DX9ProxyConfig::back_buffer_format_override`] to presentation parameters passed to device creation or reset.
+///
+/// The format is only replaced if `CheckDeviceType` reports it as presentable in the requested windowed or fullscreen mode.
+///
+/// # Arguments
+/// * `config` - The configuration of the device.
+/// * `direct3d` - The target [`IDirect3D9`] the device is created with.
+/// * `adapter` - The adapter of the device.
+/// * `devicetype` - The type of the device.
+/// * `params` - The presentation parameters, whose `BackBufferFormat` is replaced.
+pub(super) fn apply_back_buffer_format_override(config: &DX9ProxyConfig, direct3d: &IDirect3D9, adapter: u32, devicetype: D3DDEVTYPE, params: &mut D3DPRESENT_PARAMETERS) {
+    let Some(format) = config.back_buffer_format_override else {
+        return;
+    };
+    if params.BackBufferFormat == format {
+        return;
+    }
+
+    let windowed = params.Windowed.as_bool();
+    let adapter_format = if windowed {
+        let mut mode = D3DDISPLAYMODE::default();
+        if let Err(_err) = unsafe { direct3d.GetAdapterDisplayMode(adapter, &mut mode) } {
+            #[cfg(feature = "tracing")]
+            tracing::warn!("Failed to get display mode for back buffer format override: {_err}");
+            return;
+        }
+        mode.Format
+    } else {
+        fullscreen_display_format(format)
+    };
+    if let Err(_err) = unsafe { direct3d.CheckDeviceType(adapter, devicetype, adapter_format, format, windowed) } {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(
+            "Keeping back buffer format {:?}, as {format:?} is not presentable on {adapter_format:?}: {_err}",
+            params.BackBufferFormat
+        );
+        return;
+    }
+
+    #[cfg(feature = "tracing")]
+    tracing::info!("Replacing back buffer format {:?} with {format:?}", params.BackBufferFormat);
+    params.BackBufferFormat = format;
+}
+
+/// Applies the overrides of presentation parameters passed to `Reset` or `ResetEx`.
+pub(super) fn apply_reset_overrides(context: &DX9ProxyDeviceContext, target: &IDirect3DDevice9, params: &mut D3DPRESENT_PARAMETERS) {
+    apply_device_window_override(context.get_config(), params);
+    apply_back_buffer_format_override_on_reset(context.get_config(), target, params);
+}
+
+/// Returns the number of presentation parameters passed to `Reset` or `ResetEx` of `target`, see [`present::params_count`].
+pub(super) fn reset_params_count(target: &IDirect3DDevice9) -> Option<usize> {
+    let mut creation_parameters = D3DDEVICE_CREATION_PARAMETERS::default();
+    if let Err(_err) = unsafe { target.GetCreationParameters(&mut creation_parameters) } {
+        #[cfg(feature = "tracing")]
+        tracing::warn!("Failed to get creation parameters, not overriding presentation parameters: {_err}");
+        return None;
+    }
+    present::params_count(creation_parameters.BehaviorFlags, |caps| unsafe { target.GetDeviceCaps(caps) })
+}
+
+/// Applies [`DX9ProxyConfig::back_buffer_format_override`] to presentation parameters passed to `Reset` or `ResetEx`.
+///
+/// The adapter and device type are taken from the creation parameters of `target`.
+pub(super) fn apply_back_buffer_format_override_on_reset(config: &DX9ProxyConfig, target: &IDirect3DDevice9, params: &mut D3DPRESENT_PARAMETERS) {
+    if config.back_buffer_format_override.is_none() {
+        return;
+    }
+
+    let mut creation_parameters = D3DDEVICE_CREATION_PARAMETERS::default();
+    match unsafe { target.GetCreationParameters(&mut creation_parameters) }.and_then(|()| unsafe { target.GetDirect3D() }) {
+        Ok(direct3d) => apply_back_buffer_format_override(config, &direct3d, creation_parameters.AdapterOrdinal, creation_parameters.DeviceType, params),
+        Err(_err) => {
+            #[cfg(feature = "tracing")]
+            tracing::warn!("Failed to get creation parameters for back buffer format override: {_err}");
+        }
+    }
+}
+
+/// Returns the display format used with a fullscreen back buffer format, which is the format without alpha.
+fn fullscreen_display_format(format: D3DFORMAT) -> D3DFORMAT {
+    match format {
+        D3DFMT_A8R8G8B8 => D3DFMT_X8R8G8B8,
+        D3DFMT_A1R5G5B5 => D3DFMT_X1R5G5B5,
+        format => format,
+    }
+}
+
+/// Re-asserts or releases the cursor clip of [`DX9ProxyConfig::clip_cursor_in_window`], to be called at each `Present`.
+pub(super) fn update_cursor_clip(target: &IDirect3DDevice9, context: &DX9ProxyDeviceContext) {
+    if context.get_config().clip_cursor_in_window {
+        context.set_cursor_clip(cursor_clip_rect(target, context));
+    }
+}
+
+/// Returns the client area of the device window in screen coordinates, if the device is windowed
+/// and its window is in the foreground.
+fn cursor_clip_rect(target: &IDirect3DDevice9, context: &DX9ProxyDeviceContext) -> Option<RECT> {
+    let params = context.get_present_params()?;
+    if !params.Windowed.as_bool() {
+        return None;
+    }
+
+    // Windowed devices without a device window present to the focus window
+    let mut window = params.hDeviceWindow;
+    if window.is_invalid() {
+        let mut creation_parameters = D3DDEVICE_CREATION_PARAMETERS::default();
+        unsafe { target.GetCreationParameters(&mut creation_parameters) }.ok()?;
+        window = creation_parameters.hFocusWindow;
+    }
+
+    let foreground = unsafe { GetForegroundWindow() };
+    if window != foreground && unsafe { GetAncestor(window, GA_ROOT) } != foreground {
+        return None;
+    }
+
+    let mut client_rect = RECT::default();
+    unsafe { GetClientRect(window, &mut client_rect) }.ok()?;
+    let mut origin = POINT::default();
+    if client_rect.right <= 0 || client_rect.bottom <= 0 || !unsafe { ClientToScreen(window, &mut origin) }.as_bool() {
+        return None;
+    }
+    Some(RECT {
+        left: origin.x,
+        top: origin.y,
+        right: origin.x + client_rect.right,
+        bottom: origin.y + client_rect.bottom,
+    })
+}
+
+/// Number of pixel shader samplers the forced sampler states are applied to.
+const FORCED_SAMPLER_COUNT: u32 = 16;
+
+/// Re-applies the render and sampler states forced by the configuration.
+///
+/// Called at each `BeginScene` and after state blocks are applied, so that forced states also survive
+/// state blocks and device resets, and so that hotkey changes take effect.
+/// See [`DX9ProxyConfig::forced_render_states`] and [`DX9ProxyConfig::forced_sampler_states`].
+pub(super) fn apply_forced_states(target: &IDirect3DDevice9, context: &DX9ProxyDeviceContext) {
+    for (state, value) in context.take_forced_render_states() {
+        let _ = unsafe { target.SetRenderState(state, value) }.inspect_err(|_err| {
+            #[cfg(feature = "tracing")]
+            tracing::warn!("Failed to force render state {state:?} to {value}: {_err}");
+        });
+    }
+
+    for &(r#type, value) in context.get_forced_sampler_states() {
+        for sampler in 0..FORCED_SAMPLER_COUNT {
+            let _ = unsafe { target.SetSamplerState(sampler, r#type, value) }.inspect_err(|_err| {
+                #[cfg(feature = "tracing")]
+                tracing::warn!("Failed to force sampler state {type:?} of sampler {sampler} to {value}: {_err}", type = r#type);
+            });
+        }
+    }
+}
+
+/// Waits until the GPU has finished all work queued on `target`.
+///
+/// Issues an event query and polls it with `D3DGETDATA_FLUSH`. Errors (e.g. a lost device) stop the wait.
+/// See [`DX9ProxyConfig::flush_after_present`] and [`DX9ProxyConfig::flush_on_drop`].
+pub(super) fn flush_device(target: &IDirect3DDevice9) {
+    let query = match unsafe { target.CreateQuery(D3DQUERYTYPE_EVENT) } {
+        Ok(query) => query,
+        Err(_err) => {
+            #[cfg(feature = "tracing")]
+            tracing::warn!("Failed to create event query for flushing: {_err}");
+            return;
+        }
+    };
+
+    if unsafe { query.Issue(D3DISSUE_END) }.is_err() {
+        return;
+    }
+
+    let _ = wait_for_query(&query, WaitMode::Spin);
+}
+
+/// Decodes a `SetStreamSourceFreq` setting into a readable description.
+#[cfg(feature = "tracing")]
+fn describe_stream_source_freq(setting: u32) -> String {
+    let value = setting & !(D3DSTREAMSOURCE_INDEXEDDATA | D3DSTREAMSOURCE_INSTANCEDATA);
+    if setting & D3DSTREAMSOURCE_INDEXEDDATA != 0 {
+        format!("INDEXEDDATA ({value} instances)")
+    } else if setting & D3DSTREAMSOURCE_INSTANCEDATA != 0 {
+        format!("INSTANCEDATA (divisor {value})")
+    } else {
+        format!("{value}")
+    }
+}
+
+/// Clamps a viewport so that it does not extend beyond a render target of `width` x `height`.
+fn clamp_viewport(viewport: &D3DVIEWPORT9, width: u32, height: u32) -> D3DVIEWPORT9 {
+    let x = viewport.X.min(width);
+    let y = viewport.Y.min(height);
+    D3DVIEWPORT9 {
+        X: x,
+        Y: y,
+        Width: viewport.Width.min(width - x),
+        Height: viewport.Height.min(height - y),
+        ..*viewport
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dx9::com::mock::*;
+
+    fn proxy_device() -> (ComObject<MockDevice>, IDirect3DDevice9) {
+        proxy_device_with_config(DX9ProxyConfig::default())
+    }
+
+    fn proxy_device_with_config(config: DX9ProxyConfig) -> (ComObject<MockDevice>, IDirect3DDevice9) {
+        let mock = ComObject::new(MockDevice::default());
+        let target: IDirect3DDevice9Ex = mock.to_interface();
+        let container: IDirect3D9Ex = MockDirect3D9::default().into();
+        let proxy = ProxyDirect3DDevice9::new(target.into(), config, container.into(), None).into();
+        (mock, proxy)
+    }
+
+    fn create_texture(device: &IDirect3DDevice9, pool: D3DPOOL) -> IDirect3DTexture9 {
+        try_out_param(|out| unsafe { device.CreateTexture(64, 64, 1, 0, D3DFMT_A8R8G8B8, pool, out, std::ptr::null_mut()) }).unwrap()
+    }
+
+    #[test]
+    fn update_texture_translates_proxied_textures() {
+        let (mock, device) = proxy_device();
+        let source = create_texture(&device, D3DPOOL_SYSTEMMEM);
+        let dest = create_texture(&device, D3DPOOL_DEFAULT);
+
+        unsafe { device.UpdateTexture(&source, &dest) }.unwrap();
+
+        let state = mock.state.lock().unwrap();
+        let [target_source, target_dest] = &state.textures[..] else {
+            panic!("expected two target textures, got {:?}", state.textures);
+        };
+        assert_ne!(target_source, &source);
+        assert_eq!(state.updated_textures, [(Some(target_source.clone().into()), Some(target_dest.clone().into()))]);
+    }
+
+    #[test]
+    fn update_texture_finds_proxies_through_any_interface() {
+        let (mock, device) = proxy_device();
+        let source = create_texture(&device, D3DPOOL_SYSTEMMEM);
+        let dest = create_texture(&device, D3DPOOL_DEFAULT);
+        let source_base: IDirect3DBaseTexture9 = source.cast().unwrap();
+        let dest_base: IDirect3DBaseTexture9 = dest.cast::<IUnknown>().unwrap().cast().unwrap();
+        let dest_resource: IDirect3DResource9 = dest.cast().unwrap();
+
+        unsafe { device.UpdateTexture(&source_base, &dest_base) }.unwrap();
+        unsafe { device.UpdateTexture(&source, &dest_resource.cast::<IDirect3DBaseTexture9>().unwrap()) }.unwrap();
+
+        let state = mock.state.lock().unwrap();
+        let expected = (Some(state.textures[0].clone().into()), Some(state.textures[1].clone().into()));
+        assert_eq!(state.updated_textures, [expected.clone(), expected]);
+    }
+
+    #[test]
+    fn update_texture_rejects_unproxied_textures() {
+        let (mock, device) = proxy_device();
+        let source = create_texture(&device, D3DPOOL_SYSTEMMEM);
+        let unproxied: IDirect3DTexture9 = MockTexture { log: mock.log.clone() }.into();
+
+        assert_eq!(unsafe { device.UpdateTexture(&source, &unproxied) }.unwrap_err().code(), D3DERR_INVALIDCALL);
+        assert_eq!(mock.log.count("UpdateTexture"), 0);
+    }
+
+    #[test]
+    fn set_viewport_clamps_to_render_target() {
+        let (mock, device) = proxy_device_with_config(DX9ProxyConfig {
+            clamp_viewport: true,
+            ..Default::default()
+        });
+        mock.state.lock().unwrap().render_target = Some(MockSurface::new(mock.log.clone(), 100, 50, D3DFMT_A8R8G8B8, 400).into());
+        let viewport = D3DVIEWPORT9 {
+            X: 20,
+            Y: 10,
+            Width: 200,
+            Height: 200,
+            MinZ: 0.0,
+            MaxZ: 1.0,
+        };
+
+        for _ in 0..2 {
+            unsafe { device.SetViewport(&viewport) }.unwrap();
+            assert_eq!(mock.state.lock().unwrap().viewport, D3DVIEWPORT9 { Width: 80, Height: 40, ..viewport });
+        }
+        assert_eq!(mock.log.count("GetRenderTarget"), 1);
+        let proxy: &ProxyDirect3DDevice9 = unsafe { device.as_impl() };
+        assert!(!proxy.get_context().on_viewport_clamped());
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn set_viewport_warns_on_first_clamp_only() {
+        let (mock, device) = proxy_device_with_config(DX9ProxyConfig {
+            clamp_viewport: true,
+            ..Default::default()
+        });
+        mock.state.lock().unwrap().render_target = Some(MockSurface::new(mock.log.clone(), 100, 50, D3DFMT_A8R8G8B8, 400).into());
+        let viewport = D3DVIEWPORT9 {
+            Width: 200,
+            Height: 200,
+            ..Default::default()
+        };
+
+        let events = crate::with_captured_logs(|| {
+            for _ in 0..3 {
+                unsafe { device.SetViewport(&viewport) }.unwrap();
+            }
+        });
+        let levels: Vec<_> = events.iter().filter(|event| event.message.starts_with("Clamped viewport")).map(|event| event.level).collect();
+        assert_eq!(levels, [tracing::Level::WARN, tracing::Level::DEBUG, tracing::Level::DEBUG]);
+    }
+
+    #[test]
+    fn get_light_enable_passes_nonzero_values_through() {
+        let (mock, device) = proxy_device();
+        mock.state.lock().unwrap().light_enables.extend([(0, BOOL(0x80)), (1, FALSE)]);
+
+        let mut enable = TRUE;
+        unsafe { device.GetLightEnable(0, &mut enable) }.unwrap();
+        assert_eq!(enable, BOOL(0x80));
+        assert!(enable.as_bool());
+        unsafe { device.GetLightEnable(1, &mut enable) }.unwrap();
+        assert!(!enable.as_bool());
+    }
+
+    #[test]
+    fn forced_render_states_survive_set_render_state_and_begin_scene() {
+        let (mock, device) = proxy_device_with_config(DX9ProxyConfig {
+            force_z_enable: Some(false),
+            ..Default::default()
+        });
+        let render_state = |state: D3DRENDERSTATETYPE| mock.state.lock().unwrap().render_states.get(&state.0).copied();
+
+        unsafe { device.SetRenderState(D3DRS_ZENABLE, 1) }.unwrap();
+        unsafe { device.SetRenderState(D3DRS_ZWRITEENABLE, 1) }.unwrap();
+        assert_eq!(render_state(D3DRS_ZENABLE), Some(0));
+        assert_eq!(render_state(D3DRS_ZWRITEENABLE), Some(1));
+
+        // Simulate a change behind the proxy's back, e.g. by a state block
+        mock.state.lock().unwrap().render_states.insert(D3DRS_ZENABLE.0, 1);
+        unsafe { device.BeginScene() }.unwrap();
+        assert_eq!(render_state(D3DRS_ZENABLE), Some(0));
+    }
+
+    fn create_render_target(device: &IDirect3DDevice9) -> IDirect3DSurface9 {
+        try_out_param(|out| unsafe { device.CreateRenderTarget(64, 64, D3DFMT_A8R8G8B8, D3DMULTISAMPLE_NONE, 0, false, out, std::ptr::null_mut()) }).unwrap()
+    }
+
+    fn create_depth_stencil(device: &IDirect3DDevice9) -> IDirect3DSurface9 {
+        try_out_param(|out| unsafe { device.CreateDepthStencilSurface(64, 64, D3DFMT_D24S8, D3DMULTISAMPLE_NONE, 0, false, out, std::ptr::null_mut()) }).unwrap()
+    }
+
+    #[test]
+    fn surface_getters_cache_proxies() {
+        let (mock, device) = proxy_device();
+        let mut state = mock.state.lock().unwrap();
+        state.render_target = Some(MockSurface::new(mock.log.clone(), 64, 64, D3DFMT_A8R8G8B8, 256).into());
+        state.depth_stencil = Some(MockSurface::new(mock.log.clone(), 64, 64, D3DFMT_D24S8, 256).into());
+        drop(state);
+
+        let render_target = unsafe { device.GetRenderTarget(0) }.unwrap();
+        assert_eq!(unsafe { device.GetRenderTarget(0) }.unwrap(), render_target);
+        assert_ne!(Some(&render_target), mock.state.lock().unwrap().render_target.as_ref());
+        assert_eq!(mock.log.count("GetRenderTarget"), 1);
+
+        let depth_stencil = unsafe { device.GetDepthStencilSurface() }.unwrap();
+        assert_eq!(unsafe { device.GetDepthStencilSurface() }.unwrap(), depth_stencil);
+        assert_eq!(mock.log.count("GetDepthStencilSurface"), 1);
+    }
+
+    #[test]
+    fn setting_surfaces_invalidates_cached_proxies() {
+        let (mock, device) = proxy_device();
+        let first = create_render_target(&device);
+        let second = create_render_target(&device);
+        let depth_stencil = create_depth_stencil(&device);
+
+        unsafe { device.SetRenderTarget(0, &first) }.unwrap();
+        assert_eq!(unsafe { device.GetRenderTarget(0) }.unwrap(), first);
+        unsafe { device.SetRenderTarget(0, &second) }.unwrap();
+        assert_eq!(unsafe { device.GetRenderTarget(0) }.unwrap(), second);
+        assert_eq!(mock.log.count("GetRenderTarget"), 2);
+
+        unsafe { device.SetDepthStencilSurface(None) }.unwrap();
+        assert!(unsafe { device.GetDepthStencilSurface() }.is_err());
+        unsafe { device.SetDepthStencilSurface(&depth_stencil) }.unwrap();
+        assert_eq!(unsafe { device.GetDepthStencilSurface() }.unwrap(), depth_stencil);
+        assert_eq!(mock.log.count("GetDepthStencilSurface"), 2);
+    }
+
+    #[test]
+    fn reset_clears_cached_proxies() {
+        let (mock, device) = proxy_device();
+        let render_target = create_render_target(&device);
+        unsafe { device.SetRenderTarget(0, &render_target) }.unwrap();
+        assert_eq!(unsafe { device.GetRenderTarget(0) }.unwrap(), render_target);
+
+        // Resetting recreates the back buffer, which becomes the render target
+        let mut params = D3DPRESENT_PARAMETERS::default();
+        unsafe { device.Reset(&mut params) }.unwrap();
+        let back_buffer: IDirect3DSurface9 = MockSurface::new(mock.log.clone(), 64, 64, D3DFMT_A8R8G8B8, 256).into();
+        mock.state.lock().unwrap().render_target = Some(back_buffer);
+
+        let reset_render_target = unsafe { device.GetRenderTarget(0) }.unwrap();
+        assert_ne!(reset_render_target, render_target);
+        assert_eq!(mock.log.count("GetRenderTarget"), 2);
+    }
+
+    #[test]
+    fn set_indices_records_weak_proxy_only_when_capturing() {
+        for capture_index_buffers in [false, true] {
+            let (_mock, device) = proxy_device_with_config(DX9ProxyConfig {
+                capture_index_buffers,
+                ..Default::default()
+            });
+            let index_buffer = try_out_param(|out| unsafe { device.CreateIndexBuffer(64, 0, D3DFMT_INDEX16, D3DPOOL_DEFAULT, out, std::ptr::null_mut()) }).unwrap();
+            unsafe { device.SetIndices(&index_buffer) }.unwrap();
+
+            let proxy: &ProxyDirect3DDevice9 = unsafe { device.as_impl() };
+            assert_eq!(proxy.get_context().get_bound_index_buffer(), capture_index_buffers.then(|| index_buffer.clone()));
+
+            // The binding does not keep the proxy alive
+            drop(index_buffer);
+            assert_eq!(proxy.get_context().get_bound_index_buffer(), None);
+        }
+    }
+
+    /// Performs `draw` on a proxy device with stream 0 and the indices bound, and returns the draws that
+    /// reached the target, and whether stream 0 and the indices are still bound afterwards.
+    fn user_pointer_draw(optimize_up_draws: bool, draw: impl FnOnce(&IDirect3DDevice9)) -> (Vec<MockDraw>, bool, bool) {
+        let (mock, device) = proxy_device_with_config(DX9ProxyConfig {
+            optimize_up_draws,
+            ..Default::default()
+        });
+        let vertex_buffer = try_out_param(|out| unsafe { device.CreateVertexBuffer(256, 0, 0, D3DPOOL_DEFAULT, out, std::ptr::null_mut()) }).unwrap();
+        let index_buffer = try_out_param(|out| unsafe { device.CreateIndexBuffer(256, 0, D3DFMT_INDEX16, D3DPOOL_DEFAULT, out, std::ptr::null_mut()) }).unwrap();
+        unsafe {
+            device.SetStreamSource(0, &vertex_buffer, 0, 16).unwrap();
+            device.SetIndices(&index_buffer).unwrap();
+        }
+
+        draw(&device);
+
+        // The optimized draws must not fall back to the user-pointer draws
+        let user_pointer_draws = mock.log.count("DrawPrimitiveUP") + mock.log.count("DrawIndexedPrimitiveUP");
+        assert_eq!(user_pointer_draws == 0, optimize_up_draws);
+        let mut state = mock.state.lock().unwrap();
+        (std::mem::take(&mut state.draws), state.stream_zero.is_some(), state.indices.is_some())
+    }
+
+    #[test]
+    fn optimized_draw_primitive_up_matches_user_pointer_draw() {
+        let vertices: Vec<u8> = (0..48).collect();
+        let draw = |device: &IDirect3DDevice9| unsafe { device.DrawPrimitiveUP(D3DPT_TRIANGLESTRIP, 2, vertices.as_ptr().cast(), 12) }.unwrap();
+
+        let (draws, stream_bound, indices_bound) = user_pointer_draw(false, draw);
+        assert_eq!(
+            draws,
+            [MockDraw {
+                primitive_type: D3DPT_TRIANGLESTRIP,
+                primitive_count: 2,
+                num_vertices: 4,
+                stride: 12,
+                vertices: vertices.clone(),
+                ..Default::default()
+            }]
+        );
+        assert_eq!((stream_bound, indices_bound), (false, true));
+        assert_eq!(user_pointer_draw(true, draw), (draws, false, true));
+    }
+
+    #[test]
+    fn optimized_draw_indexed_primitive_up_matches_user_pointer_draw() {
+        let vertices: Vec<u8> = (0..60).collect();
+        let indices16: Vec<u8> = [1u16, 2, 3, 3, 2, 4].iter().flat_map(|index| index.to_le_bytes()).collect();
+        let indices32: Vec<u8> = [1u32, 2, 3, 3, 2, 4].iter().flat_map(|index| index.to_le_bytes()).collect();
+
+        for (indices, format) in [(indices16, D3DFMT_INDEX16), (indices32, D3DFMT_INDEX32)] {
+            let draw = |device: &IDirect3DDevice9| unsafe { device.DrawIndexedPrimitiveUP(D3DPT_TRIANGLELIST, 1, 4, 2, indices.as_ptr().cast(), format, vertices.as_ptr().cast(), 12) }.unwrap();
+
+            let (draws, stream_bound, indices_bound) = user_pointer_draw(false, draw);
+            assert_eq!(
+                draws,
+                [MockDraw {
+                    primitive_type: D3DPT_TRIANGLELIST,
+                    primitive_count: 2,
+                    min_vertex_index: 1,
+                    num_vertices: 4,
+                    stride: 12,
+                    vertices: vertices.clone(),
+                    indices: indices.clone(),
+                    index_format: format,
+                }]
+            );
+            assert_eq!((stream_bound, indices_bound), (false, false));
+            assert_eq!(user_pointer_draw(true, draw), (draws, false, false));
+        }
+    }
+}
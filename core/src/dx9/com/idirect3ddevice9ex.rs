@@ -26,6 +26,12 @@ use windows_numerics::Matrix4x4;
 /// including resource residency checks, presentation controls, and GPU priority management.
 ///
 /// Methods of [`IDirect3DDevice9`] are delegated to the inner [`IDirect3DDevice9`] proxy, which is implemented by [`ProxyDirect3DDevice9`].
+///
+/// # Drop Order
+/// Fields are dropped in declaration order: the inner proxy first, so its `Drop` runs while this
+/// object still holds the target device, then the target, and the context last. Resource proxies
+/// hold their own clone of the context, so the context outlives every proxy that accesses it in its
+/// `Drop`, whichever order device fields are dropped in.
 #[implement(IDirect3DDevice9Ex)]
 #[derive(Debug)]
 pub struct ProxyDirect3DDevice9Ex {
@@ -36,8 +42,8 @@ pub struct ProxyDirect3DDevice9Ex {
 
 impl ProxyDirect3DDevice9Ex {
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(ret))]
-    pub fn new(target: IDirect3DDevice9Ex, config: DX9ProxyConfig, container: IDirect3D9Ex) -> Self {
-        let proxy = ProxyDirect3DDevice9::new(target.clone().into(), config, container.into());
+    pub fn new(target: IDirect3DDevice9Ex, config: DX9ProxyConfig, container: IDirect3D9Ex, present_params: Option<D3DPRESENT_PARAMETERS>) -> Self {
+        let proxy = ProxyDirect3DDevice9::new(target.clone().into(), config, container.into(), present_params);
         let context = proxy.get_context().clone();
 
         Self { proxy: proxy.into(), target, context }
@@ -55,6 +61,10 @@ impl_debug!(ProxyDirect3DDevice9Ex_Impl);
 impl IDirect3DDevice9Ex_Impl for ProxyDirect3DDevice9Ex_Impl {
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn CheckDeviceState(&self, hdestinationwindow: HWND) -> Result<()> {
+        if let Some(result) = self.context.get_forced_result(DX9OverridableMethod::CheckDeviceState) {
+            return result;
+        }
+
         unsafe { self.target.CheckDeviceState(hdestinationwindow) }
     }
 
@@ -71,7 +81,7 @@ impl IDirect3DDevice9Ex_Impl for ProxyDirect3DDevice9Ex_Impl {
         }
     }
 
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(psrc, pdst, psrcrectdescs, pdstrectdescs)))]
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(psrc, pdst, psrcrectdescs, pdstrectdescs), fields(psrc = ?TracedObject::new(psrc.as_ref()), pdst = ?TracedObject::new(pdst.as_ref()), psrcrectdescs = ?TracedObject::new(psrcrectdescs.as_ref()), pdstrectdescs = ?TracedObject::new(pdstrectdescs.as_ref()))))]
     fn ComposeRects(
         &self,
         psrc: Ref<IDirect3DSurface9>,
@@ -107,27 +117,39 @@ impl IDirect3DDevice9Ex_Impl for ProxyDirect3DDevice9Ex_Impl {
         psharedhandle: *mut HANDLE,
         usage: u32,
     ) -> Result<()> {
-        check_nullptr!(ppsurface);
-
-        let target = try_out_param(|out| unsafe {
-            self.target
-                .CreateDepthStencilSurfaceEx(width, height, format, multisample, multisamplequality, discard.into(), out, psharedhandle, usage)
-        })?;
-        let proxy = self.context.ensure_proxy(target, |target| {
-            ProxyDirect3DSurface9::new(target, self.context.clone(), self.to_interface::<IDirect3DDevice9Ex>().into(), DX9SurfaceContainer::Standalone).into()
-        });
-        ppsurface.write(Some(proxy))
+        with_required_out("CreateDepthStencilSurfaceEx", ppsurface, |ppsurface| {
+            check_nullptr!(ppsurface);
+
+            let target = self.context.time_driver_call("CreateDepthStencilSurfaceEx", || {
+                try_out_param(|out| unsafe {
+                    self.target
+                        .CreateDepthStencilSurfaceEx(width, height, format, multisample, multisamplequality, discard.into(), out, psharedhandle, usage)
+                })
+            })?;
+            self.context.on_resource_create(&target, D3DPOOL_DEFAULT, usage | D3DUSAGE_DEPTHSTENCIL as u32, || {
+                format!("{width}x{height} {format:?} depth stencil surface")
+            });
+            let proxy = self.context.ensure_proxy(target, |target| {
+                ProxyDirect3DSurface9::new(target, self.context.clone(), self.to_interface::<IDirect3DDevice9Ex>().into(), DX9SurfaceContainer::Standalone).into()
+            });
+            ppsurface.write(Some(proxy))
+        })
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(ppsurface)))]
     fn CreateOffscreenPlainSurfaceEx(&self, width: u32, height: u32, format: D3DFORMAT, pool: D3DPOOL, ppsurface: OutRef<IDirect3DSurface9>, psharedhandle: *mut HANDLE, usage: u32) -> Result<()> {
-        check_nullptr!(ppsurface);
+        with_required_out("CreateOffscreenPlainSurfaceEx", ppsurface, |ppsurface| {
+            check_nullptr!(ppsurface);
 
-        let target = try_out_param(|out| unsafe { self.target.CreateOffscreenPlainSurfaceEx(width, height, format, pool, out, psharedhandle, usage) })?;
-        let proxy = self.context.ensure_proxy(target, |target| {
-            ProxyDirect3DSurface9::new(target, self.context.clone(), self.to_interface::<IDirect3DDevice9Ex>().into(), DX9SurfaceContainer::Standalone).into()
-        });
-        ppsurface.write(Some(proxy))
+            let target = self.context.time_driver_call("CreateOffscreenPlainSurfaceEx", || {
+                try_out_param(|out| unsafe { self.target.CreateOffscreenPlainSurfaceEx(width, height, format, pool, out, psharedhandle, usage) })
+            })?;
+            self.context.on_resource_create(&target, pool, usage, || format!("{width}x{height} {format:?} offscreen plain surface"));
+            let proxy = self.context.ensure_proxy(target, |target| {
+                ProxyDirect3DSurface9::new(target, self.context.clone(), self.to_interface::<IDirect3DDevice9Ex>().into(), DX9SurfaceContainer::Standalone).into()
+            });
+            ppsurface.write(Some(proxy))
+        })
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(ppsurface)))]
@@ -143,26 +165,83 @@ impl IDirect3DDevice9Ex_Impl for ProxyDirect3DDevice9Ex_Impl {
         psharedhandle: *mut HANDLE,
         usage: u32,
     ) -> Result<()> {
-        check_nullptr!(ppsurface);
-
-        let target = try_out_param(|out| unsafe {
-            self.target
-                .CreateRenderTargetEx(width, height, format, multisample, multisamplequality, lockable.into(), out, psharedhandle, usage)
-        })?;
-        let proxy = self.context.ensure_proxy(target, |target| {
-            ProxyDirect3DSurface9::new(target, self.context.clone(), self.to_interface::<IDirect3DDevice9Ex>().into(), DX9SurfaceContainer::Standalone).into()
-        });
-        ppsurface.write(Some(proxy))
+        with_required_out("CreateRenderTargetEx", ppsurface, |ppsurface| {
+            check_nullptr!(ppsurface);
+
+            let target = self.context.time_driver_call("CreateRenderTargetEx", || {
+                try_out_param(|out| unsafe {
+                    self.target
+                        .CreateRenderTargetEx(width, height, format, multisample, multisamplequality, lockable.into(), out, psharedhandle, usage)
+                })
+            })?;
+            self.context
+                .on_resource_create(&target, D3DPOOL_DEFAULT, usage | D3DUSAGE_RENDERTARGET as u32, || format!("{width}x{height} {format:?} render target"));
+            let proxy = self.context.ensure_proxy(target, |target| {
+                ProxyDirect3DSurface9::new(target, self.context.clone(), self.to_interface::<IDirect3DDevice9Ex>().into(), DX9SurfaceContainer::Standalone).into()
+            });
+            ppsurface.write(Some(proxy))
+        })
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn PresentEx(&self, psourcerect: *const RECT, pdestrect: *const RECT, hdestwindowoverride: HWND, pdirtyregion: *const RGNDATA, dwflags: u32) -> Result<()> {
-        unsafe { self.target.PresentEx(psourcerect, pdestrect, hdestwindowoverride, pdirtyregion, dwflags) }
+        let present_guard = present::enter_present("PresentEx");
+        if !present_guard.is_outermost() {
+            return unsafe { self.target.PresentEx(psourcerect, pdestrect, hdestwindowoverride, pdirtyregion, dwflags) };
+        }
+
+        if let Some(mut pacer) = self.context.lock_frame_pacer() {
+            pacer.pace();
+        }
+
+        let call = || {
+            if let Some(mut watermark) = self.context.lock_watermark() {
+                watermark.draw(&self.target.clone().into());
+            }
+            self.context
+                .time_driver_call("PresentEx", || unsafe { self.target.PresentEx(psourcerect, pdestrect, hdestwindowoverride, pdirtyregion, dwflags) })
+        };
+        // SAFETY: The call only uses the context, the target device and the arguments of the application
+        unsafe { self.context.serialize(call) }?;
+        self.context.on_present();
+        update_cursor_clip(&self.target, &self.context);
+
+        if self.context.get_config().flush_after_present {
+            flush_device(&self.target.clone().into());
+        }
+
+        Ok(())
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn ResetEx(&self, ppresentationparameters: *mut D3DPRESENT_PARAMETERS, pfullscreendisplaymode: *mut D3DDISPLAYMODEEX) -> Result<()> {
-        unsafe { self.target.ResetEx(ppresentationparameters, pfullscreendisplaymode) }
+        // Default pool resources must be released before resetting the device
+        self.context.lock_scratch_buffers().release();
+        if let Some(mut watermark) = self.context.lock_watermark() {
+            watermark.release();
+        }
+        if let Some(mut scratch) = self.context.lock_stretch_scratch() {
+            scratch.release();
+        }
+        if let Some(mut pacer) = self.context.lock_frame_pacer() {
+            pacer.clear();
+        }
+        self.context.invalidate_render_target_size();
+        self.context.clear_surface_proxies();
+        self.context.lock_bound_textures().clear();
+        self.context.set_bound_index_buffer(None);
+        let mut params = unsafe {
+            present::with_overridden_params(ppresentationparameters, reset_params_count(&self.target), |params| {
+                apply_reset_overrides(&self.context, &self.target, params)
+            })
+        };
+
+        unsafe { self.target.ResetEx(present::params_ptr(&mut params, ppresentationparameters), pfullscreendisplaymode) }.inspect_err(|_err| self.context.on_reset_failed())?;
+        if let Some(params) = &params {
+            unsafe { present::write_back_params(ppresentationparameters, params) };
+        }
+        self.context.set_present_params(params.map(|params| params[0]));
+        Ok(())
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
@@ -177,6 +256,10 @@ impl IDirect3DDevice9Ex_Impl for ProxyDirect3DDevice9Ex_Impl {
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn SetGPUThreadPriority(&self, priority: i32) -> Result<()> {
+        if let Some(result) = self.context.get_forced_result(DX9OverridableMethod::SetGPUThreadPriority) {
+            return result;
+        }
+
         unsafe { self.target.SetGPUThreadPriority(priority) }
     }
 
@@ -187,6 +270,10 @@ impl IDirect3DDevice9Ex_Impl for ProxyDirect3DDevice9Ex_Impl {
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn SetMaximumFrameLatency(&self, maxlatency: u32) -> Result<()> {
+        if let Some(result) = self.context.get_forced_result(DX9OverridableMethod::SetMaximumFrameLatency) {
+            return result;
+        }
+
         unsafe { self.target.SetMaximumFrameLatency(maxlatency) }
     }
 
@@ -197,6 +284,10 @@ impl IDirect3DDevice9Ex_Impl for ProxyDirect3DDevice9Ex_Impl {
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn WaitForVBlank(&self, iswapchain: u32) -> Result<()> {
+        if let Some(result) = self.context.get_forced_result(DX9OverridableMethod::WaitForVBlank) {
+            return result;
+        }
+
         unsafe { self.target.WaitForVBlank(iswapchain) }
     }
 }
@@ -431,11 +522,11 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9Ex_Impl {
     }
 
     fn BeginScene(&self) -> Result<()> {
-        unsafe { proxy_as_interface!(self).BeginScene() }
+        unsafe { self.proxy.BeginScene_Impl(get_base_interface_fn!(self)) }
     }
 
     fn EndScene(&self) -> Result<()> {
-        unsafe { proxy_as_interface!(self).EndScene() }
+        unsafe { self.proxy.EndScene_Impl(get_base_interface_fn!(self)) }
     }
 
     fn Clear(&self, count: u32, prects: *const D3DRECT, flags: u32, color: u32, z: f32, stencil: u32) -> Result<()> {
@@ -763,3 +854,166 @@ impl IDirect3DDevice9_Impl for ProxyDirect3DDevice9Ex_Impl {
         unsafe { self.proxy.CreateQuery_Impl(get_base_interface_fn!(self), r#type) }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dx9::com::mock::*;
+    use std::ptr::null_mut;
+
+    /// A base proxy and an Ex proxy, each wrapping its own mock device.
+    struct DelegationHarness {
+        base_mock: ComObject<MockDevice>,
+        base: IDirect3DDevice9,
+        ex_mock: ComObject<MockDevice>,
+        ex: IDirect3DDevice9,
+    }
+
+    impl DelegationHarness {
+        fn new() -> Self {
+            let mock_device = || {
+                let mock = ComObject::new(MockDevice::default());
+                {
+                    let mut state = mock.state.lock().unwrap();
+                    state.state_blocks_supported = true;
+                    state.render_target = Some(MockSurface::default().into());
+                    state.depth_stencil = Some(MockSurface::default().into());
+                }
+                mock
+            };
+            let container = || -> IDirect3D9Ex { MockDirect3D9::default().into() };
+
+            let base_mock = mock_device();
+            let base_target: IDirect3DDevice9Ex = base_mock.to_interface();
+            let base = ProxyDirect3DDevice9::new(base_target.into(), DX9ProxyConfig::default(), container().into(), None).into();
+            let ex_mock = mock_device();
+            let ex_proxy: IDirect3DDevice9Ex = ProxyDirect3DDevice9Ex::new(ex_mock.to_interface(), DX9ProxyConfig::default(), container(), None).into();
+            Self {
+                base_mock,
+                base,
+                ex_mock,
+                ex: ex_proxy.into(),
+            }
+        }
+
+        /// Calls `call` on both proxies, and checks that both invoke the target method `method` and the same other target methods.
+        ///
+        /// # Returns
+        /// The results of `call` on the base proxy and on the Ex proxy.
+        fn call<T>(&self, method: &str, call: impl Fn(&IDirect3DDevice9) -> T) -> (T, T) {
+            self.base_mock.log.clear();
+            self.ex_mock.log.clear();
+            let results = (call(&self.base), call(&self.ex));
+            let (base_calls, ex_calls) = (self.base_mock.log.calls(), self.ex_mock.log.calls());
+            assert!(base_calls.contains(&method), "{method} was not forwarded by the base proxy: {base_calls:?}");
+            assert_eq!(ex_calls, base_calls, "{method}");
+            results
+        }
+
+        /// Checks that objects created by `call` report the proxy they were created on as their device.
+        fn check_created<T: Interface>(&self, method: &str, call: impl Fn(&IDirect3DDevice9) -> Result<T>, get_device: impl Fn(&T) -> Result<IDirect3DDevice9>) {
+            let (base_object, ex_object) = self.call(method, call);
+            for (device, object) in [(&self.base, base_object), (&self.ex, ex_object)] {
+                let object = object.unwrap_or_else(|err| panic!("{method} failed: {err}"));
+                let object_device = get_device(&object).unwrap();
+                assert_eq!(object_device.cast::<IUnknown>().unwrap(), device.cast::<IUnknown>().unwrap(), "{method}");
+            }
+        }
+    }
+
+    #[test]
+    fn ex_proxy_delegates_create_methods() {
+        let harness = DelegationHarness::new();
+        let create_surface_device = |surface: &IDirect3DSurface9| unsafe { surface.GetDevice() };
+
+        harness.check_created(
+            "CreateTexture",
+            |device| try_out_param(|out| unsafe { device.CreateTexture(16, 16, 1, 0, D3DFMT_A8R8G8B8, D3DPOOL_MANAGED, out, null_mut()) }),
+            |texture| unsafe { texture.GetDevice() },
+        );
+        harness.check_created(
+            "CreateRenderTarget",
+            |device| try_out_param(|out| unsafe { device.CreateRenderTarget(16, 16, D3DFMT_A8R8G8B8, D3DMULTISAMPLE_NONE, 0, false, out, null_mut()) }),
+            create_surface_device,
+        );
+        harness.check_created(
+            "CreateDepthStencilSurface",
+            |device| try_out_param(|out| unsafe { device.CreateDepthStencilSurface(16, 16, D3DFMT_D24S8, D3DMULTISAMPLE_NONE, 0, true, out, null_mut()) }),
+            create_surface_device,
+        );
+        harness.check_created(
+            "CreateOffscreenPlainSurface",
+            |device| try_out_param(|out| unsafe { device.CreateOffscreenPlainSurface(16, 16, D3DFMT_A8R8G8B8, D3DPOOL_SYSTEMMEM, out, null_mut()) }),
+            create_surface_device,
+        );
+        harness.check_created(
+            "CreateStateBlock",
+            |device| unsafe { device.CreateStateBlock(D3DSBT_ALL) },
+            |state_block| unsafe { state_block.GetDevice() },
+        );
+        harness.check_created("CreateQuery", |device| unsafe { device.CreateQuery(D3DQUERYTYPE_EVENT) }, |query| unsafe { query.GetDevice() });
+
+        // The mock device cannot create these, but they must still be forwarded the same way
+        harness.call("CreateVolumeTexture", |device| {
+            try_out_param(|out| unsafe { device.CreateVolumeTexture(16, 16, 16, 1, 0, D3DFMT_A8R8G8B8, D3DPOOL_MANAGED, out, null_mut()) }).is_ok()
+        });
+        harness.call("CreateCubeTexture", |device| {
+            try_out_param(|out| unsafe { device.CreateCubeTexture(16, 1, 0, D3DFMT_A8R8G8B8, D3DPOOL_MANAGED, out, null_mut()) }).is_ok()
+        });
+        harness.call("CreateVertexBuffer", |device| {
+            try_out_param(|out| unsafe { device.CreateVertexBuffer(64, 0, 0, D3DPOOL_MANAGED, out, null_mut()) }).is_ok()
+        });
+        harness.call("CreateIndexBuffer", |device| {
+            try_out_param(|out| unsafe { device.CreateIndexBuffer(64, 0, D3DFMT_INDEX16, D3DPOOL_MANAGED, out, null_mut()) }).is_ok()
+        });
+        harness.call("CreateAdditionalSwapChain", |device| {
+            let mut params = D3DPRESENT_PARAMETERS {
+                Windowed: true.into(),
+                SwapEffect: D3DSWAPEFFECT_DISCARD,
+                ..Default::default()
+            };
+            try_out_param(|out| unsafe { device.CreateAdditionalSwapChain(&mut params, out) }).is_ok()
+        });
+        // D3DDECL_END()
+        let elements = [D3DVERTEXELEMENT9 {
+            Stream: 0xff,
+            Type: D3DDECLTYPE_UNUSED.0 as u8,
+            ..Default::default()
+        }];
+        harness.call("CreateVertexDeclaration", |device| unsafe { device.CreateVertexDeclaration(elements.as_ptr()) }.is_ok());
+        // vs_2_0 and ps_2_0 version tokens followed by the end token
+        let vertex_shader = [0xfffe0200u32, 0x0000ffff];
+        harness.call("CreateVertexShader", |device| unsafe { device.CreateVertexShader(vertex_shader.as_ptr()) }.is_ok());
+        let pixel_shader = [0xffff0200u32, 0x0000ffff];
+        harness.call("CreatePixelShader", |device| unsafe { device.CreatePixelShader(pixel_shader.as_ptr()) }.is_ok());
+    }
+
+    #[test]
+    fn ex_proxy_delegates_get_methods() {
+        let harness = DelegationHarness::new();
+
+        harness.check_created("GetRenderTarget", |device| unsafe { device.GetRenderTarget(0) }, |surface| unsafe { surface.GetDevice() });
+        harness.check_created("GetDepthStencilSurface", |device| unsafe { device.GetDepthStencilSurface() }, |surface| unsafe { surface.GetDevice() });
+
+        harness.call("GetSwapChain", |device| unsafe { device.GetSwapChain(0) }.is_ok());
+        // Back buffers are retrieved through the proxied swap chain
+        harness.call("GetSwapChain", |device| unsafe { device.GetBackBuffer(0, 0, D3DBACKBUFFER_TYPE_MONO) }.is_ok());
+        harness.call("GetVertexDeclaration", |device| unsafe { device.GetVertexDeclaration() }.is_ok());
+        harness.call("GetVertexShader", |device| unsafe { device.GetVertexShader() }.is_ok());
+        harness.call("GetPixelShader", |device| unsafe { device.GetPixelShader() }.is_ok());
+        harness.call("GetIndices", |device| unsafe { device.GetIndices() }.is_ok());
+        harness.call("GetStreamSource", |device| {
+            let (mut offset, mut stride) = (0, 0);
+            try_out_param(|out| unsafe { device.GetStreamSource(0, out, &mut offset, &mut stride) }).is_ok()
+        });
+    }
+
+    #[test]
+    fn ex_proxy_delegates_scene_and_state_block_methods() {
+        let harness = DelegationHarness::new();
+
+        harness.call("BeginScene", |device| unsafe { device.BeginScene() }.is_ok());
+        harness.call("EndScene", |device| unsafe { device.EndScene() }.is_ok());
+        harness.call("EndStateBlock", |device| unsafe { device.EndStateBlock() }.is_ok());
+    }
+}
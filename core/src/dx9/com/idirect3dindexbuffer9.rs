@@ -10,18 +10,25 @@ pub struct ProxyDirect3DIndexBuffer9 {
     target: IDirect3DIndexBuffer9,
     context: DX9ProxyDeviceContext,
     proxy_device: IDirect3DDevice9,
+    lock_count: DX9LockCount,
 }
 
 impl ProxyDirect3DIndexBuffer9 {
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(ret, level = "debug"))]
     pub fn new(target: IDirect3DIndexBuffer9, context: DX9ProxyDeviceContext, proxy_device: IDirect3DDevice9) -> Self {
-        Self { target, context, proxy_device }
+        Self {
+            target,
+            context,
+            proxy_device,
+            lock_count: DX9LockCount::default(),
+        }
     }
 }
 
 impl Drop for ProxyDirect3DIndexBuffer9 {
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(ret, level = "debug"))]
     fn drop(&mut self) {
+        self.context.on_resource_destroy(&self.lock_count);
         self.context.on_proxy_destroy(&self.target);
     }
 }
@@ -32,12 +39,16 @@ impl_debug!(ProxyDirect3DIndexBuffer9_Impl);
 impl IDirect3DIndexBuffer9_Impl for ProxyDirect3DIndexBuffer9_Impl {
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn Lock(&self, offsettolock: u32, sizetolock: u32, ppbdata: *mut *mut c_void, flags: u32) -> Result<()> {
-        unsafe { self.target.Lock(offsettolock, sizetolock, ppbdata, flags) }
+        self.context.time_driver_call("Lock", || unsafe { self.target.Lock(offsettolock, sizetolock, ppbdata, flags) })?;
+        self.context.on_resource_lock(&self.lock_count, self);
+        Ok(())
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn Unlock(&self) -> Result<()> {
-        unsafe { self.target.Unlock() }
+        unsafe { self.target.Unlock() }?;
+        self.context.on_resource_unlock(&self.lock_count);
+        Ok(())
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
@@ -53,20 +64,7 @@ impl IDirect3DResource9_Impl for ProxyDirect3DIndexBuffer9_Impl {
         Ok(self.proxy_device.clone())
     }
 
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
-    fn SetPrivateData(&self, refguid: *const GUID, pdata: *const c_void, sizeofdata: u32, flags: u32) -> Result<()> {
-        unsafe { self.target.SetPrivateData(refguid, pdata, sizeofdata, flags) }
-    }
-
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
-    fn GetPrivateData(&self, refguid: *const GUID, pdata: *mut c_void, psizeofdata: *mut u32) -> Result<()> {
-        unsafe { self.target.GetPrivateData(refguid, pdata, psizeofdata) }
-    }
-
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
-    fn FreePrivateData(&self, refguid: *const GUID) -> Result<()> {
-        unsafe { self.target.FreePrivateData(refguid) }
-    }
+    impl_private_data!();
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(ret, level = "trace"))]
     fn SetPriority(&self, prioritynew: u32) -> u32 {
@@ -50,8 +50,9 @@ impl IDirect3DQuery9_Impl for ProxyDirect3DQuery9_Impl {
         unsafe { self.target.Issue(dwissueflags) }
     }
 
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
+    // S_FALSE is returned as an error while polling, so do not log errors at the error level
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err(level = "trace"), ret, level = "trace"))]
     fn GetData(&self, pdata: *mut c_void, dwsize: u32, dwgetdataflags: u32) -> Result<()> {
-        unsafe { self.target.GetData(pdata, dwsize, dwgetdataflags) }
+        unsafe { get_query_data(&self.target, pdata, dwsize, dwgetdataflags) }
     }
 }
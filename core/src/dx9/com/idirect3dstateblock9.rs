@@ -41,6 +41,13 @@ impl IDirect3DStateBlock9_Impl for ProxyDirect3DStateBlock9_Impl {
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn Apply(&self) -> Result<()> {
-        unsafe { self.target.Apply() }
+        unsafe { self.target.Apply() }?;
+
+        // The state block may have overwritten forced states.
+        if self.context.has_forced_states() {
+            let device = unsafe { self.target.GetDevice() }?;
+            apply_forced_states(&device, &self.context);
+        }
+        Ok(())
     }
 }
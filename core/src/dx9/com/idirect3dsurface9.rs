@@ -25,6 +25,7 @@ pub struct ProxyDirect3DSurface9 {
     context: DX9ProxyDeviceContext,
     proxy_device: IDirect3DDevice9,
     proxy_container: DX9SurfaceContainer,
+    lock_count: DX9LockCount,
 }
 
 impl ProxyDirect3DSurface9 {
@@ -35,6 +36,7 @@ impl ProxyDirect3DSurface9 {
             context,
             proxy_device,
             proxy_container,
+            lock_count: DX9LockCount::default(),
         }
     }
 }
@@ -42,6 +44,7 @@ impl ProxyDirect3DSurface9 {
 impl Drop for ProxyDirect3DSurface9 {
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(ret, level = "debug"))]
     fn drop(&mut self) {
+        self.context.on_resource_destroy(&self.lock_count);
         self.context.on_proxy_destroy(&self.target);
     }
 }
@@ -99,12 +102,16 @@ impl IDirect3DSurface9_Impl for ProxyDirect3DSurface9_Impl {
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn LockRect(&self, plockedrect: *mut D3DLOCKED_RECT, prect: *const RECT, flags: u32) -> Result<()> {
-        unsafe { self.target.LockRect(plockedrect, prect, flags) }
+        self.context.time_driver_call("LockRect", || unsafe { self.target.LockRect(plockedrect, prect, flags) })?;
+        self.context.on_resource_lock(&self.lock_count, self);
+        Ok(())
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn UnlockRect(&self) -> Result<()> {
-        unsafe { self.target.UnlockRect() }
+        unsafe { self.target.UnlockRect() }?;
+        self.context.on_resource_unlock(&self.lock_count);
+        Ok(())
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
@@ -125,20 +132,7 @@ impl IDirect3DResource9_Impl for ProxyDirect3DSurface9_Impl {
         Ok(self.proxy_device.clone())
     }
 
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
-    fn SetPrivateData(&self, refguid: *const GUID, pdata: *const c_void, sizeofdata: u32, flags: u32) -> Result<()> {
-        unsafe { self.target.SetPrivateData(refguid, pdata, sizeofdata, flags) }
-    }
-
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
-    fn GetPrivateData(&self, refguid: *const GUID, pdata: *mut c_void, psizeofdata: *mut u32) -> Result<()> {
-        unsafe { self.target.GetPrivateData(refguid, pdata, psizeofdata) }
-    }
-
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
-    fn FreePrivateData(&self, refguid: *const GUID) -> Result<()> {
-        unsafe { self.target.FreePrivateData(refguid) }
-    }
+    impl_private_data!();
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(ret, level = "trace"))]
     fn SetPriority(&self, prioritynew: u32) -> u32 {
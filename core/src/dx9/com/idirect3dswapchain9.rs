@@ -1,9 +1,14 @@
 //! [`IDirect3DSwapChain9`] proxy implementation.
 
 use super::*;
+use std::{
+    mem::size_of,
+    ptr::{copy, null, write_bytes},
+};
 use windows::{
     Win32::Foundation::*,
     Win32::Graphics::{Direct3D9::*, Gdi::*},
+    Win32::UI::WindowsAndMessaging::GetClientRect,
     core::*,
 };
 
@@ -13,12 +18,26 @@ pub struct ProxyDirect3DSwapChain9 {
     target: IDirect3DSwapChain9,
     context: DX9ProxyDeviceContext,
     proxy_device: IDirect3DDevice9,
+    present_params: Option<D3DPRESENT_PARAMETERS>,
 }
 
 impl ProxyDirect3DSwapChain9 {
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(ret, level = "debug"))]
-    pub fn new(target: IDirect3DSwapChain9, context: DX9ProxyDeviceContext, proxy_device: IDirect3DDevice9) -> Self {
-        Self { target, context, proxy_device }
+    pub fn new(target: IDirect3DSwapChain9, context: DX9ProxyDeviceContext, proxy_device: IDirect3DDevice9, present_params: Option<D3DPRESENT_PARAMETERS>) -> Self {
+        Self {
+            target,
+            context,
+            proxy_device,
+            present_params,
+        }
+    }
+
+    /// Returns the effective presentation parameters of an additional swap chain, as passed to `CreateAdditionalSwapChain`.
+    ///
+    /// Returns `None` for implicit swap chains, whose parameters change with `Reset` and are tracked
+    /// by the device context instead, see [`DX9ProxyDeviceContext::get_present_params`].
+    pub fn get_present_params(&self) -> Option<D3DPRESENT_PARAMETERS> {
+        self.present_params
     }
 
     /// Creates a new proxy swap chain or upgrades to an Ex version if available.
@@ -34,6 +53,7 @@ impl ProxyDirect3DSwapChain9 {
     /// * `target` - The target swap chain to wrap.
     /// * `context` - The device context for the proxy.
     /// * `proxy_device` - The proxy device associated with the swap chain.
+    /// * `present_params` - The effective presentation parameters of an additional swap chain, or `None` for implicit swap chains.
     ///
     /// # Returns
     /// An [`IDirect3DSwapChain9`] instance, which may be a proxy for either
@@ -41,13 +61,26 @@ impl ProxyDirect3DSwapChain9 {
     ///
     /// [`new`]: Self::new
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(ret, level = "debug"))]
-    pub fn new_or_upgrade(target: IDirect3DSwapChain9, context: DX9ProxyDeviceContext, proxy_device: IDirect3DDevice9) -> IDirect3DSwapChain9 {
-        if let Ok(ex_target) = target.cast::<IDirect3DSwapChain9Ex>() {
-            let ex_interface: IDirect3DSwapChain9Ex = ProxyDirect3DSwapChain9Ex::new(ex_target, context, proxy_device).into();
+    pub fn new_or_upgrade(target: IDirect3DSwapChain9, context: DX9ProxyDeviceContext, proxy_device: IDirect3DDevice9, present_params: Option<D3DPRESENT_PARAMETERS>) -> IDirect3DSwapChain9 {
+        let ex_target = target.cast::<IDirect3DSwapChain9Ex>();
+
+        #[cfg(feature = "tracing")]
+        match (&ex_target, proxy_device.cast::<IDirect3DDevice9Ex>()) {
+            (Ok(_), Err(_)) => {
+                tracing::warn!("Target {target:?} is IDirect3DSwapChain9Ex but its device {proxy_device:?} is not IDirect3DDevice9Ex");
+            }
+            (Err(_), Ok(_)) => {
+                tracing::warn!("Device {proxy_device:?} is IDirect3DDevice9Ex but its swap chain {target:?} is not IDirect3DSwapChain9Ex, downgrading to ProxyDirect3DSwapChain9");
+            }
+            _ => {}
+        }
+
+        if let Ok(ex_target) = ex_target {
+            let ex_interface: IDirect3DSwapChain9Ex = ProxyDirect3DSwapChain9Ex::new(ex_target, context, proxy_device, present_params).into();
             ex_interface.into()
         } else {
             // If the target is not an Ex version, we downgrade to the regular swap chain.
-            Self::new(target, context, proxy_device).into()
+            Self::new(target, context, proxy_device, present_params).into()
         }
     }
 }
@@ -88,13 +121,37 @@ impl ProxyDirect3DSwapChain9_Impl {
 impl IDirect3DSwapChain9_Impl for ProxyDirect3DSwapChain9_Impl {
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn Present(&self, psourcerect: *const RECT, pdestrect: *const RECT, hdestwindowoverride: HWND, pdirtyregion: *const RGNDATA, dwflags: u32) -> Result<()> {
-        unsafe { self.target.Present(psourcerect, pdestrect, hdestwindowoverride, pdirtyregion, dwflags) }
+        let present_guard = present::enter_present("IDirect3DSwapChain9::Present");
+        if !present_guard.is_outermost() {
+            return unsafe { self.target.Present(psourcerect, pdestrect, hdestwindowoverride, pdirtyregion, dwflags) };
+        }
+
+        self.context
+            .time_driver_call("Present", || unsafe { self.target.Present(psourcerect, pdestrect, hdestwindowoverride, pdirtyregion, dwflags) })?;
+        self.context.on_present();
+
+        if self.context.get_config().flush_after_present {
+            let _ = unsafe { self.target.GetDevice() }.map(|device| flush_device(&device));
+        }
+
+        Ok(())
     }
 
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(pdestsurface)))]
+    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace", skip(pdestsurface), fields(pdestsurface = ?TracedObject::new(pdestsurface.as_ref()))))]
     fn GetFrontBufferData(&self, pdestsurface: Ref<IDirect3DSurface9>) -> Result<()> {
-        let target = self.context.get_target_nullable(pdestsurface).ok_or(D3DERR_INVALIDCALL)?;
-        unsafe { self.target.GetFrontBufferData(target) }
+        let target = self.context.get_target_nullable(pdestsurface.as_ref()).ok_or(D3DERR_INVALIDCALL)?;
+        unsafe { self.target.GetFrontBufferData(target) }?;
+
+        if self.context.get_config().crop_front_buffer_to_window
+            && let Some(surface) = pdestsurface.as_ref()
+        {
+            let _ = crop_front_buffer_to_window(&self.target, surface).inspect_err(|_err| {
+                #[cfg(feature = "tracing")]
+                tracing::warn!("Failed to crop front buffer data to the device window, passing through: {_err}");
+            });
+        }
+
+        Ok(())
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
@@ -122,3 +179,90 @@ impl IDirect3DSwapChain9_Impl for ProxyDirect3DSwapChain9_Impl {
         unsafe { self.target.GetPresentParameters(ppresentationparameters) }
     }
 }
+
+/// Crops front buffer data captured in windowed mode to the client area of the device window.
+///
+/// The client area is moved to the top-left corner of `surface` and the remaining pixels are cleared.
+/// Fullscreen swap chains are left untouched, as their front buffer already matches the window.
+///
+/// See [`DX9ProxyConfig::crop_front_buffer_to_window`].
+///
+/// # Arguments
+/// * `swap_chain` - The target swap chain the front buffer data was read from.
+/// * `surface` - The destination surface passed to `GetFrontBufferData`.
+pub(super) fn crop_front_buffer_to_window(swap_chain: &IDirect3DSwapChain9, surface: &IDirect3DSurface9) -> Result<()> {
+    // GetFrontBufferData always produces 32-bit pixels.
+    const BYTES_PER_PIXEL: usize = 4;
+
+    let mut params = D3DPRESENT_PARAMETERS::default();
+    unsafe { swap_chain.GetPresentParameters(&mut params) }?;
+    if !params.Windowed.as_bool() {
+        return Ok(());
+    }
+
+    let mut window = params.hDeviceWindow;
+    if window.is_invalid() {
+        let mut creation_params = D3DDEVICE_CREATION_PARAMETERS::default();
+        unsafe { swap_chain.GetDevice()?.GetCreationParameters(&mut creation_params) }?;
+        window = creation_params.hFocusWindow;
+    }
+    if window.is_invalid() {
+        #[cfg(feature = "tracing")]
+        tracing::warn!("Cannot determine the device window, front buffer data is not cropped");
+        return Ok(());
+    }
+
+    // Client rectangle in screen coordinates, relative to the monitor the front buffer covers
+    let mut client_rect = RECT::default();
+    unsafe { GetClientRect(window, &mut client_rect) }?;
+    let mut client_origin = POINT::default();
+    if !unsafe { ClientToScreen(window, &mut client_origin) }.as_bool() {
+        return Err(E_FAIL.into());
+    }
+    let mut monitor_info = MONITORINFO {
+        cbSize: size_of::<MONITORINFO>() as u32,
+        ..Default::default()
+    };
+    if !unsafe { GetMonitorInfoW(MonitorFromWindow(window, MONITOR_DEFAULTTONEAREST), &mut monitor_info) }.as_bool() {
+        return Err(E_FAIL.into());
+    }
+    let left = client_origin.x - monitor_info.rcMonitor.left;
+    let top = client_origin.y - monitor_info.rcMonitor.top;
+
+    let mut desc = D3DSURFACE_DESC::default();
+    unsafe { surface.GetDesc(&mut desc) }?;
+    if desc.Format != D3DFMT_A8R8G8B8 && desc.Format != D3DFMT_X8R8G8B8 {
+        return Err(D3DERR_INVALIDCALL.into());
+    }
+
+    let (width, height) = (desc.Width as i32, desc.Height as i32);
+    let x0 = left.clamp(0, width) as usize;
+    let x1 = (left + client_rect.right).clamp(0, width) as usize;
+    let y0 = top.clamp(0, height) as usize;
+    let y1 = (top + client_rect.bottom).clamp(0, height) as usize;
+    let (width, height) = (width as usize, height as usize);
+
+    #[cfg(feature = "tracing")]
+    tracing::debug!("Cropping front buffer data of {width}x{height} to ({x0}, {y0})-({x1}, {y1}) of window {window:?}");
+
+    let mut locked = D3DLOCKED_RECT::default();
+    unsafe { surface.LockRect(&mut locked, null(), 0) }?;
+
+    let bits = locked.pBits as *mut u8;
+    let pitch = locked.Pitch as usize;
+    let row_bytes = (x1 - x0) * BYTES_PER_PIXEL;
+    for row in 0..height {
+        // Rows are moved upwards (and pixels leftwards), so processing rows in order never reads overwritten data.
+        unsafe {
+            let dest = bits.add(row * pitch);
+            if row < y1 - y0 {
+                copy(bits.add((y0 + row) * pitch + x0 * BYTES_PER_PIXEL), dest, row_bytes);
+                write_bytes(dest.add(row_bytes), 0, width * BYTES_PER_PIXEL - row_bytes);
+            } else {
+                write_bytes(dest, 0, width * BYTES_PER_PIXEL);
+            }
+        }
+    }
+
+    unsafe { surface.UnlockRect() }
+}
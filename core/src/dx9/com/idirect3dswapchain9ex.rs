@@ -17,9 +17,9 @@ pub struct ProxyDirect3DSwapChain9Ex {
 
 impl ProxyDirect3DSwapChain9Ex {
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(ret, level = "debug"))]
-    pub fn new(target: IDirect3DSwapChain9Ex, context: DX9ProxyDeviceContext, proxy_device: IDirect3DDevice9) -> Self {
+    pub fn new(target: IDirect3DSwapChain9Ex, context: DX9ProxyDeviceContext, proxy_device: IDirect3DDevice9, present_params: Option<D3DPRESENT_PARAMETERS>) -> Self {
         Self {
-            proxy: ProxyDirect3DSwapChain9::new(target.clone().into(), context.clone(), proxy_device).into_object(),
+            proxy: ProxyDirect3DSwapChain9::new(target.clone().into(), context.clone(), proxy_device, present_params).into_object(),
             target,
             context,
         }
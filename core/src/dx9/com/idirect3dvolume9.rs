@@ -11,6 +11,7 @@ pub struct ProxyDirect3DVolume9 {
     context: DX9ProxyDeviceContext,
     proxy_device: IDirect3DDevice9,
     proxy_container: IDirect3DVolumeTexture9,
+    lock_count: DX9LockCount,
 }
 
 impl ProxyDirect3DVolume9 {
@@ -21,6 +22,7 @@ impl ProxyDirect3DVolume9 {
             context,
             proxy_device,
             proxy_container,
+            lock_count: DX9LockCount::default(),
         }
     }
 }
@@ -28,6 +30,7 @@ impl ProxyDirect3DVolume9 {
 impl Drop for ProxyDirect3DVolume9 {
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(ret, level = "debug"))]
     fn drop(&mut self) {
+        self.context.on_resource_destroy(&self.lock_count);
         self.context.on_proxy_destroy(&self.target);
     }
 }
@@ -61,26 +64,17 @@ impl IDirect3DVolume9_Impl for ProxyDirect3DVolume9_Impl {
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn LockBox(&self, plockedvolume: *mut D3DLOCKED_BOX, pbox: *const D3DBOX, flags: u32) -> Result<()> {
-        unsafe { self.target.LockBox(plockedvolume, pbox, flags) }
+        self.context.time_driver_call("LockBox", || unsafe { self.target.LockBox(plockedvolume, pbox, flags) })?;
+        self.context.on_resource_lock(&self.lock_count, self);
+        Ok(())
     }
 
     #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
     fn UnlockBox(&self) -> Result<()> {
-        unsafe { self.target.UnlockBox() }
-    }
-
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
-    fn FreePrivateData(&self, refguid: *const GUID) -> Result<()> {
-        unsafe { self.target.FreePrivateData(refguid) }
-    }
-
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
-    fn GetPrivateData(&self, refguid: *const GUID, pdata: *mut c_void, psizeofdata: *mut u32) -> Result<()> {
-        unsafe { self.target.GetPrivateData(refguid, pdata, psizeofdata) }
+        unsafe { self.target.UnlockBox() }?;
+        self.context.on_resource_unlock(&self.lock_count);
+        Ok(())
     }
 
-    #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
-    fn SetPrivateData(&self, refguid: *const GUID, pdata: *const c_void, sizeofdata: u32, flags: u32) -> Result<()> {
-        unsafe { self.target.SetPrivateData(refguid, pdata, sizeofdata, flags) }
-    }
+    impl_private_data!();
 }
@@ -0,0 +1,86 @@
+//! Detection of resources that are locked and never unlocked.
+//!
+//! A game that forgets to `Unlock` a buffer or texture stalls the GPU, and the mismatch is
+//! otherwise invisible. Each resource proxy counts its outstanding locks in a [`DX9LockCount`],
+//! and resources are registered in the [`DX9LockTracker`] of the device only while locked, so
+//! the per-lock bookkeeping stays cheap. Each `Present` then reports resources that have been
+//! locked for more than one frame. See [`check_lock_leaks`].
+//!
+//! [`check_lock_leaks`]: crate::dx9::DX9ProxyConfig::check_lock_leaks
+
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+/// Number of outstanding locks of a resource proxy.
+#[derive(Debug, Default)]
+pub struct DX9LockCount(AtomicU32);
+
+impl DX9LockCount {
+    /// Records a successful lock, returning `true` if the resource was not locked before.
+    pub fn lock(&self) -> bool {
+        self.0.fetch_add(1, Ordering::Relaxed) == 0
+    }
+
+    /// Records a successful unlock, returning `true` if the resource is no longer locked.
+    ///
+    /// Unlocks without a matching lock are ignored.
+    pub fn unlock(&self) -> bool {
+        self.0.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| count.checked_sub(1)) == Ok(1)
+    }
+}
+
+/// A resource that is currently locked.
+#[derive(Debug)]
+struct DX9LockedResource {
+    name: String,
+    frame: u64,
+    reported: bool,
+}
+
+/// Resources of a device that are currently locked, keyed by the address of their [`DX9LockCount`].
+#[derive(Debug, Default)]
+pub struct DX9LockTracker {
+    frame: u64,
+    locked: HashMap<usize, DX9LockedResource>,
+}
+
+impl DX9LockTracker {
+    /// Registers a resource that has become locked.
+    pub fn on_locked(&mut self, count: &DX9LockCount, name: impl FnOnce() -> String) {
+        self.locked.insert(
+            count as *const _ as usize,
+            DX9LockedResource {
+                name: name(),
+                frame: self.frame,
+                reported: false,
+            },
+        );
+    }
+
+    /// Deregisters a resource that has become unlocked or is being destroyed.
+    pub fn on_unlocked(&mut self, count: &DX9LockCount) {
+        self.locked.remove(&(count as *const _ as usize));
+    }
+
+    /// Returns the number of resources that are currently locked.
+    pub fn locked_count(&self) -> usize {
+        self.locked.len()
+    }
+
+    /// Advances to the next frame, returning the resources that have been locked for more than one frame.
+    ///
+    /// Each resource is only returned once per lock, along with the number of frames it has been locked for.
+    pub fn end_frame(&mut self) -> Vec<(String, u64)> {
+        self.frame += 1;
+        self.locked
+            .values_mut()
+            .filter(|resource| !resource.reported && self.frame - resource.frame > 1)
+            .map(|resource| {
+                resource.reported = true;
+                (resource.name.clone(), self.frame - resource.frame)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,1590 @@
+//! Mock Direct3D 9 objects for unit tests.
+//!
+//! The mocks record the name of each method called on them in a [`CallLog`], so that tests can
+//! check what proxies forward to their targets. Methods without a specific behavior return
+//! `Ok(())`, `E_NOTIMPL` if they return an interface, or zero.
+
+use super::D3DERR_INVALIDCALL;
+use crate::dx9::capture::bytes_per_pixel;
+use std::{
+    collections::{HashMap, VecDeque},
+    ffi::c_void,
+    sync::{Arc, Mutex},
+};
+use windows::{
+    Win32::{
+        Foundation::*,
+        Graphics::{Direct3D9::*, Gdi::*},
+    },
+    core::*,
+};
+use windows_numerics::Matrix4x4;
+
+/// Names of the methods called on mock objects, in call order.
+///
+/// Clones share the same log, so that the mocks of a test can record into a single one.
+#[derive(Debug, Clone, Default)]
+pub struct CallLog(Arc<Mutex<Vec<&'static str>>>);
+
+impl CallLog {
+    /// Records a call of the method `name`.
+    pub fn record(&self, name: &'static str) {
+        self.0.lock().unwrap().push(name);
+    }
+
+    /// Returns the recorded calls, oldest first.
+    pub fn calls(&self) -> Vec<&'static str> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Returns the number of recorded calls of the method `name`.
+    pub fn count(&self, name: &str) -> usize {
+        self.0.lock().unwrap().iter().filter(|&&call| call == name).count()
+    }
+
+    /// Forgets the recorded calls.
+    pub fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+}
+
+/// Device state that [`MockDevice`] stores on `Set*` calls and returns on `Get*` calls.
+#[derive(Debug, Default)]
+pub struct MockDeviceState {
+    pub render_states: HashMap<i32, u32>,
+    pub viewport: D3DVIEWPORT9,
+    pub render_target: Option<IDirect3DSurface9>,
+    pub depth_stencil: Option<IDirect3DSurface9>,
+    pub fvf: u32,
+    /// Values returned by `GetLightEnable`, by light index.
+    pub light_enables: HashMap<u32, BOOL>,
+    /// Bytes added to the pitch of the surfaces created by `CreateOffscreenPlainSurface`.
+    pub offscreen_pitch_padding: u32,
+    /// Whether `CreateStateBlock` succeeds.
+    pub state_blocks_supported: bool,
+    /// Textures created by `CreateTexture`, oldest first.
+    pub textures: Vec<IDirect3DTexture9>,
+    /// Source and destination textures of the `UpdateTexture` calls, oldest first.
+    pub updated_textures: Vec<(Option<IDirect3DBaseTexture9>, Option<IDirect3DBaseTexture9>)>,
+    /// Vertex buffer bound to stream 0, with its offset and stride.
+    pub stream_zero: Option<(IDirect3DVertexBuffer9, u32, u32)>,
+    pub indices: Option<IDirect3DIndexBuffer9>,
+    /// Draws performed with the `Draw*` methods, oldest first.
+    pub draws: Vec<MockDraw>,
+}
+
+impl MockDeviceState {
+    /// Returns `count` vertices of the buffer bound to stream 0 from vertex `start`, and the stride.
+    ///
+    /// Returns no vertices if no buffer of [`MockDevice`] is bound.
+    fn stream_zero_data(&self, start: u32, count: u32) -> (Vec<u8>, u32) {
+        let Some((buffer, offset, stride)) = &self.stream_zero else {
+            return (Vec::new(), 0);
+        };
+        let buffer: &MockVertexBuffer = unsafe { buffer.as_impl() };
+        let start = (offset + start * stride) as usize;
+        (buffer.data.lock().unwrap()[start..start + (count * stride) as usize].to_vec(), *stride)
+    }
+
+    /// Returns `count` indices of the bound index buffer from index `start`, and their format.
+    ///
+    /// Returns no indices if no buffer of [`MockDevice`] is bound.
+    fn index_data(&self, start: u32, count: u32) -> (Vec<u8>, D3DFORMAT) {
+        let Some(indices) = &self.indices else {
+            return (Vec::new(), D3DFORMAT::default());
+        };
+        let indices: &MockIndexBuffer = unsafe { indices.as_impl() };
+        let size = index_size(indices.format);
+        (indices.data.lock().unwrap()[(start * size) as usize..((start + count) * size) as usize].to_vec(), indices.format)
+    }
+}
+
+/// Draw recorded by [`MockDevice`], with the vertex and index data it read.
+///
+/// A user-pointer draw and a buffer draw of the same data are recorded as equal draws.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MockDraw {
+    pub primitive_type: D3DPRIMITIVETYPE,
+    pub primitive_count: u32,
+    pub min_vertex_index: u32,
+    pub num_vertices: u32,
+    pub stride: u32,
+    /// Vertices from the one indices are relative to, up to the last one used.
+    pub vertices: Vec<u8>,
+    /// Indices used, empty for non-indexed draws.
+    pub indices: Vec<u8>,
+    pub index_format: D3DFORMAT,
+}
+
+/// Returns the number of vertices (or indices) used by a draw of `primitive_count` primitives.
+fn primitive_vertex_count(primitive_type: D3DPRIMITIVETYPE, primitive_count: u32) -> u32 {
+    match primitive_type {
+        D3DPT_LINELIST => primitive_count * 2,
+        D3DPT_LINESTRIP => primitive_count + 1,
+        D3DPT_TRIANGLELIST => primitive_count * 3,
+        D3DPT_TRIANGLESTRIP | D3DPT_TRIANGLEFAN => primitive_count + 2,
+        _ => primitive_count,
+    }
+}
+
+/// Returns the size of an index of `format` in bytes.
+fn index_size(format: D3DFORMAT) -> u32 {
+    if format == D3DFMT_INDEX32 { 4 } else { 2 }
+}
+
+/// Returns a copy of the `size` bytes at `data`.
+///
+/// # Safety
+/// `data` must point to at least `size` readable bytes.
+unsafe fn read_bytes(data: *const c_void, size: u32) -> Vec<u8> {
+    unsafe { std::slice::from_raw_parts(data as *const u8, size as usize) }.to_vec()
+}
+
+/// Mock target device.
+#[implement(IDirect3DDevice9Ex)]
+#[derive(Debug, Default)]
+pub struct MockDevice {
+    pub log: CallLog,
+    pub state: Mutex<MockDeviceState>,
+}
+
+/// Mock surface, e.g. a render target of [`MockDevice`].
+///
+/// The pixels are stored in memory with the pitch given on creation, and can be locked.
+#[implement(IDirect3DSurface9)]
+#[derive(Debug, Default)]
+pub struct MockSurface {
+    pub log: CallLog,
+    pub desc: D3DSURFACE_DESC,
+    pub pitch: u32,
+    pub data: Mutex<Vec<u8>>,
+}
+
+impl MockSurface {
+    /// Creates a surface of zeroed pixels, whose rows are `pitch` bytes apart.
+    pub fn new(log: CallLog, width: u32, height: u32, format: D3DFORMAT, pitch: u32) -> Self {
+        Self {
+            log,
+            desc: D3DSURFACE_DESC {
+                Format: format,
+                Type: D3DRTYPE_SURFACE,
+                Width: width,
+                Height: height,
+                ..Default::default()
+            },
+            pitch,
+            data: Mutex::new(vec![0; (pitch * height) as usize]),
+        }
+    }
+
+    /// Returns the bytes of the pixels of row `y`, without padding.
+    pub fn row(&self, y: u32) -> Vec<u8> {
+        let start = (y * self.pitch) as usize;
+        let len = (self.desc.Width * bytes_per_pixel(self.desc.Format).unwrap()) as usize;
+        self.data.lock().unwrap()[start..start + len].to_vec()
+    }
+}
+
+/// Mock texture, created by `CreateTexture` of [`MockDevice`].
+#[implement(IDirect3DTexture9)]
+#[derive(Debug, Default)]
+pub struct MockTexture {
+    pub log: CallLog,
+}
+
+/// Mock vertex buffer, created by `CreateVertexBuffer` of [`MockDevice`], whose data can be locked.
+#[implement(IDirect3DVertexBuffer9)]
+#[derive(Debug, Default)]
+pub struct MockVertexBuffer {
+    pub log: CallLog,
+    pub data: Mutex<Vec<u8>>,
+}
+
+/// Mock index buffer, created by `CreateIndexBuffer` of [`MockDevice`], whose data can be locked.
+#[implement(IDirect3DIndexBuffer9)]
+#[derive(Debug, Default)]
+pub struct MockIndexBuffer {
+    pub log: CallLog,
+    pub format: D3DFORMAT,
+    pub data: Mutex<Vec<u8>>,
+}
+
+/// Mock Direct3D object, whose `CreateDevice` returns a [`MockDevice`].
+#[implement(IDirect3D9Ex)]
+#[derive(Debug, Default)]
+pub struct MockDirect3D9 {
+    pub log: CallLog,
+}
+
+/// Mock query, whose `GetData` returns scripted results.
+#[implement(IDirect3DQuery9)]
+#[derive(Debug, Default)]
+pub struct MockQuery {
+    pub log: CallLog,
+    /// Results of the next `GetData` calls, oldest first. `GetData` succeeds once they run out.
+    pub get_data_results: Mutex<VecDeque<HRESULT>>,
+}
+
+impl MockQuery {
+    /// Creates a query whose `GetData` returns `results`, then succeeds.
+    pub fn new(log: CallLog, results: impl IntoIterator<Item = HRESULT>) -> Self {
+        Self {
+            log,
+            get_data_results: Mutex::new(results.into_iter().collect()),
+        }
+    }
+}
+
+/// Mock state block, returned by `CreateStateBlock` of [`MockDevice`].
+#[implement(IDirect3DStateBlock9)]
+#[derive(Debug, Default)]
+pub struct MockStateBlock {
+    pub log: CallLog,
+}
+
+impl IDirect3DDevice9_Impl for MockDevice_Impl {
+    fn TestCooperativeLevel(&self) -> Result<()> {
+        self.log.record("TestCooperativeLevel");
+        Ok(())
+    }
+
+    fn GetAvailableTextureMem(&self) -> u32 {
+        self.log.record("GetAvailableTextureMem");
+        0
+    }
+
+    fn EvictManagedResources(&self) -> Result<()> {
+        self.log.record("EvictManagedResources");
+        Ok(())
+    }
+
+    fn GetDirect3D(&self) -> Result<IDirect3D9> {
+        self.log.record("GetDirect3D");
+        Err(E_NOTIMPL.into())
+    }
+
+    fn GetDeviceCaps(&self, _pcaps: *mut D3DCAPS9) -> Result<()> {
+        self.log.record("GetDeviceCaps");
+        Ok(())
+    }
+
+    fn GetDisplayMode(&self, _iswapchain: u32, _pmode: *mut D3DDISPLAYMODE) -> Result<()> {
+        self.log.record("GetDisplayMode");
+        Ok(())
+    }
+
+    fn GetCreationParameters(&self, _pparameters: *mut D3DDEVICE_CREATION_PARAMETERS) -> Result<()> {
+        self.log.record("GetCreationParameters");
+        Ok(())
+    }
+
+    fn SetCursorProperties(&self, _xhotspot: u32, _yhotspot: u32, _pcursorbitmap: Ref<'_, IDirect3DSurface9>) -> Result<()> {
+        self.log.record("SetCursorProperties");
+        Ok(())
+    }
+
+    fn SetCursorPosition(&self, _x: i32, _y: i32, _flags: u32) {
+        self.log.record("SetCursorPosition");
+    }
+
+    fn ShowCursor(&self, _bshow: BOOL) -> BOOL {
+        self.log.record("ShowCursor");
+        FALSE
+    }
+
+    fn CreateAdditionalSwapChain(&self, _ppresentationparameters: *mut D3DPRESENT_PARAMETERS, _pswapchain: OutRef<'_, IDirect3DSwapChain9>) -> Result<()> {
+        self.log.record("CreateAdditionalSwapChain");
+        Ok(())
+    }
+
+    fn GetSwapChain(&self, _iswapchain: u32) -> Result<IDirect3DSwapChain9> {
+        self.log.record("GetSwapChain");
+        Err(E_NOTIMPL.into())
+    }
+
+    fn GetNumberOfSwapChains(&self) -> u32 {
+        self.log.record("GetNumberOfSwapChains");
+        0
+    }
+
+    fn Reset(&self, _ppresentationparameters: *mut D3DPRESENT_PARAMETERS) -> Result<()> {
+        self.log.record("Reset");
+        Ok(())
+    }
+
+    fn Present(&self, _psourcerect: *const RECT, _pdestrect: *const RECT, _hdestwindowoverride: HWND, _pdirtyregion: *const RGNDATA) -> Result<()> {
+        self.log.record("Present");
+        Ok(())
+    }
+
+    fn GetBackBuffer(&self, _iswapchain: u32, _ibackbuffer: u32, _type: D3DBACKBUFFER_TYPE) -> Result<IDirect3DSurface9> {
+        self.log.record("GetBackBuffer");
+        Err(E_NOTIMPL.into())
+    }
+
+    fn GetRasterStatus(&self, _iswapchain: u32, _prasterstatus: *mut D3DRASTER_STATUS) -> Result<()> {
+        self.log.record("GetRasterStatus");
+        Ok(())
+    }
+
+    fn SetDialogBoxMode(&self, _benabledialogs: BOOL) -> Result<()> {
+        self.log.record("SetDialogBoxMode");
+        Ok(())
+    }
+
+    fn SetGammaRamp(&self, _iswapchain: u32, _flags: u32, _pramp: *const D3DGAMMARAMP) {
+        self.log.record("SetGammaRamp");
+    }
+
+    fn GetGammaRamp(&self, _iswapchain: u32, _pramp: *mut D3DGAMMARAMP) {
+        self.log.record("GetGammaRamp");
+    }
+
+    fn CreateTexture(
+        &self,
+        _width: u32,
+        _height: u32,
+        _levels: u32,
+        _usage: u32,
+        _format: D3DFORMAT,
+        _pool: D3DPOOL,
+        pptexture: OutRef<'_, IDirect3DTexture9>,
+        _psharedhandle: *mut HANDLE,
+    ) -> Result<()> {
+        self.log.record("CreateTexture");
+        let texture: IDirect3DTexture9 = MockTexture { log: self.log.clone() }.into();
+        self.state.lock().unwrap().textures.push(texture.clone());
+        pptexture.write(Some(texture))
+    }
+
+    fn CreateVolumeTexture(
+        &self,
+        _width: u32,
+        _height: u32,
+        _depth: u32,
+        _levels: u32,
+        _usage: u32,
+        _format: D3DFORMAT,
+        _pool: D3DPOOL,
+        _ppvolumetexture: OutRef<'_, IDirect3DVolumeTexture9>,
+        _psharedhandle: *mut HANDLE,
+    ) -> Result<()> {
+        self.log.record("CreateVolumeTexture");
+        Ok(())
+    }
+
+    fn CreateCubeTexture(
+        &self,
+        _edgelength: u32,
+        _levels: u32,
+        _usage: u32,
+        _format: D3DFORMAT,
+        _pool: D3DPOOL,
+        _ppcubetexture: OutRef<'_, IDirect3DCubeTexture9>,
+        _psharedhandle: *mut HANDLE,
+    ) -> Result<()> {
+        self.log.record("CreateCubeTexture");
+        Ok(())
+    }
+
+    fn CreateVertexBuffer(&self, length: u32, _usage: u32, _fvf: u32, _pool: D3DPOOL, ppvertexbuffer: OutRef<'_, IDirect3DVertexBuffer9>, _psharedhandle: *mut HANDLE) -> Result<()> {
+        self.log.record("CreateVertexBuffer");
+        let data = Mutex::new(vec![0; length as usize]);
+        ppvertexbuffer.write(Some(MockVertexBuffer { log: self.log.clone(), data }.into()))
+    }
+
+    fn CreateIndexBuffer(&self, length: u32, _usage: u32, format: D3DFORMAT, _pool: D3DPOOL, ppindexbuffer: OutRef<'_, IDirect3DIndexBuffer9>, _psharedhandle: *mut HANDLE) -> Result<()> {
+        self.log.record("CreateIndexBuffer");
+        let data = Mutex::new(vec![0; length as usize]);
+        ppindexbuffer.write(Some(MockIndexBuffer { log: self.log.clone(), format, data }.into()))
+    }
+
+    fn CreateRenderTarget(
+        &self,
+        width: u32,
+        height: u32,
+        format: D3DFORMAT,
+        _multisample: D3DMULTISAMPLE_TYPE,
+        _multisamplequality: u32,
+        _lockable: BOOL,
+        ppsurface: OutRef<'_, IDirect3DSurface9>,
+        _psharedhandle: *mut HANDLE,
+    ) -> Result<()> {
+        self.log.record("CreateRenderTarget");
+        ppsurface.write(Some(MockSurface::new(self.log.clone(), width, height, format, 0).into()))
+    }
+
+    fn CreateDepthStencilSurface(
+        &self,
+        width: u32,
+        height: u32,
+        format: D3DFORMAT,
+        _multisample: D3DMULTISAMPLE_TYPE,
+        _multisamplequality: u32,
+        _discard: BOOL,
+        ppsurface: OutRef<'_, IDirect3DSurface9>,
+        _psharedhandle: *mut HANDLE,
+    ) -> Result<()> {
+        self.log.record("CreateDepthStencilSurface");
+        ppsurface.write(Some(MockSurface::new(self.log.clone(), width, height, format, 0).into()))
+    }
+
+    fn UpdateSurface(&self, _psourcesurface: Ref<'_, IDirect3DSurface9>, _psourcerect: *const RECT, _pdestinationsurface: Ref<'_, IDirect3DSurface9>, _pdestpoint: *const POINT) -> Result<()> {
+        self.log.record("UpdateSurface");
+        Ok(())
+    }
+
+    fn UpdateTexture(&self, psourcetexture: Ref<'_, IDirect3DBaseTexture9>, pdestinationtexture: Ref<'_, IDirect3DBaseTexture9>) -> Result<()> {
+        self.log.record("UpdateTexture");
+        self.state.lock().unwrap().updated_textures.push((psourcetexture.cloned(), pdestinationtexture.cloned()));
+        Ok(())
+    }
+
+    fn GetRenderTargetData(&self, prendertarget: Ref<'_, IDirect3DSurface9>, pdestsurface: Ref<'_, IDirect3DSurface9>) -> Result<()> {
+        self.log.record("GetRenderTargetData");
+        let (source, dest) = (prendertarget.ok()?, pdestsurface.ok()?);
+        let (source, dest): (&MockSurface, &MockSurface) = unsafe { (source.as_impl(), dest.as_impl()) };
+        for y in 0..source.desc.Height {
+            let row = source.row(y);
+            let start = (y * dest.pitch) as usize;
+            dest.data.lock().unwrap()[start..start + row.len()].copy_from_slice(&row);
+        }
+        Ok(())
+    }
+
+    fn GetFrontBufferData(&self, _iswapchain: u32, _pdestsurface: Ref<'_, IDirect3DSurface9>) -> Result<()> {
+        self.log.record("GetFrontBufferData");
+        Ok(())
+    }
+
+    fn StretchRect(
+        &self,
+        _psourcesurface: Ref<'_, IDirect3DSurface9>,
+        _psourcerect: *const RECT,
+        _pdestsurface: Ref<'_, IDirect3DSurface9>,
+        _pdestrect: *const RECT,
+        _filter: D3DTEXTUREFILTERTYPE,
+    ) -> Result<()> {
+        self.log.record("StretchRect");
+        Ok(())
+    }
+
+    fn ColorFill(&self, _psurface: Ref<'_, IDirect3DSurface9>, _prect: *const RECT, _color: u32) -> Result<()> {
+        self.log.record("ColorFill");
+        Ok(())
+    }
+
+    fn CreateOffscreenPlainSurface(&self, width: u32, height: u32, format: D3DFORMAT, _pool: D3DPOOL, ppsurface: OutRef<'_, IDirect3DSurface9>, _psharedhandle: *mut HANDLE) -> Result<()> {
+        self.log.record("CreateOffscreenPlainSurface");
+        let pitch = width * bytes_per_pixel(format).ok_or(D3DERR_INVALIDCALL)? + self.state.lock().unwrap().offscreen_pitch_padding;
+        ppsurface.write(Some(MockSurface::new(self.log.clone(), width, height, format, pitch).into()))
+    }
+
+    fn SetRenderTarget(&self, rendertargetindex: u32, prendertarget: Ref<'_, IDirect3DSurface9>) -> Result<()> {
+        self.log.record("SetRenderTarget");
+        if rendertargetindex == 0 {
+            self.state.lock().unwrap().render_target = prendertarget.cloned();
+        }
+        Ok(())
+    }
+
+    fn GetRenderTarget(&self, rendertargetindex: u32) -> Result<IDirect3DSurface9> {
+        self.log.record("GetRenderTarget");
+        let render_target = if rendertargetindex == 0 { self.state.lock().unwrap().render_target.clone() } else { None };
+        render_target.ok_or_else(|| D3DERR_INVALIDCALL.into())
+    }
+
+    fn SetDepthStencilSurface(&self, pnewzstencil: Ref<'_, IDirect3DSurface9>) -> Result<()> {
+        self.log.record("SetDepthStencilSurface");
+        self.state.lock().unwrap().depth_stencil = pnewzstencil.cloned();
+        Ok(())
+    }
+
+    fn GetDepthStencilSurface(&self) -> Result<IDirect3DSurface9> {
+        self.log.record("GetDepthStencilSurface");
+        self.state.lock().unwrap().depth_stencil.clone().ok_or_else(|| E_FAIL.into())
+    }
+
+    fn BeginScene(&self) -> Result<()> {
+        self.log.record("BeginScene");
+        Ok(())
+    }
+
+    fn EndScene(&self) -> Result<()> {
+        self.log.record("EndScene");
+        Ok(())
+    }
+
+    fn Clear(&self, _count: u32, _prects: *const D3DRECT, _flags: u32, _color: u32, _z: f32, _stencil: u32) -> Result<()> {
+        self.log.record("Clear");
+        Ok(())
+    }
+
+    fn SetTransform(&self, _state: D3DTRANSFORMSTATETYPE, _pmatrix: *const Matrix4x4) -> Result<()> {
+        self.log.record("SetTransform");
+        Ok(())
+    }
+
+    fn GetTransform(&self, _state: D3DTRANSFORMSTATETYPE, _pmatrix: *mut Matrix4x4) -> Result<()> {
+        self.log.record("GetTransform");
+        Ok(())
+    }
+
+    fn MultiplyTransform(&self, _param0: D3DTRANSFORMSTATETYPE, _param1: *const Matrix4x4) -> Result<()> {
+        self.log.record("MultiplyTransform");
+        Ok(())
+    }
+
+    fn SetViewport(&self, pviewport: *const D3DVIEWPORT9) -> Result<()> {
+        self.log.record("SetViewport");
+        self.state.lock().unwrap().viewport = unsafe { *pviewport };
+        Ok(())
+    }
+
+    fn GetViewport(&self, pviewport: *mut D3DVIEWPORT9) -> Result<()> {
+        self.log.record("GetViewport");
+        unsafe { *pviewport = self.state.lock().unwrap().viewport };
+        Ok(())
+    }
+
+    fn SetMaterial(&self, _pmaterial: *const D3DMATERIAL9) -> Result<()> {
+        self.log.record("SetMaterial");
+        Ok(())
+    }
+
+    fn GetMaterial(&self, _pmaterial: *mut D3DMATERIAL9) -> Result<()> {
+        self.log.record("GetMaterial");
+        Ok(())
+    }
+
+    fn SetLight(&self, _index: u32, _param1: *const D3DLIGHT9) -> Result<()> {
+        self.log.record("SetLight");
+        Ok(())
+    }
+
+    fn GetLight(&self, _index: u32, _param1: *mut D3DLIGHT9) -> Result<()> {
+        self.log.record("GetLight");
+        Ok(())
+    }
+
+    fn LightEnable(&self, _index: u32, _enable: BOOL) -> Result<()> {
+        self.log.record("LightEnable");
+        Ok(())
+    }
+
+    fn GetLightEnable(&self, index: u32, penable: *mut BOOL) -> Result<()> {
+        self.log.record("GetLightEnable");
+        unsafe { *penable = self.state.lock().unwrap().light_enables.get(&index).copied().unwrap_or_default() };
+        Ok(())
+    }
+
+    fn SetClipPlane(&self, _index: u32, _pplane: *const f32) -> Result<()> {
+        self.log.record("SetClipPlane");
+        Ok(())
+    }
+
+    fn GetClipPlane(&self, _index: u32, _pplane: *mut f32) -> Result<()> {
+        self.log.record("GetClipPlane");
+        Ok(())
+    }
+
+    fn SetRenderState(&self, state: D3DRENDERSTATETYPE, value: u32) -> Result<()> {
+        self.log.record("SetRenderState");
+        self.state.lock().unwrap().render_states.insert(state.0, value);
+        Ok(())
+    }
+
+    fn GetRenderState(&self, state: D3DRENDERSTATETYPE, pvalue: *mut u32) -> Result<()> {
+        self.log.record("GetRenderState");
+        unsafe { *pvalue = self.state.lock().unwrap().render_states.get(&state.0).copied().unwrap_or_default() };
+        Ok(())
+    }
+
+    fn CreateStateBlock(&self, _type: D3DSTATEBLOCKTYPE) -> Result<IDirect3DStateBlock9> {
+        self.log.record("CreateStateBlock");
+        if !self.state.lock().unwrap().state_blocks_supported {
+            return Err(E_NOTIMPL.into());
+        }
+        Ok(MockStateBlock { log: self.log.clone() }.into())
+    }
+
+    fn BeginStateBlock(&self) -> Result<()> {
+        self.log.record("BeginStateBlock");
+        Ok(())
+    }
+
+    fn EndStateBlock(&self) -> Result<IDirect3DStateBlock9> {
+        self.log.record("EndStateBlock");
+        Err(E_NOTIMPL.into())
+    }
+
+    fn SetClipStatus(&self, _pclipstatus: *const D3DCLIPSTATUS9) -> Result<()> {
+        self.log.record("SetClipStatus");
+        Ok(())
+    }
+
+    fn GetClipStatus(&self, _pclipstatus: *mut D3DCLIPSTATUS9) -> Result<()> {
+        self.log.record("GetClipStatus");
+        Ok(())
+    }
+
+    fn GetTexture(&self, _stage: u32) -> Result<IDirect3DBaseTexture9> {
+        self.log.record("GetTexture");
+        Err(E_NOTIMPL.into())
+    }
+
+    fn SetTexture(&self, _stage: u32, _ptexture: Ref<'_, IDirect3DBaseTexture9>) -> Result<()> {
+        self.log.record("SetTexture");
+        Ok(())
+    }
+
+    fn GetTextureStageState(&self, _stage: u32, _type: D3DTEXTURESTAGESTATETYPE, _pvalue: *mut u32) -> Result<()> {
+        self.log.record("GetTextureStageState");
+        Ok(())
+    }
+
+    fn SetTextureStageState(&self, _stage: u32, _type: D3DTEXTURESTAGESTATETYPE, _value: u32) -> Result<()> {
+        self.log.record("SetTextureStageState");
+        Ok(())
+    }
+
+    fn GetSamplerState(&self, _sampler: u32, _type: D3DSAMPLERSTATETYPE, _pvalue: *mut u32) -> Result<()> {
+        self.log.record("GetSamplerState");
+        Ok(())
+    }
+
+    fn SetSamplerState(&self, _sampler: u32, _type: D3DSAMPLERSTATETYPE, _value: u32) -> Result<()> {
+        self.log.record("SetSamplerState");
+        Ok(())
+    }
+
+    fn ValidateDevice(&self, _pnumpasses: *mut u32) -> Result<()> {
+        self.log.record("ValidateDevice");
+        Ok(())
+    }
+
+    fn SetPaletteEntries(&self, _palettenumber: u32, _pentries: *const PALETTEENTRY) -> Result<()> {
+        self.log.record("SetPaletteEntries");
+        Ok(())
+    }
+
+    fn GetPaletteEntries(&self, _palettenumber: u32, _pentries: *mut PALETTEENTRY) -> Result<()> {
+        self.log.record("GetPaletteEntries");
+        Ok(())
+    }
+
+    fn SetCurrentTexturePalette(&self, _palettenumber: u32) -> Result<()> {
+        self.log.record("SetCurrentTexturePalette");
+        Ok(())
+    }
+
+    fn GetCurrentTexturePalette(&self, _palettenumber: *mut u32) -> Result<()> {
+        self.log.record("GetCurrentTexturePalette");
+        Ok(())
+    }
+
+    fn SetScissorRect(&self, _prect: *const RECT) -> Result<()> {
+        self.log.record("SetScissorRect");
+        Ok(())
+    }
+
+    fn GetScissorRect(&self, _prect: *mut RECT) -> Result<()> {
+        self.log.record("GetScissorRect");
+        Ok(())
+    }
+
+    fn SetSoftwareVertexProcessing(&self, _bsoftware: BOOL) -> Result<()> {
+        self.log.record("SetSoftwareVertexProcessing");
+        Ok(())
+    }
+
+    fn GetSoftwareVertexProcessing(&self) -> BOOL {
+        self.log.record("GetSoftwareVertexProcessing");
+        FALSE
+    }
+
+    fn SetNPatchMode(&self, _nsegments: f32) -> Result<()> {
+        self.log.record("SetNPatchMode");
+        Ok(())
+    }
+
+    fn GetNPatchMode(&self) -> f32 {
+        self.log.record("GetNPatchMode");
+        0.0
+    }
+
+    fn DrawPrimitive(&self, primitivetype: D3DPRIMITIVETYPE, startvertex: u32, primitivecount: u32) -> Result<()> {
+        self.log.record("DrawPrimitive");
+        let mut state = self.state.lock().unwrap();
+        let num_vertices = primitive_vertex_count(primitivetype, primitivecount);
+        let (vertices, stride) = state.stream_zero_data(startvertex, num_vertices);
+        state.draws.push(MockDraw {
+            primitive_type: primitivetype,
+            primitive_count: primitivecount,
+            num_vertices,
+            stride,
+            vertices,
+            ..Default::default()
+        });
+        Ok(())
+    }
+
+    fn DrawIndexedPrimitive(&self, param0: D3DPRIMITIVETYPE, basevertexindex: i32, minvertexindex: u32, numvertices: u32, startindex: u32, primcount: u32) -> Result<()> {
+        self.log.record("DrawIndexedPrimitive");
+        let mut state = self.state.lock().unwrap();
+        let (vertices, stride) = state.stream_zero_data(basevertexindex as u32, minvertexindex + numvertices);
+        let (indices, index_format) = state.index_data(startindex, primitive_vertex_count(param0, primcount));
+        state.draws.push(MockDraw {
+            primitive_type: param0,
+            primitive_count: primcount,
+            min_vertex_index: minvertexindex,
+            num_vertices: numvertices,
+            stride,
+            vertices,
+            indices,
+            index_format,
+        });
+        Ok(())
+    }
+
+    fn DrawPrimitiveUP(&self, primitivetype: D3DPRIMITIVETYPE, primitivecount: u32, pvertexstreamzerodata: *const c_void, vertexstreamzerostride: u32) -> Result<()> {
+        self.log.record("DrawPrimitiveUP");
+        let num_vertices = primitive_vertex_count(primitivetype, primitivecount);
+        let vertices = unsafe { read_bytes(pvertexstreamzerodata, num_vertices * vertexstreamzerostride) };
+        let mut state = self.state.lock().unwrap();
+        // Like the runtime, user-pointer draws unbind stream 0
+        state.stream_zero = None;
+        state.draws.push(MockDraw {
+            primitive_type: primitivetype,
+            primitive_count: primitivecount,
+            num_vertices,
+            stride: vertexstreamzerostride,
+            vertices,
+            ..Default::default()
+        });
+        Ok(())
+    }
+
+    fn DrawIndexedPrimitiveUP(
+        &self,
+        primitivetype: D3DPRIMITIVETYPE,
+        minvertexindex: u32,
+        numvertices: u32,
+        primitivecount: u32,
+        pindexdata: *const c_void,
+        indexdataformat: D3DFORMAT,
+        pvertexstreamzerodata: *const c_void,
+        vertexstreamzerostride: u32,
+    ) -> Result<()> {
+        self.log.record("DrawIndexedPrimitiveUP");
+        let vertices = unsafe { read_bytes(pvertexstreamzerodata, (minvertexindex + numvertices) * vertexstreamzerostride) };
+        let indices = unsafe { read_bytes(pindexdata, primitive_vertex_count(primitivetype, primitivecount) * index_size(indexdataformat)) };
+        let mut state = self.state.lock().unwrap();
+        // Like the runtime, user-pointer draws unbind stream 0 and the indices
+        state.stream_zero = None;
+        state.indices = None;
+        state.draws.push(MockDraw {
+            primitive_type: primitivetype,
+            primitive_count: primitivecount,
+            min_vertex_index: minvertexindex,
+            num_vertices: numvertices,
+            stride: vertexstreamzerostride,
+            vertices,
+            indices,
+            index_format: indexdataformat,
+        });
+        Ok(())
+    }
+
+    fn ProcessVertices(
+        &self,
+        _srcstartindex: u32,
+        _destindex: u32,
+        _vertexcount: u32,
+        _pdestbuffer: Ref<'_, IDirect3DVertexBuffer9>,
+        _pvertexdecl: Ref<'_, IDirect3DVertexDeclaration9>,
+        _flags: u32,
+    ) -> Result<()> {
+        self.log.record("ProcessVertices");
+        Ok(())
+    }
+
+    fn CreateVertexDeclaration(&self, _pvertexelements: *const D3DVERTEXELEMENT9) -> Result<IDirect3DVertexDeclaration9> {
+        self.log.record("CreateVertexDeclaration");
+        Err(E_NOTIMPL.into())
+    }
+
+    fn SetVertexDeclaration(&self, _pdecl: Ref<'_, IDirect3DVertexDeclaration9>) -> Result<()> {
+        self.log.record("SetVertexDeclaration");
+        Ok(())
+    }
+
+    fn GetVertexDeclaration(&self) -> Result<IDirect3DVertexDeclaration9> {
+        self.log.record("GetVertexDeclaration");
+        Err(E_NOTIMPL.into())
+    }
+
+    fn SetFVF(&self, fvf: u32) -> Result<()> {
+        self.log.record("SetFVF");
+        self.state.lock().unwrap().fvf = fvf;
+        Ok(())
+    }
+
+    fn GetFVF(&self, pfvf: *mut u32) -> Result<()> {
+        self.log.record("GetFVF");
+        unsafe { *pfvf = self.state.lock().unwrap().fvf };
+        Ok(())
+    }
+
+    fn CreateVertexShader(&self, _pfunction: *const u32) -> Result<IDirect3DVertexShader9> {
+        self.log.record("CreateVertexShader");
+        Err(E_NOTIMPL.into())
+    }
+
+    fn SetVertexShader(&self, _pshader: Ref<'_, IDirect3DVertexShader9>) -> Result<()> {
+        self.log.record("SetVertexShader");
+        Ok(())
+    }
+
+    fn GetVertexShader(&self) -> Result<IDirect3DVertexShader9> {
+        self.log.record("GetVertexShader");
+        Err(E_NOTIMPL.into())
+    }
+
+    fn SetVertexShaderConstantF(&self, _startregister: u32, _pconstantdata: *const f32, _vector4fcount: u32) -> Result<()> {
+        self.log.record("SetVertexShaderConstantF");
+        Ok(())
+    }
+
+    fn GetVertexShaderConstantF(&self, _startregister: u32, _pconstantdata: *mut f32, _vector4fcount: u32) -> Result<()> {
+        self.log.record("GetVertexShaderConstantF");
+        Ok(())
+    }
+
+    fn SetVertexShaderConstantI(&self, _startregister: u32, _pconstantdata: *const i32, _vector4icount: u32) -> Result<()> {
+        self.log.record("SetVertexShaderConstantI");
+        Ok(())
+    }
+
+    fn GetVertexShaderConstantI(&self, _startregister: u32, _pconstantdata: *mut i32, _vector4icount: u32) -> Result<()> {
+        self.log.record("GetVertexShaderConstantI");
+        Ok(())
+    }
+
+    fn SetVertexShaderConstantB(&self, _startregister: u32, _pconstantdata: *const BOOL, _boolcount: u32) -> Result<()> {
+        self.log.record("SetVertexShaderConstantB");
+        Ok(())
+    }
+
+    fn GetVertexShaderConstantB(&self, _startregister: u32, _pconstantdata: *mut BOOL, _boolcount: u32) -> Result<()> {
+        self.log.record("GetVertexShaderConstantB");
+        Ok(())
+    }
+
+    fn SetStreamSource(&self, streamnumber: u32, pstreamdata: Ref<'_, IDirect3DVertexBuffer9>, offsetinbytes: u32, stride: u32) -> Result<()> {
+        self.log.record("SetStreamSource");
+        if streamnumber == 0 {
+            self.state.lock().unwrap().stream_zero = pstreamdata.cloned().map(|buffer| (buffer, offsetinbytes, stride));
+        }
+        Ok(())
+    }
+
+    fn GetStreamSource(&self, _streamnumber: u32, _ppstreamdata: OutRef<'_, IDirect3DVertexBuffer9>, _poffsetinbytes: *mut u32, _pstride: *mut u32) -> Result<()> {
+        self.log.record("GetStreamSource");
+        Ok(())
+    }
+
+    fn SetStreamSourceFreq(&self, _streamnumber: u32, _setting: u32) -> Result<()> {
+        self.log.record("SetStreamSourceFreq");
+        Ok(())
+    }
+
+    fn GetStreamSourceFreq(&self, _streamnumber: u32, _psetting: *mut u32) -> Result<()> {
+        self.log.record("GetStreamSourceFreq");
+        Ok(())
+    }
+
+    fn SetIndices(&self, pindexdata: Ref<'_, IDirect3DIndexBuffer9>) -> Result<()> {
+        self.log.record("SetIndices");
+        self.state.lock().unwrap().indices = pindexdata.cloned();
+        Ok(())
+    }
+
+    fn GetIndices(&self) -> Result<IDirect3DIndexBuffer9> {
+        self.log.record("GetIndices");
+        Err(E_NOTIMPL.into())
+    }
+
+    fn CreatePixelShader(&self, _pfunction: *const u32) -> Result<IDirect3DPixelShader9> {
+        self.log.record("CreatePixelShader");
+        Err(E_NOTIMPL.into())
+    }
+
+    fn SetPixelShader(&self, _pshader: Ref<'_, IDirect3DPixelShader9>) -> Result<()> {
+        self.log.record("SetPixelShader");
+        Ok(())
+    }
+
+    fn GetPixelShader(&self) -> Result<IDirect3DPixelShader9> {
+        self.log.record("GetPixelShader");
+        Err(E_NOTIMPL.into())
+    }
+
+    fn SetPixelShaderConstantF(&self, _startregister: u32, _pconstantdata: *const f32, _vector4fcount: u32) -> Result<()> {
+        self.log.record("SetPixelShaderConstantF");
+        Ok(())
+    }
+
+    fn GetPixelShaderConstantF(&self, _startregister: u32, _pconstantdata: *mut f32, _vector4fcount: u32) -> Result<()> {
+        self.log.record("GetPixelShaderConstantF");
+        Ok(())
+    }
+
+    fn SetPixelShaderConstantI(&self, _startregister: u32, _pconstantdata: *const i32, _vector4icount: u32) -> Result<()> {
+        self.log.record("SetPixelShaderConstantI");
+        Ok(())
+    }
+
+    fn GetPixelShaderConstantI(&self, _startregister: u32, _pconstantdata: *mut i32, _vector4icount: u32) -> Result<()> {
+        self.log.record("GetPixelShaderConstantI");
+        Ok(())
+    }
+
+    fn SetPixelShaderConstantB(&self, _startregister: u32, _pconstantdata: *const BOOL, _boolcount: u32) -> Result<()> {
+        self.log.record("SetPixelShaderConstantB");
+        Ok(())
+    }
+
+    fn GetPixelShaderConstantB(&self, _startregister: u32, _pconstantdata: *mut BOOL, _boolcount: u32) -> Result<()> {
+        self.log.record("GetPixelShaderConstantB");
+        Ok(())
+    }
+
+    fn DrawRectPatch(&self, _handle: u32, _pnumsegs: *const f32, _prectpatchinfo: *const D3DRECTPATCH_INFO) -> Result<()> {
+        self.log.record("DrawRectPatch");
+        Ok(())
+    }
+
+    fn DrawTriPatch(&self, _handle: u32, _pnumsegs: *const f32, _ptripatchinfo: *const D3DTRIPATCH_INFO) -> Result<()> {
+        self.log.record("DrawTriPatch");
+        Ok(())
+    }
+
+    fn DeletePatch(&self, _handle: u32) -> Result<()> {
+        self.log.record("DeletePatch");
+        Ok(())
+    }
+
+    fn CreateQuery(&self, _type: D3DQUERYTYPE) -> Result<IDirect3DQuery9> {
+        self.log.record("CreateQuery");
+        Ok(MockQuery::new(self.log.clone(), []).into())
+    }
+}
+
+impl IDirect3DDevice9Ex_Impl for MockDevice_Impl {
+    fn SetConvolutionMonoKernel(&self, _width: u32, _height: u32, _rows: *mut f32, _columns: *mut f32) -> Result<()> {
+        self.log.record("SetConvolutionMonoKernel");
+        Ok(())
+    }
+
+    fn ComposeRects(
+        &self,
+        _psrc: Ref<'_, IDirect3DSurface9>,
+        _pdst: Ref<'_, IDirect3DSurface9>,
+        _psrcrectdescs: Ref<'_, IDirect3DVertexBuffer9>,
+        _numrects: u32,
+        _pdstrectdescs: Ref<'_, IDirect3DVertexBuffer9>,
+        _operation: D3DCOMPOSERECTSOP,
+        _xoffset: i32,
+        _yoffset: i32,
+    ) -> Result<()> {
+        self.log.record("ComposeRects");
+        Ok(())
+    }
+
+    fn PresentEx(&self, _psourcerect: *const RECT, _pdestrect: *const RECT, _hdestwindowoverride: HWND, _pdirtyregion: *const RGNDATA, _dwflags: u32) -> Result<()> {
+        self.log.record("PresentEx");
+        Ok(())
+    }
+
+    fn GetGPUThreadPriority(&self, _ppriority: *mut i32) -> Result<()> {
+        self.log.record("GetGPUThreadPriority");
+        Ok(())
+    }
+
+    fn SetGPUThreadPriority(&self, _priority: i32) -> Result<()> {
+        self.log.record("SetGPUThreadPriority");
+        Ok(())
+    }
+
+    fn WaitForVBlank(&self, _iswapchain: u32) -> Result<()> {
+        self.log.record("WaitForVBlank");
+        Ok(())
+    }
+
+    fn CheckResourceResidency(&self, _presourcearray: OutRef<'_, IDirect3DResource9>, _numresources: u32) -> Result<()> {
+        self.log.record("CheckResourceResidency");
+        Ok(())
+    }
+
+    fn SetMaximumFrameLatency(&self, _maxlatency: u32) -> Result<()> {
+        self.log.record("SetMaximumFrameLatency");
+        Ok(())
+    }
+
+    fn GetMaximumFrameLatency(&self, _pmaxlatency: *mut u32) -> Result<()> {
+        self.log.record("GetMaximumFrameLatency");
+        Ok(())
+    }
+
+    fn CheckDeviceState(&self, _hdestinationwindow: HWND) -> Result<()> {
+        self.log.record("CheckDeviceState");
+        Ok(())
+    }
+
+    fn CreateRenderTargetEx(
+        &self,
+        _width: u32,
+        _height: u32,
+        _format: D3DFORMAT,
+        _multisample: D3DMULTISAMPLE_TYPE,
+        _multisamplequality: u32,
+        _lockable: BOOL,
+        _ppsurface: OutRef<'_, IDirect3DSurface9>,
+        _psharedhandle: *mut HANDLE,
+        _usage: u32,
+    ) -> Result<()> {
+        self.log.record("CreateRenderTargetEx");
+        Ok(())
+    }
+
+    fn CreateOffscreenPlainSurfaceEx(
+        &self,
+        _width: u32,
+        _height: u32,
+        _format: D3DFORMAT,
+        _pool: D3DPOOL,
+        _ppsurface: OutRef<'_, IDirect3DSurface9>,
+        _psharedhandle: *mut HANDLE,
+        _usage: u32,
+    ) -> Result<()> {
+        self.log.record("CreateOffscreenPlainSurfaceEx");
+        Ok(())
+    }
+
+    fn CreateDepthStencilSurfaceEx(
+        &self,
+        _width: u32,
+        _height: u32,
+        _format: D3DFORMAT,
+        _multisample: D3DMULTISAMPLE_TYPE,
+        _multisamplequality: u32,
+        _discard: BOOL,
+        _ppsurface: OutRef<'_, IDirect3DSurface9>,
+        _psharedhandle: *mut HANDLE,
+        _usage: u32,
+    ) -> Result<()> {
+        self.log.record("CreateDepthStencilSurfaceEx");
+        Ok(())
+    }
+
+    fn ResetEx(&self, _ppresentationparameters: *mut D3DPRESENT_PARAMETERS, _pfullscreendisplaymode: *mut D3DDISPLAYMODEEX) -> Result<()> {
+        self.log.record("ResetEx");
+        Ok(())
+    }
+
+    fn GetDisplayModeEx(&self, _iswapchain: u32, _pmode: *mut D3DDISPLAYMODEEX, _protation: *mut D3DDISPLAYROTATION) -> Result<()> {
+        self.log.record("GetDisplayModeEx");
+        Ok(())
+    }
+}
+
+impl IDirect3DResource9_Impl for MockSurface_Impl {
+    fn GetDevice(&self) -> Result<IDirect3DDevice9> {
+        self.log.record("GetDevice");
+        Err(E_NOTIMPL.into())
+    }
+
+    fn SetPrivateData(&self, _refguid: *const GUID, _pdata: *const c_void, _sizeofdata: u32, _flags: u32) -> Result<()> {
+        self.log.record("SetPrivateData");
+        Ok(())
+    }
+
+    fn GetPrivateData(&self, _refguid: *const GUID, _pdata: *mut c_void, _psizeofdata: *mut u32) -> Result<()> {
+        self.log.record("GetPrivateData");
+        Ok(())
+    }
+
+    fn FreePrivateData(&self, _refguid: *const GUID) -> Result<()> {
+        self.log.record("FreePrivateData");
+        Ok(())
+    }
+
+    fn SetPriority(&self, _prioritynew: u32) -> u32 {
+        self.log.record("SetPriority");
+        0
+    }
+
+    fn GetPriority(&self) -> u32 {
+        self.log.record("GetPriority");
+        0
+    }
+
+    fn PreLoad(&self) {
+        self.log.record("PreLoad");
+    }
+
+    fn GetType(&self) -> D3DRESOURCETYPE {
+        self.log.record("GetType");
+        Default::default()
+    }
+}
+
+impl IDirect3DSurface9_Impl for MockSurface_Impl {
+    fn GetContainer(&self, _riid: *const GUID, _ppcontainer: *mut *mut c_void) -> Result<()> {
+        self.log.record("GetContainer");
+        Ok(())
+    }
+
+    fn GetDesc(&self, pdesc: *mut D3DSURFACE_DESC) -> Result<()> {
+        self.log.record("GetDesc");
+        unsafe { *pdesc = self.desc };
+        Ok(())
+    }
+
+    fn LockRect(&self, plockedrect: *mut D3DLOCKED_RECT, _prect: *const RECT, _flags: u32) -> Result<()> {
+        self.log.record("LockRect");
+        unsafe {
+            *plockedrect = D3DLOCKED_RECT {
+                Pitch: self.pitch as i32,
+                pBits: self.data.lock().unwrap().as_mut_ptr().cast(),
+            }
+        };
+        Ok(())
+    }
+
+    fn UnlockRect(&self) -> Result<()> {
+        self.log.record("UnlockRect");
+        Ok(())
+    }
+
+    fn GetDC(&self, _phdc: *mut HDC) -> Result<()> {
+        self.log.record("GetDC");
+        Ok(())
+    }
+
+    fn ReleaseDC(&self, _hdc: HDC) -> Result<()> {
+        self.log.record("ReleaseDC");
+        Ok(())
+    }
+}
+
+impl IDirect3DResource9_Impl for MockTexture_Impl {
+    fn GetDevice(&self) -> Result<IDirect3DDevice9> {
+        self.log.record("GetDevice");
+        Err(E_NOTIMPL.into())
+    }
+
+    fn SetPrivateData(&self, _refguid: *const GUID, _pdata: *const c_void, _sizeofdata: u32, _flags: u32) -> Result<()> {
+        self.log.record("SetPrivateData");
+        Ok(())
+    }
+
+    fn GetPrivateData(&self, _refguid: *const GUID, _pdata: *mut c_void, _psizeofdata: *mut u32) -> Result<()> {
+        self.log.record("GetPrivateData");
+        Ok(())
+    }
+
+    fn FreePrivateData(&self, _refguid: *const GUID) -> Result<()> {
+        self.log.record("FreePrivateData");
+        Ok(())
+    }
+
+    fn SetPriority(&self, _prioritynew: u32) -> u32 {
+        self.log.record("SetPriority");
+        0
+    }
+
+    fn GetPriority(&self) -> u32 {
+        self.log.record("GetPriority");
+        0
+    }
+
+    fn PreLoad(&self) {
+        self.log.record("PreLoad");
+    }
+
+    fn GetType(&self) -> D3DRESOURCETYPE {
+        self.log.record("GetType");
+        Default::default()
+    }
+}
+
+impl IDirect3DBaseTexture9_Impl for MockTexture_Impl {
+    fn SetLOD(&self, _lodnew: u32) -> u32 {
+        self.log.record("SetLOD");
+        0
+    }
+
+    fn GetLOD(&self) -> u32 {
+        self.log.record("GetLOD");
+        0
+    }
+
+    fn GetLevelCount(&self) -> u32 {
+        self.log.record("GetLevelCount");
+        0
+    }
+
+    fn SetAutoGenFilterType(&self, _filtertype: D3DTEXTUREFILTERTYPE) -> Result<()> {
+        self.log.record("SetAutoGenFilterType");
+        Ok(())
+    }
+
+    fn GetAutoGenFilterType(&self) -> D3DTEXTUREFILTERTYPE {
+        self.log.record("GetAutoGenFilterType");
+        Default::default()
+    }
+
+    fn GenerateMipSubLevels(&self) {
+        self.log.record("GenerateMipSubLevels");
+    }
+}
+
+impl IDirect3DTexture9_Impl for MockTexture_Impl {
+    fn GetLevelDesc(&self, _level: u32, _pdesc: *mut D3DSURFACE_DESC) -> Result<()> {
+        self.log.record("GetLevelDesc");
+        Ok(())
+    }
+
+    fn GetSurfaceLevel(&self, _level: u32) -> Result<IDirect3DSurface9> {
+        self.log.record("GetSurfaceLevel");
+        Err(E_NOTIMPL.into())
+    }
+
+    fn LockRect(&self, _level: u32, _plockedrect: *mut D3DLOCKED_RECT, _prect: *const RECT, _flags: u32) -> Result<()> {
+        self.log.record("LockRect");
+        Ok(())
+    }
+
+    fn UnlockRect(&self, _level: u32) -> Result<()> {
+        self.log.record("UnlockRect");
+        Ok(())
+    }
+
+    fn AddDirtyRect(&self, _pdirtyrect: *const RECT) -> Result<()> {
+        self.log.record("AddDirtyRect");
+        Ok(())
+    }
+}
+
+impl IDirect3DResource9_Impl for MockVertexBuffer_Impl {
+    fn GetDevice(&self) -> Result<IDirect3DDevice9> {
+        self.log.record("GetDevice");
+        Err(E_NOTIMPL.into())
+    }
+
+    fn SetPrivateData(&self, _refguid: *const GUID, _pdata: *const c_void, _sizeofdata: u32, _flags: u32) -> Result<()> {
+        self.log.record("SetPrivateData");
+        Ok(())
+    }
+
+    fn GetPrivateData(&self, _refguid: *const GUID, _pdata: *mut c_void, _psizeofdata: *mut u32) -> Result<()> {
+        self.log.record("GetPrivateData");
+        Ok(())
+    }
+
+    fn FreePrivateData(&self, _refguid: *const GUID) -> Result<()> {
+        self.log.record("FreePrivateData");
+        Ok(())
+    }
+
+    fn SetPriority(&self, _prioritynew: u32) -> u32 {
+        self.log.record("SetPriority");
+        0
+    }
+
+    fn GetPriority(&self) -> u32 {
+        self.log.record("GetPriority");
+        0
+    }
+
+    fn PreLoad(&self) {
+        self.log.record("PreLoad");
+    }
+
+    fn GetType(&self) -> D3DRESOURCETYPE {
+        self.log.record("GetType");
+        Default::default()
+    }
+}
+
+impl IDirect3DVertexBuffer9_Impl for MockVertexBuffer_Impl {
+    fn Lock(&self, offsettolock: u32, _sizetolock: u32, ppbdata: *mut *mut c_void, _flags: u32) -> Result<()> {
+        self.log.record("Lock");
+        unsafe { *ppbdata = self.data.lock().unwrap().as_mut_ptr().add(offsettolock as usize).cast() };
+        Ok(())
+    }
+
+    fn Unlock(&self) -> Result<()> {
+        self.log.record("Unlock");
+        Ok(())
+    }
+
+    fn GetDesc(&self, pdesc: *mut D3DVERTEXBUFFER_DESC) -> Result<()> {
+        self.log.record("GetDesc");
+        unsafe {
+            *pdesc = D3DVERTEXBUFFER_DESC {
+                Format: D3DFMT_VERTEXDATA,
+                Type: D3DRTYPE_VERTEXBUFFER,
+                Size: self.data.lock().unwrap().len() as u32,
+                ..Default::default()
+            }
+        };
+        Ok(())
+    }
+}
+
+impl IDirect3DResource9_Impl for MockIndexBuffer_Impl {
+    fn GetDevice(&self) -> Result<IDirect3DDevice9> {
+        self.log.record("GetDevice");
+        Err(E_NOTIMPL.into())
+    }
+
+    fn SetPrivateData(&self, _refguid: *const GUID, _pdata: *const c_void, _sizeofdata: u32, _flags: u32) -> Result<()> {
+        self.log.record("SetPrivateData");
+        Ok(())
+    }
+
+    fn GetPrivateData(&self, _refguid: *const GUID, _pdata: *mut c_void, _psizeofdata: *mut u32) -> Result<()> {
+        self.log.record("GetPrivateData");
+        Ok(())
+    }
+
+    fn FreePrivateData(&self, _refguid: *const GUID) -> Result<()> {
+        self.log.record("FreePrivateData");
+        Ok(())
+    }
+
+    fn SetPriority(&self, _prioritynew: u32) -> u32 {
+        self.log.record("SetPriority");
+        0
+    }
+
+    fn GetPriority(&self) -> u32 {
+        self.log.record("GetPriority");
+        0
+    }
+
+    fn PreLoad(&self) {
+        self.log.record("PreLoad");
+    }
+
+    fn GetType(&self) -> D3DRESOURCETYPE {
+        self.log.record("GetType");
+        Default::default()
+    }
+}
+
+impl IDirect3DIndexBuffer9_Impl for MockIndexBuffer_Impl {
+    fn Lock(&self, offsettolock: u32, _sizetolock: u32, ppbdata: *mut *mut c_void, _flags: u32) -> Result<()> {
+        self.log.record("Lock");
+        unsafe { *ppbdata = self.data.lock().unwrap().as_mut_ptr().add(offsettolock as usize).cast() };
+        Ok(())
+    }
+
+    fn Unlock(&self) -> Result<()> {
+        self.log.record("Unlock");
+        Ok(())
+    }
+
+    fn GetDesc(&self, pdesc: *mut D3DINDEXBUFFER_DESC) -> Result<()> {
+        self.log.record("GetDesc");
+        unsafe {
+            *pdesc = D3DINDEXBUFFER_DESC {
+                Format: self.format,
+                Type: D3DRTYPE_INDEXBUFFER,
+                Size: self.data.lock().unwrap().len() as u32,
+                ..Default::default()
+            }
+        };
+        Ok(())
+    }
+}
+
+impl IDirect3D9_Impl for MockDirect3D9_Impl {
+    fn RegisterSoftwareDevice(&self, _pinitializefunction: *mut c_void) -> Result<()> {
+        self.log.record("RegisterSoftwareDevice");
+        Ok(())
+    }
+
+    fn GetAdapterCount(&self) -> u32 {
+        self.log.record("GetAdapterCount");
+        0
+    }
+
+    fn GetAdapterIdentifier(&self, _adapter: u32, _flags: u32, _pidentifier: *mut D3DADAPTER_IDENTIFIER9) -> Result<()> {
+        self.log.record("GetAdapterIdentifier");
+        Ok(())
+    }
+
+    fn GetAdapterModeCount(&self, _adapter: u32, _format: D3DFORMAT) -> u32 {
+        self.log.record("GetAdapterModeCount");
+        0
+    }
+
+    fn EnumAdapterModes(&self, _adapter: u32, _format: D3DFORMAT, _mode: u32, _pmode: *mut D3DDISPLAYMODE) -> Result<()> {
+        self.log.record("EnumAdapterModes");
+        Ok(())
+    }
+
+    fn GetAdapterDisplayMode(&self, _adapter: u32, _pmode: *mut D3DDISPLAYMODE) -> Result<()> {
+        self.log.record("GetAdapterDisplayMode");
+        Ok(())
+    }
+
+    fn CheckDeviceType(&self, _adapter: u32, _devtype: D3DDEVTYPE, _adapterformat: D3DFORMAT, _backbufferformat: D3DFORMAT, _bwindowed: BOOL) -> Result<()> {
+        self.log.record("CheckDeviceType");
+        Ok(())
+    }
+
+    fn CheckDeviceFormat(&self, _adapter: u32, _devicetype: D3DDEVTYPE, _adapterformat: D3DFORMAT, _usage: u32, _rtype: D3DRESOURCETYPE, _checkformat: D3DFORMAT) -> Result<()> {
+        self.log.record("CheckDeviceFormat");
+        Ok(())
+    }
+
+    fn CheckDeviceMultiSampleType(
+        &self,
+        _adapter: u32,
+        _devicetype: D3DDEVTYPE,
+        _surfaceformat: D3DFORMAT,
+        _windowed: BOOL,
+        _multisampletype: D3DMULTISAMPLE_TYPE,
+        _pqualitylevels: *mut u32,
+    ) -> Result<()> {
+        self.log.record("CheckDeviceMultiSampleType");
+        Ok(())
+    }
+
+    fn CheckDepthStencilMatch(&self, _adapter: u32, _devicetype: D3DDEVTYPE, _adapterformat: D3DFORMAT, _rendertargetformat: D3DFORMAT, _depthstencilformat: D3DFORMAT) -> Result<()> {
+        self.log.record("CheckDepthStencilMatch");
+        Ok(())
+    }
+
+    fn CheckDeviceFormatConversion(&self, _adapter: u32, _devicetype: D3DDEVTYPE, _sourceformat: D3DFORMAT, _targetformat: D3DFORMAT) -> Result<()> {
+        self.log.record("CheckDeviceFormatConversion");
+        Ok(())
+    }
+
+    fn GetDeviceCaps(&self, _adapter: u32, _devicetype: D3DDEVTYPE, _pcaps: *mut D3DCAPS9) -> Result<()> {
+        self.log.record("GetDeviceCaps");
+        Ok(())
+    }
+
+    fn GetAdapterMonitor(&self, _adapter: u32) -> HMONITOR {
+        self.log.record("GetAdapterMonitor");
+        Default::default()
+    }
+
+    fn CreateDevice(
+        &self,
+        _adapter: u32,
+        _devicetype: D3DDEVTYPE,
+        _hfocuswindow: HWND,
+        _behaviorflags: u32,
+        _ppresentationparameters: *mut D3DPRESENT_PARAMETERS,
+        ppreturneddeviceinterface: OutRef<'_, IDirect3DDevice9>,
+    ) -> Result<()> {
+        self.log.record("CreateDevice");
+        let device: IDirect3DDevice9Ex = MockDevice {
+            log: self.log.clone(),
+            ..Default::default()
+        }
+        .into();
+        ppreturneddeviceinterface.write(Some(device.into()))
+    }
+}
+
+impl IDirect3D9Ex_Impl for MockDirect3D9_Impl {
+    fn GetAdapterModeCountEx(&self, _adapter: u32, _pfilter: *const D3DDISPLAYMODEFILTER) -> u32 {
+        self.log.record("GetAdapterModeCountEx");
+        0
+    }
+
+    fn EnumAdapterModesEx(&self, _adapter: u32, _pfilter: *const D3DDISPLAYMODEFILTER, _mode: u32, _pmode: *mut D3DDISPLAYMODEEX) -> Result<()> {
+        self.log.record("EnumAdapterModesEx");
+        Ok(())
+    }
+
+    fn GetAdapterDisplayModeEx(&self, _adapter: u32, _pmode: *mut D3DDISPLAYMODEEX, _protation: *mut D3DDISPLAYROTATION) -> Result<()> {
+        self.log.record("GetAdapterDisplayModeEx");
+        Ok(())
+    }
+
+    fn CreateDeviceEx(
+        &self,
+        _adapter: u32,
+        _devicetype: D3DDEVTYPE,
+        _hfocuswindow: HWND,
+        _behaviorflags: u32,
+        _ppresentationparameters: *mut D3DPRESENT_PARAMETERS,
+        _pfullscreendisplaymode: *mut D3DDISPLAYMODEEX,
+        _ppreturneddeviceinterface: OutRef<'_, IDirect3DDevice9Ex>,
+    ) -> Result<()> {
+        self.log.record("CreateDeviceEx");
+        Ok(())
+    }
+
+    fn GetAdapterLUID(&self, _adapter: u32, _pluid: *mut LUID) -> Result<()> {
+        self.log.record("GetAdapterLUID");
+        Ok(())
+    }
+}
+
+impl IDirect3DQuery9_Impl for MockQuery_Impl {
+    fn GetDevice(&self) -> Result<IDirect3DDevice9> {
+        self.log.record("GetDevice");
+        Err(E_NOTIMPL.into())
+    }
+
+    fn GetType(&self) -> D3DQUERYTYPE {
+        self.log.record("GetType");
+        Default::default()
+    }
+
+    fn GetDataSize(&self) -> u32 {
+        self.log.record("GetDataSize");
+        0
+    }
+
+    fn Issue(&self, _dwissueflags: u32) -> Result<()> {
+        self.log.record("Issue");
+        Ok(())
+    }
+
+    fn GetData(&self, _pdata: *mut c_void, _dwsize: u32, _dwgetdataflags: u32) -> Result<()> {
+        self.log.record("GetData");
+        // Error::from_hresult keeps S_FALSE, which the caller receives as the returned HRESULT
+        match self.get_data_results.lock().unwrap().pop_front() {
+            Some(result) if result != S_OK => Err(Error::from_hresult(result)),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl IDirect3DStateBlock9_Impl for MockStateBlock_Impl {
+    fn GetDevice(&self) -> Result<IDirect3DDevice9> {
+        self.log.record("GetDevice");
+        Err(E_NOTIMPL.into())
+    }
+
+    fn Capture(&self) -> Result<()> {
+        self.log.record("Capture");
+        Ok(())
+    }
+
+    fn Apply(&self) -> Result<()> {
+        self.log.record("Apply");
+        Ok(())
+    }
+}
@@ -4,26 +4,6 @@
 //! providing instrumentation, logging, and potential interception capabilities
 //! for DirectX 9 graphics API calls.
 
-use windows::Win32::Foundation::S_OK;
-use windows_core::HRESULT;
-
-/// Creates a Direct3D-specific HRESULT from a given error code.
-#[allow(non_snake_case)]
-const fn MAKE_D3DHRESULT(code: u32) -> HRESULT {
-    // MAKE_HRESULT(1, _FACD3D, code) where _FACD3D is 0x876
-    // -> (1 << 31) | (0x876 << 16) | code
-    HRESULT((0x88760800 | code) as i32)
-}
-
-/// Standard success result for Direct3D operations.
-pub const D3D_OK: HRESULT = S_OK;
-
-/// Device lost error - occurs when the Direct3D device becomes unavailable.
-pub const D3DERR_DEVICELOST: HRESULT = MAKE_D3DHRESULT(2152);
-
-/// Invalid call error - indicates improper API usage or invalid parameters.
-pub const D3DERR_INVALIDCALL: HRESULT = MAKE_D3DHRESULT(2156);
-
 /// Implements Debug trait for proxy COM interfaces.
 ///
 /// Provides formatted debug output showing the type name and both proxy and target interface pointers.
@@ -31,6 +11,8 @@ macro_rules! impl_debug {
     ($name:ident) => {
         impl std::fmt::Debug for $name {
             fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                #[cfg(feature = "tracing-instrument")]
+                crate::trace::on_object_formatted(self.as_interface::<IUnknown>().as_raw());
                 write!(
                     f,
                     "{} {:p} (<=> {:p})",
@@ -64,10 +46,52 @@ macro_rules! check_nullptr {
     };
 }
 
-use super::config::*;
-use crate::try_out_param;
+/// Implements the private data methods of resource proxies (`SetPrivateData`, `GetPrivateData` and `FreePrivateData`).
+///
+/// The calls are forwarded to the target and logged by [`DX9ProxyDeviceContext::log_private_data`].
+/// The proxy must have `target` and `context` fields.
+macro_rules! impl_private_data {
+    () => {
+        #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
+        fn SetPrivateData(&self, refguid: *const GUID, pdata: *const c_void, sizeofdata: u32, flags: u32) -> Result<()> {
+            let result = unsafe { self.target.SetPrivateData(refguid, pdata, sizeofdata, flags) };
+            self.context.log_private_data(self, "SetPrivateData", unsafe { refguid.as_ref() }, Some(sizeofdata), &result);
+            result
+        }
+
+        #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
+        fn GetPrivateData(&self, refguid: *const GUID, pdata: *mut c_void, psizeofdata: *mut u32) -> Result<()> {
+            let result = unsafe { self.target.GetPrivateData(refguid, pdata, psizeofdata) };
+            self.context
+                .log_private_data(self, "GetPrivateData", unsafe { refguid.as_ref() }, unsafe { psizeofdata.as_ref() }.copied(), &result);
+            result
+        }
+
+        #[cfg_attr(feature = "tracing-instrument", tracing::instrument(err, ret, level = "trace"))]
+        fn FreePrivateData(&self, refguid: *const GUID) -> Result<()> {
+            let result = unsafe { self.target.FreePrivateData(refguid) };
+            self.context.log_private_data(self, "FreePrivateData", unsafe { refguid.as_ref() }, None, &result);
+            result
+        }
+    };
+}
+
+use super::{config::*, present, query::*};
+#[cfg(feature = "tracing-instrument")]
+use crate::trace::TracedObject;
+use crate::{try_out_param, with_required_out};
 
+mod bound_textures;
+mod call_stats;
+mod create_flags;
+mod cursor_scale;
+mod default_pool_tracker;
 mod device_context;
+mod display_modes;
+mod emulated_query;
+mod forced_render_states;
+mod frame_pacer;
+mod hresult;
 mod idirect3d9;
 mod idirect3d9ex;
 mod idirect3dcubetexture9;
@@ -86,8 +110,28 @@ mod idirect3dvertexdeclaration9;
 mod idirect3dvertexshader9;
 mod idirect3dvolume9;
 mod idirect3dvolumetexture9;
+mod lock_tracker;
+#[cfg(test)]
+pub(crate) mod mock;
+mod scene_hooks;
+mod scratch_buffers;
+mod shader_constant_shadow;
+mod state_preserver;
+mod stretch_scratch;
+mod surface_cache;
+mod watermark;
 
+pub use bound_textures::*;
+pub use call_stats::*;
+pub use create_flags::*;
+pub use cursor_scale::*;
+pub use default_pool_tracker::*;
 pub use device_context::*;
+pub use display_modes::*;
+pub use emulated_query::*;
+pub use forced_render_states::*;
+pub use frame_pacer::*;
+pub use hresult::*;
 pub use idirect3d9::*;
 pub use idirect3d9ex::*;
 pub use idirect3dcubetexture9::*;
@@ -106,3 +150,11 @@ pub use idirect3dvertexdeclaration9::*;
 pub use idirect3dvertexshader9::*;
 pub use idirect3dvolume9::*;
 pub use idirect3dvolumetexture9::*;
+pub use lock_tracker::*;
+pub use scene_hooks::*;
+pub use scratch_buffers::*;
+pub use shader_constant_shadow::*;
+pub use state_preserver::*;
+pub use stretch_scratch::*;
+pub use surface_cache::*;
+pub use watermark::*;
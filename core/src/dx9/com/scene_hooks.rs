@@ -0,0 +1,79 @@
+//! Hooks that inject additional rendering at `BeginScene` and `EndScene`.
+//!
+//! This is an extension point for rendering features such as overlays or post-processing,
+//! which lets other crates draw into the application's scenes without modifying the proxies.
+//! Hooks are registered process-wide with [`register_scene_hook`] and called for every device.
+//!
+//! Hooks run on the render thread, i.e. the thread that calls `BeginScene` or `EndScene`, while
+//! the application is rendering. They should return quickly, as they delay every frame.
+
+use super::*;
+use std::{
+    panic::{AssertUnwindSafe, catch_unwind},
+    sync::{Arc, Mutex},
+};
+use windows::Win32::Graphics::Direct3D9::*;
+
+/// Hook called at `BeginScene` and `EndScene` of every device, to issue additional draw calls.
+///
+/// The device passed to the methods is the device proxy, so calls made through it behave as if made
+/// by the application (e.g. forced states apply). The device state is captured with
+/// [`DX9StatePreserver`] before each call and restored afterwards, so hooks can change any state.
+/// Hooks must not call `BeginScene`, `EndScene`, `Present` or `Reset` on the device.
+///
+/// Panics are caught and logged, and do not affect the frame. Both methods do nothing by default.
+pub trait DX9SceneHook: Send + Sync {
+    /// Called after `BeginScene` succeeded, at the start of a scene.
+    fn on_begin_scene(&self, _device: &IDirect3DDevice9) {}
+
+    /// Called before `EndScene`, at the end of a scene.
+    fn on_end_scene(&self, _device: &IDirect3DDevice9) {}
+}
+
+/// Scene hooks registered with [`register_scene_hook`].
+static SCENE_HOOKS: Mutex<Vec<Arc<dyn DX9SceneHook>>> = Mutex::new(Vec::new());
+
+/// Registers a hook to be called at `BeginScene` and `EndScene` of every device.
+///
+/// Hooks are called in registration order, and can be registered at any time, including from another thread.
+pub fn register_scene_hook(hook: Arc<dyn DX9SceneHook>) {
+    SCENE_HOOKS.lock().unwrap().push(hook);
+}
+
+/// Deregisters a hook registered with [`register_scene_hook`]. Hooks that are not registered are ignored.
+pub fn unregister_scene_hook(hook: &Arc<dyn DX9SceneHook>) {
+    SCENE_HOOKS.lock().unwrap().retain(|registered| !Arc::ptr_eq(registered, hook));
+}
+
+/// Calls `f` for each registered scene hook with the state of `target` preserved, catching panics.
+///
+/// Does nothing, and does not capture the state, if no hooks are registered.
+///
+/// # Arguments
+/// * `target` - The target device, whose state is preserved.
+/// * `get_proxy` - Returns the device proxy passed to the hooks.
+/// * `f` - Calls the hook method, e.g. [`DX9SceneHook::on_begin_scene`].
+pub(super) fn call_scene_hooks(target: &IDirect3DDevice9, get_proxy: impl FnOnce() -> IDirect3DDevice9, f: impl Fn(&dyn DX9SceneHook, &IDirect3DDevice9)) {
+    // Clone the hooks so that the lock is not held while they run, as they may (de)register hooks
+    let hooks = SCENE_HOOKS.lock().unwrap().clone();
+    if hooks.is_empty() {
+        return;
+    }
+
+    let _state_preserver = match DX9StatePreserver::capture(target) {
+        Ok(state_preserver) => state_preserver,
+        Err(_err) => {
+            #[cfg(feature = "tracing")]
+            tracing::warn!("Failed to capture device state, skipping scene hooks: {_err}");
+            return;
+        }
+    };
+
+    let proxy = get_proxy();
+    for hook in hooks {
+        if catch_unwind(AssertUnwindSafe(|| f(hook.as_ref(), &proxy))).is_err() {
+            #[cfg(feature = "tracing")]
+            tracing::error!("Scene hook panicked");
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! Scratch buffers for rewriting user-pointer draws into buffer draws.
+//!
+//! `DrawPrimitiveUP` and `DrawIndexedPrimitiveUP` are slow on some drivers.
+//! This module uploads the user data into dynamic buffers that are reused across draws,
+//! and issues regular `DrawPrimitive` / `DrawIndexedPrimitive` calls instead.
+//!
+//! See [`DX9ProxyConfig::optimize_up_draws`].
+
+use super::*;
+use std::{
+    ffi::c_void,
+    ptr::{copy_nonoverlapping, null_mut},
+};
+use windows::{Win32::Graphics::Direct3D9::*, core::*};
+
+/// Minimum size of the scratch buffers in bytes, to avoid reallocating for small draws.
+const MIN_SCRATCH_BUFFER_SIZE: u32 = 64 * 1024;
+
+/// Dynamic vertex and index buffers reused for rewritten user-pointer draws.
+///
+/// The buffers are created on the target device in `D3DPOOL_DEFAULT` and grow as needed.
+/// They must be released with [`release`](Self::release) before the device is reset.
+#[derive(Debug, Default)]
+pub struct DX9ScratchBuffers {
+    vertex_buffer: Option<(IDirect3DVertexBuffer9, u32)>,
+    index_buffer: Option<(IDirect3DIndexBuffer9, u32, D3DFORMAT)>,
+    #[cfg(feature = "tracing")]
+    fallback_logged: bool,
+}
+
+impl DX9ScratchBuffers {
+    /// Releases the scratch buffers, which is required before resetting the device.
+    pub fn release(&mut self) {
+        self.vertex_buffer = None;
+        self.index_buffer = None;
+    }
+
+    /// Logs that a draw could not be rewritten and is forwarded as is.
+    ///
+    /// Only the first failure is logged as a warning, as failures usually repeat on every draw.
+    pub fn log_fallback(&mut self, _draw_call: &str, _err: &Error) {
+        #[cfg(feature = "tracing")]
+        if std::mem::replace(&mut self.fallback_logged, true) {
+            tracing::trace!("Failed to rewrite {_draw_call} into a buffer draw, falling back: {_err}");
+        } else {
+            tracing::warn!("Failed to rewrite {_draw_call} into a buffer draw, falling back: {_err}. Further failures are logged at trace level");
+        }
+    }
+
+    /// Performs `DrawPrimitiveUP` on `device` using the scratch vertex buffer.
+    ///
+    /// Like `DrawPrimitiveUP`, stream 0 is set to null afterwards.
+    ///
+    /// # Safety
+    /// `pvertexstreamzerodata` must point to the vertex data required by the draw.
+    pub unsafe fn draw_primitive_up(
+        &mut self,
+        device: &IDirect3DDevice9,
+        primitivetype: D3DPRIMITIVETYPE,
+        primitivecount: u32,
+        pvertexstreamzerodata: *const c_void,
+        vertexstreamzerostride: u32,
+    ) -> Result<()> {
+        let vertex_count = vertex_count(primitivetype, primitivecount).ok_or(D3DERR_INVALIDCALL)?;
+        let vertex_size = vertex_count.checked_mul(vertexstreamzerostride).ok_or(D3DERR_INVALIDCALL)?;
+        let vertex_buffer = unsafe { self.upload_vertices(device, pvertexstreamzerodata, vertex_size) }?;
+
+        unsafe {
+            device.SetStreamSource(0, &vertex_buffer, 0, vertexstreamzerostride)?;
+            let result = device.DrawPrimitive(primitivetype, 0, primitivecount);
+            device.SetStreamSource(0, None, 0, 0)?;
+            result
+        }
+    }
+
+    /// Performs `DrawIndexedPrimitiveUP` on `device` using the scratch vertex and index buffers.
+    ///
+    /// Like `DrawIndexedPrimitiveUP`, stream 0 and the indices are set to null afterwards.
+    ///
+    /// # Safety
+    /// `pindexdata` and `pvertexstreamzerodata` must point to the index and vertex data required by the draw.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn draw_indexed_primitive_up(
+        &mut self,
+        device: &IDirect3DDevice9,
+        primitivetype: D3DPRIMITIVETYPE,
+        minvertexindex: u32,
+        numvertices: u32,
+        primitivecount: u32,
+        pindexdata: *const c_void,
+        indexdataformat: D3DFORMAT,
+        pvertexstreamzerodata: *const c_void,
+        vertexstreamzerostride: u32,
+    ) -> Result<()> {
+        let index_count = vertex_count(primitivetype, primitivecount).ok_or(D3DERR_INVALIDCALL)?;
+        let index_size = match indexdataformat {
+            D3DFMT_INDEX16 => 2,
+            D3DFMT_INDEX32 => 4,
+            _ => return Err(D3DERR_INVALIDCALL.into()),
+        };
+
+        // Indices are relative to the start of the vertex data, so upload all vertices up to the last one used
+        let vertex_size = minvertexindex
+            .checked_add(numvertices)
+            .and_then(|vertex_count| vertex_count.checked_mul(vertexstreamzerostride))
+            .ok_or(D3DERR_INVALIDCALL)?;
+        let index_size = index_count.checked_mul(index_size).ok_or(D3DERR_INVALIDCALL)?;
+        let vertex_buffer = unsafe { self.upload_vertices(device, pvertexstreamzerodata, vertex_size) }?;
+        let index_buffer = unsafe { self.upload_indices(device, pindexdata, index_size, indexdataformat) }?;
+
+        unsafe {
+            device.SetStreamSource(0, &vertex_buffer, 0, vertexstreamzerostride)?;
+            device.SetIndices(&index_buffer)?;
+            let result = device.DrawIndexedPrimitive(primitivetype, 0, minvertexindex, numvertices, 0, primitivecount);
+            device.SetStreamSource(0, None, 0, 0)?;
+            device.SetIndices(None)?;
+            result
+        }
+    }
+
+    /// Copies `size` bytes of vertex data into the scratch vertex buffer, growing it if needed.
+    unsafe fn upload_vertices(&mut self, device: &IDirect3DDevice9, data: *const c_void, size: u32) -> Result<IDirect3DVertexBuffer9> {
+        if !matches!(&self.vertex_buffer, Some((_, capacity)) if *capacity >= size) {
+            self.vertex_buffer = None;
+
+            let capacity = scratch_buffer_capacity(size);
+            let vertex_buffer = try_out_param(|out| unsafe { device.CreateVertexBuffer(capacity, (D3DUSAGE_DYNAMIC | D3DUSAGE_WRITEONLY) as u32, 0, D3DPOOL_DEFAULT, out, null_mut()) })?;
+
+            #[cfg(feature = "tracing")]
+            tracing::debug!("Created scratch vertex buffer of {capacity} bytes");
+
+            self.vertex_buffer = Some((vertex_buffer, capacity));
+        }
+
+        let (vertex_buffer, _) = self.vertex_buffer.as_ref().unwrap();
+        unsafe {
+            let mut locked = null_mut();
+            vertex_buffer.Lock(0, size, &mut locked, D3DLOCK_DISCARD as u32)?;
+            copy_nonoverlapping(data as *const u8, locked as *mut u8, size as usize);
+            vertex_buffer.Unlock()?;
+        }
+        Ok(vertex_buffer.clone())
+    }
+
+    /// Copies `size` bytes of index data into the scratch index buffer, recreating it if needed.
+    unsafe fn upload_indices(&mut self, device: &IDirect3DDevice9, data: *const c_void, size: u32, format: D3DFORMAT) -> Result<IDirect3DIndexBuffer9> {
+        if !matches!(&self.index_buffer, Some((_, capacity, buffer_format)) if *capacity >= size && *buffer_format == format) {
+            self.index_buffer = None;
+
+            let capacity = scratch_buffer_capacity(size);
+            let index_buffer = try_out_param(|out| unsafe { device.CreateIndexBuffer(capacity, (D3DUSAGE_DYNAMIC | D3DUSAGE_WRITEONLY) as u32, format, D3DPOOL_DEFAULT, out, null_mut()) })?;
+
+            #[cfg(feature = "tracing")]
+            tracing::debug!("Created scratch index buffer of {capacity} bytes with format {format:?}");
+
+            self.index_buffer = Some((index_buffer, capacity, format));
+        }
+
+        let (index_buffer, _, _) = self.index_buffer.as_ref().unwrap();
+        unsafe {
+            let mut locked = null_mut();
+            index_buffer.Lock(0, size, &mut locked, D3DLOCK_DISCARD as u32)?;
+            copy_nonoverlapping(data as *const u8, locked as *mut u8, size as usize);
+            index_buffer.Unlock()?;
+        }
+        Ok(index_buffer.clone())
+    }
+}
+
+/// Returns the number of vertices (or indices) consumed by a draw of `primitivecount` primitives.
+///
+/// Returns `None` for unknown primitive types and counts that overflow.
+fn vertex_count(primitivetype: D3DPRIMITIVETYPE, primitivecount: u32) -> Option<u32> {
+    match primitivetype {
+        D3DPT_POINTLIST => Some(primitivecount),
+        D3DPT_LINELIST => primitivecount.checked_mul(2),
+        D3DPT_LINESTRIP => primitivecount.checked_add(1),
+        D3DPT_TRIANGLELIST => primitivecount.checked_mul(3),
+        D3DPT_TRIANGLESTRIP | D3DPT_TRIANGLEFAN => primitivecount.checked_add(2),
+        _ => None,
+    }
+}
+
+/// Returns the capacity to allocate for a scratch buffer that must hold `size` bytes.
+fn scratch_buffer_capacity(size: u32) -> u32 {
+    let size = size.max(MIN_SCRATCH_BUFFER_SIZE);
+    size.checked_next_power_of_two().unwrap_or(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vertex_count_per_primitive_type() {
+        assert_eq!(vertex_count(D3DPT_POINTLIST, 5), Some(5));
+        assert_eq!(vertex_count(D3DPT_LINELIST, 5), Some(10));
+        assert_eq!(vertex_count(D3DPT_LINESTRIP, 5), Some(6));
+        assert_eq!(vertex_count(D3DPT_TRIANGLELIST, 5), Some(15));
+        assert_eq!(vertex_count(D3DPT_TRIANGLESTRIP, 5), Some(7));
+        assert_eq!(vertex_count(D3DPT_TRIANGLEFAN, 5), Some(7));
+        assert_eq!(vertex_count(D3DPRIMITIVETYPE(0), 5), None);
+    }
+
+    #[test]
+    fn vertex_count_overflow() {
+        assert_eq!(vertex_count(D3DPT_TRIANGLELIST, u32::MAX / 2), None);
+        assert_eq!(vertex_count(D3DPT_LINESTRIP, u32::MAX), None);
+        assert_eq!(vertex_count(D3DPT_POINTLIST, u32::MAX), Some(u32::MAX));
+    }
+
+    #[test]
+    fn scratch_buffer_capacity_rounding() {
+        assert_eq!(scratch_buffer_capacity(1), MIN_SCRATCH_BUFFER_SIZE);
+        assert_eq!(scratch_buffer_capacity(MIN_SCRATCH_BUFFER_SIZE + 1), MIN_SCRATCH_BUFFER_SIZE * 2);
+        assert_eq!(scratch_buffer_capacity(u32::MAX), u32::MAX);
+    }
+}
@@ -0,0 +1,141 @@
+//! Shadow copy of shader float constants for per-draw capture.
+//!
+//! When [`DX9ProxyConfig::capture_shader_constants`] is enabled, the float constants set through
+//! `SetVertexShaderConstantF` / `SetPixelShaderConstantF` are mirrored here, and each draw call
+//! logs only the registers that changed since the previous draw.
+
+use std::{fmt, slice::from_raw_parts};
+
+/// Number of float constant registers tracked per shader stage.
+///
+/// This covers the 256 vertex shader and 224 pixel shader registers of shader model 3.0.
+pub const SHADER_CONSTANT_REGISTER_COUNT: usize = 256;
+
+/// Float constant registers of one shader stage, with a record of the registers changed since the last draw.
+#[derive(Debug, Clone)]
+struct DX9ShaderConstantBank {
+    registers: [[f32; 4]; SHADER_CONSTANT_REGISTER_COUNT],
+    changed: [bool; SHADER_CONSTANT_REGISTER_COUNT],
+}
+
+impl DX9ShaderConstantBank {
+    fn new() -> Self {
+        Self {
+            registers: [[0.0; 4]; SHADER_CONSTANT_REGISTER_COUNT],
+            changed: [false; SHADER_CONSTANT_REGISTER_COUNT],
+        }
+    }
+
+    fn set(&mut self, start_register: u32, data: &[[f32; 4]]) {
+        let start = start_register as usize;
+        let end = (start + data.len()).min(SHADER_CONSTANT_REGISTER_COUNT);
+        if start >= end {
+            return;
+        }
+
+        for (index, value) in (start..end).zip(data) {
+            if self.registers[index] != *value {
+                self.changed[index] = true;
+            }
+        }
+        self.registers[start..end].copy_from_slice(&data[..end - start]);
+    }
+
+    fn take_changes(&mut self) -> Vec<(u32, [f32; 4])> {
+        let changes = (0..SHADER_CONSTANT_REGISTER_COUNT)
+            .filter(|&index| self.changed[index])
+            .map(|index| (index as u32, self.registers[index]))
+            .collect();
+        self.changed = [false; SHADER_CONSTANT_REGISTER_COUNT];
+        changes
+    }
+}
+
+/// Shadow copy of the vertex and pixel shader float constants of a device.
+#[derive(Debug, Clone)]
+pub struct DX9ShaderConstantShadow {
+    vertex: DX9ShaderConstantBank,
+    pixel: DX9ShaderConstantBank,
+}
+
+impl Default for DX9ShaderConstantShadow {
+    fn default() -> Self {
+        Self {
+            vertex: DX9ShaderConstantBank::new(),
+            pixel: DX9ShaderConstantBank::new(),
+        }
+    }
+}
+
+impl DX9ShaderConstantShadow {
+    /// Records a successful `SetVertexShaderConstantF` call.
+    ///
+    /// # Safety
+    /// `pconstantdata` must point to `vector4fcount * 4` floats.
+    pub unsafe fn set_vertex_constants(&mut self, startregister: u32, pconstantdata: *const f32, vector4fcount: u32) {
+        if let Some(data) = unsafe { constant_slice(pconstantdata, vector4fcount) } {
+            self.vertex.set(startregister, data);
+        }
+    }
+
+    /// Records a successful `SetPixelShaderConstantF` call.
+    ///
+    /// # Safety
+    /// `pconstantdata` must point to `vector4fcount * 4` floats.
+    pub unsafe fn set_pixel_constants(&mut self, startregister: u32, pconstantdata: *const f32, vector4fcount: u32) {
+        if let Some(data) = unsafe { constant_slice(pconstantdata, vector4fcount) } {
+            self.pixel.set(startregister, data);
+        }
+    }
+
+    /// Returns the registers changed since the previous call, and resets the change record.
+    pub fn take_changes(&mut self) -> DX9ShaderConstantChanges {
+        DX9ShaderConstantChanges {
+            vertex: self.vertex.take_changes(),
+            pixel: self.pixel.take_changes(),
+        }
+    }
+}
+
+/// Float constant registers changed between two draws.
+///
+/// Formats as `vs c4=[..] c5=[..] ps c0=[..]`, omitting stages without changes.
+#[derive(Debug, Clone, Default)]
+pub struct DX9ShaderConstantChanges {
+    /// Changed vertex shader registers and their new values.
+    pub vertex: Vec<(u32, [f32; 4])>,
+    /// Changed pixel shader registers and their new values.
+    pub pixel: Vec<(u32, [f32; 4])>,
+}
+
+impl DX9ShaderConstantChanges {
+    /// Returns `true` if no register changed.
+    pub fn is_empty(&self) -> bool {
+        self.vertex.is_empty() && self.pixel.is_empty()
+    }
+}
+
+impl fmt::Display for DX9ShaderConstantChanges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut separator = "";
+        for (stage, changes) in [("vs", &self.vertex), ("ps", &self.pixel)] {
+            if changes.is_empty() {
+                continue;
+            }
+            write!(f, "{separator}{stage}")?;
+            for (register, value) in changes {
+                write!(f, " c{register}={value:?}")?;
+            }
+            separator = " ";
+        }
+        Ok(())
+    }
+}
+
+/// Reinterprets the constant data of a `Set*ShaderConstantF` call as 4-component registers.
+unsafe fn constant_slice<'a>(pconstantdata: *const f32, vector4fcount: u32) -> Option<&'a [[f32; 4]]> {
+    if pconstantdata.is_null() {
+        return None;
+    }
+    Some(unsafe { from_raw_parts(pconstantdata as *const [f32; 4], vector4fcount as usize) })
+}
@@ -0,0 +1,230 @@
+//! Snapshot and restoration of device state around injected rendering.
+//!
+//! Features that draw on their own (e.g. into the back buffer before `Present`) must not
+//! leave any trace in the application's render state. [`DX9StatePreserver`] captures the
+//! state of a target device and restores it when dropped.
+
+use windows::{Win32::Graphics::Direct3D9::*, core::*};
+
+/// Render states captured when a state block is not available.
+const FALLBACK_RENDER_STATES: [D3DRENDERSTATETYPE; 10] = [
+    D3DRS_ZENABLE,
+    D3DRS_ZWRITEENABLE,
+    D3DRS_ALPHABLENDENABLE,
+    D3DRS_ALPHATESTENABLE,
+    D3DRS_SRCBLEND,
+    D3DRS_DESTBLEND,
+    D3DRS_CULLMODE,
+    D3DRS_FILLMODE,
+    D3DRS_LIGHTING,
+    D3DRS_SRGBWRITEENABLE,
+];
+
+/// State captured without a state block.
+#[derive(Debug)]
+struct DX9FallbackState {
+    render_states: Vec<(D3DRENDERSTATETYPE, u32)>,
+    texture: Option<IDirect3DBaseTexture9>,
+    vertex_shader: Option<IDirect3DVertexShader9>,
+    pixel_shader: Option<IDirect3DPixelShader9>,
+    vertex_declaration: Option<IDirect3DVertexDeclaration9>,
+    fvf: u32,
+}
+
+/// Captures the state of a target device and restores it when dropped.
+///
+/// The complete device state is captured with an [`IDirect3DStateBlock9`] of type `D3DSBT_ALL`
+/// created on the target device. If the state block cannot be created, a few key render states,
+/// the texture of stage 0 and the shaders are captured instead. The render target 0, the depth
+/// stencil surface and the viewport are always captured, as state blocks do not include render targets.
+///
+/// Must be used with target devices, not proxies.
+///
+/// # Example
+/// ```ignore
+/// let _state_preserver = DX9StatePreserver::capture(&self.target)?;
+/// // Draw the overlay...
+/// // The state is restored when `_state_preserver` goes out of scope
+/// ```
+#[derive(Debug)]
+pub struct DX9StatePreserver {
+    device: IDirect3DDevice9,
+    render_target: Option<IDirect3DSurface9>,
+    depth_stencil: Option<IDirect3DSurface9>,
+    viewport: D3DVIEWPORT9,
+    state_block: Option<IDirect3DStateBlock9>,
+    fallback_state: Option<DX9FallbackState>,
+}
+
+impl DX9StatePreserver {
+    /// Captures the current state of `device`.
+    ///
+    /// # Arguments
+    /// * `device` - The target device whose state should be preserved
+    pub fn capture(device: &IDirect3DDevice9) -> Result<Self> {
+        let render_target = unsafe { device.GetRenderTarget(0) }.ok();
+        // Fails with D3DERR_NOTFOUND if there is no depth stencil surface
+        let depth_stencil = unsafe { device.GetDepthStencilSurface() }.ok();
+        let mut viewport = D3DVIEWPORT9::default();
+        unsafe { device.GetViewport(&mut viewport) }?;
+
+        let state_block = unsafe { device.CreateStateBlock(D3DSBT_ALL) }
+            .inspect_err(|_err| {
+                #[cfg(feature = "tracing")]
+                tracing::warn!("Failed to create state block, capturing only key states: {_err}");
+            })
+            .ok();
+
+        let fallback_state = match state_block {
+            Some(_) => None,
+            None => Some(Self::capture_fallback_state(device)?),
+        };
+
+        Ok(Self {
+            device: device.clone(),
+            render_target,
+            depth_stencil,
+            viewport,
+            state_block,
+            fallback_state,
+        })
+    }
+
+    fn capture_fallback_state(device: &IDirect3DDevice9) -> Result<DX9FallbackState> {
+        let render_states = FALLBACK_RENDER_STATES
+            .iter()
+            .map(|&state| {
+                let mut value = 0;
+                unsafe { device.GetRenderState(state, &mut value) }.map(|_| (state, value))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut fvf = 0;
+        unsafe { device.GetFVF(&mut fvf) }?;
+
+        Ok(DX9FallbackState {
+            render_states,
+            texture: unsafe { device.GetTexture(0) }.ok(),
+            vertex_shader: unsafe { device.GetVertexShader() }.ok(),
+            pixel_shader: unsafe { device.GetPixelShader() }.ok(),
+            vertex_declaration: unsafe { device.GetVertexDeclaration() }.ok(),
+            fvf,
+        })
+    }
+
+    fn restore(&self) -> Result<()> {
+        unsafe {
+            // Setting a render target resets the viewport, so restore the viewport afterwards
+            if let Some(render_target) = &self.render_target {
+                self.device.SetRenderTarget(0, render_target)?;
+            }
+            self.device.SetDepthStencilSurface(self.depth_stencil.as_ref())?;
+
+            if let Some(state_block) = &self.state_block {
+                state_block.Apply()?;
+            }
+
+            if let Some(state) = &self.fallback_state {
+                for &(render_state, value) in &state.render_states {
+                    self.device.SetRenderState(render_state, value)?;
+                }
+                self.device.SetTexture(0, state.texture.as_ref())?;
+                self.device.SetVertexShader(state.vertex_shader.as_ref())?;
+                self.device.SetPixelShader(state.pixel_shader.as_ref())?;
+                if let Some(vertex_declaration) = &state.vertex_declaration {
+                    self.device.SetVertexDeclaration(vertex_declaration)?;
+                } else {
+                    self.device.SetFVF(state.fvf)?;
+                }
+            }
+
+            self.device.SetViewport(&self.viewport)
+        }
+    }
+}
+
+impl Drop for DX9StatePreserver {
+    fn drop(&mut self) {
+        let _ = self.restore().inspect_err(|_err| {
+            #[cfg(feature = "tracing")]
+            tracing::error!("Failed to restore device state: {_err}");
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dx9::com::mock::*;
+
+    fn viewport(width: u32, height: u32) -> D3DVIEWPORT9 {
+        D3DVIEWPORT9 {
+            Width: width,
+            Height: height,
+            MaxZ: 1.0,
+            ..Default::default()
+        }
+    }
+
+    fn mock_device(state_blocks_supported: bool) -> (ComObject<MockDevice>, IDirect3DDevice9) {
+        let mock = ComObject::new(MockDevice::default());
+        {
+            let mut state = mock.state.lock().unwrap();
+            state.state_blocks_supported = state_blocks_supported;
+            state.render_target = Some(MockSurface::default().into());
+            state.viewport = viewport(640, 480);
+        }
+        let device = mock.to_interface::<IDirect3DDevice9Ex>().into();
+        (mock, device)
+    }
+
+    #[test]
+    fn restores_render_target_and_viewport_with_state_block() {
+        let (mock, device) = mock_device(true);
+        let render_target = mock.state.lock().unwrap().render_target.clone();
+
+        let state_preserver = DX9StatePreserver::capture(&device).unwrap();
+        let other_target: IDirect3DSurface9 = MockSurface::default().into();
+        unsafe {
+            device.SetRenderTarget(0, &other_target).unwrap();
+            device.SetViewport(&viewport(16, 16)).unwrap();
+        }
+        mock.log.clear();
+        drop(state_preserver);
+
+        assert_eq!(mock.log.calls(), ["SetRenderTarget", "SetDepthStencilSurface", "Apply", "SetViewport"]);
+        let state = mock.state.lock().unwrap();
+        assert_eq!(state.render_target, render_target);
+        assert_eq!(state.depth_stencil, None);
+        assert_eq!(state.viewport, viewport(640, 480));
+    }
+
+    #[test]
+    fn restores_key_states_without_state_block() {
+        let (mock, device) = mock_device(false);
+        unsafe {
+            device.SetRenderState(D3DRS_ZENABLE, 1).unwrap();
+            device.SetRenderState(D3DRS_CULLMODE, D3DCULL_CCW.0 as u32).unwrap();
+            device.SetFVF(D3DFVF_XYZRHW | D3DFVF_DIFFUSE).unwrap();
+        }
+
+        let state_preserver = DX9StatePreserver::capture(&device).unwrap();
+        unsafe {
+            device.SetRenderState(D3DRS_ZENABLE, 0).unwrap();
+            device.SetRenderState(D3DRS_CULLMODE, D3DCULL_NONE.0 as u32).unwrap();
+            device.SetRenderState(D3DRS_LIGHTING, 1).unwrap();
+            device.SetFVF(D3DFVF_XYZ).unwrap();
+            device.SetViewport(&viewport(16, 16)).unwrap();
+        }
+        mock.log.clear();
+        drop(state_preserver);
+
+        assert_eq!(mock.log.count("Apply"), 0);
+        assert_eq!(mock.log.count("SetRenderState"), FALLBACK_RENDER_STATES.len());
+        let state = mock.state.lock().unwrap();
+        assert_eq!(state.render_states[&D3DRS_ZENABLE.0], 1);
+        assert_eq!(state.render_states[&D3DRS_CULLMODE.0], D3DCULL_CCW.0 as u32);
+        assert_eq!(state.render_states[&D3DRS_LIGHTING.0], 0);
+        assert_eq!(state.fvf, D3DFVF_XYZRHW | D3DFVF_DIFFUSE);
+        assert_eq!(state.viewport, viewport(640, 480));
+    }
+}
@@ -0,0 +1,73 @@
+//! Scratch surface for `StretchRect` calls whose source and destination are the same surface.
+//!
+//! The runtime rejects such calls, but some games make them anyway and then render incorrectly.
+//! This module performs them in two steps through an intermediate render target, which is
+//! reused across calls.
+//!
+//! See [`DX9ProxyConfig::allow_self_stretchrect`].
+
+use super::*;
+use std::ptr::null_mut;
+use windows::{
+    Win32::{Foundation::RECT, Graphics::Direct3D9::*},
+    core::*,
+};
+
+/// Render target used as the intermediate of `StretchRect` calls from a surface onto itself.
+///
+/// The surface is created on the target device in `D3DPOOL_DEFAULT`, with the size and format of
+/// the last surface it was used for. It must be released with [`release`](Self::release) before
+/// the device is reset.
+#[derive(Debug, Default)]
+pub struct DX9StretchScratch {
+    surface: Option<(IDirect3DSurface9, u32, u32, D3DFORMAT)>,
+}
+
+impl DX9StretchScratch {
+    /// Releases the scratch surface, which is required before resetting the device.
+    pub fn release(&mut self) {
+        self.surface = None;
+    }
+
+    /// Performs `StretchRect` from `surface` onto itself on `device`.
+    ///
+    /// The source rectangle is first copied into the scratch surface, and then stretched from
+    /// there into the destination rectangle with `filter`.
+    ///
+    /// # Safety
+    /// `psourcerect` and `pdestrect` must be null or point to valid rectangles.
+    pub unsafe fn stretch_rect_to_self(
+        &mut self,
+        device: &IDirect3DDevice9,
+        surface: &IDirect3DSurface9,
+        psourcerect: *const RECT,
+        pdestrect: *const RECT,
+        filter: D3DTEXTUREFILTERTYPE,
+    ) -> Result<()> {
+        let scratch = self.get_surface(device, surface)?;
+        unsafe {
+            device.StretchRect(surface, psourcerect, &scratch, psourcerect, D3DTEXF_NONE)?;
+            device.StretchRect(&scratch, psourcerect, surface, pdestrect, filter)
+        }
+    }
+
+    /// Returns a scratch surface with the size and format of `surface`, recreating it if needed.
+    fn get_surface(&mut self, device: &IDirect3DDevice9, surface: &IDirect3DSurface9) -> Result<IDirect3DSurface9> {
+        let mut desc = D3DSURFACE_DESC::default();
+        unsafe { surface.GetDesc(&mut desc) }?;
+
+        if !matches!(&self.surface, Some((_, width, height, format)) if *width == desc.Width && *height == desc.Height && *format == desc.Format) {
+            self.surface = None;
+
+            let scratch = try_out_param(|out| unsafe { device.CreateRenderTarget(desc.Width, desc.Height, desc.Format, D3DMULTISAMPLE_NONE, 0, false, out, null_mut()) })?;
+
+            #[cfg(feature = "tracing")]
+            tracing::debug!("Created {}x{} {:?} scratch surface for StretchRect", desc.Width, desc.Height, desc.Format);
+
+            self.surface = Some((scratch, desc.Width, desc.Height, desc.Format));
+        }
+
+        let (scratch, _, _, _) = self.surface.as_ref().unwrap();
+        Ok(scratch.clone())
+    }
+}
@@ -0,0 +1,166 @@
+//! Cache of the proxies of the current render targets and depth stencil surface.
+//!
+//! Games often poll `GetRenderTarget` and `GetDepthStencilSurface` several times per frame. Looking
+//! up the proxy of the returned surface takes the lock of the mapping tracker, which is shared by
+//! all proxies of the device, so the proxies of the current surfaces are cached per device instead.
+
+use std::ffi::c_void;
+use windows::{Win32::Graphics::Direct3D9::*, core::*};
+
+/// Number of simultaneous render targets supported by Direct3D 9.
+const MAX_RENDER_TARGETS: usize = 4;
+
+/// Surface bound to the device whose proxy can be cached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DX9SurfaceSlot {
+    /// The render target of the given index.
+    RenderTarget(u32),
+    /// The depth stencil surface.
+    DepthStencil,
+}
+
+impl DX9SurfaceSlot {
+    /// Returns the index of this slot in the cache, or `None` for render target indices out of range.
+    fn index(self) -> Option<usize> {
+        match self {
+            Self::RenderTarget(index) => ((index as usize) < MAX_RENDER_TARGETS).then_some(index as usize),
+            Self::DepthStencil => Some(MAX_RENDER_TARGETS),
+        }
+    }
+}
+
+/// A cached surface, identified by the addresses of its target and proxy.
+#[derive(Debug, Clone, Copy)]
+struct DX9CachedSurface {
+    target: usize,
+    proxy: usize,
+}
+
+/// Proxies of the surfaces currently bound to the device, per [`DX9SurfaceSlot`].
+///
+/// Only the addresses are recorded, without holding references, as holding the proxies would keep
+/// them, and through them the device, alive. An entry must therefore be removed whenever its
+/// surface may be unbound (on `SetRenderTarget`, `SetDepthStencilSurface` and `Reset`) and when
+/// its proxy is destroyed.
+#[derive(Debug, Default)]
+pub struct DX9SurfaceCache {
+    slots: [Option<DX9CachedSurface>; MAX_RENDER_TARGETS + 1],
+}
+
+impl DX9SurfaceCache {
+    /// Returns a new reference to the cached proxy of the surface in `slot`, if any.
+    pub fn get(&self, slot: DX9SurfaceSlot) -> Option<IDirect3DSurface9> {
+        let cached = self.slots[slot.index()?]?;
+        let proxy = cached.proxy as *mut c_void;
+        unsafe { IDirect3DSurface9::from_raw_borrowed(&proxy) }.cloned()
+    }
+
+    /// Caches the proxy of the surface in `slot`. Slots out of range are ignored.
+    pub fn insert(&mut self, slot: DX9SurfaceSlot, target_ptr: *mut c_void, proxy: &IDirect3DSurface9) {
+        if let Some(index) = slot.index() {
+            self.slots[index] = Some(DX9CachedSurface {
+                target: target_ptr as usize,
+                proxy: proxy.as_raw() as usize,
+            });
+        }
+    }
+
+    /// Removes the cached proxy of `slot`, whose surface may have changed.
+    pub fn invalidate(&mut self, slot: DX9SurfaceSlot) {
+        if let Some(index) = slot.index() {
+            self.slots[index] = None;
+        }
+    }
+
+    /// Removes all cached proxies, as the bound surfaces change when resetting the device.
+    pub fn clear(&mut self) {
+        self.slots = Default::default();
+    }
+
+    /// Removes the cached proxies of the target `target_ptr`, whose proxy is being destroyed.
+    pub fn on_proxy_destroy(&mut self, target_ptr: *mut c_void) {
+        for slot in &mut self.slots {
+            if slot.is_some_and(|cached| cached.target == target_ptr as usize) {
+                *slot = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dx9::com::mock::*;
+
+    fn surfaces() -> (IDirect3DSurface9, IDirect3DSurface9) {
+        (MockSurface::default().into(), MockSurface::default().into())
+    }
+
+    #[test]
+    fn slots_are_cached_separately() {
+        let mut cache = DX9SurfaceCache::default();
+        let (target, proxy) = surfaces();
+        let (depth_target, depth_proxy) = surfaces();
+        cache.insert(DX9SurfaceSlot::RenderTarget(1), target.as_raw(), &proxy);
+        cache.insert(DX9SurfaceSlot::DepthStencil, depth_target.as_raw(), &depth_proxy);
+
+        assert_eq!(cache.get(DX9SurfaceSlot::RenderTarget(1)), Some(proxy));
+        assert_eq!(cache.get(DX9SurfaceSlot::DepthStencil), Some(depth_proxy));
+        assert_eq!(cache.get(DX9SurfaceSlot::RenderTarget(0)), None);
+    }
+
+    #[test]
+    fn out_of_range_render_targets_are_ignored() {
+        let mut cache = DX9SurfaceCache::default();
+        let (target, proxy) = surfaces();
+        cache.insert(DX9SurfaceSlot::RenderTarget(MAX_RENDER_TARGETS as u32), target.as_raw(), &proxy);
+        assert_eq!(cache.get(DX9SurfaceSlot::RenderTarget(MAX_RENDER_TARGETS as u32)), None);
+        cache.invalidate(DX9SurfaceSlot::RenderTarget(u32::MAX));
+    }
+
+    #[test]
+    fn get_returns_new_reference() {
+        let mut cache = DX9SurfaceCache::default();
+        let (target, proxy) = surfaces();
+        cache.insert(DX9SurfaceSlot::RenderTarget(0), target.as_raw(), &proxy);
+
+        let cached = cache.get(DX9SurfaceSlot::RenderTarget(0)).unwrap();
+        // The cache holds no reference, so only `proxy`, `cached` and this AddRef hold references
+        let count = unsafe { (Interface::vtable(&proxy).base__.base__.AddRef)(proxy.as_raw()) };
+        unsafe { (Interface::vtable(&proxy).base__.base__.Release)(proxy.as_raw()) };
+        drop(cached);
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn invalidate_and_clear_remove_slots() {
+        let mut cache = DX9SurfaceCache::default();
+        let (target, proxy) = surfaces();
+        let (depth_target, depth_proxy) = surfaces();
+        cache.insert(DX9SurfaceSlot::RenderTarget(0), target.as_raw(), &proxy);
+        cache.insert(DX9SurfaceSlot::DepthStencil, depth_target.as_raw(), &depth_proxy);
+
+        cache.invalidate(DX9SurfaceSlot::RenderTarget(0));
+        assert_eq!(cache.get(DX9SurfaceSlot::RenderTarget(0)), None);
+        assert_eq!(cache.get(DX9SurfaceSlot::DepthStencil), Some(depth_proxy));
+
+        cache.clear();
+        assert_eq!(cache.get(DX9SurfaceSlot::DepthStencil), None);
+    }
+
+    #[test]
+    fn on_proxy_destroy_removes_every_slot_of_target() {
+        let mut cache = DX9SurfaceCache::default();
+        let (target, proxy) = surfaces();
+        let (other_target, other_proxy) = surfaces();
+        // The same surface may be bound to several slots
+        cache.insert(DX9SurfaceSlot::RenderTarget(0), target.as_raw(), &proxy);
+        cache.insert(DX9SurfaceSlot::RenderTarget(2), target.as_raw(), &proxy);
+        cache.insert(DX9SurfaceSlot::DepthStencil, other_target.as_raw(), &other_proxy);
+
+        cache.on_proxy_destroy(target.as_raw());
+        assert_eq!(cache.get(DX9SurfaceSlot::RenderTarget(0)), None);
+        assert_eq!(cache.get(DX9SurfaceSlot::RenderTarget(2)), None);
+        assert_eq!(cache.get(DX9SurfaceSlot::DepthStencil), Some(other_proxy));
+    }
+}
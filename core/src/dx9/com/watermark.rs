@@ -0,0 +1,271 @@
+//! Watermark text drawn into the back buffer at each `Present`.
+//!
+//! Useful to identify builds in captures and streams without an external overlay.
+//! The text is rasterized with a small bitmap font embedded in the DLL into a dynamic texture,
+//! which is drawn as a single quad into the bottom-right corner of the back buffer.
+//!
+//! See [`DX9ProxyConfig::watermark`].
+
+use super::*;
+use std::{ffi::c_void, ptr::null_mut};
+use windows::{Win32::Graphics::Direct3D9::*, core::*};
+
+/// Width of a glyph of [`FONT`] in pixels.
+const GLYPH_WIDTH: u32 = 5;
+
+/// Height of a glyph of [`FONT`] in pixels.
+const GLYPH_HEIGHT: u32 = 7;
+
+/// Padding around the text and between glyphs in texels.
+const PADDING: u32 = 1;
+
+/// Size of a texel of the watermark texture on screen, in pixels.
+const SCALE: u32 = 2;
+
+/// Distance between the watermark and the edges of the back buffer in pixels.
+const MARGIN: u32 = 8;
+
+/// Color of the text.
+const TEXT_COLOR: u32 = 0xffffffff;
+
+/// Color of the background box behind the text.
+const BACKGROUND_COLOR: u32 = 0x80000000;
+
+/// Bitmap font of 5x7 glyphs, one byte per row with the leftmost pixel in bit 4.
+///
+/// Lowercase letters are drawn as uppercase, and characters without a glyph are drawn as `?`.
+const FONT: [(char, [u8; GLYPH_HEIGHT as usize]); 48] = [
+    (' ', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('0', [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110]),
+    ('1', [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('2', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111]),
+    ('3', [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110]),
+    ('4', [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010]),
+    ('5', [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110]),
+    ('6', [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110]),
+    ('7', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000]),
+    ('8', [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110]),
+    ('9', [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100]),
+    ('A', [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('B', [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110]),
+    ('C', [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110]),
+    ('D', [0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110]),
+    ('E', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111]),
+    ('F', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('G', [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111]),
+    ('H', [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('I', [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('J', [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100]),
+    ('K', [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001]),
+    ('L', [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111]),
+    ('M', [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001]),
+    ('N', [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001]),
+    ('O', [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('P', [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('Q', [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101]),
+    ('R', [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001]),
+    ('S', [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110]),
+    ('T', [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100]),
+    ('U', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('V', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100]),
+    ('W', [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010]),
+    ('X', [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001]),
+    ('Y', [0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100, 0b00100]),
+    ('Z', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111]),
+    ('-', [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000]),
+    ('.', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100]),
+    ('_', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111]),
+    (':', [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000]),
+    ('/', [0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000]),
+    ('(', [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010]),
+    (')', [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000]),
+    ('+', [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000]),
+    ('#', [0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010]),
+    ('=', [0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000]),
+    ('?', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100]),
+];
+
+/// Returns the glyph of a character, falling back to `?` for characters not in [`FONT`].
+fn glyph(c: char) -> &'static [u8; GLYPH_HEIGHT as usize] {
+    let c = c.to_ascii_uppercase();
+    FONT.iter()
+        .find(|(glyph_char, _)| *glyph_char == c)
+        .or_else(|| FONT.iter().find(|(glyph_char, _)| *glyph_char == '?'))
+        .map(|(_, glyph)| glyph)
+        .unwrap()
+}
+
+/// Vertex of the watermark quad, matching `D3DFVF_XYZRHW | D3DFVF_TEX1`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct DX9WatermarkVertex {
+    x: f32,
+    y: f32,
+    z: f32,
+    rhw: f32,
+    u: f32,
+    v: f32,
+}
+
+/// Watermark drawn into the back buffer of a device.
+///
+/// The texture is created on the target device in `D3DPOOL_DEFAULT` on the first draw.
+/// It must be released with [`release`](Self::release) before the device is reset.
+#[derive(Debug)]
+pub struct DX9Watermark {
+    text: String,
+    texture: Option<(IDirect3DTexture9, u32, u32)>,
+    disabled: bool,
+}
+
+impl DX9Watermark {
+    /// Creates a watermark showing `text`.
+    pub fn new(text: String) -> Self {
+        Self { text, texture: None, disabled: false }
+    }
+
+    /// Releases the watermark texture, which is required before resetting the device.
+    pub fn release(&mut self) {
+        self.texture = None;
+    }
+
+    /// Draws the watermark into back buffer 0 of swap chain 0, to be called right before `Present`.
+    ///
+    /// The device state is preserved with [`DX9StatePreserver`]. Nothing is drawn while the device
+    /// is lost, and the watermark is disabled if its texture cannot be created.
+    ///
+    /// # Arguments
+    /// * `device` - The target device to draw into
+    pub fn draw(&mut self, device: &IDirect3DDevice9) {
+        if self.disabled || unsafe { device.TestCooperativeLevel() }.is_err() {
+            return;
+        }
+
+        if self.texture.is_none() {
+            match self.create_texture(device) {
+                Ok(texture) => self.texture = Some(texture),
+                Err(_err) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!("Failed to create watermark texture, disabling the watermark: {_err}");
+                    self.disabled = true;
+                    return;
+                }
+            }
+        }
+
+        let _ = self.draw_quad(device).inspect_err(|_err| {
+            #[cfg(feature = "tracing")]
+            tracing::warn!("Failed to draw watermark: {_err}");
+        });
+    }
+
+    /// Rasterizes the text into a new dynamic texture, returning it with its size in texels.
+    fn create_texture(&self, device: &IDirect3DDevice9) -> Result<(IDirect3DTexture9, u32, u32)> {
+        let chars: Vec<char> = self.text.chars().collect();
+        let width = PADDING + chars.len() as u32 * (GLYPH_WIDTH + PADDING);
+        let height = GLYPH_HEIGHT + 2 * PADDING;
+
+        let texture = try_out_param(|out| unsafe { device.CreateTexture(width, height, 1, D3DUSAGE_DYNAMIC as u32, D3DFMT_A8R8G8B8, D3DPOOL_DEFAULT, out, null_mut()) })?;
+
+        let mut locked = D3DLOCKED_RECT::default();
+        unsafe { texture.LockRect(0, &mut locked, std::ptr::null(), D3DLOCK_DISCARD as u32) }?;
+        for y in 0..height {
+            let row = unsafe { std::slice::from_raw_parts_mut((locked.pBits as *mut u8).add((y * locked.Pitch as u32) as usize) as *mut u32, width as usize) };
+            row.fill(BACKGROUND_COLOR);
+
+            let Some(glyph_y) = y.checked_sub(PADDING).filter(|&glyph_y| glyph_y < GLYPH_HEIGHT) else {
+                continue;
+            };
+            for (index, &c) in chars.iter().enumerate() {
+                let bits = glyph(c)[glyph_y as usize];
+                let left = PADDING + index as u32 * (GLYPH_WIDTH + PADDING);
+                for glyph_x in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - glyph_x)) != 0 {
+                        row[(left + glyph_x) as usize] = TEXT_COLOR;
+                    }
+                }
+            }
+        }
+        unsafe { texture.UnlockRect(0) }?;
+
+        #[cfg(feature = "tracing")]
+        tracing::info!("Created watermark texture for {:?}", self.text);
+
+        Ok((texture, width, height))
+    }
+
+    /// Draws the watermark texture as a quad into the bottom-right corner of the back buffer.
+    fn draw_quad(&self, device: &IDirect3DDevice9) -> Result<()> {
+        let Some((texture, width, height)) = &self.texture else {
+            return Ok(());
+        };
+
+        let _state_preserver = DX9StatePreserver::capture(device)?;
+
+        let back_buffer = unsafe { device.GetBackBuffer(0, 0, D3DBACKBUFFER_TYPE_MONO) }?;
+        let mut desc = D3DSURFACE_DESC::default();
+        unsafe { back_buffer.GetDesc(&mut desc) }?;
+
+        // Offset by half a pixel so that texels map exactly to pixels
+        let right = desc.Width.saturating_sub(MARGIN) as f32 - 0.5;
+        let bottom = desc.Height.saturating_sub(MARGIN) as f32 - 0.5;
+        let left = right - (width * SCALE) as f32;
+        let top = bottom - (height * SCALE) as f32;
+        let vertex = |x, y, u, v| DX9WatermarkVertex { x, y, z: 0.0, rhw: 1.0, u, v };
+        let vertices = [
+            vertex(left, top, 0.0, 0.0),
+            vertex(right, top, 1.0, 0.0),
+            vertex(left, bottom, 0.0, 1.0),
+            vertex(right, bottom, 1.0, 1.0),
+        ];
+
+        unsafe {
+            // Setting the render target also resets the viewport to the whole back buffer
+            device.SetRenderTarget(0, &back_buffer)?;
+            device.SetDepthStencilSurface(None)?;
+
+            device.SetVertexShader(None)?;
+            device.SetPixelShader(None)?;
+            device.SetFVF(D3DFVF_XYZRHW | D3DFVF_TEX1)?;
+
+            for (state, value) in [
+                (D3DRS_ZENABLE, 0),
+                (D3DRS_ZWRITEENABLE, 0),
+                (D3DRS_STENCILENABLE, 0),
+                (D3DRS_ALPHATESTENABLE, 0),
+                (D3DRS_ALPHABLENDENABLE, 1),
+                (D3DRS_SRCBLEND, D3DBLEND_SRCALPHA.0 as u32),
+                (D3DRS_DESTBLEND, D3DBLEND_INVSRCALPHA.0 as u32),
+                (D3DRS_BLENDOP, D3DBLENDOP_ADD.0 as u32),
+                (D3DRS_SEPARATEALPHABLENDENABLE, 0),
+                (D3DRS_CULLMODE, D3DCULL_NONE.0 as u32),
+                (D3DRS_FILLMODE, D3DFILL_SOLID.0 as u32),
+                (D3DRS_SCISSORTESTENABLE, 0),
+                (D3DRS_CLIPPLANEENABLE, 0),
+                (D3DRS_FOGENABLE, 0),
+                (D3DRS_SRGBWRITEENABLE, 0),
+                (D3DRS_COLORWRITEENABLE, 0xf),
+            ] {
+                device.SetRenderState(state, value)?;
+            }
+
+            device.SetTexture(0, texture)?;
+            device.SetTextureStageState(0, D3DTSS_COLOROP, D3DTOP_SELECTARG1.0 as u32)?;
+            device.SetTextureStageState(0, D3DTSS_COLORARG1, D3DTA_TEXTURE)?;
+            device.SetTextureStageState(0, D3DTSS_ALPHAOP, D3DTOP_SELECTARG1.0 as u32)?;
+            device.SetTextureStageState(0, D3DTSS_ALPHAARG1, D3DTA_TEXTURE)?;
+            device.SetTextureStageState(1, D3DTSS_COLOROP, D3DTOP_DISABLE.0 as u32)?;
+            device.SetSamplerState(0, D3DSAMP_MINFILTER, D3DTEXF_POINT.0 as u32)?;
+            device.SetSamplerState(0, D3DSAMP_MAGFILTER, D3DTEXF_POINT.0 as u32)?;
+            device.SetSamplerState(0, D3DSAMP_MIPFILTER, D3DTEXF_NONE.0 as u32)?;
+            device.SetSamplerState(0, D3DSAMP_ADDRESSU, D3DTADDRESS_CLAMP.0 as u32)?;
+            device.SetSamplerState(0, D3DSAMP_ADDRESSV, D3DTADDRESS_CLAMP.0 as u32)?;
+            device.SetSamplerState(0, D3DSAMP_SRGBTEXTURE, 0)?;
+
+            device.BeginScene()?;
+            let result = device.DrawPrimitiveUP(D3DPT_TRIANGLESTRIP, 2, vertices.as_ptr() as *const c_void, size_of::<DX9WatermarkVertex>() as u32);
+            device.EndScene()?;
+            result
+        }
+    }
+}
@@ -1,5 +1,1302 @@
+//! Configuration for the DX9 proxy.
+//!
+//! Settings are read from `DXPROXY_*` environment variables when a device is created,
+//! so they can be adjusted per launch without rebuilding the DLL.
+
+use super::com::parse_hresult;
+use std::{
+    env::var,
+    fmt::{self, Display, Formatter},
+    path::PathBuf,
+};
+use windows::{Win32::Graphics::Direct3D9::*, core::HRESULT};
+
 /// Configuration for the DX9 proxy.
 /// You can extend this struct to include additional settings
 /// such as logging options, performance tuning, or feature flags.
-#[derive(Debug, Clone, Default)]
-pub struct DX9ProxyConfig;
+#[derive(Debug, Clone, PartialEq)]
+pub struct DX9ProxyConfig {
+    /// Crops the data returned by `GetFrontBufferData` to the client area of the presentation window.
+    ///
+    /// In windowed mode, D3D9 copies the entire desktop into the destination surface.
+    /// When enabled, the client area of the device window is moved to the top-left corner
+    /// of the surface and the remainder is cleared to zero.
+    ///
+    /// **Note**: This deliberately changes standard D3D9 semantics.
+    ///
+    /// Environment variable: `DXPROXY_CROP_FRONT_BUFFER_TO_WINDOW=1`
+    pub crop_front_buffer_to_window: bool,
+
+    /// Forces `SetNPatchMode` to `0.0`, disabling N-patch tessellation.
+    ///
+    /// Some drivers render N-patches incorrectly. `GetNPatchMode` keeps reporting the
+    /// value that is actually applied on the device, which is `0.0` when this is enabled.
+    ///
+    /// Environment variable: `DXPROXY_DISABLE_NPATCH=1`
+    pub disable_npatch: bool,
+
+    /// Serializes `Present` and draw calls of each device through a dedicated worker thread.
+    ///
+    /// This is an experimental workaround for drivers that are unstable under the application's threading.
+    /// Every serialized call waits for a round-trip to the worker thread, which adds latency to each call.
+    ///
+    /// Environment variable: `DXPROXY_SERIALIZE_DEVICE_CALLS=1`
+    #[cfg(feature = "experimental-serialize-device-calls")]
+    pub serialize_device_calls: bool,
+
+    /// Wraps query objects created by `CreateQuery` with proxies.
+    ///
+    /// Queries are created at a high frequency by some engines, and proxying them adds
+    /// mapping overhead with little benefit. When disabled, the original query objects are
+    /// returned without proxying, so `IDirect3DQuery9::GetDevice` returns the original
+    /// device instead of the proxy device.
+    ///
+    /// Environment variable: `DXPROXY_PROXY_QUERIES=0` (enabled by default)
+    pub proxy_queries: bool,
+
+    /// Emulates query types that the driver does not support, instead of failing `CreateQuery`.
+    ///
+    /// Event, occlusion and timestamp queries are emulated with approximate values, which keeps
+    /// games that rely on them working. See [`EmulatedDirect3DQuery9`](super::com::EmulatedDirect3DQuery9)
+    /// for the limitations per query type.
+    ///
+    /// Environment variable: `DXPROXY_EMULATE_QUERIES=1`
+    pub emulate_queries: bool,
+
+    /// Display modes to add to the modes enumerated by `EnumAdapterModes` / `EnumAdapterModesEx`.
+    ///
+    /// Useful for games with hardcoded mode filters, e.g. to offer ultrawide resolutions.
+    /// Modes without a refresh rate are added with 60 Hz.
+    ///
+    /// Environment variable: `DXPROXY_ADD_DISPLAY_MODES=<width>x<height>[@<refresh rate>],...`
+    pub add_display_modes: Vec<DX9DisplayModeSpec>,
+
+    /// Display modes to hide from the modes enumerated by `EnumAdapterModes` / `EnumAdapterModesEx`.
+    ///
+    /// Modes without a refresh rate hide the resolution at all refresh rates.
+    ///
+    /// Environment variable: `DXPROXY_HIDE_DISPLAY_MODES=<width>x<height>[@<refresh rate>],...`
+    pub hide_display_modes: Vec<DX9DisplayModeSpec>,
+
+    /// Rewrites `DrawPrimitiveUP` and `DrawIndexedPrimitiveUP` into regular buffer draws.
+    ///
+    /// This is an experimental optimization for engines that use user-pointer draws heavily,
+    /// which are slow on some drivers. The user data is uploaded into dynamic vertex and
+    /// index buffers that are reused across draws. If the rewrite fails, the original call is used.
+    ///
+    /// Environment variable: `DXPROXY_OPTIMIZE_UP_DRAWS=1`
+    pub optimize_up_draws: bool,
+
+    /// Maximum number of frames the device is allowed to queue, set right after device creation.
+    ///
+    /// Lower values reduce input latency at the cost of throughput, as the CPU cannot run ahead
+    /// of the GPU as far. Only supported on `IDirect3DDevice9Ex` devices (via `SetMaximumFrameLatency`).
+    /// For other devices, see [`flush_after_present`](Self::flush_after_present).
+    ///
+    /// Environment variable: `DXPROXY_MAX_FRAME_LATENCY=<frames>`
+    pub max_frame_latency: Option<u32>,
+
+    /// Waits for the GPU to finish all queued work after each `Present`.
+    ///
+    /// This caps the number of queued frames to zero on any device, minimizing input latency.
+    /// However, the CPU and GPU no longer overlap across frames, which can significantly reduce the frame rate.
+    ///
+    /// Environment variable: `DXPROXY_FLUSH_AFTER_PRESENT=1`
+    pub flush_after_present: bool,
+
+    /// Waits for the GPU to finish all pending work before the proxy device is destroyed.
+    ///
+    /// Some drivers become unstable when a device is released while the GPU is still using its
+    /// resources. This is a best-effort defensive measure for a clean shutdown, skipped if the
+    /// device is lost, and it stalls the teardown until the GPU is idle.
+    ///
+    /// Environment variable: `DXPROXY_FLUSH_ON_DROP=1`
+    pub flush_on_drop: bool,
+
+    /// Logs the shader float constants that changed since the previous draw call at each draw.
+    ///
+    /// A shadow copy of all vertex and pixel shader float constants is kept per device and updated
+    /// by `SetVertexShaderConstantF` / `SetPixelShaderConstantF`. Each draw then logs only the changed
+    /// registers at the debug level, which gives a per-draw constant diff for shader debugging.
+    ///
+    /// Environment variable: `DXPROXY_CAPTURE_SHADER_CONSTANTS=1`
+    pub capture_shader_constants: bool,
+
+    /// Logs the format and size of the index buffer used by each indexed draw call.
+    ///
+    /// Together with the other per-draw captures, this tells whether indices are 16- or 32-bit,
+    /// which is needed to reconstruct geometry offline. Logged at the debug level.
+    ///
+    /// Environment variable: `DXPROXY_CAPTURE_INDEX_BUFFERS=1`
+    pub capture_index_buffers: bool,
+
+    /// Number of tracked proxies above which a possible proxy leak is reported, or `0` to disable the check.
+    ///
+    /// When exceeded, an error with the number of tracked proxies per interface type is logged,
+    /// and the report is repeated each time the number doubles. The proxy keeps working normally.
+    /// The default is far above what applications create in normal operation.
+    ///
+    /// Environment variable: `DXPROXY_MAX_TRACKED_OBJECTS=<count>`
+    pub max_tracked_objects: u32,
+
+    /// File to also write the proxy leak report to, see [`max_tracked_objects`](Self::max_tracked_objects).
+    ///
+    /// Environment variable: `DXPROXY_TRACKER_REPORT_FILE=<path>`
+    pub tracker_report_file: Option<PathBuf>,
+
+    /// Captures the call stack at the creation of each proxy, for attributing leaks to their creation sites.
+    ///
+    /// The proxy leak report (see [`max_tracked_objects`](Self::max_tracked_objects)) then also groups
+    /// the tracked proxies by creation stack. Frames are reported as raw addresses with module offsets.
+    /// Capturing a stack on every creation is costly, so only enable this while investigating leaks.
+    ///
+    /// Environment variable: `DXPROXY_CAPTURE_CREATION_STACKS=1`
+    pub capture_creation_stacks: bool,
+
+    /// Warns when a resource stays locked for more than one frame.
+    ///
+    /// Outstanding `Lock` / `LockRect` / `LockBox` calls are counted per resource, and each `Present`
+    /// reports resources that have not been unlocked since before the previous frame, once per lock.
+    /// This surfaces missing `Unlock` calls, which stall the GPU.
+    ///
+    /// Environment variable: `DXPROXY_CHECK_LOCK_LEAKS=1`
+    pub check_lock_leaks: bool,
+
+    /// Clamps viewports passed to `SetViewport` to the dimensions of render target 0.
+    ///
+    /// Some applications resize their window without updating the viewport, and pass a viewport
+    /// larger than the render target, which makes `SetViewport` fail. When enabled, `X + Width` and
+    /// `Y + Height` are clamped to the render target size, and each clamp is logged.
+    ///
+    /// Environment variable: `DXPROXY_CLAMP_VIEWPORT=1`
+    pub clamp_viewport: bool,
+
+    /// Forces `D3DRS_SRGBWRITEENABLE` on (`Some(true)`) or off (`Some(false)`).
+    ///
+    /// Gamma handling differs across drivers, and some games look washed out or too dark.
+    /// This can fix specific gamma issues but may worsen others, so it is a per-game tweak.
+    /// See [`forced_render_states`](Self::forced_render_states).
+    ///
+    /// Environment variable: `DXPROXY_FORCE_SRGB_WRITE=1` or `DXPROXY_FORCE_SRGB_WRITE=0`
+    pub force_srgb_write: Option<bool>,
+
+    /// Forces `D3DSAMP_SRGBTEXTURE` on (`Some(true)`) or off (`Some(false)`) for all samplers.
+    ///
+    /// Like [`force_srgb_write`](Self::force_srgb_write), this is a per-game tweak for gamma issues.
+    /// See [`forced_sampler_states`](Self::forced_sampler_states).
+    ///
+    /// Environment variable: `DXPROXY_FORCE_SRGB_TEXTURE=1` or `DXPROXY_FORCE_SRGB_TEXTURE=0`
+    pub force_srgb_texture: Option<bool>,
+
+    /// Window handle that replaces the focus window passed to `CreateDevice` / `CreateDeviceEx`.
+    ///
+    /// Together with [`device_window_override`](Self::device_window_override), this lets a host
+    /// application embed the game's rendering into its own window without the game's cooperation.
+    /// Invalid handles are ignored with a warning.
+    ///
+    /// Environment variable: `DXPROXY_FOCUS_WINDOW=<hwnd>` (decimal or `0x`-prefixed hexadecimal)
+    pub focus_window_override: Option<isize>,
+
+    /// Window handle that replaces `hDeviceWindow` of the presentation parameters at device creation and reset.
+    ///
+    /// See [`focus_window_override`](Self::focus_window_override). Invalid handles are ignored with a warning.
+    ///
+    /// Environment variable: `DXPROXY_DEVICE_WINDOW=<hwnd>` (decimal or `0x`-prefixed hexadecimal)
+    pub device_window_override: Option<isize>,
+
+    /// Counts frames, draw calls and primitives per device.
+    ///
+    /// The counters can be reset with [`reset_stats_key`](Self::reset_stats_key) to measure a specific
+    /// interval, and the counts up to the reset are logged. See [`DX9CallStats`](crate::dx9::com::DX9CallStats).
+    ///
+    /// Environment variable: `DXPROXY_CALL_STATS=1`
+    pub collect_call_stats: bool,
+
+    /// Virtual-key code of a hotkey that logs and resets the call statistics of all devices.
+    ///
+    /// The key is polled at each `Present`. Only effective with [`collect_call_stats`](Self::collect_call_stats).
+    ///
+    /// Environment variable: `DXPROXY_RESET_STATS_KEY=<virtual-key code>` (decimal or `0x`-prefixed hexadecimal)
+    pub reset_stats_key: Option<u32>,
+
+    /// Creates textures without mipmaps with `D3DUSAGE_AUTOGENMIPMAP` and a full mipmap chain.
+    ///
+    /// Some games ship textures without mipmaps, which shimmer when minified at higher resolutions.
+    /// Only single-level textures are changed, and only if the format supports automatic mipmap
+    /// generation in the requested pool. The generated mipmaps use linear filtering.
+    ///
+    /// Environment variable: `DXPROXY_FORCE_AUTOGEN_MIPMAPS=1`
+    pub force_autogen_mipmaps: bool,
+
+    /// Short text drawn into the bottom-right corner of the back buffer at each `Present`.
+    ///
+    /// Useful to identify builds (e.g. a build id or `DEBUG`) in captures and streams.
+    /// Only digits, letters and a few symbols are supported; other characters are drawn as `?`.
+    ///
+    /// Environment variable: `DXPROXY_WATERMARK=<text>`
+    pub watermark: Option<String>,
+
+    /// Disables hardware instancing by resetting instanced stream frequencies to 1.
+    ///
+    /// `SetStreamSourceFreq` calls with `D3DSTREAMSOURCE_INDEXEDDATA` or `D3DSTREAMSOURCE_INSTANCEDATA`
+    /// are rewritten so that the streams are treated as non-instanced, for drivers that render
+    /// instancing incorrectly. This may break geometry in games that rely on instancing, as only
+    /// the first instance is drawn.
+    ///
+    /// Environment variable: `DXPROXY_DISABLE_INSTANCING=1`
+    pub disable_instancing: bool,
+
+    /// Serves runtime commands on the named pipe `\\.\pipe\dxproxy-<pid>`.
+    ///
+    /// Lets external tools query and reset statistics without focusing the game window.
+    /// See [`crate::dx9::control`] for the commands.
+    ///
+    /// Environment variable: `DXPROXY_CONTROL_PIPE=1`
+    pub control_pipe: bool,
+
+    /// Overrides of the results of `CheckDeviceMultiSampleType` for specific formats and multisample types.
+    ///
+    /// Each entry either forces a multisample type to be reported as supported with the given number
+    /// of quality levels, or as not supported. This lets a game's MSAA picker offer levels the driver
+    /// reports conservatively, or hide levels that crash. Forcing a level the driver does not actually
+    /// support makes device or surface creation fail when the game picks it, which some games do not
+    /// handle gracefully.
+    ///
+    /// Environment variable: `DXPROXY_MULTISAMPLE_OVERRIDES=<format>:<type>=<quality levels|off>,...`
+    pub multisample_overrides: Vec<DX9MultiSampleOverride>,
+
+    /// Number of times loading the system `d3d9.dll` is retried after a failure.
+    ///
+    /// On some systems (e.g. with antivirus interference or a slow disk), loading the DLL at
+    /// process start fails sporadically. Retries wait a little longer after each failed attempt.
+    /// `0` makes a single attempt.
+    ///
+    /// Environment variable: `DXPROXY_LOAD_LIBRARY_RETRIES=<count>`
+    pub load_library_retries: u32,
+
+    /// Debug aid that makes `Clear` succeed without clearing anything.
+    ///
+    /// Render targets keep their previous content, which reveals whether something is drawn over
+    /// them or only cleared. Scenes normally look broken with this enabled. See also
+    /// [`disable_clears_key`](Self::disable_clears_key) and [`disable_clears_render_target`](Self::disable_clears_render_target).
+    ///
+    /// Environment variable: `DXPROXY_DISABLE_CLEARS=1`
+    pub disable_clears: bool,
+
+    /// Virtual-key code of a hotkey that toggles [`disable_clears`](Self::disable_clears) on all devices.
+    ///
+    /// The key is polled at each `Present`.
+    ///
+    /// Environment variable: `DXPROXY_DISABLE_CLEARS_KEY=<virtual-key code>` (decimal or `0x`-prefixed hexadecimal)
+    pub disable_clears_key: Option<u32>,
+
+    /// Restricts [`disable_clears`](Self::disable_clears) to clears made while a render target is set at this index.
+    ///
+    /// `Clear` affects all bound render targets, so e.g. `1` only suppresses clears of multiple render target passes.
+    ///
+    /// Environment variable: `DXPROXY_DISABLE_CLEARS_RENDER_TARGET=<index>`
+    pub disable_clears_render_target: Option<u32>,
+
+    /// Back buffer format that replaces the format requested at device creation and reset.
+    ///
+    /// Useful for experiments such as requesting alpha or 10-bit back buffers. The format is only
+    /// replaced if `CheckDeviceType` reports it as presentable in the requested windowed or fullscreen
+    /// mode. Most games tolerate swapping `D3DFMT_X8R8G8B8` (22) and `D3DFMT_A8R8G8B8` (21), but
+    /// 10-bit formats such as `D3DFMT_A2R10G10B10` (35) often need engine support to render correctly.
+    ///
+    /// Environment variable: `DXPROXY_BACK_BUFFER_FORMAT=<D3DFORMAT value>`
+    pub back_buffer_format_override: Option<D3DFORMAT>,
+
+    /// Measures the time spent in the target driver by `Present`, draw, resource creation and lock calls.
+    ///
+    /// The call statistics then also report the average driver time per call of each method, which
+    /// tells stalls in the driver apart from overhead of the proxy. Reading the clock around each
+    /// call adds some cost. Only effective with [`collect_call_stats`](Self::collect_call_stats).
+    ///
+    /// Environment variable: `DXPROXY_MEASURE_DRIVER_TIME=1`
+    pub measure_driver_time: bool,
+
+    /// Sets (`Some(true)`) or clears (`Some(false)`) `D3DCREATE_DISABLE_DRIVER_MANAGEMENT` at device creation.
+    ///
+    /// The flag makes the runtime rather than the driver manage `D3DPOOL_MANAGED` resources, which
+    /// occasionally fixes or causes corruption. Useful to reproduce driver management related bugs.
+    ///
+    /// Environment variable: `DXPROXY_DISABLE_DRIVER_MANAGEMENT=1` or `DXPROXY_DISABLE_DRIVER_MANAGEMENT=0`
+    pub disable_driver_management: Option<bool>,
+
+    /// Sets (`Some(true)`) or clears (`Some(false)`) `D3DCREATE_DISABLE_DRIVER_MANAGEMENT_EX` at device creation.
+    ///
+    /// Like [`disable_driver_management`](Self::disable_driver_management), but resource allocation
+    /// failures are reported to the application instead of being handled by the runtime.
+    ///
+    /// Environment variable: `DXPROXY_DISABLE_DRIVER_MANAGEMENT_EX=1` or `DXPROXY_DISABLE_DRIVER_MANAGEMENT_EX=0`
+    pub disable_driver_management_ex: Option<bool>,
+
+    /// Confines the cursor to the client area of the device window in windowed mode, while the window is in the foreground.
+    ///
+    /// Restores the mouse confinement of exclusive fullscreen for games that are forced into windowed
+    /// mode. The clip is re-asserted at each `Present`, so it follows window moves, and it is released
+    /// at the first `Present` after the window loses the foreground, e.g. on alt-tab. Games that stop
+    /// presenting in the background therefore keep the cursor confined until they present again.
+    /// The clip is also released when the device is destroyed.
+    ///
+    /// Environment variable: `DXPROXY_CLIP_CURSOR=1`
+    pub clip_cursor_in_window: bool,
+
+    /// Logs the pool and usage of every created texture, surface and buffer, and tracks the live
+    /// `D3DPOOL_DEFAULT` resources of each device.
+    ///
+    /// `Reset` fails as long as any default pool resource is alive, so when it fails, the resources
+    /// that are still alive are logged by object ID, i.e. the address of the target object. With
+    /// [`capture_creation_stacks`](Self::capture_creation_stacks), their creation stacks are logged as well.
+    ///
+    /// Environment variable: `DXPROXY_TRACK_DEFAULT_POOL=1`
+    pub track_default_pool: bool,
+
+    /// Results forced for specific methods, which then return them without calling the driver.
+    ///
+    /// This is an advanced escape hatch for compatibility hacks, e.g. making `SetDialogBoxMode` or
+    /// `ValidateDevice` always succeed. Only the methods of [`DX9OverridableMethod`] can be overridden.
+    /// Skipping a call can easily break a game in ways that are hard to trace back to the override,
+    /// and forcing failures of methods the game does not expect to fail may crash it. Each forced
+    /// result is logged at debug level.
+    ///
+    /// Results are given as symbolic names (e.g. `D3DERR_INVALIDCALL`), in `0x`-prefixed hexadecimal or
+    /// in decimal. All success codes are returned as `D3D_OK`. A forced success of `ValidateDevice`
+    /// reports a single pass.
+    ///
+    /// Environment variable: `DXPROXY_HRESULT_OVERRIDES=<method>=<result>,...`, e.g. `SetDialogBoxMode=D3D_OK`
+    pub hresult_overrides: Vec<DX9HResultOverride>,
+
+    /// File to write the effective configuration to at each device creation, e.g. `dxproxy.effective.env`.
+    ///
+    /// The file lists every `DXPROXY_*` variable with its resolved value, including defaults, one
+    /// `NAME=value` assignment per line. Unset options are listed as comments. Setting the listed
+    /// variables reproduces the same configuration, so the file shows exactly which settings are
+    /// active, e.g. for support requests. See [`to_env_vars`](Self::to_env_vars).
+    ///
+    /// Environment variable: `DXPROXY_EFFECTIVE_CONFIG_FILE=<path>`
+    pub effective_config_file: Option<PathBuf>,
+
+    /// Makes `StretchRect` calls succeed whose source and destination are the same surface.
+    ///
+    /// The runtime rejects such calls, which some games make anyway. With this option, they are
+    /// performed in two steps through an intermediate render target of the device, which is
+    /// created on first use and reused afterwards. Depth stencil surfaces are not supported.
+    ///
+    /// Environment variable: `DXPROXY_ALLOW_SELF_STRETCHRECT=1`
+    pub allow_self_stretchrect: bool,
+
+    /// Forces `D3DRS_ZENABLE` to `D3DZB_TRUE` (`Some(true)`) or `D3DZB_FALSE` (`Some(false)`), to diagnose depth sorting artifacts.
+    ///
+    /// See [`forced_render_states`](Self::forced_render_states) and [`force_z_enable_key`](Self::force_z_enable_key).
+    ///
+    /// Environment variable: `DXPROXY_FORCE_ZENABLE=1` or `DXPROXY_FORCE_ZENABLE=0`
+    pub force_z_enable: Option<bool>,
+
+    /// Forces `D3DRS_ZWRITEENABLE` on (`Some(true)`) or off (`Some(false)`), to diagnose depth sorting artifacts.
+    ///
+    /// See [`forced_render_states`](Self::forced_render_states) and [`force_z_write_enable_key`](Self::force_z_write_enable_key).
+    ///
+    /// Environment variable: `DXPROXY_FORCE_ZWRITEENABLE=1` or `DXPROXY_FORCE_ZWRITEENABLE=0`
+    pub force_z_write_enable: Option<bool>,
+
+    /// Forces `D3DRS_COLORWRITEENABLE` to this mask of `D3DCOLORWRITEENABLE_*` flags.
+    ///
+    /// Restricting the written channels (e.g. to red only) makes the depth complexity of the scene
+    /// visible. See [`forced_render_states`](Self::forced_render_states) and
+    /// [`force_color_write_enable_key`](Self::force_color_write_enable_key).
+    ///
+    /// Environment variable: `DXPROXY_FORCE_COLORWRITEENABLE=<mask>`
+    pub force_color_write_enable: Option<u32>,
+
+    /// Virtual-key code of a hotkey that toggles forcing `D3DRS_ZENABLE` on all devices.
+    ///
+    /// The state is forced to [`force_z_enable`](Self::force_z_enable), or to `D3DZB_FALSE` if unset,
+    /// in which case it starts unforced. The key is polled at each `Present`.
+    ///
+    /// Environment variable: `DXPROXY_FORCE_ZENABLE_KEY=<virtual-key code>` (decimal or `0x`-prefixed hexadecimal)
+    pub force_z_enable_key: Option<u32>,
+
+    /// Virtual-key code of a hotkey that toggles forcing `D3DRS_ZWRITEENABLE` on all devices.
+    ///
+    /// The state is forced to [`force_z_write_enable`](Self::force_z_write_enable), or to `FALSE` if unset,
+    /// in which case it starts unforced. The key is polled at each `Present`.
+    ///
+    /// Environment variable: `DXPROXY_FORCE_ZWRITEENABLE_KEY=<virtual-key code>` (decimal or `0x`-prefixed hexadecimal)
+    pub force_z_write_enable_key: Option<u32>,
+
+    /// Virtual-key code of a hotkey that toggles forcing `D3DRS_COLORWRITEENABLE` on all devices.
+    ///
+    /// The state is forced to [`force_color_write_enable`](Self::force_color_write_enable), or to
+    /// `D3DCOLORWRITEENABLE_RED` if unset, in which case it starts unforced. The key is polled at each `Present`.
+    ///
+    /// Environment variable: `DXPROXY_FORCE_COLORWRITEENABLE_KEY=<virtual-key code>` (decimal or `0x`-prefixed hexadecimal)
+    pub force_color_write_enable_key: Option<u32>,
+
+    /// Logs the GUID and data size of every `SetPrivateData`, `GetPrivateData` and `FreePrivateData` call on resources.
+    ///
+    /// Tools such as overlays and post-processing injectors attach data to resources through private
+    /// data GUIDs, so this reveals which tools attach to which resources. Verbose, so off by default.
+    ///
+    /// Environment variable: `DXPROXY_LOG_PRIVATE_DATA=1`
+    pub log_private_data: bool,
+
+    /// Adapter ordinal that replaces the adapter passed to `CreateDevice` / `CreateDeviceEx`.
+    ///
+    /// Each adapter corresponds to a monitor, so this selects the monitor that exclusive fullscreen
+    /// uses, for games that always use the primary monitor. Windowed devices are also created on this
+    /// adapter. Ordinals out of range are ignored with a warning.
+    ///
+    /// Environment variable: `DXPROXY_FULLSCREEN_MONITOR=<adapter ordinal>`
+    pub fullscreen_monitor: Option<u32>,
+
+    /// Delays `Present` to smooth out the variance of frame intervals, against microstutter.
+    ///
+    /// Frames that finish faster than the median of the recent frame intervals are delayed to it.
+    /// This is a heuristic: it can lower the frame rate slightly and add up to a frame of latency,
+    /// so only enable it for games whose frame times jitter. See also
+    /// [`frame_pacing_window`](Self::frame_pacing_window) and [`frame_pacing_max_sleep_ms`](Self::frame_pacing_max_sleep_ms).
+    ///
+    /// Environment variable: `DXPROXY_FRAME_PACING=1`
+    pub frame_pacing: bool,
+
+    /// Number of recent frames whose median interval [`frame_pacing`](Self::frame_pacing) targets.
+    ///
+    /// Larger windows react more slowly to changes of the frame rate.
+    ///
+    /// Environment variable: `DXPROXY_FRAME_PACING_WINDOW=<frames>` (default: 30)
+    pub frame_pacing_window: u32,
+
+    /// Maximum time in milliseconds by which [`frame_pacing`](Self::frame_pacing) delays a single frame.
+    ///
+    /// Environment variable: `DXPROXY_FRAME_PACING_MAX_SLEEP_MS=<milliseconds>` (default: 8)
+    pub frame_pacing_max_sleep_ms: u32,
+
+    /// Logs the result and number of passes reported by the driver for every `ValidateDevice` call.
+    ///
+    /// Some drivers fail `ValidateDevice` on complex multi-texture setups, which makes games drop effects.
+    /// This helps to decide whether to force it to succeed with [`hresult_overrides`](Self::hresult_overrides)
+    /// (`ValidateDevice=D3D_OK`). While it is overridden, the driver is still called to log its result
+    /// next to the forced one.
+    ///
+    /// Environment variable: `DXPROXY_LOG_VALIDATE_DEVICE=1`
+    pub log_validate_device: bool,
+
+    /// Virtual-key code of a hotkey that dumps the most recent log lines kept in memory to a file.
+    ///
+    /// The key is polled at each `Present`. Only effective if the log ring is enabled with
+    /// `DXPROXY_LOG_RING_SIZE=<lines>`, see [`dump_log_ring`](crate::dx9::dump_log_ring).
+    ///
+    /// Environment variable: `DXPROXY_LOG_RING_DUMP_KEY=<virtual-key code>` (decimal or `0x`-prefixed hexadecimal)
+    pub log_ring_dump_key: Option<u32>,
+
+    /// Scale factor applied to the cursor bitmap passed to `SetCursorProperties`, and to its hotspot.
+    ///
+    /// Games size their hardware cursor for the resolution they expect, so it looks tiny when the
+    /// resolution is forced higher. The bitmap is scaled with nearest-neighbor sampling, to at most 256
+    /// pixels wide and high. If the scaled cursor is rejected (e.g. because it is larger than the display
+    /// mode), the original one is used.
+    ///
+    /// Environment variable: `DXPROXY_CURSOR_SCALE=<factor>`, e.g. `2` or `1.5`
+    pub cursor_scale: Option<f32>,
+}
+
+impl Default for DX9ProxyConfig {
+    fn default() -> Self {
+        Self {
+            crop_front_buffer_to_window: false,
+            disable_npatch: false,
+            #[cfg(feature = "experimental-serialize-device-calls")]
+            serialize_device_calls: false,
+            proxy_queries: true,
+            emulate_queries: false,
+            add_display_modes: Vec::new(),
+            hide_display_modes: Vec::new(),
+            optimize_up_draws: false,
+            max_frame_latency: None,
+            flush_after_present: false,
+            flush_on_drop: false,
+            capture_shader_constants: false,
+            capture_index_buffers: false,
+            max_tracked_objects: 500_000,
+            tracker_report_file: None,
+            capture_creation_stacks: false,
+            check_lock_leaks: false,
+            clamp_viewport: false,
+            force_srgb_write: None,
+            force_srgb_texture: None,
+            focus_window_override: None,
+            device_window_override: None,
+            collect_call_stats: false,
+            reset_stats_key: None,
+            force_autogen_mipmaps: false,
+            watermark: None,
+            disable_instancing: false,
+            control_pipe: false,
+            multisample_overrides: Vec::new(),
+            load_library_retries: 2,
+            disable_clears: false,
+            disable_clears_key: None,
+            disable_clears_render_target: None,
+            back_buffer_format_override: None,
+            measure_driver_time: false,
+            disable_driver_management: None,
+            disable_driver_management_ex: None,
+            clip_cursor_in_window: false,
+            track_default_pool: false,
+            hresult_overrides: Vec::new(),
+            effective_config_file: None,
+            allow_self_stretchrect: false,
+            force_z_enable: None,
+            force_z_write_enable: None,
+            force_color_write_enable: None,
+            force_z_enable_key: None,
+            force_z_write_enable_key: None,
+            force_color_write_enable_key: None,
+            log_private_data: false,
+            fullscreen_monitor: None,
+            frame_pacing: false,
+            frame_pacing_window: 30,
+            frame_pacing_max_sleep_ms: 8,
+            log_validate_device: false,
+            log_ring_dump_key: None,
+            cursor_scale: None,
+        }
+    }
+}
+
+impl DX9ProxyConfig {
+    /// Creates a configuration from `DXPROXY_*` environment variables.
+    ///
+    /// Variables that are not set keep their default values.
+    pub fn from_env() -> Self {
+        Self::from_vars(|name| var(name).ok())
+    }
+
+    /// Creates a configuration from `DXPROXY_*` variables returned by `var`, e.g. read from a file
+    /// written by [`format_env_file`](Self::format_env_file).
+    ///
+    /// Variables for which `var` returns `None` keep their default values.
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        let var = &var;
+        let mut config = Self::default();
+
+        if let Some(value) = var_bool(var, "DXPROXY_CROP_FRONT_BUFFER_TO_WINDOW") {
+            config.crop_front_buffer_to_window = value;
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_DISABLE_NPATCH") {
+            config.disable_npatch = value;
+        }
+
+        #[cfg(feature = "experimental-serialize-device-calls")]
+        if let Some(value) = var_bool(var, "DXPROXY_SERIALIZE_DEVICE_CALLS") {
+            config.serialize_device_calls = value;
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_PROXY_QUERIES") {
+            config.proxy_queries = value;
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_EMULATE_QUERIES") {
+            config.emulate_queries = value;
+        }
+
+        if let Some(value) = var_display_modes(var, "DXPROXY_ADD_DISPLAY_MODES") {
+            config.add_display_modes = value;
+        }
+
+        if let Some(value) = var_display_modes(var, "DXPROXY_HIDE_DISPLAY_MODES") {
+            config.hide_display_modes = value;
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_OPTIMIZE_UP_DRAWS") {
+            config.optimize_up_draws = value;
+        }
+
+        if let Some(value) = var_u32(var, "DXPROXY_MAX_FRAME_LATENCY") {
+            config.max_frame_latency = Some(value);
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_FLUSH_AFTER_PRESENT") {
+            config.flush_after_present = value;
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_FLUSH_ON_DROP") {
+            config.flush_on_drop = value;
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_CAPTURE_SHADER_CONSTANTS") {
+            config.capture_shader_constants = value;
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_CAPTURE_INDEX_BUFFERS") {
+            config.capture_index_buffers = value;
+        }
+
+        if let Some(value) = var_u32(var, "DXPROXY_MAX_TRACKED_OBJECTS") {
+            config.max_tracked_objects = value;
+        }
+
+        if let Some(value) = var("DXPROXY_TRACKER_REPORT_FILE") {
+            config.tracker_report_file = Some(PathBuf::from(value));
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_CAPTURE_CREATION_STACKS") {
+            config.capture_creation_stacks = value;
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_CHECK_LOCK_LEAKS") {
+            config.check_lock_leaks = value;
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_CLAMP_VIEWPORT") {
+            config.clamp_viewport = value;
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_FORCE_SRGB_WRITE") {
+            config.force_srgb_write = Some(value);
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_FORCE_SRGB_TEXTURE") {
+            config.force_srgb_texture = Some(value);
+        }
+
+        if let Some(value) = var_handle(var, "DXPROXY_FOCUS_WINDOW") {
+            config.focus_window_override = Some(value);
+        }
+
+        if let Some(value) = var_handle(var, "DXPROXY_DEVICE_WINDOW") {
+            config.device_window_override = Some(value);
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_CALL_STATS") {
+            config.collect_call_stats = value;
+        }
+
+        if let Some(value) = var_handle(var, "DXPROXY_RESET_STATS_KEY") {
+            config.reset_stats_key = Some(value as u32);
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_FORCE_AUTOGEN_MIPMAPS") {
+            config.force_autogen_mipmaps = value;
+        }
+
+        if let Some(value) = var("DXPROXY_WATERMARK")
+            && !value.is_empty()
+        {
+            config.watermark = Some(value);
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_DISABLE_INSTANCING") {
+            config.disable_instancing = value;
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_CONTROL_PIPE") {
+            config.control_pipe = value;
+        }
+
+        if let Some(value) = var_multisample_overrides(var, "DXPROXY_MULTISAMPLE_OVERRIDES") {
+            config.multisample_overrides = value;
+        }
+
+        if let Some(value) = var_u32(var, "DXPROXY_LOAD_LIBRARY_RETRIES") {
+            config.load_library_retries = value;
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_DISABLE_CLEARS") {
+            config.disable_clears = value;
+        }
+
+        if let Some(value) = var_handle(var, "DXPROXY_DISABLE_CLEARS_KEY") {
+            config.disable_clears_key = Some(value as u32);
+        }
+
+        if let Some(value) = var_u32(var, "DXPROXY_DISABLE_CLEARS_RENDER_TARGET") {
+            config.disable_clears_render_target = Some(value);
+        }
+
+        if let Some(value) = var_u32(var, "DXPROXY_BACK_BUFFER_FORMAT") {
+            config.back_buffer_format_override = Some(D3DFORMAT(value));
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_MEASURE_DRIVER_TIME") {
+            config.measure_driver_time = value;
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_DISABLE_DRIVER_MANAGEMENT") {
+            config.disable_driver_management = Some(value);
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_DISABLE_DRIVER_MANAGEMENT_EX") {
+            config.disable_driver_management_ex = Some(value);
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_CLIP_CURSOR") {
+            config.clip_cursor_in_window = value;
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_TRACK_DEFAULT_POOL") {
+            config.track_default_pool = value;
+        }
+
+        if let Some(value) = var_hresult_overrides(var, "DXPROXY_HRESULT_OVERRIDES") {
+            config.hresult_overrides = value;
+        }
+
+        if let Some(value) = var("DXPROXY_EFFECTIVE_CONFIG_FILE") {
+            config.effective_config_file = Some(PathBuf::from(value));
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_ALLOW_SELF_STRETCHRECT") {
+            config.allow_self_stretchrect = value;
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_FORCE_ZENABLE") {
+            config.force_z_enable = Some(value);
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_FORCE_ZWRITEENABLE") {
+            config.force_z_write_enable = Some(value);
+        }
+
+        if let Some(value) = var_u32(var, "DXPROXY_FORCE_COLORWRITEENABLE") {
+            config.force_color_write_enable = Some(value);
+        }
+
+        if let Some(value) = var_handle(var, "DXPROXY_FORCE_ZENABLE_KEY") {
+            config.force_z_enable_key = Some(value as u32);
+        }
+
+        if let Some(value) = var_handle(var, "DXPROXY_FORCE_ZWRITEENABLE_KEY") {
+            config.force_z_write_enable_key = Some(value as u32);
+        }
+
+        if let Some(value) = var_handle(var, "DXPROXY_FORCE_COLORWRITEENABLE_KEY") {
+            config.force_color_write_enable_key = Some(value as u32);
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_LOG_PRIVATE_DATA") {
+            config.log_private_data = value;
+        }
+
+        if let Some(value) = var_u32(var, "DXPROXY_FULLSCREEN_MONITOR") {
+            config.fullscreen_monitor = Some(value);
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_FRAME_PACING") {
+            config.frame_pacing = value;
+        }
+
+        if let Some(value) = var_u32(var, "DXPROXY_FRAME_PACING_WINDOW") {
+            config.frame_pacing_window = value;
+        }
+
+        if let Some(value) = var_u32(var, "DXPROXY_FRAME_PACING_MAX_SLEEP_MS") {
+            config.frame_pacing_max_sleep_ms = value;
+        }
+
+        if let Some(value) = var_bool(var, "DXPROXY_LOG_VALIDATE_DEVICE") {
+            config.log_validate_device = value;
+        }
+
+        if let Some(value) = var_handle(var, "DXPROXY_LOG_RING_DUMP_KEY") {
+            config.log_ring_dump_key = Some(value as u32);
+        }
+
+        if let Some(value) = var_f32(var, "DXPROXY_CURSOR_SCALE").filter(|&value| value > 0.0) {
+            config.cursor_scale = Some(value);
+        }
+
+        config
+    }
+
+    /// Returns the effective value of each environment variable read by [`from_env`](Self::from_env), in the same order.
+    ///
+    /// Values are `None` for options that are unset. Setting the variables to the returned values,
+    /// and leaving the others unset, makes [`from_env`](Self::from_env) and [`from_vars`](Self::from_vars)
+    /// return an identical configuration.
+    pub fn to_env_vars(&self) -> Vec<(&'static str, Option<String>)> {
+        let flag = |value: bool| Some(if value { "1" } else { "0" }.to_string());
+        let path = |value: &Option<PathBuf>| value.as_ref().map(|path| path.display().to_string());
+
+        #[allow(unused_mut)]
+        let mut vars = vec![
+            ("DXPROXY_CROP_FRONT_BUFFER_TO_WINDOW", flag(self.crop_front_buffer_to_window)),
+            ("DXPROXY_DISABLE_NPATCH", flag(self.disable_npatch)),
+        ];
+        #[cfg(feature = "experimental-serialize-device-calls")]
+        vars.push(("DXPROXY_SERIALIZE_DEVICE_CALLS", flag(self.serialize_device_calls)));
+        vars.extend([
+            ("DXPROXY_PROXY_QUERIES", flag(self.proxy_queries)),
+            ("DXPROXY_EMULATE_QUERIES", flag(self.emulate_queries)),
+            ("DXPROXY_ADD_DISPLAY_MODES", Some(join(&self.add_display_modes))),
+            ("DXPROXY_HIDE_DISPLAY_MODES", Some(join(&self.hide_display_modes))),
+            ("DXPROXY_OPTIMIZE_UP_DRAWS", flag(self.optimize_up_draws)),
+            ("DXPROXY_MAX_FRAME_LATENCY", self.max_frame_latency.map(|value| value.to_string())),
+            ("DXPROXY_FLUSH_AFTER_PRESENT", flag(self.flush_after_present)),
+            ("DXPROXY_FLUSH_ON_DROP", flag(self.flush_on_drop)),
+            ("DXPROXY_CAPTURE_SHADER_CONSTANTS", flag(self.capture_shader_constants)),
+            ("DXPROXY_CAPTURE_INDEX_BUFFERS", flag(self.capture_index_buffers)),
+            ("DXPROXY_MAX_TRACKED_OBJECTS", Some(self.max_tracked_objects.to_string())),
+            ("DXPROXY_TRACKER_REPORT_FILE", path(&self.tracker_report_file)),
+            ("DXPROXY_CAPTURE_CREATION_STACKS", flag(self.capture_creation_stacks)),
+            ("DXPROXY_CHECK_LOCK_LEAKS", flag(self.check_lock_leaks)),
+            ("DXPROXY_CLAMP_VIEWPORT", flag(self.clamp_viewport)),
+            ("DXPROXY_FORCE_SRGB_WRITE", self.force_srgb_write.and_then(flag)),
+            ("DXPROXY_FORCE_SRGB_TEXTURE", self.force_srgb_texture.and_then(flag)),
+            ("DXPROXY_FOCUS_WINDOW", self.focus_window_override.map(|value| value.to_string())),
+            ("DXPROXY_DEVICE_WINDOW", self.device_window_override.map(|value| value.to_string())),
+            ("DXPROXY_CALL_STATS", flag(self.collect_call_stats)),
+            ("DXPROXY_RESET_STATS_KEY", self.reset_stats_key.map(|value| value.to_string())),
+            ("DXPROXY_FORCE_AUTOGEN_MIPMAPS", flag(self.force_autogen_mipmaps)),
+            ("DXPROXY_WATERMARK", self.watermark.clone()),
+            ("DXPROXY_DISABLE_INSTANCING", flag(self.disable_instancing)),
+            ("DXPROXY_CONTROL_PIPE", flag(self.control_pipe)),
+            ("DXPROXY_MULTISAMPLE_OVERRIDES", Some(join(&self.multisample_overrides))),
+            ("DXPROXY_LOAD_LIBRARY_RETRIES", Some(self.load_library_retries.to_string())),
+            ("DXPROXY_DISABLE_CLEARS", flag(self.disable_clears)),
+            ("DXPROXY_DISABLE_CLEARS_KEY", self.disable_clears_key.map(|value| value.to_string())),
+            ("DXPROXY_DISABLE_CLEARS_RENDER_TARGET", self.disable_clears_render_target.map(|value| value.to_string())),
+            ("DXPROXY_BACK_BUFFER_FORMAT", self.back_buffer_format_override.map(|value| value.0.to_string())),
+            ("DXPROXY_MEASURE_DRIVER_TIME", flag(self.measure_driver_time)),
+            ("DXPROXY_DISABLE_DRIVER_MANAGEMENT", self.disable_driver_management.and_then(flag)),
+            ("DXPROXY_DISABLE_DRIVER_MANAGEMENT_EX", self.disable_driver_management_ex.and_then(flag)),
+            ("DXPROXY_CLIP_CURSOR", flag(self.clip_cursor_in_window)),
+            ("DXPROXY_TRACK_DEFAULT_POOL", flag(self.track_default_pool)),
+            ("DXPROXY_HRESULT_OVERRIDES", Some(join(&self.hresult_overrides))),
+            ("DXPROXY_EFFECTIVE_CONFIG_FILE", path(&self.effective_config_file)),
+            ("DXPROXY_ALLOW_SELF_STRETCHRECT", flag(self.allow_self_stretchrect)),
+            ("DXPROXY_FORCE_ZENABLE", self.force_z_enable.and_then(flag)),
+            ("DXPROXY_FORCE_ZWRITEENABLE", self.force_z_write_enable.and_then(flag)),
+            ("DXPROXY_FORCE_COLORWRITEENABLE", self.force_color_write_enable.map(|value| value.to_string())),
+            ("DXPROXY_FORCE_ZENABLE_KEY", self.force_z_enable_key.map(|value| value.to_string())),
+            ("DXPROXY_FORCE_ZWRITEENABLE_KEY", self.force_z_write_enable_key.map(|value| value.to_string())),
+            ("DXPROXY_FORCE_COLORWRITEENABLE_KEY", self.force_color_write_enable_key.map(|value| value.to_string())),
+            ("DXPROXY_LOG_PRIVATE_DATA", flag(self.log_private_data)),
+            ("DXPROXY_FULLSCREEN_MONITOR", self.fullscreen_monitor.map(|value| value.to_string())),
+            ("DXPROXY_FRAME_PACING", flag(self.frame_pacing)),
+            ("DXPROXY_FRAME_PACING_WINDOW", Some(self.frame_pacing_window.to_string())),
+            ("DXPROXY_FRAME_PACING_MAX_SLEEP_MS", Some(self.frame_pacing_max_sleep_ms.to_string())),
+            ("DXPROXY_LOG_VALIDATE_DEVICE", flag(self.log_validate_device)),
+            ("DXPROXY_LOG_RING_DUMP_KEY", self.log_ring_dump_key.map(|value| value.to_string())),
+            ("DXPROXY_CURSOR_SCALE", self.cursor_scale.map(|value| value.to_string())),
+        ]);
+        vars
+    }
+
+    /// Formats the effective configuration as `NAME=value` lines, listing unset options as comments.
+    ///
+    /// See [`to_env_vars`](Self::to_env_vars) and [`effective_config_file`](Self::effective_config_file).
+    pub fn format_env_file(&self) -> String {
+        let mut text = String::from("# Effective dxproxy configuration, including defaults\n");
+        for (name, value) in self.to_env_vars() {
+            match value {
+                Some(value) => text.push_str(&format!("{name}={value}\n")),
+                None => text.push_str(&format!("# {name} is not set\n")),
+            }
+        }
+        text
+    }
+
+    /// Returns the render states forced by this configuration, and the values they are forced to.
+    ///
+    /// `SetRenderState` calls for these states are overridden with the forced values, and the forced
+    /// values are re-applied at each `BeginScene` and after state blocks are applied. Hotkeys such as
+    /// [`force_z_enable_key`](Self::force_z_enable_key) can change the forced states at runtime.
+    pub fn forced_render_states(&self) -> Vec<(D3DRENDERSTATETYPE, u32)> {
+        let mut states = Vec::new();
+        if let Some(value) = self.force_srgb_write {
+            states.push((D3DRS_SRGBWRITEENABLE, value as u32));
+        }
+        if let Some(value) = self.force_z_enable {
+            states.push((D3DRS_ZENABLE, value as u32));
+        }
+        if let Some(value) = self.force_z_write_enable {
+            states.push((D3DRS_ZWRITEENABLE, value as u32));
+        }
+        if let Some(value) = self.force_color_write_enable {
+            states.push((D3DRS_COLORWRITEENABLE, value));
+        }
+        states
+    }
+
+    /// Returns the sampler states forced by this configuration for all samplers, and the values they are forced to.
+    ///
+    /// `SetSamplerState` calls for these states are overridden with the forced values,
+    /// and the forced values are re-applied to all pixel samplers at each `BeginScene`.
+    pub fn forced_sampler_states(&self) -> Vec<(D3DSAMPLERSTATETYPE, u32)> {
+        let mut states = Vec::new();
+        if let Some(value) = self.force_srgb_texture {
+            states.push((D3DSAMP_SRGBTEXTURE, value as u32));
+        }
+        states
+    }
+}
+
+/// Display mode given in the configuration as `<width>x<height>[@<refresh rate>]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DX9DisplayModeSpec {
+    pub width: u32,
+    pub height: u32,
+    /// Refresh rate in Hz, or `None` to match any refresh rate.
+    pub refresh_rate: Option<u32>,
+}
+
+/// Formats the display mode as accepted by [`DX9DisplayModeSpec::parse`].
+impl Display for DX9DisplayModeSpec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)?;
+        if let Some(refresh_rate) = self.refresh_rate {
+            write!(f, "@{refresh_rate}")?;
+        }
+        Ok(())
+    }
+}
+
+impl DX9DisplayModeSpec {
+    /// Refresh rate of added modes without an explicit refresh rate.
+    const DEFAULT_REFRESH_RATE: u32 = 60;
+
+    /// Parses a display mode in the form `<width>x<height>[@<refresh rate>]`, e.g. `2560x1080@60`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (size, refresh_rate) = match text.trim().split_once('@') {
+            Some((size, refresh_rate)) => (size, Some(refresh_rate.trim().parse().ok()?)),
+            None => (text.trim(), None),
+        };
+        let (width, height) = size.split_once(['x', 'X'])?;
+        Some(Self {
+            width: width.trim().parse().ok()?,
+            height: height.trim().parse().ok()?,
+            refresh_rate,
+        })
+    }
+
+    /// Returns `true` if `mode` has the size and, if specified, the refresh rate of this display mode.
+    pub fn matches(&self, mode: &D3DDISPLAYMODEEX) -> bool {
+        mode.Width == self.width && mode.Height == self.height && self.refresh_rate.is_none_or(|refresh_rate| mode.RefreshRate == refresh_rate)
+    }
+
+    /// Converts this display mode into a progressive mode of the specified format.
+    pub fn to_display_mode(&self, format: D3DFORMAT) -> D3DDISPLAYMODEEX {
+        D3DDISPLAYMODEEX {
+            Size: size_of::<D3DDISPLAYMODEEX>() as u32,
+            Width: self.width,
+            Height: self.height,
+            RefreshRate: self.refresh_rate.unwrap_or(Self::DEFAULT_REFRESH_RATE),
+            Format: format,
+            ScanLineOrdering: D3DSCANLINEORDERING_PROGRESSIVE,
+        }
+    }
+}
+
+/// Override of a `CheckDeviceMultiSampleType` result, given in the configuration as
+/// `<format>:<type>=<quality levels|off>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DX9MultiSampleOverride {
+    /// Surface format to match, or `None` to match any format.
+    pub format: Option<D3DFORMAT>,
+    pub multisample_type: D3DMULTISAMPLE_TYPE,
+    /// Number of quality levels to report, or `None` to report the type as not supported.
+    pub quality_levels: Option<u32>,
+}
+
+/// Formats the override as accepted by [`DX9MultiSampleOverride::parse`].
+impl Display for DX9MultiSampleOverride {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.format {
+            Some(format) => write!(f, "{}:", format.0)?,
+            None => write!(f, "*:")?,
+        }
+        write!(f, "{}=", self.multisample_type.0)?;
+        match self.quality_levels {
+            Some(quality_levels) => write!(f, "{quality_levels}"),
+            None => write!(f, "off"),
+        }
+    }
+}
+
+impl DX9MultiSampleOverride {
+    /// Parses an override in the form `<format>:<type>=<quality levels|off>`, e.g. `21:8=off` or `*:4=2`.
+    ///
+    /// The format is a numeric `D3DFORMAT` value, or `*` for any format.
+    /// The number of quality levels must be at least 1.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (key, quality_levels) = text.trim().split_once('=')?;
+        let (format, multisample_type) = key.split_once(':')?;
+        let format = match format.trim() {
+            "*" => None,
+            format => Some(D3DFORMAT(format.parse().ok()?)),
+        };
+        let quality_levels = match quality_levels.trim() {
+            "off" => None,
+            quality_levels => Some(quality_levels.parse().ok().filter(|&levels| levels > 0)?),
+        };
+        Some(Self {
+            format,
+            multisample_type: D3DMULTISAMPLE_TYPE(multisample_type.trim().parse().ok()?),
+            quality_levels,
+        })
+    }
+
+    /// Returns `true` if this override applies to `format` and `multisample_type`.
+    pub fn matches(&self, format: D3DFORMAT, multisample_type: D3DMULTISAMPLE_TYPE) -> bool {
+        self.format.is_none_or(|expected| expected == format) && self.multisample_type == multisample_type
+    }
+}
+
+/// Method whose result can be forced with [`DX9ProxyConfig::hresult_overrides`].
+///
+/// The list is deliberately limited to methods that games are known to handle badly, and that
+/// can be skipped without corrupting the state of the device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DX9OverridableMethod {
+    TestCooperativeLevel,
+    EvictManagedResources,
+    SetCursorProperties,
+    SetDialogBoxMode,
+    ValidateDevice,
+    CheckDeviceState,
+    SetGPUThreadPriority,
+    SetMaximumFrameLatency,
+    WaitForVBlank,
+}
+
+impl DX9OverridableMethod {
+    /// Parses a method name, e.g. `ValidateDevice`.
+    pub fn parse(text: &str) -> Option<Self> {
+        Some(match text.trim() {
+            "TestCooperativeLevel" => Self::TestCooperativeLevel,
+            "EvictManagedResources" => Self::EvictManagedResources,
+            "SetCursorProperties" => Self::SetCursorProperties,
+            "SetDialogBoxMode" => Self::SetDialogBoxMode,
+            "ValidateDevice" => Self::ValidateDevice,
+            "CheckDeviceState" => Self::CheckDeviceState,
+            "SetGPUThreadPriority" => Self::SetGPUThreadPriority,
+            "SetMaximumFrameLatency" => Self::SetMaximumFrameLatency,
+            "WaitForVBlank" => Self::WaitForVBlank,
+            _ => return None,
+        })
+    }
+}
+
+/// Result forced for a method, given in the configuration as `<method>=<result>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DX9HResultOverride {
+    pub method: DX9OverridableMethod,
+    pub result: HRESULT,
+}
+
+/// Formats the override as accepted by [`DX9HResultOverride::parse`], with the result in hexadecimal.
+impl Display for DX9HResultOverride {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}={:#010X}", self.method, self.result.0)
+    }
+}
+
+impl DX9HResultOverride {
+    /// Parses an override in the form `<method>=<result>`, e.g. `ValidateDevice=D3D_OK` or `SetDialogBoxMode=0`.
+    ///
+    /// See [`parse_hresult`] for the accepted results.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (method, result) = text.trim().split_once('=')?;
+        Some(Self {
+            method: DX9OverridableMethod::parse(method)?,
+            result: parse_hresult(result)?,
+        })
+    }
+}
+
+/// Joins list entries with commas, as read by the `var_*` list functions.
+fn join<T: Display>(entries: &[T]) -> String {
+    entries.iter().map(ToString::to_string).collect::<Vec<_>>().join(",")
+}
+
+/// Parses a comma-separated list written by [`join`], ignoring entries that cannot be parsed.
+fn parse_list<T>(text: &str, parse: fn(&str) -> Option<T>) -> Vec<T> {
+    text.split(',').filter(|entry| !entry.trim().is_empty()).filter_map(parse).collect()
+}
+
+/// Reads a boolean flag from a variable, where `1` means enabled.
+fn var_bool(var: impl Fn(&str) -> Option<String>, name: &str) -> Option<bool> {
+    var(name).map(|value| value == "1")
+}
+
+/// Reads an unsigned integer from a variable, ignoring values that cannot be parsed.
+fn var_u32(var: impl Fn(&str) -> Option<String>, name: &str) -> Option<u32> {
+    var(name).and_then(|value| value.trim().parse().ok())
+}
+
+/// Reads a floating-point number from a variable, ignoring values that cannot be parsed.
+fn var_f32(var: impl Fn(&str) -> Option<String>, name: &str) -> Option<f32> {
+    var(name).and_then(|value| value.trim().parse().ok())
+}
+
+/// Reads a handle value from a variable, in decimal or `0x`-prefixed hexadecimal.
+fn var_handle(var: impl Fn(&str) -> Option<String>, name: &str) -> Option<isize> {
+    let value = var(name)?;
+    let value = value.trim();
+    match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(hex) => isize::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+/// Reads a comma-separated list of display modes from a variable, ignoring entries that cannot be parsed.
+fn var_display_modes(var: impl Fn(&str) -> Option<String>, name: &str) -> Option<Vec<DX9DisplayModeSpec>> {
+    var(name).map(|value| parse_list(&value, DX9DisplayModeSpec::parse))
+}
+
+/// Reads a comma-separated list of multisample overrides from a variable, ignoring entries that cannot be parsed.
+fn var_multisample_overrides(var: impl Fn(&str) -> Option<String>, name: &str) -> Option<Vec<DX9MultiSampleOverride>> {
+    var(name).map(|value| parse_list(&value, DX9MultiSampleOverride::parse))
+}
+
+/// Reads a comma-separated list of HRESULT overrides from a variable, ignoring entries that cannot be parsed.
+fn var_hresult_overrides(var: impl Fn(&str) -> Option<String>, name: &str) -> Option<Vec<DX9HResultOverride>> {
+    var(name).map(|value| parse_list(&value, DX9HResultOverride::parse))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dx9::com::{D3D_OK, D3DERR_DEVICELOST, D3DERR_INVALIDCALL};
+    use windows::Win32::Foundation::{E_FAIL, S_FALSE};
+
+    /// Checks that `values` are written by [`join`] as `text`, and parsed back by [`parse_list`].
+    fn assert_round_trip<T: Display + PartialEq + fmt::Debug>(values: &[T], text: &str, parse: fn(&str) -> Option<T>) {
+        assert_eq!(join(values), text);
+        assert_eq!(parse_list(text, parse), values);
+    }
+
+    #[test]
+    fn display_mode_round_trips() {
+        let modes = [
+            DX9DisplayModeSpec {
+                width: 2560,
+                height: 1080,
+                refresh_rate: Some(144),
+            },
+            DX9DisplayModeSpec {
+                width: 640,
+                height: 480,
+                refresh_rate: None,
+            },
+        ];
+        assert_round_trip(&modes, "2560x1080@144,640x480", DX9DisplayModeSpec::parse);
+        assert_eq!(DX9DisplayModeSpec::parse(" 640 X 480 @ 60 "), Some(DX9DisplayModeSpec { refresh_rate: Some(60), ..modes[1] }));
+        assert_eq!(DX9DisplayModeSpec::parse("640x"), None);
+        assert_eq!(DX9DisplayModeSpec::parse("640x480@"), None);
+    }
+
+    #[test]
+    fn multisample_override_round_trips() {
+        let overrides = [
+            DX9MultiSampleOverride {
+                format: Some(D3DFMT_X8R8G8B8),
+                multisample_type: D3DMULTISAMPLE_8_SAMPLES,
+                quality_levels: None,
+            },
+            DX9MultiSampleOverride {
+                format: None,
+                multisample_type: D3DMULTISAMPLE_4_SAMPLES,
+                quality_levels: Some(2),
+            },
+        ];
+        assert_round_trip(&overrides, "22:8=off,*:4=2", DX9MultiSampleOverride::parse);
+        assert_eq!(DX9MultiSampleOverride::parse("*:4=0"), None);
+        assert_eq!(DX9MultiSampleOverride::parse("*:4"), None);
+    }
+
+    #[test]
+    fn hresult_override_round_trips() {
+        let overrides = [
+            DX9HResultOverride {
+                method: DX9OverridableMethod::ValidateDevice,
+                result: D3D_OK,
+            },
+            DX9HResultOverride {
+                method: DX9OverridableMethod::SetDialogBoxMode,
+                result: S_FALSE,
+            },
+            DX9HResultOverride {
+                method: DX9OverridableMethod::TestCooperativeLevel,
+                result: D3DERR_DEVICELOST,
+            },
+            DX9HResultOverride {
+                method: DX9OverridableMethod::EvictManagedResources,
+                result: E_FAIL,
+            },
+        ];
+        // Negative results are written as their unsigned bits
+        assert_round_trip(
+            &overrides,
+            "ValidateDevice=0x00000000,SetDialogBoxMode=0x00000001,TestCooperativeLevel=0x88760868,EvictManagedResources=0x80004005",
+            DX9HResultOverride::parse,
+        );
+        assert_eq!(
+            DX9HResultOverride::parse("CheckDeviceState=D3DERR_INVALIDCALL"),
+            Some(DX9HResultOverride {
+                method: DX9OverridableMethod::CheckDeviceState,
+                result: D3DERR_INVALIDCALL,
+            })
+        );
+        assert_eq!(DX9HResultOverride::parse("Present=0"), None);
+    }
+
+    #[test]
+    fn parse_list_skips_invalid_entries() {
+        assert_eq!(parse_list(" 800x600 ,, bad,1024x768@75,", DX9DisplayModeSpec::parse).len(), 2);
+        assert_eq!(parse_list("", DX9DisplayModeSpec::parse), []);
+    }
+
+    /// Parses the `NAME=value` lines written by [`DX9ProxyConfig::format_env_file`], skipping comments.
+    fn parse_env_file(text: &str) -> Vec<(String, String)> {
+        text.lines()
+            .filter(|line| !line.starts_with('#'))
+            .filter_map(|line| line.split_once('='))
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn exported_config_loads_identically() {
+        let config = DX9ProxyConfig {
+            disable_npatch: true,
+            proxy_queries: false,
+            add_display_modes: vec![DX9DisplayModeSpec {
+                width: 2560,
+                height: 1440,
+                refresh_rate: Some(144),
+            }],
+            max_frame_latency: Some(1),
+            max_tracked_objects: 5000,
+            tracker_report_file: Some(PathBuf::from(r"C:\Games\dxproxy tracker.txt")),
+            force_srgb_write: Some(false),
+            force_srgb_texture: Some(true),
+            focus_window_override: Some(0x1234),
+            device_window_override: Some(-1),
+            reset_stats_key: Some(0x7B),
+            watermark: Some("dxproxy = on".to_string()),
+            multisample_overrides: parse_list("22:8=off,*:4=2", DX9MultiSampleOverride::parse),
+            back_buffer_format_override: Some(D3DFMT_A2R10G10B10),
+            disable_driver_management: Some(false),
+            hresult_overrides: parse_list("ValidateDevice=0x00000000,TestCooperativeLevel=0x88760868", DX9HResultOverride::parse),
+            force_z_enable: Some(true),
+            force_color_write_enable: Some(0x7),
+            frame_pacing_max_sleep_ms: 2,
+            cursor_scale: Some(1.75),
+            ..Default::default()
+        };
+        assert_ne!(config, DX9ProxyConfig::default());
+
+        let vars = parse_env_file(&config.format_env_file());
+        let loaded = DX9ProxyConfig::from_vars(|name| vars.iter().find(|(var, _)| var == name).map(|(_, value)| value.clone()));
+        assert_eq!(loaded, config);
+
+        let defaults = parse_env_file(&DX9ProxyConfig::default().format_env_file());
+        let loaded = DX9ProxyConfig::from_vars(|name| defaults.iter().find(|(var, _)| var == name).map(|(_, value)| value.clone()));
+        assert_eq!(loaded, DX9ProxyConfig::default());
+    }
+}
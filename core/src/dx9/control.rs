@@ -0,0 +1,126 @@
+//! Named pipe server for controlling the proxy from another process.
+//!
+//! When [`DX9ProxyConfig::control_pipe`] is enabled, a background thread serves the pipe
+//! `\\.\pipe\dxproxy-<pid>`, where `<pid>` is the process ID of the application. Clients send
+//! one command per line and receive one response line per command, starting with `ok` or `error`.
+//! One client is served at a time.
+//!
+//! Commands:
+//! - `help`: Lists the commands.
+//! - `ping`: Responds with `ok pong`, to check that the proxy is running.
+//! - `devices`: Responds with the number of live devices.
+//! - `stats`: Responds with the call statistics of each device (requires `DXPROXY_CALL_STATS=1`).
+//! - `reset-stats`: Responds with the call statistics of each device and resets them, like the
+//!   `DXPROXY_RESET_STATS_KEY` hotkey.
+//! - `locked`: Responds with the number of locked resources of each device (requires `DXPROXY_CHECK_LOCK_LEAKS=1`).
+//!
+//! Responses covering several devices list them separated by `; `, in creation order.
+
+use super::{DX9ProxyConfig, com::DX9ProxyDeviceContext};
+use windows::{
+    Win32::{Foundation::*, Storage::FileSystem::*, System::Pipes::*},
+    core::*,
+};
+
+/// Commands understood by the control pipe, listed by `help`.
+const COMMANDS: &str = "help ping devices stats reset-stats locked";
+
+/// Size of the pipe buffers and of a single read in bytes.
+const PIPE_BUFFER_SIZE: u32 = 4096;
+
+/// Starts the control pipe server on a background thread, if enabled by the configuration.
+///
+/// See [`DX9ProxyConfig::control_pipe`].
+pub fn start_control_pipe(config: &DX9ProxyConfig) {
+    if !config.control_pipe {
+        return;
+    }
+
+    let name = format!(r"\\.\pipe\dxproxy-{}", std::process::id());
+    let _ = std::thread::Builder::new().name("dxproxy-control-pipe".to_string()).spawn(move || serve(&name)).inspect_err(|_err| {
+        #[cfg(feature = "tracing")]
+        tracing::error!("Failed to start control pipe thread: {_err}");
+    });
+}
+
+/// Serves clients of the pipe `name` one after another, forever.
+fn serve(name: &str) {
+    let pipe = unsafe {
+        CreateNamedPipeW(
+            &HSTRING::from(name),
+            PIPE_ACCESS_DUPLEX,
+            PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+            1,
+            PIPE_BUFFER_SIZE,
+            PIPE_BUFFER_SIZE,
+            0,
+            None,
+        )
+    };
+    if pipe.is_invalid() {
+        #[cfg(feature = "tracing")]
+        tracing::error!("Failed to create control pipe {name}: {}", Error::from_win32());
+        return;
+    }
+
+    #[cfg(feature = "tracing")]
+    tracing::info!("Control pipe listening on {name}");
+
+    loop {
+        // Fails with ERROR_PIPE_CONNECTED if a client connected between the calls, which is fine
+        if let Err(err) = unsafe { ConnectNamedPipe(pipe, None) }
+            && err.code() != ERROR_PIPE_CONNECTED.to_hresult()
+        {
+            #[cfg(feature = "tracing")]
+            tracing::error!("Failed to accept control pipe client, stopping: {err}");
+            return;
+        }
+
+        serve_client(pipe);
+        let _ = unsafe { DisconnectNamedPipe(pipe) };
+    }
+}
+
+/// Handles the commands of a connected client until it disconnects.
+fn serve_client(pipe: HANDLE) {
+    let mut pending = Vec::new();
+    let mut buffer = [0u8; PIPE_BUFFER_SIZE as usize];
+    loop {
+        let mut read = 0;
+        if unsafe { ReadFile(pipe, Some(&mut buffer), Some(&mut read), None) }.is_err() || read == 0 {
+            return;
+        }
+        pending.extend_from_slice(&buffer[..read as usize]);
+
+        while let Some(end) = pending.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            let command = String::from_utf8_lossy(&line);
+            let response = execute(command.trim());
+            if unsafe { WriteFile(pipe, Some(format!("{response}\n").as_bytes()), None, None) }.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Executes a command, returning the response line without the trailing newline.
+fn execute(command: &str) -> String {
+    #[cfg(feature = "tracing")]
+    tracing::info!("Control pipe command: {command:?}");
+
+    let contexts = DX9ProxyDeviceContext::live_contexts();
+    let per_device = |f: &dyn Fn(&DX9ProxyDeviceContext) -> Option<String>, disabled: &str| {
+        let results = contexts.iter().map(|context| f(context).unwrap_or_else(|| disabled.to_string())).collect::<Vec<_>>();
+        format!("ok {}", results.join("; "))
+    };
+
+    match command {
+        "help" => format!("ok {COMMANDS}"),
+        "ping" => "ok pong".to_string(),
+        "devices" => format!("ok {}", contexts.len()),
+        "stats" => per_device(&|context| context.get_call_stats().map(|stats| stats.to_string()), "disabled"),
+        "reset-stats" => per_device(&|context| context.reset_stats().map(|stats| stats.to_string()), "disabled"),
+        "locked" => per_device(&|context| context.get_locked_resource_count().map(|count| count.to_string()), "disabled"),
+        _ => format!("error unknown command {command:?}, expected one of: {COMMANDS}"),
+    }
+}
@@ -6,17 +6,26 @@
 //! - Initializing logging and tracing
 //! - Intercepting Direct3DCreate9 and Direct3DCreate9Ex calls
 //! - Creating proxy wrappers around the original DirectX objects
+//! - Forwarding auxiliary exports (`D3DPERF_*`, `DebugSetMute`, `Direct3DShaderValidatorCreate9`) to the original DLL
+//!
+//! When the `passthrough` feature is enabled, the original DirectX objects are returned
+//! without any proxy wrapping, while DLL loading and logging stay the same.
+//! This is useful for checking whether an issue is caused by the proxy itself.
 //!
 //! Note that the actual DLL exports are in the crates under the entrypoints directory,
 //! which are built as dynamic libraries. This module provides the implementation
 //! for the proxy DLL that intercepts these calls and provides enhanced functionality.
 
+#[cfg(not(feature = "passthrough"))]
 use super::com::*;
 use std::{
     env::var,
+    ffi::c_void,
     fs::File,
     mem::transmute,
-    sync::{Mutex, Once},
+    sync::{Mutex, Once, OnceLock},
+    thread::sleep,
+    time::Duration,
 };
 use windows::{
     Win32::{
@@ -27,8 +36,15 @@ use windows::{
     core::*,
 };
 
-/// One-time initialization guard for DLL setup.
-static INIT: Once = Once::new();
+/// Whether the DLL is initialized, i.e. [`init`] ran and [`shutdown`] has not been called since.
+static INITIALIZED: Mutex<bool> = Mutex::new(false);
+
+/// One-time initialization guard for the parts of the DLL setup that are not undone by [`shutdown`].
+static INIT_ONCE: Once = Once::new();
+
+/// Time [`shutdown`] waits for asynchronously written log lines to be flushed.
+#[cfg(any(feature = "tracing", feature = "tracing-instrument"))]
+const SHUTDOWN_FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
 
 /// Handle to the original system d3d9.dll.
 static mut ORIGINAL_D3D9: HMODULE = HMODULE(std::ptr::null_mut());
@@ -39,72 +55,315 @@ static mut ORIGINAL_DIRECT3DCREATE9: Option<extern "system" fn(u32) -> Option<ID
 /// Function pointer to the original Direct3DCreate9Ex function.
 static mut ORIGINAL_DIRECT3DCREATE9EX: Option<extern "system" fn(u32, *mut Option<IDirect3D9Ex>) -> HRESULT> = None;
 
+/// Background writer of the log file, if asynchronous logging is enabled with `DXPROXY_LOG_ASYNC=1`.
+///
+/// Kept here so that the enqueued log lines can be flushed with [`flush_logs`], as statics are never dropped.
+#[cfg(any(feature = "tracing", feature = "tracing-instrument"))]
+static ASYNC_LOG_WRITER: OnceLock<crate::NonBlockingWriter> = OnceLock::new();
+
+/// Ring of the most recent log lines and the file it is dumped to, if enabled with `DXPROXY_LOG_RING_SIZE=<lines>`.
+#[cfg(any(feature = "tracing", feature = "tracing-instrument"))]
+static LOG_RING: OnceLock<(crate::LogRing, String)> = OnceLock::new();
+
 #[cfg(any(feature = "tracing", feature = "tracing-instrument"))]
 fn init_tracing() {
     use tracing_subscriber::layer::SubscriberExt;
     use tracing_subscriber::util::SubscriberInitExt;
+    use tracing_subscriber::{EnvFilter, Layer, Registry};
 
-    let do_alloc_console = var("DXPROXY_ALLOC_CONSOLE").map_or(true, |v| v == "1");
+    // Under Wine, the allocated console misbehaves, while standard output already reaches the terminal
+    let wine_version = wine_version();
+    let do_alloc_console = var("DXPROXY_ALLOC_CONSOLE").map_or(wine_version.is_none(), |v| v == "1");
     if do_alloc_console {
         let _ = unsafe { AllocConsole() }.inspect_err(|err| {
             eprintln!("Failed to allocate console: {err}");
         });
     }
 
-    let log_filename = var("DXPROXY_LOG_FILE").unwrap_or_else(|_| "dxproxy.log".to_string());
+    // Optional session name to label logs of this run, also available as `{session}` in the log filename
+    let session = var("DXPROXY_SESSION").ok().filter(|session| !session.is_empty());
+
+    let default_log_filename = if session.is_some() { "dxproxy-{session}.log" } else { "dxproxy.log" };
+    let log_filename = var("DXPROXY_LOG_FILE")
+        .unwrap_or_else(|_| default_log_filename.to_string())
+        .replace("{session}", session.as_deref().unwrap_or_default());
 
-    // Initialize tracing with console and optional file logging
-    let registry = tracing_subscriber::registry().with(tracing_subscriber::EnvFilter::from_default_env());
+    // Initialize tracing with console and optional file logging, each filtered by `RUST_LOG`
+    let mut layers: Vec<Box<dyn Layer<Registry> + Send + Sync>> = Vec::new();
+
+    // Use ANSI colors only if the console can render them, unless overridden
+    let console_ansi = var("DXPROXY_LOG_ANSI").map_or_else(|_| enable_console_ansi(), |v| v == "1");
 
     // Console layer with formatting
     let console_layer = tracing_subscriber::fmt::layer()
+        .fmt_fields(format_fields())
         .with_target(true)
         .with_thread_ids(true)
         .with_file(true)
         .with_line_number(true)
         .with_thread_names(true)
-        .with_ansi(true);
+        .map_event_format(|format| crate::SessionEventFormat::new(session.clone(), format))
+        .with_ansi(console_ansi)
+        .with_filter(EnvFilter::from_default_env());
+    layers.push(console_layer.boxed());
+
+    // Write the log file on a background thread if requested, so that slow disks do not stall rendering.
+    // Lines that are still queued are lost if the process terminates without calling `flush_logs`.
+    let log_async = var("DXPROXY_LOG_ASYNC").is_ok_and(|v| v == "1");
 
     // Try to create file layer, fall back to console-only if it fails
-    match File::create(&log_filename) {
-        Ok(log_file) => {
-            let file_layer = tracing_subscriber::fmt::layer()
-                .with_target(true)
-                .with_thread_ids(true)
-                .with_file(true)
-                .with_line_number(true)
-                .with_thread_names(true)
-                .with_writer(Mutex::new(log_file))
-                .with_ansi(false);
-
-            registry.with(console_layer).with(file_layer).init();
-
-            tracing::info!("Logging initialized with console and file output: {log_filename}");
+    let file_result = File::create(&log_filename).and_then(|log_file| {
+        let file_layer = tracing_subscriber::fmt::layer()
+            .fmt_fields(format_fields())
+            .with_target(true)
+            .with_thread_ids(true)
+            .with_file(true)
+            .with_line_number(true)
+            .with_thread_names(true)
+            .map_event_format(|format| crate::SessionEventFormat::new(session.clone(), format))
+            .with_ansi(false);
+        if log_async {
+            let writer = crate::NonBlockingWriter::new("dxproxy-log-writer", log_file)?;
+            let _ = ASYNC_LOG_WRITER.set(writer.clone());
+            layers.push(file_layer.with_writer(move || writer.clone()).with_filter(EnvFilter::from_default_env()).boxed());
+        } else {
+            layers.push(file_layer.with_writer(Mutex::new(log_file)).with_filter(EnvFilter::from_default_env()).boxed());
         }
-        Err(err) => {
-            registry.with(console_layer).init();
+        Ok(())
+    });
+
+    // Keep the most recent lines in memory, so that they can be dumped even if file writes were lost
+    let log_ring_size = var("DXPROXY_LOG_RING_SIZE").ok().and_then(|v| v.parse::<usize>().ok()).filter(|&size| size > 0);
+    if let Some(log_ring_size) = log_ring_size {
+        let default_ring_filename = if session.is_some() { "dxproxy-{session}-ring.log" } else { "dxproxy-ring.log" };
+        let ring_filename = var("DXPROXY_LOG_RING_FILE")
+            .unwrap_or_else(|_| default_ring_filename.to_string())
+            .replace("{session}", session.as_deref().unwrap_or_default());
+        let ring = crate::LogRing::new(log_ring_size);
+        let _ = LOG_RING.set((ring.clone(), ring_filename));
+        let ring_layer = tracing_subscriber::fmt::layer()
+            .fmt_fields(format_fields())
+            .with_target(true)
+            .with_thread_ids(true)
+            .with_file(true)
+            .with_line_number(true)
+            .with_thread_names(true)
+            .map_event_format(|format| crate::SessionEventFormat::new(session.clone(), format))
+            .with_ansi(false)
+            .with_writer(move || ring.clone())
+            .with_filter(EnvFilter::from_default_env());
+        layers.push(ring_layer.boxed());
+        install_log_ring_panic_hook();
+    }
+
+    // Optional binary recording of all proxied calls, independent of `RUST_LOG`
+    #[cfg(feature = "tracing-instrument")]
+    let trace_result = var("DXPROXY_TRACE_FILE").ok().map(|trace_filename| {
+        let trace_filename = trace_filename.replace("{session}", session.as_deref().unwrap_or_default());
+        let result = File::create(&trace_filename).map(std::io::BufWriter::new).and_then(crate::trace::CallTraceWriter::new).map(|writer| {
+            let trace_layer = crate::trace::CallTraceLayer::new(writer).with_filter(tracing_subscriber::filter::filter_fn(crate::trace::CallTraceLayer::<File>::is_recorded));
+            layers.push(trace_layer.boxed());
+        });
+        (trace_filename, result)
+    });
+
+    tracing_subscriber::registry().with(layers).init();
+
+    if let Some(wine_version) = &wine_version {
+        tracing::info!("Running under Wine {wine_version}, console allocation is {}", if do_alloc_console { "forced" } else { "skipped" });
+    }
 
-            tracing::warn!("Failed to create log file {log_filename}: {err}, using console-only logging");
+    match file_result {
+        Ok(()) => tracing::info!("Logging initialized with console and {}file output: {log_filename}", if log_async { "asynchronous " } else { "" }),
+        Err(err) => tracing::warn!("Failed to create log file {log_filename}: {err}, using console-only logging"),
+    }
+
+    #[cfg(feature = "tracing-instrument")]
+    match trace_result {
+        Some((trace_filename, Ok(()))) => tracing::info!("Recording call trace to {trace_filename}"),
+        Some((trace_filename, Err(err))) => tracing::warn!("Failed to create call trace file {trace_filename}: {err}"),
+        None => {}
+    }
+}
+
+/// Blocks until all log lines enqueued so far have been written to the log file.
+///
+/// Only has an effect with asynchronous logging (`DXPROXY_LOG_ASYNC=1`), where log lines are
+/// written on a background thread. Since the proxy has no hook on process exit, embedders that
+/// need the last lines should call this before exiting.
+#[cfg(any(feature = "tracing", feature = "tracing-instrument"))]
+pub fn flush_logs() {
+    if let Some(writer) = ASYNC_LOG_WRITER.get() {
+        writer.flush();
+    }
+}
+
+/// Writes the most recent log lines kept in memory to the ring dump file, replacing its contents.
+///
+/// Only has an effect if the log ring is enabled with `DXPROXY_LOG_RING_SIZE=<lines>`. The ring is dumped
+/// automatically on panics, and on [`DX9ProxyConfig::log_ring_dump_key`](super::DX9ProxyConfig::log_ring_dump_key).
+#[cfg(any(feature = "tracing", feature = "tracing-instrument"))]
+pub fn dump_log_ring() {
+    let Some((ring, ring_filename)) = LOG_RING.get() else {
+        return;
+    };
+    match ring.dump(std::path::Path::new(ring_filename)) {
+        Ok(count) => tracing::info!("Dumped the last {count} log lines to {ring_filename}"),
+        Err(err) => tracing::error!("Failed to dump the last log lines to {ring_filename}: {err}"),
+    }
+}
+
+/// Chains a panic hook that dumps the log ring, including the panic message, before the previous hook runs.
+#[cfg(any(feature = "tracing", feature = "tracing-instrument"))]
+fn install_log_ring_panic_hook() {
+    let previous_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if let Some((ring, _)) = LOG_RING.get() {
+            ring.push(format!("Panicked: {info}"));
+        }
+        dump_log_ring();
+        previous_hook(info);
+    }));
+}
+
+/// Shuts down the proxy: reports leaks, resets the global context registry and flushes the logs.
+///
+/// Objects that are still alive at this point are leaked by the application (or by the proxy), and are
+/// logged with their tracked proxies by type. The registry of device contexts is then cleared, so that
+/// the devices are no longer affected by global actions such as hotkeys of other devices.
+///
+/// Embedders that load the proxy as a library should call this before unloading it with `FreeLibrary` or
+/// exiting. It must not be called from `DllMain` or otherwise under the loader lock: with asynchronous
+/// logging, the flush waits for the background log writer thread, which may itself be blocked on the loader
+/// lock. The flush gives up after a short timeout, so log lines that are still queued may then be lost.
+///
+/// Calling this more than once, or before any device was created, does nothing. Creating a Direct3D
+/// object afterwards initializes the proxy again. Logging, the control pipe and the original d3d9.dll
+/// stay set up, as objects created by the application may still use them.
+pub fn shutdown() {
+    let mut initialized = INITIALIZED.lock().unwrap_or_else(|err| err.into_inner());
+    if !*initialized {
+        return;
+    }
+    *initialized = false;
+
+    #[cfg(feature = "tracing")]
+    tracing::info!("Shutting down");
+
+    #[cfg(not(feature = "passthrough"))]
+    {
+        for context in DX9ProxyDeviceContext::live_contexts() {
+            context.report_leaks();
         }
+        DX9ProxyDeviceContext::clear_registry();
+    }
+
+    #[cfg(any(feature = "tracing", feature = "tracing-instrument"))]
+    if let Some(writer) = ASYNC_LOG_WRITER.get()
+        && !writer.flush_timeout(SHUTDOWN_FLUSH_TIMEOUT)
+    {
+        tracing::warn!("Timed out flushing logs at shutdown");
+    }
+}
+
+/// Returns the Wine version if running under Wine (including Proton), detected by the `wine_get_version` export of ntdll.
+#[cfg(any(feature = "tracing", feature = "tracing-instrument"))]
+fn wine_version() -> Option<String> {
+    let ntdll = unsafe { GetModuleHandleW(w!("ntdll.dll")) }.ok()?;
+    let wine_get_version = unsafe { GetProcAddress(ntdll, s!("wine_get_version")) }?;
+    let wine_get_version: extern "C" fn() -> *const std::ffi::c_char = unsafe { transmute(wine_get_version) };
+    let version = wine_get_version();
+    if version.is_null() {
+        return Some("(unknown version)".to_string());
+    }
+    Some(unsafe { std::ffi::CStr::from_ptr(version) }.to_string_lossy().into_owned())
+}
+
+/// Enables ANSI escape sequence processing on the standard output console.
+///
+/// # Returns
+/// * `true` - If standard output is a console that renders ANSI escape sequences
+/// * `false` - If standard output is redirected to a file or pipe, or the console does not support virtual terminal processing
+#[cfg(any(feature = "tracing", feature = "tracing-instrument"))]
+fn enable_console_ansi() -> bool {
+    let Ok(handle) = (unsafe { GetStdHandle(STD_OUTPUT_HANDLE) }) else {
+        return false;
+    };
+
+    // GetConsoleMode fails if the handle is not a console
+    let mut mode = CONSOLE_MODE::default();
+    if unsafe { GetConsoleMode(handle, &mut mode) }.is_err() {
+        return false;
+    }
+    if mode.contains(ENABLE_VIRTUAL_TERMINAL_PROCESSING) {
+        return true;
     }
+    unsafe { SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) }.is_ok()
+}
+
+/// Creates the field formatter used by the log layers.
+///
+/// Fields are formatted like the default formatter, except that `error` fields
+/// (such as the ones recorded by `#[instrument(err)]`) are suffixed with the symbolic
+/// HRESULT name, e.g. `error=0x8876086C [D3DERR_INVALIDCALL]`.
+#[cfg(any(feature = "tracing", feature = "tracing-instrument"))]
+fn format_fields() -> impl for<'writer> tracing_subscriber::fmt::FormatFields<'writer> + 'static {
+    use tracing_subscriber::field::MakeExt;
+
+    tracing_subscriber::fmt::format::debug_fn(|writer, field, value| match field.name() {
+        "message" => write!(writer, "{value:?}"),
+        "error" => {
+            let text = format!("{value:?}");
+            match hresult_from_error_text(&text) {
+                Some(hr) => write!(writer, "error={text} [{}]", super::com::hresult_name(hr)),
+                None => write!(writer, "error={text}"),
+            }
+        }
+        name => write!(writer, "{name}={value:?}"),
+    })
+    .delimited(" ")
+}
+
+/// Extracts the HRESULT from a formatted `windows::core::Error`, which always contains the code as `0xXXXXXXXX`.
+#[cfg(any(feature = "tracing", feature = "tracing-instrument"))]
+fn hresult_from_error_text(text: &str) -> Option<HRESULT> {
+    let start = text.find("0x")? + 2;
+    let digits = text.get(start..start + 8)?;
+    u32::from_str_radix(digits, 16).ok().map(|code| HRESULT(code as i32))
 }
 
 /// Initializes the proxy DLL by setting up logging and loading the original d3d9.dll.
 ///
 /// This function:
-/// - Allocates a console for debug output
+/// - Allocates a console for debug output, except under Wine
 /// - Sets up tracing with both console and file logging
 /// - Loads the original system d3d9.dll from System32
 /// - Resolves Direct3DCreate9 and Direct3DCreate9Ex function pointers
+/// - Starts the control pipe server, if enabled
+///
+/// Logging and the control pipe are set up once per process, and the original d3d9.dll is only loaded once,
+/// as they cannot be torn down by [`shutdown`] while objects created by the application may still use them.
 fn init() {
-    #[cfg(any(feature = "tracing", feature = "tracing-instrument"))]
-    init_tracing();
+    // Not under the lock of INITIALIZED, so that a concurrent shutdown does not wait for the one-time setup,
+    // which loads a DLL and may log. Concurrent callers of init wait for it on INIT_ONCE instead.
+    INIT_ONCE.call_once(|| {
+        #[cfg(any(feature = "tracing", feature = "tracing-instrument"))]
+        init_tracing();
+
+        let config = super::DX9ProxyConfig::from_env();
+        super::control::start_control_pipe(&config);
+        load_original_dll(&config);
+    });
+
+    *INITIALIZED.lock().unwrap_or_else(|err| err.into_inner()) = true;
+}
 
-    // Load the original d3d9.dll
+/// Loads the original d3d9.dll from System32, and resolves the Direct3DCreate9 and Direct3DCreate9Ex function pointers.
+fn load_original_dll(config: &super::DX9ProxyConfig) {
     #[allow(clippy::missing_transmute_annotations)]
     unsafe {
         let windows_dir = var("SystemRoot").map_or_else(|_| "C:\\Windows".to_string(), |value| value.trim_end_matches('\\').to_string());
-        let original_dll = LoadLibraryW(&HSTRING::from(format!("{windows_dir}\\System32\\d3d9.dll")));
+        let original_dll = load_library_with_retries(&HSTRING::from(format!("{windows_dir}\\System32\\d3d9.dll")), config.load_library_retries);
         match original_dll {
             Ok(dll_handle) => {
                 #[cfg(feature = "tracing")]
@@ -122,6 +381,28 @@ fn init() {
     }
 }
 
+/// Loads a DLL, retrying up to `retries` times with a growing delay if loading fails.
+///
+/// Each failed attempt is logged with the OS error, and the error of the last attempt is returned.
+fn load_library_with_retries(path: &HSTRING, retries: u32) -> Result<HMODULE> {
+    /// Delay before the first retry, multiplied by the number of the retry for later ones.
+    const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+    let mut attempt = 0;
+    loop {
+        match unsafe { LoadLibraryW(path) } {
+            Ok(module) => return Ok(module),
+            Err(_err) if attempt < retries => {
+                attempt += 1;
+                #[cfg(feature = "tracing")]
+                tracing::warn!("Failed to load {path} (attempt {attempt} of {}), retrying: {_err}", retries + 1);
+                sleep(RETRY_DELAY * attempt);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
 /// Creates a Direct3D9 object with proxy wrapping.
 ///
 /// This function intercepts calls to Direct3DCreate9 and creates a proxy wrapper
@@ -139,7 +420,7 @@ fn init() {
 /// from applications as it maintains the same contract as the original Direct3DCreate9.
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn Direct3DCreate9(sdkversion: u32) -> Option<IDirect3D9> {
-    INIT.call_once(init);
+    init();
 
     #[cfg(feature = "tracing")]
     tracing::info!("Direct3DCreate9 called with SDK version: {sdkversion}");
@@ -150,15 +431,26 @@ pub unsafe extern "system" fn Direct3DCreate9(sdkversion: u32) -> Option<IDirect
 
         let d3d9 = create_fn(sdkversion);
         if let Some(d3d9) = d3d9 {
-            #[cfg(feature = "tracing")]
-            tracing::info!("Successfully created IDirect3D9, creating proxy wrapper");
+            #[cfg(feature = "passthrough")]
+            {
+                #[cfg(feature = "tracing")]
+                tracing::info!("Successfully created IDirect3D9, returning it without proxy wrapping (passthrough)");
+
+                return Some(d3d9);
+            }
 
-            let proxy = ProxyDirect3D9::new_or_upgrade(d3d9);
+            #[cfg(not(feature = "passthrough"))]
+            {
+                #[cfg(feature = "tracing")]
+                tracing::info!("Successfully created IDirect3D9, creating proxy wrapper");
 
-            #[cfg(feature = "tracing")]
-            tracing::debug!("ProxyDirect3D9 created: {proxy:?}");
+                let proxy = ProxyDirect3D9::new_or_upgrade(d3d9);
 
-            return Some(proxy);
+                #[cfg(feature = "tracing")]
+                tracing::debug!("ProxyDirect3D9 created: {proxy:?}");
+
+                return Some(proxy);
+            }
         } else {
             #[cfg(feature = "tracing")]
             tracing::error!("Original Direct3DCreate9 returned null for SDK version {sdkversion}");
@@ -193,7 +485,7 @@ pub unsafe extern "system" fn Direct3DCreate9(sdkversion: u32) -> Option<IDirect
 /// that `ppd3d` points to valid memory that can hold an `Option<IDirect3D9Ex>`.
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn Direct3DCreate9Ex(sdkversion: u32, ppd3d: *mut Option<IDirect3D9Ex>) -> HRESULT {
-    INIT.call_once(init);
+    init();
 
     #[cfg(feature = "tracing")]
     tracing::info!("Direct3DCreate9Ex called with SDK version: {sdkversion}");
@@ -215,15 +507,26 @@ pub unsafe extern "system" fn Direct3DCreate9Ex(sdkversion: u32, ppd3d: *mut Opt
         match result {
             Ok(_) => {
                 if let Some(d3d9_ex) = d3d9_ex {
-                    #[cfg(feature = "tracing")]
-                    tracing::info!("Successfully created IDirect3D9Ex, creating proxy wrapper");
+                    #[cfg(feature = "passthrough")]
+                    {
+                        #[cfg(feature = "tracing")]
+                        tracing::info!("Successfully created IDirect3D9Ex, returning it without proxy wrapping (passthrough)");
 
-                    let wrapped_ex = ProxyDirect3D9Ex::new(d3d9_ex);
+                        unsafe { ppd3d.write(Some(d3d9_ex)) };
+                    }
 
-                    #[cfg(feature = "tracing")]
-                    tracing::debug!("ProxyDirect3D9Ex created: {wrapped_ex:?}");
+                    #[cfg(not(feature = "passthrough"))]
+                    {
+                        #[cfg(feature = "tracing")]
+                        tracing::info!("Successfully created IDirect3D9Ex, creating proxy wrapper");
+
+                        let wrapped_ex = ProxyDirect3D9Ex::new(d3d9_ex);
 
-                    unsafe { ppd3d.write(Some(wrapped_ex.into())) };
+                        #[cfg(feature = "tracing")]
+                        tracing::debug!("ProxyDirect3D9Ex created: {wrapped_ex:?}");
+
+                        unsafe { ppd3d.write(Some(wrapped_ex.into())) };
+                    }
 
                     #[cfg(feature = "tracing")]
                     tracing::info!("Direct3DCreate9Ex completed successfully");
@@ -236,7 +539,7 @@ pub unsafe extern "system" fn Direct3DCreate9Ex(sdkversion: u32, ppd3d: *mut Opt
             }
             Err(_err) => {
                 #[cfg(feature = "tracing")]
-                tracing::error!("Original Direct3DCreate9Ex failed with {_err} for SDK version {sdkversion}");
+                tracing::error!("Original Direct3DCreate9Ex failed with {} ({_err}) for SDK version {sdkversion}", super::com::hresult_name(_err.code()));
             }
         }
     } else {
@@ -249,3 +552,85 @@ pub unsafe extern "system" fn Direct3DCreate9Ex(sdkversion: u32, ppd3d: *mut Opt
 
     E_NOTIMPL
 }
+
+/// Defines an export that is forwarded as-is to the original d3d9.dll.
+///
+/// The original function is resolved on first use. If it cannot be resolved,
+/// the specified fallback value is returned instead.
+macro_rules! forward_export {
+    ($(#[$attr:meta])* fn $name:ident($($arg:ident: $ty:ty),*) $(-> $ret:ty)?, $fallback:expr) => {
+        $(#[$attr])*
+        ///
+        /// # Safety
+        /// This function maintains the same safety contract as the original function in the system d3d9.dll.
+        #[allow(non_snake_case)]
+        pub unsafe extern "system" fn $name($($arg: $ty),*) $(-> $ret)? {
+            static ORIGINAL: OnceLock<Option<unsafe extern "system" fn($($ty),*) $(-> $ret)?>> = OnceLock::new();
+
+            let original = ORIGINAL.get_or_init(|| {
+                init();
+
+                #[allow(clippy::missing_transmute_annotations)]
+                let original: Option<unsafe extern "system" fn($($ty),*) $(-> $ret)?> =
+                    unsafe { transmute(GetProcAddress(ORIGINAL_D3D9, PCSTR(concat!(stringify!($name), "\0").as_ptr()))) };
+                if original.is_none() {
+                    #[cfg(feature = "tracing")]
+                    tracing::error!("Original {} function not loaded from system d3d9.dll", stringify!($name));
+                }
+                original
+            });
+
+            match original {
+                Some(original) => unsafe { original($($arg),*) },
+                None => $fallback,
+            }
+        }
+    };
+}
+
+forward_export! {
+    /// Forwards `D3DPERF_BeginEvent`, which marks the beginning of a user-defined event for PIX.
+    fn D3DPERF_BeginEvent(col: u32, wszname: PCWSTR) -> i32, -1
+}
+
+forward_export! {
+    /// Forwards `D3DPERF_EndEvent`, which marks the end of a user-defined event for PIX.
+    fn D3DPERF_EndEvent() -> i32, -1
+}
+
+forward_export! {
+    /// Forwards `D3DPERF_SetMarker`, which marks an instantaneous event for PIX.
+    fn D3DPERF_SetMarker(col: u32, wszname: PCWSTR), ()
+}
+
+forward_export! {
+    /// Forwards `D3DPERF_SetRegion`, which marks a region of the frame for PIX.
+    fn D3DPERF_SetRegion(col: u32, wszname: PCWSTR), ()
+}
+
+forward_export! {
+    /// Forwards `D3DPERF_QueryRepeatFrame`, which reports whether PIX requested the frame to be repeated.
+    fn D3DPERF_QueryRepeatFrame() -> BOOL, FALSE
+}
+
+forward_export! {
+    /// Forwards `D3DPERF_SetOptions`, which can be used to opt out of PIX profiling.
+    fn D3DPERF_SetOptions(dwoptions: u32), ()
+}
+
+forward_export! {
+    /// Forwards `D3DPERF_GetStatus`, which reports whether the application is running under PIX.
+    fn D3DPERF_GetStatus() -> u32, 0
+}
+
+forward_export! {
+    /// Forwards `DebugSetMute`, which silences debug runtime output.
+    fn DebugSetMute(), ()
+}
+
+forward_export! {
+    /// Forwards `Direct3DShaderValidatorCreate9`, which creates an internal `IDirect3DShaderValidator9` object.
+    ///
+    /// The returned object is not proxied.
+    fn Direct3DShaderValidatorCreate9() -> *mut c_void, std::ptr::null_mut()
+}
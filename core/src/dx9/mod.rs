@@ -4,10 +4,18 @@
 //! - COM object proxies and wrappers
 //! - Configuration management
 //! - DLL export functions for Direct3D creation
+//! - Back buffer readback helpers
+//! - Helpers for overriding presentation parameters
+//! - Named pipe control server
+//! - Helpers for waiting on injected queries
 
+pub mod capture;
 pub mod com;
 pub mod config;
+pub mod control;
 pub mod dll;
+pub mod present;
+pub mod query;
 
 pub use config::*;
 pub use dll::*;
@@ -0,0 +1,211 @@
+//! Helpers for presentation: overriding the presentation parameters passed by the application,
+//! and detecting reentrant `Present` calls.
+//!
+//! Features that override presentation parameters at device creation and reset (e.g. the device
+//! window or back buffer format overrides) work on an owned copy, so that the application's
+//! structure is not modified. [`with_overridden_params`] centralizes the copy and the handling of
+//! null pointers, which the runtime rejects with its own error.
+
+use std::cell::Cell;
+use windows::{Win32::Graphics::Direct3D9::*, core::Result};
+
+thread_local! {
+    /// Number of `Present` calls of proxies currently running on this thread.
+    static PRESENT_DEPTH: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Returns the number of presentation parameters passed to device creation or reset with `behavior_flags`.
+///
+/// Adapter group devices (`D3DCREATE_ADAPTERGROUP_DEVICE`) take an array with one entry per adapter in the group,
+/// whose size is read from the caps of the master adapter with `get_caps`.
+///
+/// # Returns
+/// The number of entries, or `None` if the caps cannot be read.
+pub fn params_count(behavior_flags: u32, get_caps: impl FnOnce(&mut D3DCAPS9) -> Result<()>) -> Option<usize> {
+    if behavior_flags & D3DCREATE_ADAPTERGROUP_DEVICE as u32 == 0 {
+        return Some(1);
+    }
+
+    let mut caps = D3DCAPS9::default();
+    match get_caps(&mut caps) {
+        Ok(()) => Some(caps.NumberOfAdaptersInGroup.max(1) as usize),
+        Err(_err) => {
+            #[cfg(feature = "tracing")]
+            tracing::warn!("Failed to get the number of adapters in the group, not overriding presentation parameters: {_err}");
+            None
+        }
+    }
+}
+
+/// Returns a copy of the presentation parameters `pp` with `f` applied, or `None` if `pp` is null or `f` changes nothing.
+///
+/// The whole array of `count` entries is copied (see [`params_count`]), but `f` is only applied to the first
+/// entry, i.e. that of the master adapter of an adapter group. If `count` is `None`, nothing is overridden.
+///
+/// Callers pass a pointer to the returned copy to the target, or the application's `pp` unchanged if `None`,
+/// see [`params_ptr`].
+///
+/// # Safety
+/// `pp` must be null or point to `count` valid presentation parameters.
+pub unsafe fn with_overridden_params(pp: *const D3DPRESENT_PARAMETERS, count: Option<usize>, f: impl FnOnce(&mut D3DPRESENT_PARAMETERS)) -> Option<Vec<D3DPRESENT_PARAMETERS>> {
+    let count = count?;
+    if pp.is_null() || count == 0 {
+        return None;
+    }
+
+    let original = unsafe { std::slice::from_raw_parts(pp, count) };
+    let mut params = original.to_vec();
+    f(&mut params[0]);
+    (params[0] != original[0]).then_some(params)
+}
+
+/// Returns a pointer to the overridden copy of the presentation parameters, or `pp` if there is none.
+pub fn params_ptr(params: &mut Option<Vec<D3DPRESENT_PARAMETERS>>, pp: *mut D3DPRESENT_PARAMETERS) -> *mut D3DPRESENT_PARAMETERS {
+    params.as_mut().map_or(pp, |params| params.as_mut_ptr())
+}
+
+/// Copies the fields that the runtime fills in from the overridden copy back into the application's `pp`.
+///
+/// The runtime replaces a zero back buffer size and count with the actual ones, which applications
+/// may read back after creating or resetting the device. Overridden fields are not copied.
+///
+/// # Safety
+/// `pp` must be null or point to `params.len()` valid, writable presentation parameters.
+pub unsafe fn write_back_params(pp: *mut D3DPRESENT_PARAMETERS, params: &[D3DPRESENT_PARAMETERS]) {
+    if pp.is_null() {
+        return;
+    }
+
+    let originals = unsafe { std::slice::from_raw_parts_mut(pp, params.len()) };
+    for (original, params) in originals.iter_mut().zip(params) {
+        original.BackBufferWidth = params.BackBufferWidth;
+        original.BackBufferHeight = params.BackBufferHeight;
+        original.BackBufferCount = params.BackBufferCount;
+    }
+}
+
+/// Marks the current thread as running a `Present` call of a proxy until dropped, see [`enter_present`].
+#[derive(Debug)]
+pub struct PresentGuard {
+    outermost: bool,
+}
+
+impl PresentGuard {
+    /// Returns `true` if this is the outermost `Present` call on the thread.
+    ///
+    /// Reentrant calls should be forwarded plainly, without injected work or frame counting.
+    pub fn is_outermost(&self) -> bool {
+        self.outermost
+    }
+}
+
+impl Drop for PresentGuard {
+    fn drop(&mut self) {
+        PRESENT_DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+/// Enters a `Present` call of a proxy on the current thread, logging a debug line if it is reentrant.
+///
+/// Other hooks of Direct3D 9 (e.g. overlays) may call `Present` again from within the target's `Present`.
+/// Features that act on each frame must then only act in the outermost call, see [`PresentGuard::is_outermost`].
+///
+/// # Arguments
+/// * `method` - The name of the method, e.g. `PresentEx`, for logging.
+pub fn enter_present(method: &str) -> PresentGuard {
+    let outer_depth = PRESENT_DEPTH.with(|depth| depth.replace(depth.get() + 1));
+
+    #[cfg(feature = "tracing")]
+    if outer_depth > 0 {
+        tracing::debug!("Reentrant {method} detected at depth {outer_depth}, forwarding it plainly");
+    }
+    #[cfg(not(feature = "tracing"))]
+    let _ = method;
+
+    PresentGuard { outermost: outer_depth == 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use windows::Win32::Foundation::E_FAIL;
+
+    fn params(width: u32) -> D3DPRESENT_PARAMETERS {
+        D3DPRESENT_PARAMETERS {
+            BackBufferWidth: width,
+            BackBufferFormat: D3DFMT_X8R8G8B8,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn unchanged_params_are_passed_through() {
+        let mut original = params(640);
+        let mut overridden = unsafe { with_overridden_params(&original, Some(1), |_| {}) };
+        assert_eq!(overridden, None);
+        assert_eq!(params_ptr(&mut overridden, &mut original), &mut original as *mut _);
+
+        let mut overridden = unsafe { with_overridden_params(&original, Some(1), |params| params.BackBufferFormat = D3DFMT_X8R8G8B8) };
+        assert_eq!(overridden, None);
+        assert_eq!(params_ptr(&mut overridden, &mut original), &mut original as *mut _);
+
+        assert_eq!(unsafe { with_overridden_params(std::ptr::null(), Some(1), |params| params.BackBufferWidth = 1) }, None);
+        assert_eq!(unsafe { with_overridden_params(&original, None, |params| params.BackBufferWidth = 1) }, None);
+    }
+
+    #[test]
+    fn overridden_params_are_copied() {
+        let mut original = params(640);
+        let mut overridden = unsafe { with_overridden_params(&original, Some(1), |params| params.BackBufferFormat = D3DFMT_A8R8G8B8) };
+        assert_eq!(original, params(640));
+        assert_eq!(
+            overridden.as_deref(),
+            Some(
+                &[D3DPRESENT_PARAMETERS {
+                    BackBufferFormat: D3DFMT_A8R8G8B8,
+                    ..params(640)
+                }][..]
+            )
+        );
+        assert_ne!(params_ptr(&mut overridden, &mut original), &mut original as *mut _);
+
+        // The runtime fills in the back buffer size, which is written back without the overrides
+        let mut overridden = overridden.unwrap();
+        overridden[0].BackBufferHeight = 480;
+        unsafe { write_back_params(&mut original, &overridden) };
+        assert_eq!(original, D3DPRESENT_PARAMETERS { BackBufferHeight: 480, ..params(640) });
+    }
+
+    #[test]
+    fn adapter_group_params_are_copied_as_a_whole() {
+        let originals = [params(640), params(800), params(1024)];
+        let overridden = unsafe { with_overridden_params(originals.as_ptr(), Some(originals.len()), |params| params.BackBufferFormat = D3DFMT_A8R8G8B8) };
+        let master = D3DPRESENT_PARAMETERS {
+            BackBufferFormat: D3DFMT_A8R8G8B8,
+            ..params(640)
+        };
+        assert_eq!(overridden, Some(vec![master, params(800), params(1024)]));
+
+        let mut originals = originals;
+        let mut overridden = overridden.unwrap();
+        for params in &mut overridden {
+            params.BackBufferCount = 2;
+        }
+        unsafe { write_back_params(originals.as_mut_ptr(), &overridden) };
+        assert!(originals.iter().all(|params| params.BackBufferCount == 2));
+        assert_eq!(originals[0].BackBufferFormat, D3DFMT_X8R8G8B8);
+    }
+
+    #[test]
+    fn params_count_reads_adapter_group_size() {
+        assert_eq!(params_count(D3DCREATE_HARDWARE_VERTEXPROCESSING as u32, |_| unreachable!()), Some(1));
+
+        let group_flags = (D3DCREATE_HARDWARE_VERTEXPROCESSING | D3DCREATE_ADAPTERGROUP_DEVICE) as u32;
+        let count = params_count(group_flags, |caps| {
+            caps.NumberOfAdaptersInGroup = 3;
+            Ok(())
+        });
+        assert_eq!(count, Some(3));
+        assert_eq!(params_count(group_flags, |_| Err(E_FAIL.into())), None);
+    }
+}
@@ -0,0 +1,130 @@
+//! Helpers for waiting on queries that the proxy issues itself.
+//!
+//! Features such as flushing at teardown or GPU timing inject their own queries and need to wait
+//! for the results. Polling `GetData` is subtle: `S_FALSE` must be told apart from success,
+//! `D3DGETDATA_FLUSH` is needed so the command buffer is actually submitted, and a lost device
+//! must stop the wait rather than spin forever. [`wait_for_query`] centralizes this.
+
+use std::{
+    ffi::c_void,
+    thread::{sleep, yield_now},
+    time::{Duration, Instant},
+};
+use windows::{Win32::Foundation::*, Win32::Graphics::Direct3D9::*, core::*};
+
+/// How [`wait_for_query`] waits for the result of a query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitMode {
+    /// Checks the query once, failing with `S_FALSE` if the result is not available yet.
+    ///
+    /// For features that check a query once per frame rather than stalling.
+    Poll,
+    /// Checks the query until the result is available, yielding the thread between checks.
+    ///
+    /// Only stops early on errors such as a lost device, so only use this for queries that are
+    /// known to complete, e.g. event queries on a working device.
+    Spin,
+    /// Checks the query until the result is available, sleeping between checks, and fails with
+    /// `ERROR_TIMEOUT` if the result is not available within the given duration.
+    BlockWithTimeout(Duration),
+}
+
+/// Interval between checks of [`WaitMode::BlockWithTimeout`].
+const BLOCK_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Waits for the result of a query that has been issued with `Issue(D3DISSUE_END)`.
+///
+/// The query is checked with `D3DGETDATA_FLUSH` without reading the data, so the result can be
+/// read afterwards with `GetData`. Errors returned by `GetData`, such as `D3DERR_DEVICELOST`,
+/// end the wait and are returned as is.
+///
+/// # Arguments
+/// * `query` - The target query to wait for.
+/// * `mode` - How to wait. See [`WaitMode`].
+pub fn wait_for_query(query: &IDirect3DQuery9, mode: WaitMode) -> Result<()> {
+    let started = Instant::now();
+    loop {
+        match unsafe { get_query_data(query, std::ptr::null_mut(), 0, D3DGETDATA_FLUSH) } {
+            Err(err) if err.code() == S_FALSE => match mode {
+                WaitMode::Poll => return Err(err),
+                WaitMode::Spin => yield_now(),
+                WaitMode::BlockWithTimeout(timeout) if started.elapsed() >= timeout => return Err(ERROR_TIMEOUT.to_hresult().into()),
+                WaitMode::BlockWithTimeout(_) => sleep(BLOCK_POLL_INTERVAL),
+            },
+            result => return result,
+        }
+    }
+}
+
+/// Calls `GetData` on a target query, preserving `S_FALSE`.
+///
+/// `IDirect3DQuery9::GetData` maps `S_FALSE` (data not yet available) to `Ok`, which would make
+/// callers read data that has not been written. Here `S_FALSE` is returned as an error instead,
+/// which is passed through to the application unchanged when returned from a proxy method.
+///
+/// # Safety
+/// `pdata` must be null or point to at least `dwsize` writable bytes.
+pub unsafe fn get_query_data(query: &IDirect3DQuery9, pdata: *mut c_void, dwsize: u32, dwgetdataflags: u32) -> Result<()> {
+    let hr = unsafe { (Interface::vtable(query).GetData)(Interface::as_raw(query), pdata, dwsize, dwgetdataflags) };
+    if hr == S_FALSE {
+        return Err(Error::from_hresult(S_FALSE));
+    }
+    hr.ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dx9::com::{D3DERR_DEVICELOST, mock::*};
+
+    fn mock_query(results: impl IntoIterator<Item = HRESULT>) -> (ComObject<MockQuery>, IDirect3DQuery9) {
+        let mock = ComObject::new(MockQuery::new(CallLog::default(), results));
+        let query = mock.to_interface();
+        (mock, query)
+    }
+
+    #[test]
+    fn poll_checks_once() {
+        let (mock, query) = mock_query([S_FALSE]);
+        assert_eq!(wait_for_query(&query, WaitMode::Poll).unwrap_err().code(), S_FALSE);
+        assert_eq!(mock.log.count("GetData"), 1);
+
+        wait_for_query(&query, WaitMode::Poll).unwrap();
+        assert_eq!(mock.log.count("GetData"), 2);
+    }
+
+    #[test]
+    fn spin_waits_until_available() {
+        let (mock, query) = mock_query([S_FALSE; 3]);
+        wait_for_query(&query, WaitMode::Spin).unwrap();
+        assert_eq!(mock.log.count("GetData"), 4);
+    }
+
+    #[test]
+    fn errors_end_the_wait() {
+        for mode in [WaitMode::Poll, WaitMode::Spin, WaitMode::BlockWithTimeout(Duration::from_secs(10))] {
+            let (mock, query) = mock_query([S_FALSE, D3DERR_DEVICELOST, S_FALSE]);
+            let expected_calls = if mode == WaitMode::Poll { 1 } else { 2 };
+            let expected_code = if mode == WaitMode::Poll { S_FALSE } else { D3DERR_DEVICELOST };
+            assert_eq!(wait_for_query(&query, mode).unwrap_err().code(), expected_code, "{mode:?}");
+            assert_eq!(mock.log.count("GetData"), expected_calls, "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn block_with_timeout_waits_until_available() {
+        let (mock, query) = mock_query([S_FALSE; 2]);
+        wait_for_query(&query, WaitMode::BlockWithTimeout(Duration::from_secs(10))).unwrap();
+        assert_eq!(mock.log.count("GetData"), 3);
+    }
+
+    #[test]
+    fn block_with_timeout_times_out() {
+        let (mock, query) = mock_query([S_FALSE; 1000]);
+        let timeout = Duration::from_millis(5);
+        let started = Instant::now();
+        assert_eq!(wait_for_query(&query, WaitMode::BlockWithTimeout(timeout)).unwrap_err().code(), ERROR_TIMEOUT.to_hresult());
+        assert!(started.elapsed() >= timeout);
+        assert!(mock.log.count("GetData") < 1000);
+    }
+}
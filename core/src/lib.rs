@@ -6,6 +6,8 @@
 //! - DirectX 9 proxying with COM object management
 //! - Common utilities for proxy lifecycle management
 //! - Configuration and context management
+//! - Binary recording of proxied calls
+//! - Version and build information for tools that detect the proxy
 //!
 //! The library is designed to be used by DLL entry points that proxies system
 //! graphics libraries while maintaining full API compatibility.
@@ -14,6 +16,8 @@ mod common;
 use common::*;
 
 pub mod dx9;
+pub mod trace;
+pub mod version;
 
 pub use windows;
 pub use windows_core;
@@ -0,0 +1,568 @@
+//! Binary recording of proxied method calls.
+//!
+//! A call trace records every instrumented proxy method call of a session in a compact binary
+//! file, for offline analysis of the complete call sequence. Calls are captured from the
+//! `tracing-instrument` spans of the proxy methods by [`CallTraceLayer`], written with
+//! [`CallTraceWriter`], and parsed back with [`CallTraceReader`].
+//!
+//! # Format
+//!
+//! All integers are little-endian. A file starts with a header:
+//!
+//! | Offset | Size | Description |
+//! | --- | --- | --- |
+//! | 0 | 8 | Magic, `DXPTRACE` |
+//! | 8 | 4 | Format version, currently [`CALL_TRACE_VERSION`] |
+//!
+//! It is followed by records, each starting with a one-byte tag:
+//!
+//! - `0x01` String: `id: u16`, `len: u16`, `len` bytes of UTF-8.
+//!   Defines a string referenced by later records. Each string is defined once, before its first use.
+//! - `0x02` Call: `timestamp: u64`, `method: u16`, `object: u64`, `argc: u8`, then `argc` arguments
+//!   of `name: u16`, `kind: u8`, `value: u64`.
+//!   - `timestamp` is the time since the start of recording in microseconds.
+//!   - `method` is the string ID of the method name.
+//!   - `object` is the address of the proxy object the method was called on, or `0` if unknown.
+//!     Addresses are unique among live objects, but may be reused after an object is released.
+//!   - `name` is the string ID of the argument name.
+//!   - `kind` is `0` for unsigned integers, `1` for signed integers (two's complement),
+//!     `2` for floats (bits of an `f64`), `3` for booleans (`0` or `1`) and `4` for object
+//!     references (the address of the object, as in `object`, or `0` for null). Object references
+//!     were added in version 2.
+//!
+//! Scalar and interface arguments are recorded. Other pointers are recorded by instrumentation as
+//! formatted text and are omitted. A trace may end with a truncated record if the process exits
+//! while writing, which the reader treats as the end of the trace.
+
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+};
+
+/// Magic bytes at the start of a call trace file.
+pub const CALL_TRACE_MAGIC: [u8; 8] = *b"DXPTRACE";
+
+/// Version of the call trace format written by [`CallTraceWriter`].
+pub const CALL_TRACE_VERSION: u32 = 2;
+
+const TAG_STRING: u8 = 0x01;
+const TAG_CALL: u8 = 0x02;
+
+/// Value of an argument of a recorded call.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CallTraceValue {
+    U64(u64),
+    I64(i64),
+    F64(f64),
+    Bool(bool),
+    /// Address of the object an interface argument refers to, or `0` for null.
+    Object(u64),
+}
+
+impl CallTraceValue {
+    fn encode(self) -> (u8, u64) {
+        match self {
+            Self::U64(value) => (0, value),
+            Self::I64(value) => (1, value as u64),
+            Self::F64(value) => (2, value.to_bits()),
+            Self::Bool(value) => (3, value as u64),
+            Self::Object(value) => (4, value),
+        }
+    }
+
+    fn decode(kind: u8, value: u64) -> io::Result<Self> {
+        match kind {
+            0 => Ok(Self::U64(value)),
+            1 => Ok(Self::I64(value as i64)),
+            2 => Ok(Self::F64(f64::from_bits(value))),
+            3 => Ok(Self::Bool(value != 0)),
+            4 => Ok(Self::Object(value)),
+            _ => Err(invalid_data(format!("unknown argument kind {kind}"))),
+        }
+    }
+}
+
+/// A recorded method call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallTraceEvent {
+    /// Time since the start of recording in microseconds.
+    pub timestamp: u64,
+    /// Name of the called method.
+    pub method: String,
+    /// Address of the proxy object the method was called on, or `0` if unknown.
+    pub object: u64,
+    /// Names and values of the recorded arguments.
+    pub args: Vec<(String, CallTraceValue)>,
+}
+
+/// Writes a call trace in the format described in the [module documentation](self).
+#[derive(Debug)]
+pub struct CallTraceWriter<W: Write> {
+    writer: W,
+    strings: HashMap<String, u16>,
+}
+
+impl<W: Write> CallTraceWriter<W> {
+    /// Creates a writer and writes the header.
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(&CALL_TRACE_MAGIC)?;
+        writer.write_all(&CALL_TRACE_VERSION.to_le_bytes())?;
+        Ok(Self { writer, strings: HashMap::new() })
+    }
+
+    /// Writes a call record, preceded by the definitions of any new strings.
+    pub fn write_call(&mut self, timestamp: u64, method: &str, object: u64, args: &[(&str, CallTraceValue)]) -> io::Result<()> {
+        let method_id = self.string_id(method)?;
+        let arg_ids = args.iter().map(|(name, value)| Ok((self.string_id(name)?, *value))).collect::<io::Result<Vec<_>>>()?;
+        let argc = u8::try_from(arg_ids.len()).map_err(|_| invalid_data("too many arguments"))?;
+
+        let mut record = Vec::with_capacity(20 + arg_ids.len() * 11);
+        record.push(TAG_CALL);
+        record.extend_from_slice(&timestamp.to_le_bytes());
+        record.extend_from_slice(&method_id.to_le_bytes());
+        record.extend_from_slice(&object.to_le_bytes());
+        record.push(argc);
+        for (name_id, value) in arg_ids {
+            let (kind, bits) = value.encode();
+            record.extend_from_slice(&name_id.to_le_bytes());
+            record.push(kind);
+            record.extend_from_slice(&bits.to_le_bytes());
+        }
+        self.writer.write_all(&record)
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Returns the ID of a string, writing its definition first if it is new.
+    fn string_id(&mut self, string: &str) -> io::Result<u16> {
+        if let Some(&id) = self.strings.get(string) {
+            return Ok(id);
+        }
+
+        let id = u16::try_from(self.strings.len()).map_err(|_| invalid_data("too many strings"))?;
+        let len = u16::try_from(string.len()).map_err(|_| invalid_data("string too long"))?;
+        self.writer.write_all(&[TAG_STRING])?;
+        self.writer.write_all(&id.to_le_bytes())?;
+        self.writer.write_all(&len.to_le_bytes())?;
+        self.writer.write_all(string.as_bytes())?;
+        self.strings.insert(string.to_string(), id);
+        Ok(id)
+    }
+}
+
+/// Reads a call trace written by [`CallTraceWriter`], yielding the recorded calls.
+///
+/// # Example
+/// ```ignore
+/// let reader = CallTraceReader::new(BufReader::new(File::open("dxproxy.trace")?))?;
+/// for event in reader {
+///     let event = event?;
+///     println!("{} {} {:#x} {:?}", event.timestamp, event.method, event.object, event.args);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct CallTraceReader<R: Read> {
+    reader: R,
+    strings: Vec<String>,
+    version: u32,
+}
+
+impl<R: Read> CallTraceReader<R> {
+    /// Creates a reader and validates the header.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if magic != CALL_TRACE_MAGIC {
+            return Err(invalid_data("not a call trace"));
+        }
+        let version = read_u32(&mut reader)?;
+        if version > CALL_TRACE_VERSION {
+            return Err(invalid_data(format!("unsupported call trace version {version}")));
+        }
+        Ok(Self { reader, strings: Vec::new(), version })
+    }
+
+    /// Returns the format version of the trace.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Reads the next call, or returns `None` at the end of the trace.
+    pub fn read_event(&mut self) -> io::Result<Option<CallTraceEvent>> {
+        loop {
+            let mut tag = [0];
+            if self.reader.read(&mut tag)? == 0 {
+                return Ok(None);
+            }
+
+            let result = match tag[0] {
+                TAG_STRING => self.read_string_definition().map(|_| None),
+                TAG_CALL => self.read_call().map(Some),
+                tag => return Err(invalid_data(format!("unknown record tag {tag:#04x}"))),
+            };
+            match result {
+                Ok(Some(event)) => return Ok(Some(event)),
+                Ok(None) => continue,
+                // A truncated record at the end means the process exited while writing
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn read_string_definition(&mut self) -> io::Result<()> {
+        let id = read_u16(&mut self.reader)?;
+        let len = read_u16(&mut self.reader)?;
+        let mut bytes = vec![0; len as usize];
+        self.reader.read_exact(&mut bytes)?;
+        if id as usize != self.strings.len() {
+            return Err(invalid_data(format!("unexpected string ID {id}")));
+        }
+        self.strings.push(String::from_utf8(bytes).map_err(invalid_data)?);
+        Ok(())
+    }
+
+    fn read_call(&mut self) -> io::Result<CallTraceEvent> {
+        let timestamp = read_u64(&mut self.reader)?;
+        let method_id = read_u16(&mut self.reader)?;
+        let method = self.string(method_id)?;
+        let object = read_u64(&mut self.reader)?;
+        let mut argc = [0];
+        self.reader.read_exact(&mut argc)?;
+
+        let mut args = Vec::with_capacity(argc[0] as usize);
+        for _ in 0..argc[0] {
+            let name_id = read_u16(&mut self.reader)?;
+            let name = self.string(name_id)?;
+            let mut kind = [0];
+            self.reader.read_exact(&mut kind)?;
+            let value = CallTraceValue::decode(kind[0], read_u64(&mut self.reader)?)?;
+            args.push((name, value));
+        }
+
+        Ok(CallTraceEvent { timestamp, method, object, args })
+    }
+
+    fn string(&self, id: u16) -> io::Result<String> {
+        self.strings.get(id as usize).cloned().ok_or_else(|| invalid_data(format!("undefined string ID {id}")))
+    }
+}
+
+impl<R: Read> Iterator for CallTraceReader<R> {
+    type Item = io::Result<CallTraceEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_event().transpose()
+    }
+}
+
+fn read_u16(reader: &mut impl Read) -> io::Result<u16> {
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn invalid_data(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+#[cfg(feature = "tracing-instrument")]
+mod layer {
+    use super::*;
+    use std::{cell::Cell, ffi::c_void, fmt, ptr::null_mut, sync::Mutex, time::Instant};
+    use tracing::{
+        Metadata, Subscriber,
+        field::{Field, Visit},
+        span::{Attributes, Id},
+    };
+    use tracing_subscriber::{Layer, layer::Context};
+    use windows::core::Interface;
+
+    thread_local! {
+        /// Address of the object last formatted with `Debug` on this thread.
+        static FORMATTED_OBJECT: Cell<Option<u64>> = const { Cell::new(None) };
+    }
+
+    /// Records the address of an object that is being formatted with `Debug`.
+    ///
+    /// Called by the `Debug` implementations of the proxies and of [`TracedObject`], so that
+    /// [`CallTraceLayer`] gets the address of the objects of a method span without parsing its text.
+    pub fn on_object_formatted(object: *const c_void) {
+        FORMATTED_OBJECT.set(Some(object as u64));
+    }
+
+    /// Interface argument of an instrumented method, recorded by [`CallTraceLayer`] as an object reference.
+    ///
+    /// Interfaces do not implement `Debug` in a way the layer can recognize, so instrumented methods skip
+    /// their interface arguments and record this instead, e.g.
+    /// `skip(ptexture), fields(ptexture = ?TracedObject::new(ptexture.as_ref()))`. The object is identified
+    /// by the address of its `IUnknown`, like the `object` of the calls made on it.
+    pub struct TracedObject(*mut c_void);
+
+    impl TracedObject {
+        /// Creates the argument value for `interface`, which may be null.
+        pub fn new<T: Interface>(interface: Option<&T>) -> Self {
+            Self(interface.map_or(null_mut(), crate::com_identity))
+        }
+    }
+
+    impl fmt::Debug for TracedObject {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            on_object_formatted(self.0);
+            write!(f, "{:p}", self.0)
+        }
+    }
+
+    /// Target prefix of the spans recorded by [`CallTraceLayer`].
+    const RECORDED_TARGET_PREFIX: &str = "dxproxy::dx9::com";
+
+    /// Layer that records the instrumented proxy method calls into a call trace.
+    ///
+    /// Each span created by `#[instrument]` on a proxy method is recorded as a call when it is created. The trace is flushed after each `Present` call, so the recording is complete
+    /// up to the last presented frame even if the process exits abruptly.
+    #[derive(Debug)]
+    pub struct CallTraceLayer<W: Write> {
+        writer: Mutex<CallTraceWriter<W>>,
+        start: Instant,
+    }
+
+    impl<W: Write> CallTraceLayer<W> {
+        /// Creates a layer that records calls with the specified writer.
+        pub fn new(writer: CallTraceWriter<W>) -> Self {
+            Self {
+                writer: Mutex::new(writer),
+                start: Instant::now(),
+            }
+        }
+
+        /// Returns `true` for the spans of proxy methods, which are the ones recorded by this layer.
+        ///
+        /// Use this as a per-layer filter, so that other spans and events are not enabled just for this layer.
+        pub fn is_recorded(metadata: &Metadata<'_>) -> bool {
+            metadata.is_span() && metadata.target().starts_with(RECORDED_TARGET_PREFIX)
+        }
+    }
+
+    impl<S: Subscriber, W: Write + 'static> Layer<S> for CallTraceLayer<W> {
+        fn on_new_span(&self, attrs: &Attributes<'_>, _id: &Id, _ctx: Context<'_, S>) {
+            let metadata = attrs.metadata();
+            if !Self::is_recorded(metadata) {
+                return;
+            }
+
+            let mut visitor = CallArgsVisitor::default();
+            attrs.record(&mut visitor);
+
+            let timestamp = self.start.elapsed().as_micros() as u64;
+            let method = metadata.name();
+            let mut writer = self.writer.lock().unwrap();
+            let mut result = writer.write_call(timestamp, method, visitor.object, &visitor.args);
+            if method.starts_with("Present") {
+                result = result.and_then(|_| writer.flush());
+            }
+            if let Err(err) = result {
+                eprintln!("Failed to write call trace: {err}");
+            }
+        }
+    }
+
+    /// Collects the scalar and object arguments and the object address of a proxy method span.
+    #[derive(Default)]
+    struct CallArgsVisitor {
+        object: u64,
+        args: Vec<(&'static str, CallTraceValue)>,
+    }
+
+    impl Visit for CallArgsVisitor {
+        fn record_u64(&mut self, field: &Field, value: u64) {
+            self.args.push((field.name(), CallTraceValue::U64(value)));
+        }
+
+        fn record_i64(&mut self, field: &Field, value: i64) {
+            self.args.push((field.name(), CallTraceValue::I64(value)));
+        }
+
+        fn record_f64(&mut self, field: &Field, value: f64) {
+            self.args.push((field.name(), CallTraceValue::F64(value)));
+        }
+
+        fn record_bool(&mut self, field: &Field, value: bool) {
+            self.args.push((field.name(), CallTraceValue::Bool(value)));
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            // Objects record their address before writing anything, so formatting stops at the first write
+            FORMATTED_OBJECT.set(None);
+            let _ = fmt::write(&mut StopWriter, format_args!("{value:?}"));
+            let object = FORMATTED_OBJECT.take();
+            if field.name() == "self" {
+                self.object = object.unwrap_or(0);
+            } else if let Some(object) = object {
+                self.args.push((field.name(), CallTraceValue::Object(object)));
+            }
+        }
+    }
+
+    /// Writer that fails on the first write, to run a `Debug` implementation without formatting its output.
+    struct StopWriter;
+
+    impl fmt::Write for StopWriter {
+        fn write_str(&mut self, _s: &str) -> fmt::Result {
+            Err(fmt::Error)
+        }
+    }
+}
+
+#[cfg(feature = "tracing-instrument")]
+pub use layer::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_trace() -> Vec<u8> {
+        let mut writer = CallTraceWriter::new(Vec::new()).unwrap();
+        writer
+            .write_call(10, "SetRenderState", 0x1000, &[("state", CallTraceValue::U64(7)), ("value", CallTraceValue::I64(-1))])
+            .unwrap();
+        writer
+            .write_call(25, "SetRenderState", 0x1000, &[("state", CallTraceValue::U64(8)), ("value", CallTraceValue::F64(0.5))])
+            .unwrap();
+        writer
+            .write_call(40, "SetTexture", 0x1000, &[("stage", CallTraceValue::U64(0)), ("ptexture", CallTraceValue::Object(0x2000))])
+            .unwrap();
+        writer.write_call(55, "Present", 0, &[("windowed", CallTraceValue::Bool(true))]).unwrap();
+        writer.flush().unwrap();
+        writer.writer
+    }
+
+    #[test]
+    fn reader_round_trips_writer() {
+        let trace = write_trace();
+        let reader = CallTraceReader::new(trace.as_slice()).unwrap();
+        assert_eq!(reader.version(), CALL_TRACE_VERSION);
+
+        let events = reader.collect::<io::Result<Vec<_>>>().unwrap();
+        let event = |timestamp, method: &str, object, args: &[(&str, CallTraceValue)]| CallTraceEvent {
+            timestamp,
+            method: method.to_string(),
+            object,
+            args: args.iter().map(|(name, value)| (name.to_string(), *value)).collect(),
+        };
+        assert_eq!(
+            events,
+            [
+                event(10, "SetRenderState", 0x1000, &[("state", CallTraceValue::U64(7)), ("value", CallTraceValue::I64(-1))]),
+                event(25, "SetRenderState", 0x1000, &[("state", CallTraceValue::U64(8)), ("value", CallTraceValue::F64(0.5))]),
+                event(40, "SetTexture", 0x1000, &[("stage", CallTraceValue::U64(0)), ("ptexture", CallTraceValue::Object(0x2000))]),
+                event(55, "Present", 0, &[("windowed", CallTraceValue::Bool(true))]),
+            ]
+        );
+    }
+
+    #[test]
+    fn writer_defines_each_string_once() {
+        let trace = write_trace();
+        let count = |needle: &[u8]| trace.windows(needle.len()).filter(|window| *window == needle).count();
+        assert_eq!(count(b"SetRenderState"), 1);
+        assert_eq!(count(b"state"), 1);
+    }
+
+    #[test]
+    fn reader_stops_at_truncated_record() {
+        let mut trace = write_trace();
+        trace.truncate(trace.len() - 3);
+        let events = CallTraceReader::new(trace.as_slice()).unwrap().collect::<io::Result<Vec<_>>>().unwrap();
+        assert_eq!(events.iter().map(|event| event.timestamp).collect::<Vec<_>>(), [10, 25, 40]);
+    }
+
+    #[test]
+    fn reader_rejects_invalid_headers() {
+        assert_eq!(CallTraceReader::new(&b"NOTTRACE\x01\0\0\0"[..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let mut trace = write_trace();
+        trace[8..12].copy_from_slice(&(CALL_TRACE_VERSION + 1).to_le_bytes());
+        assert_eq!(CallTraceReader::new(trace.as_slice()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(feature = "tracing-instrument")]
+    #[test]
+    fn layer_records_object_addresses_of_spans_and_arguments() {
+        use std::{
+            ffi::c_void,
+            fmt,
+            sync::{Arc, Mutex},
+        };
+        use tracing_subscriber::layer::SubscriberExt;
+        use windows::core::IUnknown;
+
+        #[derive(Clone, Default)]
+        struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+        impl Write for SharedBuffer {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        struct Proxy;
+
+        impl fmt::Debug for Proxy {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                on_object_formatted(0x1230 as *const c_void);
+                write!(f, "Proxy at an address not written as hex")
+            }
+        }
+
+        let buffer = SharedBuffer::default();
+        let layer = CallTraceLayer::new(CallTraceWriter::new(buffer.clone()).unwrap());
+        tracing::subscriber::with_default(tracing_subscriber::registry().with(layer), || {
+            let _span = tracing::trace_span!(target: "dxproxy::dx9::com::test", "SetRenderState", "self" = ?Proxy, state = 7u64).entered();
+            let _span = tracing::trace_span!(
+                target: "dxproxy::dx9::com::test",
+                "SetTexture",
+                "self" = ?Proxy,
+                ptexture = ?TracedObject::new(None::<&IUnknown>),
+                pdata = ?(0x10 as *const c_void),
+                other = ?Proxy,
+            )
+            .entered();
+            let _span = tracing::trace_span!(target: "dxproxy::dx9::com::test", "Present").entered();
+            let _span = tracing::trace_span!(target: "dxproxy::other", "Ignored").entered();
+        });
+
+        let trace = buffer.0.lock().unwrap().clone();
+        let events = CallTraceReader::new(trace.as_slice()).unwrap().collect::<io::Result<Vec<_>>>().unwrap();
+        assert_eq!(
+            events.iter().map(|event| (event.method.as_str(), event.object, event.args.clone())).collect::<Vec<_>>(),
+            [
+                ("SetRenderState", 0x1230, vec![("state".to_string(), CallTraceValue::U64(7))]),
+                (
+                    "SetTexture",
+                    0x1230,
+                    vec![("ptexture".to_string(), CallTraceValue::Object(0)), ("other".to_string(), CallTraceValue::Object(0x1230))]
+                ),
+                ("Present", 0, vec![]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! Version and build information, for tools that detect the proxy.
+//!
+//! External launchers and overlays can verify that the proxy is loaded, and which version and
+//! build it is, by calling the `DxproxyGetVersion` export of the proxy DLL, see [`DxproxyGetVersion`].
+//! In Rust, [`dxproxy_version`] returns the same information.
+//!
+//! # ABI
+//!
+//! ```c
+//! uint32_t __stdcall DxproxyGetVersion(char *buf, uint32_t len);
+//! ```
+//!
+//! The function writes a NUL-terminated ASCII description of the build to `buf`, such as
+//! `0.1.0 (release; tracing, tracing-instrument)`, truncated to fit in `len` bytes including the
+//! terminator. [`MAX_VERSION_STRING_LEN`] bytes are always enough. It returns the build flags as a
+//! bitmask of the `VERSION_FLAG_*` constants, e.g. [`VERSION_FLAG_DEBUG`].
+
+use std::fmt;
+
+/// The build is a debug build.
+pub const VERSION_FLAG_DEBUG: u32 = 1 << 0;
+/// The `tracing` feature is enabled.
+pub const VERSION_FLAG_TRACING: u32 = 1 << 1;
+/// The `tracing-instrument` feature is enabled.
+pub const VERSION_FLAG_TRACING_INSTRUMENT: u32 = 1 << 2;
+/// The `passthrough` feature is enabled, i.e. objects are not wrapped in proxies.
+pub const VERSION_FLAG_PASSTHROUGH: u32 = 1 << 3;
+/// The `experimental-serialize-device-calls` feature is enabled.
+pub const VERSION_FLAG_SERIALIZE_DEVICE_CALLS: u32 = 1 << 4;
+
+/// Buffer size, including the terminator, that always fits the description written by [`DxproxyGetVersion`].
+pub const MAX_VERSION_STRING_LEN: u32 = 256;
+
+/// Cargo features that can be enabled, with their flags.
+const FEATURES: [(&str, bool, u32); 4] = [
+    ("tracing", cfg!(feature = "tracing"), VERSION_FLAG_TRACING),
+    ("tracing-instrument", cfg!(feature = "tracing-instrument"), VERSION_FLAG_TRACING_INSTRUMENT),
+    ("passthrough", cfg!(feature = "passthrough"), VERSION_FLAG_PASSTHROUGH),
+    (
+        "experimental-serialize-device-calls",
+        cfg!(feature = "experimental-serialize-device-calls"),
+        VERSION_FLAG_SERIALIZE_DEVICE_CALLS,
+    ),
+];
+
+/// Version and build information of the proxy, returned by [`dxproxy_version`].
+///
+/// Formats as the description written by [`DxproxyGetVersion`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionInfo {
+    /// The crate version, e.g. `0.1.0`.
+    pub version: &'static str,
+    /// Bitmask of the `VERSION_FLAG_*` constants.
+    pub flags: u32,
+}
+
+impl VersionInfo {
+    /// Returns the build profile, `debug` or `release`.
+    pub fn profile(&self) -> &'static str {
+        if self.flags & VERSION_FLAG_DEBUG != 0 { "debug" } else { "release" }
+    }
+
+    /// Returns the names of the enabled Cargo features.
+    pub fn features(&self) -> impl Iterator<Item = &'static str> + '_ {
+        FEATURES.iter().filter(|(_, _, flag)| self.flags & flag != 0).map(|(name, _, _)| *name)
+    }
+}
+
+impl fmt::Display for VersionInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}; {})", self.version, self.profile(), self.features().collect::<Vec<_>>().join(", "))
+    }
+}
+
+/// Returns the version and build information of the proxy.
+pub fn dxproxy_version() -> VersionInfo {
+    let debug_flag = if cfg!(debug_assertions) { VERSION_FLAG_DEBUG } else { 0 };
+    let flags = FEATURES.iter().filter(|(_, enabled, _)| *enabled).fold(debug_flag, |flags, (_, _, flag)| flags | flag);
+    VersionInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        flags,
+    }
+}
+
+/// Writes a description of the build to `buf`, and returns the build flags, see the [module documentation](self).
+///
+/// # Arguments
+/// * `buf` - Buffer that receives the NUL-terminated description, or null to only query the flags.
+/// * `len` - Size of `buf` in bytes. Nothing is written if it is `0`.
+///
+/// # Returns
+/// Bitmask of the `VERSION_FLAG_*` constants.
+///
+/// # Safety
+/// `buf` must be null or point to `len` writable bytes.
+#[allow(non_snake_case)]
+pub unsafe extern "system" fn DxproxyGetVersion(buf: *mut u8, len: u32) -> u32 {
+    let info = dxproxy_version();
+    if !buf.is_null() && len > 0 {
+        let description = info.to_string();
+        let count = description.len().min(len as usize - 1);
+        unsafe {
+            std::ptr::copy_nonoverlapping(description.as_ptr(), buf, count);
+            *buf.add(count) = 0;
+        }
+    }
+    info.flags
+}
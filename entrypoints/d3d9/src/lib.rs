@@ -8,10 +8,27 @@
 //! Place d3d9.dll alongside an application executable. The library will
 //! intercept calls to `Direct3DCreate9` and `Direct3DCreate9Ex`, creating
 //! proxy-wrapped Direct3D objects.
+//!
+//! The following auxiliary exports are forwarded as-is to the system d3d9.dll,
+//! so that applications importing them can still be loaded:
+//! - `D3DPERF_BeginEvent`, `D3DPERF_EndEvent`, `D3DPERF_SetMarker`, `D3DPERF_SetRegion`,
+//!   `D3DPERF_QueryRepeatFrame`, `D3DPERF_SetOptions`, `D3DPERF_GetStatus`
+//! - `DebugSetMute`
+//! - `Direct3DShaderValidatorCreate9`
+//!
+//! `DxproxyGetVersion` is exported as well, so that tools can detect the proxy and its version,
+//! see `dxproxy::version`.
+//!
+//! ## Features
+//!
+//! - `passthrough`: Returns the original Direct3D objects without proxy wrapping.
+//!   The system d3d9.dll is still loaded and logged the same way, which makes it easy
+//!   to tell whether an issue is caused by the proxy or by the DLL replacement itself.
 
 #![windows_subsystem = "windows"]
 
 use dxproxy::{windows::Win32::Graphics::Direct3D9::*, windows_core::*, *};
+use std::ffi::c_void;
 
 /// Creates a proxied Direct3D9 object.
 ///
@@ -53,3 +70,95 @@ pub unsafe extern "system" fn Direct3DCreate9(sdkversion: u32) -> Option<IDirect
 pub unsafe extern "system" fn Direct3DCreate9Ex(sdkversion: u32, ppd3d: *mut Option<IDirect3D9Ex>) -> HRESULT {
     unsafe { dx9::Direct3DCreate9Ex(sdkversion, ppd3d) }
 }
+
+/// Forwards `D3DPERF_BeginEvent` to the system d3d9.dll.
+///
+/// # Safety
+/// This function maintains the same safety contract as the original function.
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn D3DPERF_BeginEvent(col: u32, wszname: PCWSTR) -> i32 {
+    unsafe { dx9::D3DPERF_BeginEvent(col, wszname) }
+}
+
+/// Forwards `D3DPERF_EndEvent` to the system d3d9.dll.
+///
+/// # Safety
+/// This function maintains the same safety contract as the original function.
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn D3DPERF_EndEvent() -> i32 {
+    unsafe { dx9::D3DPERF_EndEvent() }
+}
+
+/// Forwards `D3DPERF_SetMarker` to the system d3d9.dll.
+///
+/// # Safety
+/// This function maintains the same safety contract as the original function.
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn D3DPERF_SetMarker(col: u32, wszname: PCWSTR) {
+    unsafe { dx9::D3DPERF_SetMarker(col, wszname) }
+}
+
+/// Forwards `D3DPERF_SetRegion` to the system d3d9.dll.
+///
+/// # Safety
+/// This function maintains the same safety contract as the original function.
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn D3DPERF_SetRegion(col: u32, wszname: PCWSTR) {
+    unsafe { dx9::D3DPERF_SetRegion(col, wszname) }
+}
+
+/// Forwards `D3DPERF_QueryRepeatFrame` to the system d3d9.dll.
+///
+/// # Safety
+/// This function maintains the same safety contract as the original function.
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn D3DPERF_QueryRepeatFrame() -> BOOL {
+    unsafe { dx9::D3DPERF_QueryRepeatFrame() }
+}
+
+/// Forwards `D3DPERF_SetOptions` to the system d3d9.dll.
+///
+/// # Safety
+/// This function maintains the same safety contract as the original function.
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn D3DPERF_SetOptions(dwoptions: u32) {
+    unsafe { dx9::D3DPERF_SetOptions(dwoptions) }
+}
+
+/// Forwards `D3DPERF_GetStatus` to the system d3d9.dll.
+///
+/// # Safety
+/// This function maintains the same safety contract as the original function.
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn D3DPERF_GetStatus() -> u32 {
+    unsafe { dx9::D3DPERF_GetStatus() }
+}
+
+/// Forwards `DebugSetMute` to the system d3d9.dll.
+///
+/// # Safety
+/// This function maintains the same safety contract as the original function.
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn DebugSetMute() {
+    unsafe { dx9::DebugSetMute() }
+}
+
+/// Forwards `Direct3DShaderValidatorCreate9` to the system d3d9.dll.
+///
+/// # Safety
+/// This function maintains the same safety contract as the original function.
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn Direct3DShaderValidatorCreate9() -> *mut c_void {
+    unsafe { dx9::Direct3DShaderValidatorCreate9() }
+}
+
+/// Writes the version and build of the proxy to `buf`, and returns the build flags.
+///
+/// See `dxproxy::version` for the ABI.
+///
+/// # Safety
+/// `buf` must be null or point to `len` writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "system" fn DxproxyGetVersion(buf: *mut u8, len: u32) -> u32 {
+    unsafe { version::DxproxyGetVersion(buf, len) }
+}